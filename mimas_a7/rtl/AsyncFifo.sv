@@ -0,0 +1,102 @@
+`default_nettype none
+
+// A FIFO between two unrelated clock domains, using gray-coded pointers that are synchronized into the other domain
+//  Each side only sees the other side's pointer a few cycles late, so the write side's free count and the read side's
+//  empty flag are conservative. Read data is show-ahead: read_data holds the oldest entry whenever read_empty is low,
+//  and read_enable moves on to the next one.
+module AsyncFifo#(parameter DATA_BITS = 8, ADDR_BITS = 4) (
+    input wire logic write_reset_n,
+    input wire logic write_clk,
+
+    input wire logic write_enable,
+    input wire logic [DATA_BITS - 1:0] write_data,
+    output wire logic [ADDR_BITS:0] write_free,
+
+    input wire logic read_reset_n,
+    input wire logic read_clk,
+
+    input wire logic read_enable,
+    output wire logic [DATA_BITS - 1:0] read_data,
+    output wire logic read_empty);
+
+    function automatic logic [ADDR_BITS:0] bin_to_gray(logic [ADDR_BITS:0] x);
+        return x ^ (x >> 1);
+    endfunction
+
+    function automatic logic [ADDR_BITS:0] gray_to_bin(logic [ADDR_BITS:0] x);
+        logic [ADDR_BITS:0] ret;
+        ret[ADDR_BITS] = x[ADDR_BITS];
+        for (int i = ADDR_BITS - 1; i >= 0; i--) begin
+            ret[i] = ret[i + 1] ^ x[i];
+        end
+        return ret;
+    endfunction
+
+    logic [DATA_BITS - 1:0] mem[2 ** ADDR_BITS];
+
+    // Write side
+    logic [ADDR_BITS:0] write_ptr;
+    logic [ADDR_BITS:0] write_ptr_gray;
+    (* ASYNC_REG = "TRUE" *) logic [ADDR_BITS:0] read_ptr_gray_sync[2];
+
+    always_ff @(posedge write_clk) begin
+        if (write_enable) begin
+            mem[write_ptr[ADDR_BITS - 1:0]] <= write_data;
+        end
+    end
+
+    always_ff @(posedge write_clk, negedge write_reset_n) begin
+        if (~write_reset_n) begin
+            write_ptr <= '0;
+            write_ptr_gray <= '0;
+            read_ptr_gray_sync[0] <= '0;
+            read_ptr_gray_sync[1] <= '0;
+        end
+        else begin
+            if (write_enable) begin
+                write_ptr <= write_ptr + 1'b1;
+                write_ptr_gray <= bin_to_gray(write_ptr + 1'b1);
+            end
+
+            read_ptr_gray_sync[0] <= read_ptr_gray;
+            read_ptr_gray_sync[1] <= read_ptr_gray_sync[0];
+        end
+    end
+
+    assign write_free = (ADDR_BITS + 1)'(2 ** ADDR_BITS) - (write_ptr - gray_to_bin(read_ptr_gray_sync[1]));
+
+    // Read side
+    logic [ADDR_BITS:0] read_ptr;
+    logic [ADDR_BITS:0] read_ptr_gray;
+    (* ASYNC_REG = "TRUE" *) logic [ADDR_BITS:0] write_ptr_gray_sync[2];
+    logic [DATA_BITS - 1:0] read_data_reg;
+
+    // The entry at the read pointer is read every cycle, so an entry that was written after the last time its slot was
+    //  read shows up well before the write pointer makes it through the sync chain
+    logic [ADDR_BITS:0] read_ptr_next;
+    assign read_ptr_next = read_ptr + (read_enable ? 1'b1 : 1'b0);
+
+    always_ff @(posedge read_clk) begin
+        read_data_reg <= mem[read_ptr_next[ADDR_BITS - 1:0]];
+    end
+
+    always_ff @(posedge read_clk, negedge read_reset_n) begin
+        if (~read_reset_n) begin
+            read_ptr <= '0;
+            read_ptr_gray <= '0;
+            write_ptr_gray_sync[0] <= '0;
+            write_ptr_gray_sync[1] <= '0;
+        end
+        else begin
+            read_ptr <= read_ptr_next;
+            read_ptr_gray <= bin_to_gray(read_ptr_next);
+
+            write_ptr_gray_sync[0] <= write_ptr_gray;
+            write_ptr_gray_sync[1] <= write_ptr_gray_sync[0];
+        end
+    end
+
+    assign read_data = read_data_reg;
+    assign read_empty = read_ptr_gray == write_ptr_gray_sync[1];
+
+endmodule
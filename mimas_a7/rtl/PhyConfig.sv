@@ -0,0 +1,83 @@
+`default_nettype none
+
+// Configures the Ethernet PHY over MDIO once after reset, so that it only autonegotiates gigabit full-duplex links
+//  (RgmiiAdapter only handles 1000mbit). PHY address 0 is a broadcast address on Realtek PHYs, so the default doesn't
+//  depend on the board's address straps. MDC is the system clock divided by 64, which stays under MDIO's 2.5mhz limit
+//  for system clocks up to 160mhz.
+module PhyConfig#(parameter PHY_ADDR = 5'd0) (
+    input wire logic reset_n,
+    input wire logic clk,
+
+    output wire logic mdc,
+    inout wire logic mdio);
+
+    // Preamble, start, write op, PHY/reg addr, turnaround, data
+    //  The 10/100 advertisement reg keeps only its selector field (802.3), the gigabit control reg only advertises
+    //  1000BASE-T full duplex, and the last frame restarts autonegotiation with the new advertisements
+    localparam FRAMES = 3;
+    localparam [63:0] FRAME_10_100_ADVERTISEMENT = {32'hffffffff, 2'b01, 2'b01, PHY_ADDR, 5'd4, 2'b10, 16'h0001};
+    localparam [63:0] FRAME_GIGABIT_ADVERTISEMENT = {32'hffffffff, 2'b01, 2'b01, PHY_ADDR, 5'd9, 2'b10, 16'h0200};
+    localparam [63:0] FRAME_RESTART_AUTONEG = {32'hffffffff, 2'b01, 2'b01, PHY_ADDR, 5'd0, 2'b10, 16'h1200};
+
+    // ~168ms at 100mhz, to give the PHY time to come out of its own reset
+    logic [23:0] startup_counter;
+    logic [5:0] mdc_counter;
+    logic [63:0] shift;
+    logic [5:0] bits_left;
+    logic [1:0] frame_index;
+    logic mdio_oe;
+    logic done;
+
+    // Frames are sent back to back; the next one's preamble starts right after the last data bit
+    logic [1:0] next_frame_index;
+    assign next_frame_index = mdio_oe ? frame_index + 2'd1 : frame_index;
+
+    logic [63:0] next_frame;
+    always_comb begin
+        case (next_frame_index)
+            2'd0: next_frame = FRAME_10_100_ADVERTISEMENT;
+            2'd1: next_frame = FRAME_GIGABIT_ADVERTISEMENT;
+            default: next_frame = FRAME_RESTART_AUTONEG;
+        endcase
+    end
+
+    always_ff @(posedge clk, negedge reset_n) begin
+        if (~reset_n) begin
+            startup_counter <= 24'h0;
+            mdc_counter <= 6'h0;
+            shift <= 64'h0;
+            bits_left <= 6'h0;
+            frame_index <= 2'd0;
+            mdio_oe <= 1'b0;
+            done <= 1'b0;
+        end
+        else begin
+            mdc_counter <= mdc_counter + 6'h1;
+
+            if (startup_counter != 24'hffffff) begin
+                startup_counter <= startup_counter + 24'h1;
+            end
+            // Data changes right after MDC falls and the PHY samples it when MDC rises
+            else if (~done && mdc_counter == 6'h0) begin
+                if (mdio_oe && bits_left != 6'h0) begin
+                    shift <= {shift[62:0], 1'b1};
+                    bits_left <= bits_left - 6'h1;
+                end
+                else if (mdio_oe && frame_index == 2'(FRAMES - 1)) begin
+                    mdio_oe <= 1'b0;
+                    done <= 1'b1;
+                end
+                else begin
+                    shift <= next_frame;
+                    bits_left <= 6'd63;
+                    frame_index <= next_frame_index;
+                    mdio_oe <= 1'b1;
+                end
+            end
+        end
+    end
+
+    assign mdc = mdc_counter[5];
+    assign mdio = mdio_oe ? shift[63] : 1'bz;
+
+endmodule
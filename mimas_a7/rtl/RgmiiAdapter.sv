@@ -0,0 +1,404 @@
+`default_nettype none
+
+// Adapts the MAC's byte stream to an RGMII PHY running a gigabit link (with a 100mhz system clock)
+//  At 1000mbit, RGMII moves one byte per 125mhz clock cycle, with the low nibble on the rising edge and the high nibble
+//  on the falling edge; CTL carries EN/DV on the rising edge and EN/DV ^ ER on the falling edge. The MAC takes at most
+//  one byte per system clock cycle, so each direction goes through a FIFO between the system clock and the 125mhz
+//  link clock:
+//  - TX is store-and-forward: frames are only sent once they're completely in the TX FIFO, since the link drains
+//    bytes faster than the MAC can provide them. The adapter strobes the MAC whenever there's room in the FIFO, and
+//    adds its own inter-frame gap on the link side.
+//  - RX bytes (from the SFD on) are written to the RX FIFO in the PHY's RX clock domain and strobed to the MAC as fast
+//    as it takes them. A max-size frame builds up a backlog of ~300 bytes, which drains during the following gaps; if
+//    the FIFO does fill up (only with sustained back-to-back traffic), the rest of the frame is dropped and the MAC
+//    sees an error, so the frame counts as dropped.
+//  TXC is driven from a 125mhz clock that's shifted by 90 degrees, so its edges are centered in the TX data, and RX
+//  data is expected to be centered around RXC edges; that is, this relies on the PHY delaying RXC (RGMII-ID) but not
+//  TXC. Set TX_CLOCK_SHIFT to 0 if the PHY's TX clock delay is strapped on.
+module RgmiiAdapter#(parameter TX_CLOCK_SHIFT = 1'b1) (
+    input wire logic reset_n,
+    input wire logic clk,
+
+    output wire logic tx_strobe,
+    input wire logic tx_en,
+    input wire logic [7:0] tx_data,
+
+    output wire logic rx_strobe,
+    output wire logic rx_dv,
+    output wire logic rx_er,
+    output wire logic [7:0] rx_data,
+
+    output wire logic rgmii_txc,
+    output wire logic rgmii_tx_ctl,
+    output wire logic [3:0] rgmii_txd,
+
+    input wire logic rgmii_rxc,
+    input wire logic rgmii_rx_ctl,
+    input wire logic [3:0] rgmii_rxd);
+
+    // Both FIFOs hold a max-size frame (including preamble/SFD, plus an end marker) with plenty to spare
+    localparam FIFO_ADDR_BITS = 11;
+    localparam IFG_SIZE = 12;
+    localparam [7:0] SFD = 8'hd5;
+
+    // Link clocks
+    //  100mhz * 10 = 1ghz VCO, / 8 = 125mhz
+    logic mmcm_feedback;
+    logic tx_clk_unbuffered;
+    logic tx_clk_shifted_unbuffered;
+    logic mmcm_locked;
+    MMCME2_BASE #(
+        .CLKIN1_PERIOD(10.0),
+        .DIVCLK_DIVIDE(1),
+        .CLKFBOUT_MULT_F(10.0),
+        .CLKOUT0_DIVIDE_F(8.0),
+        .CLKOUT1_DIVIDE(8),
+        .CLKOUT1_PHASE(TX_CLOCK_SHIFT ? 90.0 : 0.0)
+    ) tx_mmcm(
+        .CLKIN1(clk),
+        .CLKFBIN(mmcm_feedback),
+        .RST(~reset_n),
+        .PWRDWN(1'b0),
+
+        .CLKFBOUT(mmcm_feedback),
+        .CLKFBOUTB(),
+        .CLKOUT0(tx_clk_unbuffered),
+        .CLKOUT0B(),
+        .CLKOUT1(tx_clk_shifted_unbuffered),
+        .CLKOUT1B(),
+        .CLKOUT2(),
+        .CLKOUT2B(),
+        .CLKOUT3(),
+        .CLKOUT3B(),
+        .CLKOUT4(),
+        .CLKOUT5(),
+        .CLKOUT6(),
+        .LOCKED(mmcm_locked));
+
+    logic tx_clk;
+    BUFG tx_clk_bufg(
+        .I(tx_clk_unbuffered),
+        .O(tx_clk));
+
+    logic tx_clk_shifted;
+    BUFG tx_clk_shifted_bufg(
+        .I(tx_clk_shifted_unbuffered),
+        .O(tx_clk_shifted));
+
+    logic tx_reset_n;
+    SyncChain #(.DEFAULT(1'b0)) tx_reset_sync_chain(
+        .reset_n(reset_n & mmcm_locked),
+        .clk(tx_clk),
+
+        .x(1'b1),
+
+        .x_sync(tx_reset_n));
+
+    // RX is captured with a regional I/O clock right at the pins, and the RX clock domain logic runs off of a regional
+    //  clock from the same pin
+    logic rxc_io;
+    BUFIO rxc_bufio(
+        .I(rgmii_rxc),
+        .O(rxc_io));
+
+    logic rxc;
+    BUFR #(.BUFR_DIVIDE("BYPASS")) rxc_bufr(
+        .I(rgmii_rxc),
+        .O(rxc),
+        .CE(1'b1),
+        .CLR(1'b0));
+
+    // The RX clock only runs while the PHY is up, so RX domain reset is released whenever it starts
+    logic rx_reset_n;
+    SyncChain #(.DEFAULT(1'b0)) rx_reset_sync_chain(
+        .reset_n(reset_n),
+        .clk(rxc),
+
+        .x(1'b1),
+
+        .x_sync(rx_reset_n));
+
+    // TX, system clock side
+    //  FIFO entries are {EN, data}, and an entry with EN low marks the end of a frame. Frames are counted as their end
+    //  markers are written, and the count is handed over to the link side in gray code.
+    logic [FIFO_ADDR_BITS:0] tx_fifo_free;
+    logic tx_fifo_write_enable;
+    logic tx_en_prev;
+    logic [7:0] tx_frames_written;
+    logic [7:0] tx_frames_written_gray;
+
+    // The MAC's inter-frame gap bytes aren't written, apart from the end marker, so they only take a cycle each
+    assign tx_strobe = tx_fifo_free >= (FIFO_ADDR_BITS + 1)'(2);
+    assign tx_fifo_write_enable = tx_strobe & (tx_en | tx_en_prev);
+
+    always_ff @(posedge clk, negedge reset_n) begin
+        if (~reset_n) begin
+            tx_en_prev <= 1'b0;
+            tx_frames_written <= 8'h0;
+            tx_frames_written_gray <= 8'h0;
+        end
+        else begin
+            if (tx_strobe) begin
+                tx_en_prev <= tx_en;
+            end
+
+            if (tx_strobe & ~tx_en & tx_en_prev) begin
+                tx_frames_written <= tx_frames_written + 8'h1;
+                tx_frames_written_gray <= (tx_frames_written + 8'h1) ^ ((tx_frames_written + 8'h1) >> 1);
+            end
+        end
+    end
+
+    logic tx_fifo_read_enable;
+    logic [8:0] tx_fifo_read_data;
+    AsyncFifo #(.DATA_BITS(9), .ADDR_BITS(FIFO_ADDR_BITS)) tx_fifo(
+        .write_reset_n(reset_n),
+        .write_clk(clk),
+
+        .write_enable(tx_fifo_write_enable),
+        .write_data({tx_en, tx_data}),
+        .write_free(tx_fifo_free),
+
+        .read_reset_n(tx_reset_n),
+        .read_clk(tx_clk),
+
+        .read_enable(tx_fifo_read_enable),
+        .read_data(tx_fifo_read_data),
+        .read_empty());
+
+    // TX, link side
+    //  A whole frame is in the FIFO (and has been for a while) by the time its count makes it through the sync chain,
+    //  so once a frame starts, its entries are read back to back without checking for empty
+    (* ASYNC_REG = "TRUE" *) logic [7:0] tx_frames_written_gray_sync[2];
+    logic [7:0] tx_frames_read;
+    logic tx_sending;
+    logic [3:0] tx_ifg_counter;
+    logic tx_link_en;
+    logic [7:0] tx_link_data;
+
+    assign tx_fifo_read_enable = tx_sending;
+
+    always_ff @(posedge tx_clk, negedge tx_reset_n) begin
+        if (~tx_reset_n) begin
+            tx_frames_written_gray_sync[0] <= 8'h0;
+            tx_frames_written_gray_sync[1] <= 8'h0;
+            tx_frames_read <= 8'h0;
+            tx_sending <= 1'b0;
+            tx_ifg_counter <= 4'h0;
+            tx_link_en <= 1'b0;
+            tx_link_data <= 8'h0;
+        end
+        else begin
+            tx_frames_written_gray_sync[0] <= tx_frames_written_gray;
+            tx_frames_written_gray_sync[1] <= tx_frames_written_gray_sync[0];
+
+            tx_link_en <= 1'b0;
+            tx_link_data <= 8'h0;
+
+            if (tx_sending) begin
+                tx_link_en <= tx_fifo_read_data[8];
+                tx_link_data <= tx_fifo_read_data[7:0];
+
+                if (~tx_fifo_read_data[8]) begin
+                    tx_frames_read <= tx_frames_read + 8'h1;
+                    tx_sending <= 1'b0;
+                    tx_ifg_counter <= 4'(IFG_SIZE - 1);
+                end
+            end
+            else if (tx_ifg_counter != 4'h0) begin
+                tx_ifg_counter <= tx_ifg_counter - 4'h1;
+            end
+            else if (tx_frames_written_gray_sync[1] != (tx_frames_read ^ (tx_frames_read >> 1))) begin
+                tx_sending <= 1'b1;
+            end
+        end
+    end
+
+    ODDR #(
+        .DDR_CLK_EDGE("SAME_EDGE"),
+        .INIT(1'b0),
+        .SRTYPE("ASYNC")
+    ) tx_ctl_oddr(
+        .Q(rgmii_tx_ctl),
+        .C(tx_clk),
+        .CE(1'b1),
+        .D1(tx_link_en),
+        // EN ^ ER, and errors are never sent
+        .D2(tx_link_en),
+        .R(1'b0),
+        .S(1'b0));
+
+    for (genvar i = 0; i < 4; i++) begin: txd_oddrs
+        ODDR #(
+            .DDR_CLK_EDGE("SAME_EDGE"),
+            .INIT(1'b0),
+            .SRTYPE("ASYNC")
+        ) txd_oddr(
+            .Q(rgmii_txd[i]),
+            .C(tx_clk),
+            .CE(1'b1),
+            .D1(tx_link_data[i]),
+            .D2(tx_link_data[i + 4]),
+            .R(1'b0),
+            .S(1'b0));
+    end
+
+    // The clock is forwarded through an ODDR as well, so it sees the same output delay as the data
+    ODDR #(
+        .DDR_CLK_EDGE("SAME_EDGE"),
+        .INIT(1'b0),
+        .SRTYPE("ASYNC")
+    ) txc_oddr(
+        .Q(rgmii_txc),
+        .C(tx_clk_shifted),
+        .CE(1'b1),
+        .D1(1'b1),
+        .D2(1'b0),
+        .R(1'b0),
+        .S(1'b0));
+
+    // RX, link side
+    //  SAME_EDGE_PIPELINED presents both halves of each byte together on the rising edge
+    logic rx_ctl_rise;
+    logic rx_ctl_fall;
+    IDDR #(
+        .DDR_CLK_EDGE("SAME_EDGE_PIPELINED"),
+        .INIT_Q1(1'b0),
+        .INIT_Q2(1'b0),
+        .SRTYPE("SYNC")
+    ) rx_ctl_iddr(
+        .Q1(rx_ctl_rise),
+        .Q2(rx_ctl_fall),
+        .C(rxc_io),
+        .CE(1'b1),
+        .D(rgmii_rx_ctl),
+        .R(1'b0),
+        .S(1'b0));
+
+    logic [7:0] rx_byte;
+    for (genvar i = 0; i < 4; i++) begin: rxd_iddrs
+        IDDR #(
+            .DDR_CLK_EDGE("SAME_EDGE_PIPELINED"),
+            .INIT_Q1(1'b0),
+            .INIT_Q2(1'b0),
+            .SRTYPE("SYNC")
+        ) rxd_iddr(
+            .Q1(rx_byte[i]),
+            .Q2(rx_byte[i + 4]),
+            .C(rxc_io),
+            .CE(1'b1),
+            .D(rgmii_rxd[i]),
+            .R(1'b0),
+            .S(1'b0));
+    end
+
+    logic rx_byte_dv;
+    logic rx_byte_er;
+    assign rx_byte_dv = rx_ctl_rise;
+    assign rx_byte_er = rx_ctl_rise ^ rx_ctl_fall;
+
+    //  FIFO entries are {DV, ER, data}, and an entry with DV low marks the end of a frame. Data is only written while
+    //   there's room for it plus an error entry and an end marker after it, so a frame that's already in the FIFO can
+    //   always be finished off. Writes are registered, so the free count doesn't include the one that's in flight.
+    localparam RX_STATE_IDLE = 2'd0;
+    localparam RX_STATE_FRAME = 2'd1;
+    localparam RX_STATE_TRUNCATED = 2'd2;
+    localparam RX_STATE_DROP = 2'd3;
+    logic [1:0] rx_state;
+    logic [FIFO_ADDR_BITS:0] rx_fifo_free;
+    logic rx_fifo_write_enable;
+    logic [9:0] rx_fifo_write_data;
+    logic rx_fifo_has_room;
+    assign rx_fifo_has_room = rx_fifo_free >= (FIFO_ADDR_BITS + 1)'(3) + rx_fifo_write_enable;
+
+    always_ff @(posedge rxc, negedge rx_reset_n) begin
+        if (~rx_reset_n) begin
+            rx_state <= RX_STATE_IDLE;
+            rx_fifo_write_enable <= 1'b0;
+            rx_fifo_write_data <= 10'h0;
+        end
+        else begin
+            rx_fifo_write_enable <= 1'b0;
+            rx_fifo_write_data <= {rx_byte_dv, rx_byte_er, rx_byte};
+
+            if (rx_byte_dv) begin
+                case (rx_state)
+                    // The preamble isn't forwarded; the MAC only looks for the SFD
+                    RX_STATE_IDLE: begin
+                        if (rx_byte == SFD) begin
+                            if (rx_fifo_has_room) begin
+                                rx_state <= RX_STATE_FRAME;
+                                rx_fifo_write_enable <= 1'b1;
+                            end
+                            else begin
+                                rx_state <= RX_STATE_DROP;
+                            end
+                        end
+                    end
+
+                    RX_STATE_FRAME: begin
+                        rx_fifo_write_enable <= 1'b1;
+                        if (~rx_fifo_has_room) begin
+                            rx_state <= RX_STATE_TRUNCATED;
+                            rx_fifo_write_data <= {1'b1, 1'b1, 8'h0};
+                        end
+                    end
+
+                    default: begin
+                    end
+                endcase
+            end
+            else begin
+                if (rx_state == RX_STATE_FRAME || rx_state == RX_STATE_TRUNCATED) begin
+                    rx_fifo_write_enable <= 1'b1;
+                end
+                rx_state <= RX_STATE_IDLE;
+            end
+        end
+    end
+
+    logic rx_fifo_read_enable;
+    logic [9:0] rx_fifo_read_data;
+    logic rx_fifo_read_empty;
+    AsyncFifo #(.DATA_BITS(10), .ADDR_BITS(FIFO_ADDR_BITS)) rx_fifo(
+        .write_reset_n(rx_reset_n),
+        .write_clk(rxc),
+
+        .write_enable(rx_fifo_write_enable),
+        .write_data(rx_fifo_write_data),
+        .write_free(rx_fifo_free),
+
+        .read_reset_n(reset_n),
+        .read_clk(clk),
+
+        .read_enable(rx_fifo_read_enable),
+        .read_data(rx_fifo_read_data),
+        .read_empty(rx_fifo_read_empty));
+
+    // RX, system clock side
+    logic rx_strobe_reg;
+    logic rx_dv_reg;
+    logic rx_er_reg;
+    logic [7:0] rx_data_reg;
+
+    assign rx_fifo_read_enable = ~rx_fifo_read_empty;
+
+    always_ff @(posedge clk, negedge reset_n) begin
+        if (~reset_n) begin
+            rx_strobe_reg <= 1'b0;
+            rx_dv_reg <= 1'b0;
+            rx_er_reg <= 1'b0;
+            rx_data_reg <= 8'h0;
+        end
+        else begin
+            rx_strobe_reg <= rx_fifo_read_enable;
+            {rx_dv_reg, rx_er_reg, rx_data_reg} <= rx_fifo_read_data;
+        end
+    end
+
+    assign rx_strobe = rx_strobe_reg;
+    assign rx_dv = rx_dv_reg;
+    assign rx_er = rx_er_reg;
+    assign rx_data = rx_data_reg;
+
+endmodule
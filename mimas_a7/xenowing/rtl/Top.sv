@@ -7,7 +7,11 @@ module Top(
     output wire logic tx,
     input wire logic rx,
 
-    output wire logic [7:0] leds);
+    output wire logic [7:0] leds,
+
+    output wire logic spi_flash_cs_n,
+    output wire logic spi_flash_mosi,
+    input wire logic spi_flash_miso);
 
     logic reset_n;
     SyncChain #(.DEFAULT(1'b0)) reset_sync_chain(
@@ -27,6 +31,26 @@ module Top(
 
         .x_sync(rx_sync));
 
+    // The config flash clock is shared with CCLK, which is only reachable through STARTUPE2 after configuration
+    logic spi_flash_sck;
+    STARTUPE2 #(
+        .PROG_USR("FALSE"),
+        .SIM_CCLK_FREQ(0.0)
+    ) startup(
+        .CFGCLK(),
+        .CFGMCLK(),
+        .EOS(),
+        .PREQ(),
+        .CLK(1'b0),
+        .GSR(1'b0),
+        .GTS(1'b0),
+        .KEYCLEARB(1'b1),
+        .PACK(1'b0),
+        .USRCCLKO(spi_flash_sck),
+        .USRCCLKTS(1'b0),
+        .USRDONEO(1'b1),
+        .USRDONETS(1'b1));
+
     Xenowing xenowing(
         .reset_n(reset_n),
         .clk(clk),
@@ -34,6 +58,45 @@ module Top(
         .tx(tx),
         .rx(rx_sync),
 
-        .leds(leds));
+        .leds(leds),
+
+        // TODO: Route the seven-segment display once its pins have been looked up in the board schematic and assigned
+        //  in the xdc. The display is common-anode, so both digit enables and segments should be inverted on the way
+        //  out.
+        .display_digits(),
+        .display_segments(),
+
+        // TODO: Route buttons/switches (through sync chains) and a pad connector once their pins have been looked up in
+        //  the board schematic and assigned in the xdc; until then they read as nothing pressed. Note that the pad's
+        //  data line is active-low, so it idles high.
+        .buttons(4'h0),
+        .switches(8'h0),
+        .pad_data(1'b1),
+        .pad_latch(),
+        .pad_clock(),
+
+        .spi_flash_cs_n(spi_flash_cs_n),
+        .spi_flash_sck(spi_flash_sck),
+        .spi_flash_mosi(spi_flash_mosi),
+        .spi_flash_miso(spi_flash_miso),
+
+        // TODO: Route the microSD slot once its pins have been looked up in the board schematic and assigned in the xdc
+        //  (with a pullup on MISO, since cards only drive it while selected); until then MISO idles high, so no card
+        //  ever responds
+        .sd_cs_n(),
+        .sd_sck(),
+        .sd_mosi(),
+        .sd_miso(1'b1),
+
+        // TODO: Instantiate RgmiiAdapter and PhyConfig here (and add constraints/ethernet.xdc to the project) once the
+        //  PHY's pins have been looked up in the board schematic and filled in there; until then the MAC never gets to
+        //  send and never receives anything
+        .eth_tx_strobe(1'b0),
+        .eth_tx_en(),
+        .eth_tx_data(),
+        .eth_rx_strobe(1'b0),
+        .eth_rx_dv(1'b0),
+        .eth_rx_er(1'b0),
+        .eth_rx_data(8'h0));
 
 endmodule
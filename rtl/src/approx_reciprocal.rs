@@ -1,6 +1,7 @@
 use kaze::*;
 
 // 32 bit internal resolution, 32 - `fract_bits` integral bits, `fract_bits` fractional bits, 1 + 3 * `refinement_stages` cycles latency
+// `x` is unsigned, so inputs with the top bit set are valid (they're normalized with a shift of 0)
 pub fn generate<'a, S: Into<String>>(c: &'a Context<'a>, mod_name: S, fract_bits: u32, refinement_stages: u32) -> &'a Module<'a> {
     let m = c.module(mod_name);
 
@@ -53,10 +54,10 @@ pub fn generate<'a, S: Into<String>>(c: &'a Context<'a>, mod_name: S, fract_bits
     m
 }
 
-fn leading_zeros<'a>(x: &'a Signal<'a>, m: &'a Module<'a>) -> &'a Signal<'a> {
+pub fn leading_zeros<'a>(x: &'a Signal<'a>, m: &'a Module<'a>) -> &'a Signal<'a> {
     let mut ret = m.lit(0u32, 5);
 
-    for i in 0..31 {
+    for i in 0..32 {
         ret = if_(x.bit(i), {
             m.lit(31 - i, 5)
         }).else_({
@@ -14,7 +14,7 @@ pub fn generate<'a, S: Into<String>>(c: &'a Context<'a>, mod_name: S, num_primar
     let mod_name = mod_name.into();
 
     // TODO: num_primaries, num_replicas, replica_select_bit_width bounds checks
-    let primary_select_bit_width = 31 - num_primaries.leading_zeros();
+    let primary_select_bit_width = 32 - (num_primaries - 1).leading_zeros(); // ceil(log2(num_primaries))
     let replica_addr_bit_width = addr_bit_width - replica_select_bit_width; // TODO: Bounds checks
 
     let data_byte_width = data_bit_width / 8;
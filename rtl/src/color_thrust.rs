@@ -1,6 +1,12 @@
+mod command_processor;
 mod tex_cache;
+mod tile_transfer;
+pub mod binner;
+pub mod triangle_setup;
+pub mod vertex_transform;
 
 use crate::approx_reciprocal;
+use crate::buster;
 use crate::flow_controlled_pipe;
 use crate::word_mem::*;
 
@@ -11,6 +17,12 @@ pub const TILE_DIM: u32 = 1 << TILE_DIM_BITS;
 pub const TILE_PIXELS_BITS: u32 = TILE_DIM_BITS * 2;
 pub const TILE_PIXELS: u32 = 1 << TILE_PIXELS_BITS;
 pub const TILE_PIXELS_WORDS_BITS: u32 = TILE_PIXELS_BITS - 2;
+pub const TILE_SPAN_BITS: u32 = 2; // Number of pixels in a row that traversal can skip at once
+pub const TILE_SPAN: u32 = 1 << TILE_SPAN_BITS;
+
+//  Number of pixels that can be in flight in the pixel pipe at once, which covers its latency when it isn't stalled
+pub const PIXEL_PIPE_IN_FLIGHT_SLOTS_BITS: u32 = 5;
+pub const PIXEL_PIPE_IN_FLIGHT_SLOTS: u32 = 1 << PIXEL_PIPE_IN_FLIGHT_SLOTS_BITS;
 
 pub const TEX_PIXEL_ADDR_BITS: u32 = 17 - 2;
 pub const TEX_WORD_ADDR_BITS: u32 = TEX_PIXEL_ADDR_BITS - 2;
@@ -23,48 +35,147 @@ pub const Z_FRACT_BITS: u32 = 30; // Must be greater than 16
 pub const ST_FRACT_BITS: u32 = 24;
 pub const ST_FILTER_FRACT_BITS: u32 = 4; // Must be less than ST_FRACT_BITS
 pub const RESTORED_W_FRACT_BITS: u32 = 8; // Must be less than W_INVERSE_FRACT_BITS and ST_FRACT_BITS
-
-pub const REG_BUS_ADDR_BIT_WIDTH: u32 = 6;
-
+pub const TEX_LOD_FRACT_BITS: u32 = ST_FILTER_FRACT_BITS; // Must match ST_FILTER_FRACT_BITS so level weights can share the texel blend logic
+pub const TEX_TEXEL_SELECT_BITS: u32 = 3; // Enough to select any of the texels packed into a 32-bit pixel, down to 4-bit texels
+
+pub const REG_BUS_ADDR_BIT_WIDTH: u32 = 7;
+
+//  Status reads as busy while anything is in progress, and each tile buffer bank has its own busy bit as well, which is
+//   set while it's being rendered to (if it's the render bank) or used by a tile transfer (if it's the transfer bank).
+//   The start pending bit is set while a start is queued.
+//  Writing the start reg starts rasterizing a primitive with the current edge/interpolant regs and scissor rect, which
+//   are copied to a separate active set, so the next primitive's regs can be written while it's rasterized. If a
+//   primitive is still being traversed (or triangle setup is still writing regs), the start is queued until it's done;
+//   only one start can be queued, and the regs mustn't be written again until it's been taken. Only those regs are
+//   copied; the rest of the state (depth, alpha test, stencil, fog, texture, blend, and palette settings) is read live
+//   by pixels in flight, so it mustn't be written until everything in flight has finished (see wait idle).
 pub const REG_STATUS_ADDR: u32 = 0;
+pub const REG_STATUS_BUSY_BIT: u32 = 0;
+pub const REG_STATUS_BANK_BUSY_BIT_OFFSET: u32 = 1;
+pub const REG_STATUS_START_PENDING_BIT: u32 = 3;
 pub const REG_START_ADDR: u32 = 0;
 
 pub const REG_TEX_CACHE_INVALIDATE_ADDR: u32 = 1;
 
 pub const REG_DEPTH_SETTINGS_ADDR: u32 = 2;
-pub const REG_DEPTH_SETTINGS_BITS: u32 = 2;
+pub const REG_DEPTH_SETTINGS_BITS: u32 = 5;
 pub const REG_DEPTH_TEST_ENABLE_BIT: u32 = 0;
 pub const REG_DEPTH_WRITE_MASK_ENABLE_BIT: u32 = 1;
+//  Compare functions pass when the incoming z is (less, equal, greater) than the stored z for bits (0, 1, 2) respectively
+pub const REG_DEPTH_SETTINGS_FUNC_BIT_OFFSET: u32 = 2;
+pub const REG_DEPTH_SETTINGS_FUNC_BITS: u32 = 3;
+pub const REG_DEPTH_SETTINGS_FUNC_NEVER: u32 = 0;
+pub const REG_DEPTH_SETTINGS_FUNC_LESS: u32 = 1;
+pub const REG_DEPTH_SETTINGS_FUNC_EQUAL: u32 = 2;
+pub const REG_DEPTH_SETTINGS_FUNC_LEQUAL: u32 = 3;
+pub const REG_DEPTH_SETTINGS_FUNC_GREATER: u32 = 4;
+pub const REG_DEPTH_SETTINGS_FUNC_NOTEQUAL: u32 = 5;
+pub const REG_DEPTH_SETTINGS_FUNC_GEQUAL: u32 = 6;
+pub const REG_DEPTH_SETTINGS_FUNC_ALWAYS: u32 = 7;
 
 pub const REG_TEXTURE_SETTINGS_ADDR: u32 = 3;
-pub const REG_TEXTURE_SETTINGS_BITS: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_BITS: u32 = 19;
 pub const REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET: u32 = 0;
 pub const REG_TEXTURE_SETTINGS_FILTER_SELECT_BITS: u32 = 1;
 pub const REG_TEXTURE_SETTINGS_FILTER_SELECT_NEAREST: u32 = 0;
 pub const REG_TEXTURE_SETTINGS_FILTER_SELECT_BILINEAR: u32 = 1;
-pub const REG_TEXTURE_SETTINGS_DIM_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET + REG_TEXTURE_SETTINGS_FILTER_SELECT_BITS;
+pub const REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET + REG_TEXTURE_SETTINGS_FILTER_SELECT_BITS;
+pub const REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET + REG_TEXTURE_SETTINGS_DIM_BITS;
+//  Width and height use the same encoding
 pub const REG_TEXTURE_SETTINGS_DIM_BITS: u32 = 2;
 pub const REG_TEXTURE_SETTINGS_DIM_16: u32 = 0;
 pub const REG_TEXTURE_SETTINGS_DIM_32: u32 = 1;
 pub const REG_TEXTURE_SETTINGS_DIM_64: u32 = 2;
 pub const REG_TEXTURE_SETTINGS_DIM_128: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET + REG_TEXTURE_SETTINGS_DIM_BITS;
+pub const REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET + REG_TEXTURE_SETTINGS_WRAP_BITS;
+pub const REG_TEXTURE_SETTINGS_WRAP_BITS: u32 = 2;
+pub const REG_TEXTURE_SETTINGS_WRAP_REPEAT: u32 = 0;
+pub const REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT: u32 = 1;
+pub const REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE: u32 = 2;
+//  Texels outside of the texture are replaced with the border color
+pub const REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET + REG_TEXTURE_SETTINGS_WRAP_BITS;
+pub const REG_TEXTURE_SETTINGS_MIP_MODE_BITS: u32 = 2;
+pub const REG_TEXTURE_SETTINGS_MIP_MODE_NONE: u32 = 0;
+pub const REG_TEXTURE_SETTINGS_MIP_MODE_NEAREST: u32 = 1;
+pub const REG_TEXTURE_SETTINGS_MIP_MODE_LINEAR: u32 = 2;
+//  Levels are packed consecutively after level 0, each half the width and height of the last, so the max level must
+//   not shrink either dim below 16
+pub const REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET + REG_TEXTURE_SETTINGS_MIP_MODE_BITS;
+pub const REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS: u32 = 2;
+//  Texels narrower than 32 bits are packed into 32-bit pixels starting from the least significant bits, so texture
+//   footprints shrink accordingly. Indexed formats look up their texels in the palette.
+pub const REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET + REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS;
+pub const REG_TEXTURE_SETTINGS_FORMAT_BITS: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_FORMAT_ARGB8888: u32 = 0;
+pub const REG_TEXTURE_SETTINGS_FORMAT_RGB565: u32 = 1;
+pub const REG_TEXTURE_SETTINGS_FORMAT_ARGB4444: u32 = 2;
+pub const REG_TEXTURE_SETTINGS_FORMAT_ARGB1555: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_FORMAT_INDEXED8: u32 = 4;
+pub const REG_TEXTURE_SETTINGS_FORMAT_INDEXED4: u32 = 5;
+//  Vector quantized: each 2x2 texel block is an 8-bit index into a codebook of 2x2 blocks held in the palette. Block
+//   indices aren't interleaved like texels; each level is a single (width / 2) x (height / 2) index map, and block n's
+//   texels are palette entries n * 4 through n * 4 + 3, ordered like the tex buffers by s/t parity.
+pub const REG_TEXTURE_SETTINGS_FORMAT_VQ: u32 = 6;
+//  Combines the interpolated vertex color (Cf, Af) with the filtered texel (Ct, At):
+//   Modulate: Cf * Ct, Af * At
+//   Replace: Ct, At
+//   Decal: Cf * (1 - At) + Ct * At, Af
+//   Add: Cf + Ct (saturated), Af * At
+//   Disabled: Cf, Af
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_BIT_OFFSET: u32 = REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET + REG_TEXTURE_SETTINGS_FORMAT_BITS;
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_BITS: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_MODULATE: u32 = 0;
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_REPLACE: u32 = 1;
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_DECAL: u32 = 2;
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_ADD: u32 = 3;
+pub const REG_TEXTURE_SETTINGS_COMBINE_MODE_DISABLED: u32 = 4;
 
 pub const REG_TEXTURE_BASE_ADDR: u32 = 4;
 
 pub const REG_BLEND_SETTINGS_ADDR: u32 = 5;
-pub const REG_BLEND_SETTINGS_BITS: u32 = 4;
+pub const REG_BLEND_SETTINGS_BITS: u32 = 11;
 pub const REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET: u32 = 0;
-pub const REG_BLEND_SETTINGS_SRC_FACTOR_BITS: u32 = 2;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_BITS: u32 = 4;
 pub const REG_BLEND_SETTINGS_SRC_FACTOR_ZERO: u32 = 0;
 pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE: u32 = 1;
 pub const REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA: u32 = 2;
 pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA: u32 = 3;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_SRC_COLOR: u32 = 4;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_COLOR: u32 = 5;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_DST_COLOR: u32 = 6;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_COLOR: u32 = 7;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_DST_ALPHA: u32 = 8;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_ALPHA: u32 = 9;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_COLOR: u32 = 10;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_COLOR: u32 = 11;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_ALPHA: u32 = 12;
+pub const REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_ALPHA: u32 = 13;
 pub const REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET: u32 = REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET + REG_BLEND_SETTINGS_SRC_FACTOR_BITS;
-pub const REG_BLEND_SETTINGS_DST_FACTOR_BITS: u32 = 2;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_BITS: u32 = 4;
 pub const REG_BLEND_SETTINGS_DST_FACTOR_ZERO: u32 = 0;
 pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE: u32 = 1;
 pub const REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA: u32 = 2;
 pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA: u32 = 3;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_SRC_COLOR: u32 = 4;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_COLOR: u32 = 5;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_DST_COLOR: u32 = 6;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_COLOR: u32 = 7;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_DST_ALPHA: u32 = 8;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_ALPHA: u32 = 9;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_COLOR: u32 = 10;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_COLOR: u32 = 11;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_ALPHA: u32 = 12;
+pub const REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_ALPHA: u32 = 13;
+pub const REG_BLEND_SETTINGS_OP_BIT_OFFSET: u32 = REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET + REG_BLEND_SETTINGS_DST_FACTOR_BITS;
+pub const REG_BLEND_SETTINGS_OP_BITS: u32 = 3;
+pub const REG_BLEND_SETTINGS_OP_ADD: u32 = 0;
+pub const REG_BLEND_SETTINGS_OP_SUBTRACT: u32 = 1;
+pub const REG_BLEND_SETTINGS_OP_REVERSE_SUBTRACT: u32 = 2;
+//  Min and max ignore the blend factors
+pub const REG_BLEND_SETTINGS_OP_MIN: u32 = 3;
+pub const REG_BLEND_SETTINGS_OP_MAX: u32 = 4;
 
 pub const REG_W0_MIN_ADDR: u32 = 6;
 pub const REG_W0_DX_ADDR: u32 = 7;
@@ -100,6 +211,281 @@ pub const REG_T_MIN_ADDR: u32 = 36;
 pub const REG_T_DX_ADDR: u32 = 37;
 pub const REG_T_DY_ADDR: u32 = 38;
 
+pub const REG_BLEND_CONSTANT_ADDR: u32 = 39;
+
+pub const REG_TEXTURE_BORDER_COLOR_ADDR: u32 = 40;
+
+//  Signed, with TEX_LOD_FRACT_BITS fractional bits
+pub const REG_TEXTURE_LOD_BIAS_ADDR: u32 = 41;
+pub const REG_TEXTURE_LOD_BIAS_BITS: u32 = 8;
+
+//  Writes to the palette data reg store an ARGB8888 entry at the current palette index and then increment it. Indexed
+//   formats only use the first 256 (or 16) entries, while VQ uses all of them as a codebook.
+pub const REG_TEXTURE_PALETTE_INDEX_ADDR: u32 = 42;
+pub const REG_TEXTURE_PALETTE_DATA_ADDR: u32 = 43;
+pub const TEX_PALETTE_INDEX_BITS: u32 = 10;
+
+//  Tests the final (clamped) fragment alpha against the reference after texturing; failing pixels don't write color or depth
+pub const REG_ALPHA_TEST_SETTINGS_ADDR: u32 = 44;
+pub const REG_ALPHA_TEST_SETTINGS_BITS: u32 = 12;
+pub const REG_ALPHA_TEST_ENABLE_BIT: u32 = 0;
+//  Compare functions pass when the alpha is (less, equal, greater) than the reference for bits (0, 1, 2) respectively
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET: u32 = 1;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_BITS: u32 = 3;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_NEVER: u32 = 0;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_LESS: u32 = 1;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_EQUAL: u32 = 2;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_LEQUAL: u32 = 3;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_GREATER: u32 = 4;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_NOTEQUAL: u32 = 5;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_GEQUAL: u32 = 6;
+pub const REG_ALPHA_TEST_SETTINGS_FUNC_ALWAYS: u32 = 7;
+pub const REG_ALPHA_TEST_SETTINGS_REF_BIT_OFFSET: u32 = REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET + REG_ALPHA_TEST_SETTINGS_FUNC_BITS;
+pub const REG_ALPHA_TEST_SETTINGS_REF_BITS: u32 = 8;
+
+pub const REG_STENCIL_SETTINGS_ADDR: u32 = 45;
+pub const REG_STENCIL_SETTINGS_BITS: u32 = 29;
+pub const REG_STENCIL_TEST_ENABLE_BIT: u32 = 0;
+// Same encoding as REG_DEPTH_SETTINGS_FUNC_*; the test is (ref & mask) func (stencil & mask)
+pub const REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET: u32 = 1;
+pub const REG_STENCIL_SETTINGS_FUNC_BITS: u32 = 3;
+pub const REG_STENCIL_SETTINGS_FUNC_NEVER: u32 = 0;
+pub const REG_STENCIL_SETTINGS_FUNC_LESS: u32 = 1;
+pub const REG_STENCIL_SETTINGS_FUNC_EQUAL: u32 = 2;
+pub const REG_STENCIL_SETTINGS_FUNC_LEQUAL: u32 = 3;
+pub const REG_STENCIL_SETTINGS_FUNC_GREATER: u32 = 4;
+pub const REG_STENCIL_SETTINGS_FUNC_NOTEQUAL: u32 = 5;
+pub const REG_STENCIL_SETTINGS_FUNC_GEQUAL: u32 = 6;
+pub const REG_STENCIL_SETTINGS_FUNC_ALWAYS: u32 = 7;
+pub const REG_STENCIL_SETTINGS_REF_BIT_OFFSET: u32 = REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET + REG_STENCIL_SETTINGS_FUNC_BITS;
+pub const REG_STENCIL_SETTINGS_REF_BITS: u32 = 8;
+pub const REG_STENCIL_SETTINGS_MASK_BIT_OFFSET: u32 = REG_STENCIL_SETTINGS_REF_BIT_OFFSET + REG_STENCIL_SETTINGS_REF_BITS;
+pub const REG_STENCIL_SETTINGS_MASK_BITS: u32 = 8;
+pub const REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET: u32 = REG_STENCIL_SETTINGS_MASK_BIT_OFFSET + REG_STENCIL_SETTINGS_MASK_BITS;
+pub const REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET: u32 = REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET + REG_STENCIL_SETTINGS_OP_BITS;
+pub const REG_STENCIL_SETTINGS_DPPASS_OP_BIT_OFFSET: u32 = REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET + REG_STENCIL_SETTINGS_OP_BITS;
+pub const REG_STENCIL_SETTINGS_OP_BITS: u32 = 3;
+pub const REG_STENCIL_SETTINGS_OP_KEEP: u32 = 0;
+pub const REG_STENCIL_SETTINGS_OP_ZERO: u32 = 1;
+pub const REG_STENCIL_SETTINGS_OP_REPLACE: u32 = 2;
+pub const REG_STENCIL_SETTINGS_OP_INCR: u32 = 3;
+pub const REG_STENCIL_SETTINGS_OP_DECR: u32 = 4;
+pub const REG_STENCIL_SETTINGS_OP_INVERT: u32 = 5;
+pub const REG_STENCIL_SETTINGS_OP_INCR_WRAP: u32 = 6;
+pub const REG_STENCIL_SETTINGS_OP_DECR_WRAP: u32 = 7;
+
+//  Fog is applied to the combined color before blending: C' = C * (1 - f) + Cfog * f, A' = A. The fog coord is either z
+//   (16 bits) or the restored w (clamped to 16 bits, so 8.8 fixed point), and is mapped to x = (coord - start) * scale >> 16,
+//   clamped to [0, 1] in 1.8 fixed point. Linear mode uses x as the fog amount f directly, while exp and exp2 look up
+//   f in the fog table at x or x^2 respectively (clamped to the last entry).
+pub const REG_FOG_SETTINGS_ADDR: u32 = 46;
+pub const REG_FOG_SETTINGS_BITS: u32 = 4;
+pub const REG_FOG_ENABLE_BIT: u32 = 0;
+pub const REG_FOG_SETTINGS_MODE_BIT_OFFSET: u32 = 1;
+pub const REG_FOG_SETTINGS_MODE_BITS: u32 = 2;
+pub const REG_FOG_SETTINGS_MODE_LINEAR: u32 = 0;
+pub const REG_FOG_SETTINGS_MODE_EXP: u32 = 1;
+pub const REG_FOG_SETTINGS_MODE_EXP2: u32 = 2;
+pub const REG_FOG_SETTINGS_COORD_SELECT_BIT_OFFSET: u32 = REG_FOG_SETTINGS_MODE_BIT_OFFSET + REG_FOG_SETTINGS_MODE_BITS;
+pub const REG_FOG_SETTINGS_COORD_SELECT_Z: u32 = 0;
+pub const REG_FOG_SETTINGS_COORD_SELECT_W: u32 = 1;
+
+pub const REG_FOG_COLOR_ADDR: u32 = 47;
+
+pub const REG_FOG_START_ADDR: u32 = 48;
+pub const REG_FOG_SCALE_ADDR: u32 = 49;
+pub const FOG_COORD_BITS: u32 = 16;
+
+//  Writes to the fog table data reg store an entry at the current fog table index and then increment it. Entries are
+//   fog amounts in 1.8 fixed point.
+pub const REG_FOG_TABLE_INDEX_ADDR: u32 = 50;
+pub const REG_FOG_TABLE_DATA_ADDR: u32 = 51;
+pub const FOG_TABLE_INDEX_BITS: u32 = 8;
+pub const FOG_AMOUNT_BITS: u32 = 9;
+
+//  Writing a word address (relative to the start of RAM) to the command list base reg starts the command processor,
+//   which then fetches and executes commands from that address until it reaches an end command. Status reads as busy
+//   until then. Other reg and tile buffer accesses while a command list is being processed have undefined behavior.
+pub const REG_COMMAND_LIST_BASE_ADDR: u32 = 52;
+
+//  Commands are 64 bits, packed two per word starting with the least significant half. The opcode is in the low bits,
+//   followed by an arg (a reg addr or tile buffer select), and the upper 32 bits hold data (reg data or a word address).
+//   Wait idle waits for any in-flight primitive to complete, and has to come before any write to state that isn't copied
+//   on start (anything but the edge/interpolant regs and scissor rect). Wait start waits for any queued start to be
+//   taken, so the next primitive's regs can be written. Tile load/store copy a whole tile buffer from/to RAM starting
+//   at the data word address, with each row of the tile starting pitch words after the last; pitch is signed and held in
+//   the upper arg bits, so tiles can be stored bottom-up. Note that stores aren't coherent with the tex cache. Tile clear
+//   clears a tile buffer to the value of its tile clear reg.
+//  Tile buffers are double-buffered: primitives are rendered to the render bank, while tile load, store, and clear use
+//   the other, transfer bank. Transfers run in the background, so the command processor only waits for the last one to
+//   finish before starting another. Tile swap waits for any in-flight primitive and transfer to complete, then swaps the
+//   banks, so a tile can be rendered while the last one is stored and the next one is loaded. The render bank is bank 0
+//   on reset. Tile buffer bus accesses always use the render bank, so hosts that don't use tile swap (and write and
+//   read back tiles over the bus instead) see the tile that's rendered.
+pub const COMMAND_BITS: u32 = 64;
+pub const COMMAND_OPCODE_BITS: u32 = 4;
+pub const COMMAND_OPCODE_END: u32 = 0;
+pub const COMMAND_OPCODE_WRITE_REG: u32 = 1;
+pub const COMMAND_OPCODE_START: u32 = 2;
+pub const COMMAND_OPCODE_WAIT_IDLE: u32 = 3;
+pub const COMMAND_OPCODE_TILE_LOAD: u32 = 4;
+pub const COMMAND_OPCODE_TILE_STORE: u32 = 5;
+pub const COMMAND_OPCODE_JUMP: u32 = 6;
+pub const COMMAND_OPCODE_TILE_CLEAR: u32 = 7;
+pub const COMMAND_OPCODE_TILE_SWAP: u32 = 8;
+pub const COMMAND_OPCODE_WAIT_START: u32 = 9;
+pub const COMMAND_ARG_BIT_OFFSET: u32 = 8;
+pub const COMMAND_DATA_BIT_OFFSET: u32 = 32;
+pub const COMMAND_TILE_BUFFER_BITS: u32 = 2;
+pub const COMMAND_TILE_BUFFER_COLOR: u32 = 0;
+pub const COMMAND_TILE_BUFFER_DEPTH: u32 = 1;
+pub const COMMAND_TILE_BUFFER_STENCIL: u32 = 2;
+pub const COMMAND_TILE_PITCH_BIT_OFFSET: u32 = 16;
+pub const COMMAND_TILE_PITCH_BITS: u32 = 16;
+
+//  The triangle setup unit computes the edge and interpolant regs for a triangle from its vertices, so the host doesn't
+//   have to. Writing the setup vertex index reg selects a vertex (0-2) and resets to its first word, and each write to the
+//   data reg stores a word and then advances, moving on to the next vertex after the last word. Writing a tile origin in
+//   pixels (x in the low 16 bits, y in the high 16 bits) to the setup start reg starts setup, which then writes the edge
+//   and interpolant regs for that tile itself. Status reads as busy until it's done, and other reg writes in the meantime
+//   have undefined behavior.
+pub const REG_SETUP_VERTEX_INDEX_ADDR: u32 = 53;
+pub const REG_SETUP_VERTEX_DATA_ADDR: u32 = 54;
+pub const REG_SETUP_START_ADDR: u32 = 55;
+
+//  A vertex is its window position (signed, with SETUP_XY_FRACT_BITS fractional bits; x in the low 16 bits and y in the
+//   high 16 bits) followed by its attributes in interpolant reg order (r, g, b, a, w inverse, z, s, t), each in the same
+//   format as the corresponding *_MIN reg. Triangles must have positive area (counter-clockwise in window coordinates),
+//   so zero-area and back-facing triangles need to be culled beforehand.
+//  Edge regs are exact (except for saturation). Interpolant regs are within 2 LSBs of the exact values for the given
+//   vertices when those are in the i32 range; 1 / area is truncated to 33 significant bits and results are rounded down.
+//   Values out of that range are truncated to 32 bits rather than saturated, since pixel values are stepped from them
+//   with wrapping adds (*_MIN regs in particular are extrapolated to the tile origin, so they often are).
+pub const SETUP_VERTEX_WORDS: u32 = 9;
+pub const SETUP_VERTEX_WORD_BITS: u32 = 4;
+pub const SETUP_VERTEX_WORD_XY: u32 = 0;
+pub const SETUP_VERTEX_WORD_ATTRIBUTES: u32 = 1;
+pub const SETUP_VERTEX_ATTRIBUTES: u32 = SETUP_VERTEX_WORDS - SETUP_VERTEX_WORD_ATTRIBUTES;
+pub const SETUP_XY_FRACT_BITS: u32 = EDGE_FRACT_BITS / 2;
+
+//  The vertex transform unit fetches a batch of vertices from RAM, transforms them to window coordinates, optionally
+//   lights them, and writes the results to its output buffer in setup vertex format. Writes to the transform param data
+//   reg store a param at the current param index and then increment it. Writing a vertex count (at most
+//   TRANSFORM_OUTPUT_VERTICES) to the transform start reg starts transforming that many consecutive vertices from the
+//   source addr (a word address relative to the start of RAM) into output buffer entries 0 and up. Status reads as
+//   busy until it's done.
+pub const REG_TRANSFORM_PARAM_INDEX_ADDR: u32 = 56;
+pub const REG_TRANSFORM_PARAM_DATA_ADDR: u32 = 57;
+pub const REG_TRANSFORM_SETTINGS_ADDR: u32 = 58;
+pub const REG_TRANSFORM_SETTINGS_BITS: u32 = 1 + TRANSFORM_LIGHTS;
+pub const REG_TRANSFORM_LIGHTING_ENABLE_BIT: u32 = 0;
+pub const REG_TRANSFORM_SETTINGS_LIGHT_MASK_BIT_OFFSET: u32 = 1;
+pub const REG_TRANSFORM_SOURCE_ADDR: u32 = 59;
+pub const REG_TRANSFORM_START_ADDR: u32 = 60;
+
+//  Writing three output buffer entries (vertex 0 in the low 8 bits, then vertex 1 and vertex 2) to the setup vertex
+//   source reg copies them into the setup vertices, as if they'd been written through the setup vertex data reg. Status
+//   reads as busy until the copy is done.
+pub const REG_SETUP_VERTEX_SOURCE_ADDR: u32 = 61;
+
+//  Params are signed with TRANSFORM_PARAM_FRACT_BITS fractional bits, except colors, which are ARGB8888. Matrices are
+//   row-major, and the vertex matrix maps object coordinates to clip coordinates (so it's the product of the projection
+//   and model-view matrices). The normal matrix maps object normals to the space the light directions are in, and
+//   should preserve length, as transformed normals aren't renormalized. Light directions point towards the light and
+//   should be normalized. The viewport maps normalized device coordinates to window coordinates, and tex coords are
+//   scaled and biased (eg. by the texture dimensions and a half-texel offset) before the perspective divide.
+pub const TRANSFORM_PARAM_INDEX_BITS: u32 = 6;
+pub const TRANSFORM_PARAM_FRACT_BITS: u32 = 16;
+pub const TRANSFORM_PARAM_MATRIX: u32 = 0;
+pub const TRANSFORM_PARAM_NORMAL_MATRIX: u32 = 16;
+pub const TRANSFORM_PARAM_VIEWPORT_SCALE: u32 = 25;
+pub const TRANSFORM_PARAM_VIEWPORT_BIAS: u32 = 28;
+pub const TRANSFORM_PARAM_TEX_COORD_SCALE: u32 = 31;
+pub const TRANSFORM_PARAM_TEX_COORD_BIAS: u32 = 33;
+pub const TRANSFORM_PARAM_AMBIENT_COLOR: u32 = 35;
+//  Each light is a direction (x, y, z) followed by a color
+pub const TRANSFORM_PARAM_LIGHTS: u32 = 36;
+pub const TRANSFORM_PARAM_LIGHT_WORDS: u32 = 4;
+pub const TRANSFORM_LIGHTS: u32 = 4;
+
+//  Lit colors are the vertex color times the ambient color plus the sum of each enabled light's color scaled by the
+//   clamped dot product of the transformed normal and the light's direction, clamped to 1. With lighting disabled,
+//   colors pass through unchanged.
+//  A source vertex is two words. The first holds position x, y, and z (signed, with TRANSFORM_POSITION_FRACT_BITS
+//   fractional bits; w is always 1) followed by its ARGB8888 color, and the second holds tex coords s and t (signed, with
+//   TRANSFORM_TEX_COORD_FRACT_BITS fractional bits) followed by normal x, y, and z (16 bits each, signed, with
+//   TRANSFORM_NORMAL_FRACT_BITS fractional bits).
+pub const TRANSFORM_VERTEX_WORDS: u32 = 2;
+pub const TRANSFORM_POSITION_FRACT_BITS: u32 = 16;
+pub const TRANSFORM_TEX_COORD_FRACT_BITS: u32 = 16;
+pub const TRANSFORM_NORMAL_FRACT_BITS: u32 = 14;
+
+//  Output buffer entries are the setup vertex words followed by a word of clip flags, one for each clip plane the vertex
+//   is outside of. Positions are saturated to setup's 16-bit range, and vertices with w <= 0 have undefined results
+//   (other than their clip flags), so anything with clip flags set needs to be clipped or culled before setup.
+pub const TRANSFORM_OUTPUT_VERTEX_BITS: u32 = 6;
+pub const TRANSFORM_OUTPUT_VERTICES: u32 = 1 << TRANSFORM_OUTPUT_VERTEX_BITS;
+pub const TRANSFORM_OUTPUT_WORD_CLIP_FLAGS: u32 = SETUP_VERTEX_WORDS;
+pub const TRANSFORM_CLIP_FLAG_LEFT_BIT: u32 = 0;
+pub const TRANSFORM_CLIP_FLAG_RIGHT_BIT: u32 = 1;
+pub const TRANSFORM_CLIP_FLAG_BOTTOM_BIT: u32 = 2;
+pub const TRANSFORM_CLIP_FLAG_TOP_BIT: u32 = 3;
+pub const TRANSFORM_CLIP_FLAG_NEAR_BIT: u32 = 4;
+pub const TRANSFORM_CLIP_FLAG_FAR_BIT: u32 = 5;
+
+//  The binning unit sorts triangles from the transform output buffer into per-tile primitive lists in RAM. Writing to the
+//   bin begin reg empties all lists and restarts primitive numbering, writing three output buffer entries (in the same
+//   format as the setup vertex source reg) to the bin triangle reg bins that triangle, and writing to the bin end reg
+//   terminates all lists. Status reads as busy until each of these is done.
+//  Triangles that are entirely outside one of the clip planes, that cross the near or far planes (which would need
+//   clipping), that have zero area, or that are back-facing (negative area) with back-face culling enabled are dropped.
+//   Other triangles are written once to the primitive buffer and then appended to the list of each tile they touch,
+//   which is either each tile within their bounding box (tightened to the pixel centers inside it), or, with the edge
+//   test enabled, only those tiles where some pixel center in the tile might be inside all three edges.
+//  Bin memory layout (word addresses are relative to the start of RAM; 32-bit values are packed 4 to a word, starting
+//   from the least significant bits):
+//   - Primitive n is BIN_PRIMITIVE_WORDS words at primitive base + n * BIN_PRIMITIVE_WORDS, holding the 32-bit setup
+//     vertex words of its vertices in order (ready to be written to the setup vertex data reg), followed by an unused
+//     value. Vertices are reordered if necessary so that the triangle has positive area, as setup expects.
+//   - The list for tile (x, y) is at list base + (y * grid width + x) * list stride, and holds 32-bit primitive numbers
+//     in the order they were binned, terminated by BIN_LIST_END, or by BIN_LIST_OVERFLOW if primitives were dropped
+//     because the list was full (a list can hold list stride * 4 - 1 primitives before its terminator).
+pub const REG_BIN_SETTINGS_ADDR: u32 = 62;
+pub const REG_BIN_SETTINGS_BITS: u32 = 2 + 2 * (BIN_GRID_DIM_BITS + 1);
+pub const REG_BIN_EDGE_TEST_ENABLE_BIT: u32 = 0;
+pub const REG_BIN_BACK_FACE_CULL_ENABLE_BIT: u32 = 1;
+//  Grid dims are in tiles, from 1 up to 1 << BIN_GRID_DIM_BITS
+pub const REG_BIN_SETTINGS_GRID_WIDTH_BIT_OFFSET: u32 = 2;
+pub const REG_BIN_SETTINGS_GRID_HEIGHT_BIT_OFFSET: u32 = REG_BIN_SETTINGS_GRID_WIDTH_BIT_OFFSET + BIN_GRID_DIM_BITS + 1;
+pub const REG_BIN_PRIMITIVE_BASE_ADDR: u32 = 63;
+pub const REG_BIN_LIST_BASE_ADDR: u32 = 64;
+pub const REG_BIN_LIST_STRIDE_ADDR: u32 = 65;
+pub const REG_BIN_BEGIN_ADDR: u32 = 66;
+pub const REG_BIN_TRIANGLE_ADDR: u32 = 67;
+pub const REG_BIN_END_ADDR: u32 = 68;
+
+pub const BIN_GRID_DIM_BITS: u32 = 5;
+pub const BIN_LIST_STRIDE_BITS: u32 = 8;
+pub const BIN_PRIMITIVE_WORDS: u32 = (3 * SETUP_VERTEX_WORDS).div_ceil(4);
+pub const BIN_LIST_END: u32 = 0xffffffff;
+pub const BIN_LIST_OVERFLOW: u32 = 0xfffffffe;
+
+//  The scissor rect is in tile-local pixel coords, with inclusive min and max. Only pixels inside it are generated, so
+//   rows and columns outside of it cost nothing, and if min is greater than max on either axis, no pixels are generated
+//   at all. It's sampled when a primitive is started, like the interpolant regs, and covers the whole tile on reset.
+pub const REG_SCISSOR_ADDR: u32 = 69;
+pub const REG_SCISSOR_BITS: u32 = 4 * TILE_DIM_BITS;
+pub const REG_SCISSOR_MIN_X_BIT_OFFSET: u32 = 0;
+pub const REG_SCISSOR_MIN_Y_BIT_OFFSET: u32 = REG_SCISSOR_MIN_X_BIT_OFFSET + TILE_DIM_BITS;
+pub const REG_SCISSOR_MAX_X_BIT_OFFSET: u32 = REG_SCISSOR_MIN_Y_BIT_OFFSET + TILE_DIM_BITS;
+pub const REG_SCISSOR_MAX_Y_BIT_OFFSET: u32 = REG_SCISSOR_MAX_X_BIT_OFFSET + TILE_DIM_BITS;
+
+//  Tile clear values are per-pixel: argb for color, 16 bits for depth, and 8 bits for stencil. Clears write a word per
+//   cycle, like loads, and the value is latched when a clear starts.
+pub const REG_TILE_CLEAR_COLOR_ADDR: u32 = 70;
+pub const REG_TILE_CLEAR_DEPTH_ADDR: u32 = 71;
+pub const REG_TILE_CLEAR_STENCIL_ADDR: u32 = 72;
+
 pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let m = c.module("ColorThrust");
 
@@ -111,6 +497,163 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let reg_bus_write_enable = reg_bus_enable & reg_bus_write;
 
+    command_processor::generate(c);
+    let command_processor = m.instance("command_processor", "CommandProcessor");
+
+    command_processor.drive_input("start", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_COMMAND_LIST_BASE_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    command_processor.drive_input("start_addr", reg_bus_write_data.bits(TEX_WORD_ADDR_BITS - 1, 0));
+    let command_processor_active = command_processor.output("active");
+
+    //  Reg writes from the command processor share the same path as reg bus writes
+    let command_processor_reg_write_enable = command_processor.output("reg_write_enable");
+    let reg_bus_addr = if_(command_processor_reg_write_enable, {
+        command_processor.output("reg_write_addr")
+    }).else_({
+        reg_bus_addr
+    });
+    let reg_bus_write_data = if_(command_processor_reg_write_enable, {
+        command_processor.output("reg_write_data")
+    }).else_({
+        reg_bus_write_data
+    });
+    let reg_bus_write_enable = reg_bus_write_enable | command_processor_reg_write_enable;
+
+    //  Start writes are picked up before setup reg writes are muxed in, so a start can be issued while setup is still
+    //   writing the regs it'll use; it's queued until setup is done anyway
+    let start_request = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_START_ADDR, REG_BUS_ADDR_BIT_WIDTH));
+
+    let setup_vertex_write_enable = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_SETUP_VERTEX_DATA_ADDR, REG_BUS_ADDR_BIT_WIDTH));
+    let reg_setup_vertex_select = m.reg("setup_vertex_select", 2);
+    let reg_setup_vertex_word = m.reg("setup_vertex_word", SETUP_VERTEX_WORD_BITS);
+    let (next_setup_vertex_select, next_setup_vertex_word) = if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_SETUP_VERTEX_INDEX_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        (reg_bus_write_data.bits(1, 0), m.lit(0u32, SETUP_VERTEX_WORD_BITS))
+    }).else_if(setup_vertex_write_enable, {
+        if_(reg_setup_vertex_word.value.eq(m.lit(SETUP_VERTEX_WORDS - 1, SETUP_VERTEX_WORD_BITS)), {
+            (reg_setup_vertex_select.value + m.lit(1u32, 2), m.lit(0u32, SETUP_VERTEX_WORD_BITS))
+        }).else_({
+            (reg_setup_vertex_select.value, reg_setup_vertex_word.value + m.lit(1u32, SETUP_VERTEX_WORD_BITS))
+        })
+    }).else_({
+        (reg_setup_vertex_select.value, reg_setup_vertex_word.value)
+    });
+    reg_setup_vertex_select.drive_next(next_setup_vertex_select);
+    reg_setup_vertex_word.drive_next(next_setup_vertex_word);
+
+    let transform_param_write_enable = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TRANSFORM_PARAM_DATA_ADDR, REG_BUS_ADDR_BIT_WIDTH));
+    let reg_transform_param_index = m.reg("transform_param_index", TRANSFORM_PARAM_INDEX_BITS);
+    reg_transform_param_index.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TRANSFORM_PARAM_INDEX_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(TRANSFORM_PARAM_INDEX_BITS - 1, 0)
+    }).else_if(transform_param_write_enable, {
+        reg_transform_param_index.value + m.lit(1u32, TRANSFORM_PARAM_INDEX_BITS)
+    }).else_({
+        reg_transform_param_index.value
+    }));
+
+    let reg_transform_settings = m.reg("transform_settings", REG_TRANSFORM_SETTINGS_BITS);
+    reg_transform_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TRANSFORM_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_TRANSFORM_SETTINGS_BITS - 1, 0)
+    }).else_({
+        reg_transform_settings.value
+    }));
+
+    let reg_transform_source = m.reg("transform_source", TEX_WORD_ADDR_BITS);
+    reg_transform_source.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TRANSFORM_SOURCE_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(TEX_WORD_ADDR_BITS - 1, 0)
+    }).else_({
+        reg_transform_source.value
+    }));
+
+    vertex_transform::generate(c);
+    let vertex_transform = m.instance("vertex_transform", "VertexTransform");
+
+    vertex_transform.drive_input("param_write_addr", reg_transform_param_index.value);
+    vertex_transform.drive_input("param_write_data", reg_bus_write_data);
+    vertex_transform.drive_input("param_write_enable", transform_param_write_enable);
+    vertex_transform.drive_input("lighting_enable", reg_transform_settings.value.bit(REG_TRANSFORM_LIGHTING_ENABLE_BIT));
+    vertex_transform.drive_input("light_mask", reg_transform_settings.value.bits(REG_TRANSFORM_SETTINGS_LIGHT_MASK_BIT_OFFSET + TRANSFORM_LIGHTS - 1, REG_TRANSFORM_SETTINGS_LIGHT_MASK_BIT_OFFSET));
+    vertex_transform.drive_input("start", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TRANSFORM_START_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    vertex_transform.drive_input("source_addr", reg_transform_source.value);
+    vertex_transform.drive_input("vertex_count", reg_bus_write_data.bits(TRANSFORM_OUTPUT_VERTEX_BITS, 0));
+    let vertex_transform_active = vertex_transform.output("active");
+
+    triangle_setup::generate(c);
+    let triangle_setup = m.instance("triangle_setup", "TriangleSetup");
+
+    triangle_setup.drive_input("start", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_SETUP_START_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    triangle_setup.drive_input("tile_min_x", reg_bus_write_data.bits(15, 0));
+    triangle_setup.drive_input("tile_min_y", reg_bus_write_data.bits(31, 16));
+    triangle_setup.drive_input("vertex_write_addr", reg_setup_vertex_select.value.concat(reg_setup_vertex_word.value));
+    triangle_setup.drive_input("vertex_write_data", reg_bus_write_data);
+    triangle_setup.drive_input("vertex_write_enable", setup_vertex_write_enable);
+    triangle_setup.drive_input("vertex_copy_start", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_SETUP_VERTEX_SOURCE_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    let setup_vertex_source = |i: u32| reg_bus_write_data.bits(i * 8 + TRANSFORM_OUTPUT_VERTEX_BITS - 1, i * 8);
+    triangle_setup.drive_input("vertex_copy_indices", setup_vertex_source(2).concat(setup_vertex_source(1)).concat(setup_vertex_source(0)));
+    vertex_transform.drive_input("output_read_addr", triangle_setup.output("vertex_copy_read_addr"));
+    vertex_transform.drive_input("output_read_enable", triangle_setup.output("vertex_copy_read_enable"));
+    triangle_setup.drive_input("vertex_copy_read_value", vertex_transform.output("output_read_value"));
+    let triangle_setup_active = triangle_setup.output("active");
+
+    let reg_bin_settings = m.reg("bin_settings", REG_BIN_SETTINGS_BITS);
+    reg_bin_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_BIN_SETTINGS_BITS - 1, 0)
+    }).else_({
+        reg_bin_settings.value
+    }));
+
+    let reg_bin_primitive_base = m.reg("bin_primitive_base", TEX_WORD_ADDR_BITS);
+    reg_bin_primitive_base.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_PRIMITIVE_BASE_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(TEX_WORD_ADDR_BITS - 1, 0)
+    }).else_({
+        reg_bin_primitive_base.value
+    }));
+
+    let reg_bin_list_base = m.reg("bin_list_base", TEX_WORD_ADDR_BITS);
+    reg_bin_list_base.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_LIST_BASE_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(TEX_WORD_ADDR_BITS - 1, 0)
+    }).else_({
+        reg_bin_list_base.value
+    }));
+
+    let reg_bin_list_stride = m.reg("bin_list_stride", BIN_LIST_STRIDE_BITS);
+    reg_bin_list_stride.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_LIST_STRIDE_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(BIN_LIST_STRIDE_BITS - 1, 0)
+    }).else_({
+        reg_bin_list_stride.value
+    }));
+
+    binner::generate(c);
+    let binner = m.instance("binner", "Binner");
+
+    binner.drive_input("edge_test_enable", reg_bin_settings.value.bit(REG_BIN_EDGE_TEST_ENABLE_BIT));
+    binner.drive_input("back_face_cull_enable", reg_bin_settings.value.bit(REG_BIN_BACK_FACE_CULL_ENABLE_BIT));
+    binner.drive_input("grid_width", reg_bin_settings.value.bits(REG_BIN_SETTINGS_GRID_WIDTH_BIT_OFFSET + BIN_GRID_DIM_BITS, REG_BIN_SETTINGS_GRID_WIDTH_BIT_OFFSET));
+    binner.drive_input("grid_height", reg_bin_settings.value.bits(REG_BIN_SETTINGS_GRID_HEIGHT_BIT_OFFSET + BIN_GRID_DIM_BITS, REG_BIN_SETTINGS_GRID_HEIGHT_BIT_OFFSET));
+    binner.drive_input("primitive_base", reg_bin_primitive_base.value);
+    binner.drive_input("list_base", reg_bin_list_base.value);
+    binner.drive_input("list_stride", reg_bin_list_stride.value);
+    binner.drive_input("begin", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_BEGIN_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    binner.drive_input("triangle", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_TRIANGLE_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    binner.drive_input("triangle_indices", setup_vertex_source(2).concat(setup_vertex_source(1)).concat(setup_vertex_source(0)));
+    binner.drive_input("end", reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BIN_END_ADDR, REG_BUS_ADDR_BIT_WIDTH)));
+    vertex_transform.drive_input("bin_read_addr", binner.output("vertex_read_addr"));
+    vertex_transform.drive_input("bin_read_enable", binner.output("vertex_read_enable"));
+    binner.drive_input("vertex_read_value", vertex_transform.output("bin_read_value"));
+    let binner_active = binner.output("active");
+
+    //  Reg writes from the setup unit share the same path as well
+    let triangle_setup_reg_write_enable = triangle_setup.output("reg_write_enable");
+    let reg_bus_addr = if_(triangle_setup_reg_write_enable, {
+        triangle_setup.output("reg_write_addr")
+    }).else_({
+        reg_bus_addr
+    });
+    let reg_bus_write_data = if_(triangle_setup_reg_write_enable, {
+        triangle_setup.output("reg_write_data")
+    }).else_({
+        reg_bus_write_data
+    });
+    let reg_bus_write_enable = reg_bus_write_enable | triangle_setup_reg_write_enable;
+
     let tex_cache_invalidate = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEX_CACHE_INVALIDATE_ADDR, REG_BUS_ADDR_BIT_WIDTH));
 
     let reg_depth_settings = m.reg("depth_settings", REG_DEPTH_SETTINGS_BITS);
@@ -121,6 +664,72 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     }));
     let depth_test_enable = reg_depth_settings.value.bit(REG_DEPTH_TEST_ENABLE_BIT);
     let depth_write_mask_enable = reg_depth_settings.value.bit(REG_DEPTH_WRITE_MASK_ENABLE_BIT);
+    let depth_func = reg_depth_settings.value.bits(REG_DEPTH_SETTINGS_FUNC_BIT_OFFSET + REG_DEPTH_SETTINGS_FUNC_BITS - 1, REG_DEPTH_SETTINGS_FUNC_BIT_OFFSET);
+
+    let reg_alpha_test_settings = m.reg("alpha_test_settings", REG_ALPHA_TEST_SETTINGS_BITS);
+    reg_alpha_test_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_ALPHA_TEST_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_ALPHA_TEST_SETTINGS_BITS - 1, 0)
+    }).else_({
+        reg_alpha_test_settings.value
+    }));
+    let alpha_test_enable = reg_alpha_test_settings.value.bit(REG_ALPHA_TEST_ENABLE_BIT);
+    let alpha_test_func = reg_alpha_test_settings.value.bits(REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET + REG_ALPHA_TEST_SETTINGS_FUNC_BITS - 1, REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET);
+    let alpha_test_ref = reg_alpha_test_settings.value.bits(REG_ALPHA_TEST_SETTINGS_REF_BIT_OFFSET + REG_ALPHA_TEST_SETTINGS_REF_BITS - 1, REG_ALPHA_TEST_SETTINGS_REF_BIT_OFFSET);
+
+    let reg_stencil_settings = m.reg("stencil_settings", REG_STENCIL_SETTINGS_BITS);
+    reg_stencil_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_STENCIL_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_STENCIL_SETTINGS_BITS - 1, 0)
+    }).else_({
+        reg_stencil_settings.value
+    }));
+    let stencil_test_enable = reg_stencil_settings.value.bit(REG_STENCIL_TEST_ENABLE_BIT);
+    let stencil_func = reg_stencil_settings.value.bits(REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET + REG_STENCIL_SETTINGS_FUNC_BITS - 1, REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET);
+    let stencil_ref = reg_stencil_settings.value.bits(REG_STENCIL_SETTINGS_REF_BIT_OFFSET + REG_STENCIL_SETTINGS_REF_BITS - 1, REG_STENCIL_SETTINGS_REF_BIT_OFFSET);
+    let stencil_mask = reg_stencil_settings.value.bits(REG_STENCIL_SETTINGS_MASK_BIT_OFFSET + REG_STENCIL_SETTINGS_MASK_BITS - 1, REG_STENCIL_SETTINGS_MASK_BIT_OFFSET);
+    let stencil_sfail_op = reg_stencil_settings.value.bits(REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET + REG_STENCIL_SETTINGS_OP_BITS - 1, REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET);
+    let stencil_dpfail_op = reg_stencil_settings.value.bits(REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET + REG_STENCIL_SETTINGS_OP_BITS - 1, REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET);
+    let stencil_dppass_op = reg_stencil_settings.value.bits(REG_STENCIL_SETTINGS_DPPASS_OP_BIT_OFFSET + REG_STENCIL_SETTINGS_OP_BITS - 1, REG_STENCIL_SETTINGS_DPPASS_OP_BIT_OFFSET);
+
+    let reg_fog_settings = m.reg("fog_settings", REG_FOG_SETTINGS_BITS);
+    reg_fog_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_FOG_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_FOG_SETTINGS_BITS - 1, 0)
+    }).else_({
+        reg_fog_settings.value
+    }));
+    let fog_enable = reg_fog_settings.value.bit(REG_FOG_ENABLE_BIT);
+    let fog_mode = reg_fog_settings.value.bits(REG_FOG_SETTINGS_MODE_BIT_OFFSET + REG_FOG_SETTINGS_MODE_BITS - 1, REG_FOG_SETTINGS_MODE_BIT_OFFSET);
+    let fog_coord_select = reg_fog_settings.value.bit(REG_FOG_SETTINGS_COORD_SELECT_BIT_OFFSET);
+
+    let reg_fog_color = m.reg("fog_color", 32);
+    reg_fog_color.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_FOG_COLOR_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data
+    }).else_({
+        reg_fog_color.value
+    }));
+
+    let reg_fog_start = m.reg("fog_start", FOG_COORD_BITS);
+    reg_fog_start.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_FOG_START_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(FOG_COORD_BITS - 1, 0)
+    }).else_({
+        reg_fog_start.value
+    }));
+
+    let reg_fog_scale = m.reg("fog_scale", FOG_COORD_BITS);
+    reg_fog_scale.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_FOG_SCALE_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(FOG_COORD_BITS - 1, 0)
+    }).else_({
+        reg_fog_scale.value
+    }));
+
+    let fog_table_write_enable = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_FOG_TABLE_DATA_ADDR, REG_BUS_ADDR_BIT_WIDTH));
+    let reg_fog_table_index = m.reg("fog_table_index", FOG_TABLE_INDEX_BITS);
+    reg_fog_table_index.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_FOG_TABLE_INDEX_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(FOG_TABLE_INDEX_BITS - 1, 0)
+    }).else_if(fog_table_write_enable, {
+        reg_fog_table_index.value + m.lit(1u32, FOG_TABLE_INDEX_BITS)
+    }).else_({
+        reg_fog_table_index.value
+    }));
 
     let reg_texture_settings = m.reg("texture_settings", REG_TEXTURE_SETTINGS_BITS);
     reg_texture_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEXTURE_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
@@ -129,7 +738,14 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
         reg_texture_settings.value
     }));
     let tex_filter_select = reg_texture_settings.value.bit(REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET);
-    let tex_dim = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_DIM_BIT_OFFSET + REG_TEXTURE_SETTINGS_DIM_BITS - 1, REG_TEXTURE_SETTINGS_DIM_BIT_OFFSET);
+    let tex_width = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET + REG_TEXTURE_SETTINGS_DIM_BITS - 1, REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET);
+    let tex_height = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET + REG_TEXTURE_SETTINGS_DIM_BITS - 1, REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET);
+    let tex_wrap_s = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET + REG_TEXTURE_SETTINGS_WRAP_BITS - 1, REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET);
+    let tex_wrap_t = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET + REG_TEXTURE_SETTINGS_WRAP_BITS - 1, REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET);
+    let tex_mip_mode = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET + REG_TEXTURE_SETTINGS_MIP_MODE_BITS - 1, REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET);
+    let tex_max_level = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET + REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS - 1, REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET);
+    let tex_format = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET + REG_TEXTURE_SETTINGS_FORMAT_BITS - 1, REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET);
+    let tex_combine_mode = reg_texture_settings.value.bits(REG_TEXTURE_SETTINGS_COMBINE_MODE_BIT_OFFSET + REG_TEXTURE_SETTINGS_COMBINE_MODE_BITS - 1, REG_TEXTURE_SETTINGS_COMBINE_MODE_BIT_OFFSET);
 
     let reg_texture_base = m.reg("texture_base", TEX_PIXEL_ADDR_BITS - 8);
     reg_texture_base.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEXTURE_BASE_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
@@ -139,6 +755,30 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
         reg_texture_base.value
     }));
 
+    let reg_texture_border_color = m.reg("texture_border_color", 32);
+    reg_texture_border_color.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEXTURE_BORDER_COLOR_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data
+    }).else_({
+        reg_texture_border_color.value
+    }));
+
+    let reg_texture_lod_bias = m.reg("texture_lod_bias", REG_TEXTURE_LOD_BIAS_BITS);
+    reg_texture_lod_bias.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEXTURE_LOD_BIAS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_TEXTURE_LOD_BIAS_BITS - 1, 0)
+    }).else_({
+        reg_texture_lod_bias.value
+    }));
+
+    let tex_palette_write_enable = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEXTURE_PALETTE_DATA_ADDR, REG_BUS_ADDR_BIT_WIDTH));
+    let reg_texture_palette_index = m.reg("texture_palette_index", TEX_PALETTE_INDEX_BITS);
+    reg_texture_palette_index.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_TEXTURE_PALETTE_INDEX_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(TEX_PALETTE_INDEX_BITS - 1, 0)
+    }).else_if(tex_palette_write_enable, {
+        reg_texture_palette_index.value + m.lit(1u32, TEX_PALETTE_INDEX_BITS)
+    }).else_({
+        reg_texture_palette_index.value
+    }));
+
     let reg_blend_settings = m.reg("blend_settings", REG_BLEND_SETTINGS_BITS);
     reg_blend_settings.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BLEND_SETTINGS_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
         reg_bus_write_data.bits(REG_BLEND_SETTINGS_BITS - 1, 0)
@@ -147,65 +787,194 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     }));
     let blend_src_factor = reg_blend_settings.value.bits(REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET + REG_BLEND_SETTINGS_SRC_FACTOR_BITS - 1, REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET);
     let blend_dst_factor = reg_blend_settings.value.bits(REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET + REG_BLEND_SETTINGS_DST_FACTOR_BITS - 1, REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET);
+    let blend_op = reg_blend_settings.value.bits(REG_BLEND_SETTINGS_OP_BIT_OFFSET + REG_BLEND_SETTINGS_OP_BITS - 1, REG_BLEND_SETTINGS_OP_BIT_OFFSET);
+
+    let reg_blend_constant = m.reg("blend_constant", 32);
+    reg_blend_constant.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_BLEND_CONSTANT_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data
+    }).else_({
+        reg_blend_constant.value
+    }));
 
     let input_generator_active = m.reg("input_generator_active", 1);
     input_generator_active.default_value(false);
 
+    //  A start is taken once the last primitive has been traversed and setup is done writing regs, and everything that's
+    //   captured on start below (scissor mirror, edge reaches, dx/dy mirrors, and starting values) forms the active set
+    let start_pending = m.reg("start_pending", 1);
+    start_pending.default_value(false);
+    let start = (start_request | start_pending.value) & !input_generator_active.value & !triangle_setup_active;
+    start_pending.drive_next((start_request | start_pending.value) & !start);
+    command_processor.drive_input("start_pending", start_pending.value);
+
+    let reg_scissor = m.reg("scissor", REG_SCISSOR_BITS);
+    reg_scissor.default_value(((TILE_DIM - 1) << REG_SCISSOR_MAX_X_BIT_OFFSET) | ((TILE_DIM - 1) << REG_SCISSOR_MAX_Y_BIT_OFFSET));
+    reg_scissor.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_SCISSOR_ADDR, REG_BUS_ADDR_BIT_WIDTH)), {
+        reg_bus_write_data.bits(REG_SCISSOR_BITS - 1, 0)
+    }).else_({
+        reg_scissor.value
+    }));
+    let scissor_mirror = m.reg("scissor_mirror", REG_SCISSOR_BITS);
+    scissor_mirror.drive_next(if_(start, {
+        reg_scissor.value
+    }).else_({
+        scissor_mirror.value
+    }));
+    let scissor_field = |scissor: &'a Signal<'a>, bit_offset: u32| scissor.bits(bit_offset + TILE_DIM_BITS - 1, bit_offset);
+    let scissor_min_x = scissor_field(reg_scissor.value, REG_SCISSOR_MIN_X_BIT_OFFSET);
+    let scissor_min_y = scissor_field(reg_scissor.value, REG_SCISSOR_MIN_Y_BIT_OFFSET);
+    let scissor_max_x = scissor_field(reg_scissor.value, REG_SCISSOR_MAX_X_BIT_OFFSET);
+    let scissor_max_y = scissor_field(reg_scissor.value, REG_SCISSOR_MAX_Y_BIT_OFFSET);
+    let scissor_empty = scissor_min_x.gt(scissor_max_x) | scissor_min_y.gt(scissor_max_y);
+
+    //  Only pixels inside the scissor rect are generated, in the same order as the whole tile would be
     let tile_x = m.reg("tile_x", TILE_DIM_BITS);
     let tile_y = m.reg("tile_y", TILE_DIM_BITS);
-    let tile_x_last = tile_x.value.eq(m.lit(TILE_DIM - 1, TILE_DIM_BITS));
-    let tile_y_last = tile_y.value.eq(m.lit(TILE_DIM - 1, TILE_DIM_BITS));
-
-    let start = reg_bus_write_enable & reg_bus_addr.eq(m.lit(REG_START_ADDR, REG_BUS_ADDR_BIT_WIDTH));
+    let tile_x_min = scissor_field(scissor_mirror.value, REG_SCISSOR_MIN_X_BIT_OFFSET);
+    let tile_x_last = tile_x.value.eq(scissor_field(scissor_mirror.value, REG_SCISSOR_MAX_X_BIT_OFFSET));
+    let tile_y_last = tile_y.value.eq(scissor_field(scissor_mirror.value, REG_SCISSOR_MAX_Y_BIT_OFFSET));
 
     generate_pixel_pipe(c);
     let pixel_pipe = m.instance("pixel_pipe", "PixelPipe");
 
-    pixel_pipe.drive_input("start", start);
-
     pixel_pipe.drive_input("depth_test_enable", depth_test_enable);
     pixel_pipe.drive_input("depth_write_mask_enable", depth_write_mask_enable);
+    pixel_pipe.drive_input("depth_func", depth_func);
+
+    pixel_pipe.drive_input("stencil_test_enable", stencil_test_enable);
+    pixel_pipe.drive_input("stencil_func", stencil_func);
+    pixel_pipe.drive_input("stencil_ref", stencil_ref);
+    pixel_pipe.drive_input("stencil_mask", stencil_mask);
+    pixel_pipe.drive_input("stencil_sfail_op", stencil_sfail_op);
+    pixel_pipe.drive_input("stencil_dpfail_op", stencil_dpfail_op);
+    pixel_pipe.drive_input("stencil_dppass_op", stencil_dppass_op);
+
+    pixel_pipe.drive_input("alpha_test_enable", alpha_test_enable);
+    pixel_pipe.drive_input("alpha_test_func", alpha_test_func);
+    pixel_pipe.drive_input("alpha_test_ref", alpha_test_ref);
+
+    pixel_pipe.drive_input("fog_enable", fog_enable);
+    pixel_pipe.drive_input("fog_mode", fog_mode);
+    pixel_pipe.drive_input("fog_coord_select", fog_coord_select);
+    pixel_pipe.drive_input("fog_color", reg_fog_color.value);
+    pixel_pipe.drive_input("fog_start", reg_fog_start.value);
+    pixel_pipe.drive_input("fog_scale", reg_fog_scale.value);
+    pixel_pipe.drive_input("fog_table_write_addr", reg_fog_table_index.value);
+    pixel_pipe.drive_input("fog_table_write_data", reg_bus_write_data.bits(FOG_AMOUNT_BITS - 1, 0));
+    pixel_pipe.drive_input("fog_table_write_enable", fog_table_write_enable);
 
     pixel_pipe.drive_input("tex_filter_select", tex_filter_select);
-    pixel_pipe.drive_input("tex_dim", tex_dim);
+    pixel_pipe.drive_input("tex_width", tex_width);
+    pixel_pipe.drive_input("tex_height", tex_height);
     pixel_pipe.drive_input("tex_base", reg_texture_base.value);
+    pixel_pipe.drive_input("tex_wrap_s", tex_wrap_s);
+    pixel_pipe.drive_input("tex_wrap_t", tex_wrap_t);
+    pixel_pipe.drive_input("tex_border_color", reg_texture_border_color.value);
+    pixel_pipe.drive_input("tex_mip_mode", tex_mip_mode);
+    pixel_pipe.drive_input("tex_max_level", tex_max_level);
+    pixel_pipe.drive_input("tex_lod_bias", reg_texture_lod_bias.value);
+    pixel_pipe.drive_input("tex_format", tex_format);
+    pixel_pipe.drive_input("tex_combine_mode", tex_combine_mode);
+    pixel_pipe.drive_input("tex_palette_write_addr", reg_texture_palette_index.value);
+    pixel_pipe.drive_input("tex_palette_write_data", reg_bus_write_data);
+    pixel_pipe.drive_input("tex_palette_write_enable", tex_palette_write_enable);
 
     pixel_pipe.drive_input("blend_src_factor", blend_src_factor);
     pixel_pipe.drive_input("blend_dst_factor", blend_dst_factor);
+    pixel_pipe.drive_input("blend_op", blend_op);
+    pixel_pipe.drive_input("blend_constant", reg_blend_constant.value);
+
+    //  Interpolant regs are declared before they're driven, since traversal needs the edge values to decide how to step
+    //   (min, dx, dy, dx_mirror, dy_mirror, row, value)
+    type InterpolantRegs<'a> = (&'a Register<'a>, &'a Register<'a>, &'a Register<'a>, &'a Register<'a>, &'a Register<'a>, &'a Register<'a>, &'a Register<'a>);
+    let interpolant = |name: &str, num_bits: u32| -> InterpolantRegs<'a> {
+        let min = m.reg(format!("{}_min", name), num_bits);
+        let dx = m.reg(format!("{}_dx", name), num_bits);
+        let dy = m.reg(format!("{}_dy", name), num_bits);
+        let dx_mirror = m.reg(format!("{}_dx_mirror", name), num_bits);
+        let dy_mirror = m.reg(format!("{}_dy_mirror", name), num_bits);
+        let row = m.reg(format!("{}_row", name), num_bits);
+        let value = m.reg(name, num_bits);
+        (min, dx, dy, dx_mirror, dy_mirror, row, value)
+    };
 
-    pixel_pipe.drive_input("in_valid", input_generator_active.value);
+    let w0 = interpolant("w0", 32);
+    let w1 = interpolant("w1", 32);
+    let w2 = interpolant("w2", 32);
+    let r = interpolant("r", 24);
+    let g = interpolant("g", 24);
+    let b = interpolant("b", 24);
+    let a = interpolant("a", 24);
+    let w_inverse = interpolant("w_inverse", 32);
+    let z = interpolant("z", 32);
+    let s = interpolant("s", 32);
+    let t = interpolant("t", 32);
+
+    //  Traversal skips runs of pixels that can't pass the edge test instead of feeding them through the pipe one by one.
+    //   Since edge functions are linear, the largest value over the next N pixels in a row is the current value plus the
+    //   positive part of dx times N - 1; if that's negative for any edge, none of them are covered. Each cycle, the rest
+    //   of the row is tested if we're at its start, otherwise the next span of TILE_SPAN pixels is tested, and either can
+    //   be skipped in a single cycle. Reaches are computed on start, like the scissor rect and dx/dy mirrors.
+    let tile_x_max = scissor_field(scissor_mirror.value, REG_SCISSOR_MAX_X_BIT_OFFSET);
+    let tile_x_row_start = tile_x.value.eq(tile_x_min);
+    let tile_x_remaining = tile_x_max - tile_x.value;
+    let tile_x_span_last = tile_x_remaining.lt(m.lit(TILE_SPAN, TILE_DIM_BITS));
+
+    let edge_empty = |name: &str, (_, dx, _, _, _, _, value): InterpolantRegs<'a>| {
+        let positive_dx = dx.value.bit(31).mux(m.lit(0u32, 32), dx.value);
+        let reach = |name: String, pixels: &'a Signal<'a>| {
+            let reach = m.reg(name, 36);
+            reach.drive_next(if_(start, {
+                positive_dx * pixels
+            }).else_({
+                reach.value
+            }));
+            reach.value
+        };
+        let row_reach = reach(format!("{}_row_reach", name), scissor_max_x - scissor_min_x);
+        let span_reach = reach(format!("{}_span_reach", name), m.lit(TILE_SPAN - 1, TILE_DIM_BITS));
+        let empty = |reach: &'a Signal<'a>| (value.value.bit(31).repeat(4).concat(value.value) + reach).bit(35);
+        (empty(row_reach), empty(span_reach))
+    };
+    let (w0_row_empty, w0_span_empty) = edge_empty("w0", w0);
+    let (w1_row_empty, w1_span_empty) = edge_empty("w1", w1);
+    let (w2_row_empty, w2_span_empty) = edge_empty("w2", w2);
+    let row_empty = tile_x_row_start & (w0_row_empty | w1_row_empty | w2_row_empty);
+    let span_empty = w0_span_empty | w1_span_empty | w2_span_empty;
+
+    let skip = input_generator_active.value & (row_empty | span_empty);
+    let skip_row = row_empty | tile_x_span_last;
+    pixel_pipe.drive_input("in_valid", input_generator_active.value & !skip);
     pixel_pipe.drive_input("in_tile_addr", tile_y.value.concat(tile_x.value));
 
     let pixel_pipe_in_ready = pixel_pipe.output("in_ready");
 
+    let pixel_advance = !skip & pixel_pipe_in_ready;
+    let step_row = (skip & skip_row) | (pixel_advance & tile_x_last);
+    let step_span = skip & !skip_row;
+    let step_pixel = pixel_advance & !tile_x_last;
+
     let (next_input_generator_active, next_tile_x, next_tile_y) = if_(start, {
-        let next_input_generator_active = m.high();
+        let next_input_generator_active = !scissor_empty;
 
-        let next_tile_x = m.lit(0u32, TILE_DIM_BITS);
-        let next_tile_y = m.lit(0u32, TILE_DIM_BITS);
+        let next_tile_x = scissor_min_x;
+        let next_tile_y = scissor_min_y;
 
         (next_input_generator_active, next_tile_x, next_tile_y)
-    }).else_if(pixel_pipe_in_ready, {
-        let next_input_generator_active = input_generator_active.value;
-
-        let next_tile_x = tile_x.value + m.lit(1u32, TILE_DIM_BITS);
-        let next_tile_y = tile_y.value;
-
-        let (next_input_generator_active, next_tile_y) = if_(tile_x_last, {
-            let next_input_generator_active = if_(tile_y_last, {
-                m.low()
-            }).else_({
-                next_input_generator_active
-            });
-
-            let next_tile_y = tile_y.value + m.lit(1u32, TILE_DIM_BITS);
-
-            (next_input_generator_active, next_tile_y)
+    }).else_if(step_row, {
+        let next_input_generator_active = if_(tile_y_last, {
+            m.low()
         }).else_({
-            (next_input_generator_active, next_tile_y)
+            input_generator_active.value
         });
 
-        (next_input_generator_active, next_tile_x, next_tile_y)
+        let next_tile_y = tile_y.value + m.lit(1u32, TILE_DIM_BITS);
+
+        (next_input_generator_active, tile_x_min, next_tile_y)
+    }).else_if(step_span, {
+        (input_generator_active.value, tile_x.value + m.lit(TILE_SPAN, TILE_DIM_BITS), tile_y.value)
+    }).else_if(step_pixel, {
+        (input_generator_active.value, tile_x.value + m.lit(1u32, TILE_DIM_BITS), tile_y.value)
     }).else_({
         (input_generator_active.value, tile_x.value, tile_y.value)
     });
@@ -215,51 +984,44 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     tile_x.drive_next(next_tile_x);
     tile_y.drive_next(next_tile_y);
 
-    let interpolant = |name: &str, num_bits, min_addr: u32, dx_addr: u32, dy_addr: u32| {
-        let min = m.reg(format!("{}_min", name), num_bits);
+    let drive_interpolant = |(min, dx, dy, dx_mirror, dy_mirror, row, value): InterpolantRegs<'a>, num_bits: u32, min_addr: u32, dx_addr: u32, dy_addr: u32| {
         min.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(min_addr, REG_BUS_ADDR_BIT_WIDTH)), {
             reg_bus_write_data.bits(num_bits - 1, 0)
         }).else_({
             min.value
         }));
-        let dx = m.reg(format!("{}_dx", name), num_bits);
         dx.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(dx_addr, REG_BUS_ADDR_BIT_WIDTH)), {
             reg_bus_write_data.bits(num_bits - 1, 0)
         }).else_({
             dx.value
         }));
-        let dx_mirror = m.reg(format!("{}_dx_mirror", name), num_bits);
         dx_mirror.drive_next(if_(start, {
             dx.value
         }).else_({
             dx_mirror.value
         }));
-        let dy = m.reg(format!("{}_dy", name), num_bits);
         dy.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(dy_addr, REG_BUS_ADDR_BIT_WIDTH)), {
             reg_bus_write_data.bits(num_bits - 1, 0)
         }).else_({
             dy.value
         }));
-        let dy_mirror = m.reg(format!("{}_dy_mirror", name), num_bits);
         dy_mirror.drive_next(if_(start, {
             dy.value
         }).else_({
             dy_mirror.value
         }));
 
-        let row = m.reg(format!("{}_row", name), num_bits);
-
-        let value = m.reg(name, num_bits);
-
         let (next_row, next_value) = if_(start, {
-            (min.value, min.value)
-        }).else_if(pixel_pipe_in_ready, {
-            if_(tile_x_last, {
-                let next = row.value + dy_mirror.value;
-                (next, next)
-            }).else_({
-                (row.value, value.value + dx_mirror.value)
-            })
+            //  Min values are at the tile's origin, so they're stepped to the scissor rect's origin
+            let next = min.value + (dx.value * scissor_min_x).bits(num_bits - 1, 0) + (dy.value * scissor_min_y).bits(num_bits - 1, 0);
+            (next, next)
+        }).else_if(step_row, {
+            let next = row.value + dy_mirror.value;
+            (next, next)
+        }).else_if(step_span, {
+            (row.value, value.value + dx_mirror.value.bits(num_bits - 1 - TILE_SPAN_BITS, 0).concat(m.lit(0u32, TILE_SPAN_BITS)))
+        }).else_if(step_pixel, {
+            (row.value, value.value + dx_mirror.value)
         }).else_({
             (row.value, value.value)
         });
@@ -268,24 +1030,50 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
         value.drive_next(next_value);
 
-        value.value
+        (value.value, dx.value, dy.value)
     };
 
-    let w0 = interpolant("w0", 32, REG_W0_MIN_ADDR, REG_W0_DX_ADDR, REG_W0_DY_ADDR).bit(31);
-    let w1 = interpolant("w1", 32, REG_W1_MIN_ADDR, REG_W1_DX_ADDR, REG_W1_DY_ADDR).bit(31);
-    let w2 = interpolant("w2", 32, REG_W2_MIN_ADDR, REG_W2_DX_ADDR, REG_W2_DY_ADDR).bit(31);
-
-    let r = interpolant("r", 24, REG_R_MIN_ADDR, REG_R_DX_ADDR, REG_R_DY_ADDR).bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
-    let g = interpolant("g", 24, REG_G_MIN_ADDR, REG_G_DX_ADDR, REG_G_DY_ADDR).bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
-    let b = interpolant("b", 24, REG_B_MIN_ADDR, REG_B_DX_ADDR, REG_B_DY_ADDR).bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
-    let a = interpolant("a", 24, REG_A_MIN_ADDR, REG_A_DX_ADDR, REG_A_DY_ADDR).bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
-
-    let w_inverse = interpolant("w_inverse", 32, REG_W_INVERSE_MIN_ADDR, REG_W_INVERSE_DX_ADDR, REG_W_INVERSE_DY_ADDR);
-
-    let z = interpolant("z", 32, REG_Z_MIN_ADDR, REG_Z_DX_ADDR, REG_Z_DY_ADDR).bits(31, 16);
-
-    let s = interpolant("s", 32, REG_S_MIN_ADDR, REG_S_DX_ADDR, REG_S_DY_ADDR).bits(31, RESTORED_W_FRACT_BITS);
-    let t = interpolant("t", 32, REG_T_MIN_ADDR, REG_T_DX_ADDR, REG_T_DY_ADDR).bits(31, RESTORED_W_FRACT_BITS);
+    let (w0, _, _) = drive_interpolant(w0, 32, REG_W0_MIN_ADDR, REG_W0_DX_ADDR, REG_W0_DY_ADDR);
+    let (w1, _, _) = drive_interpolant(w1, 32, REG_W1_MIN_ADDR, REG_W1_DX_ADDR, REG_W1_DY_ADDR);
+    let (w2, _, _) = drive_interpolant(w2, 32, REG_W2_MIN_ADDR, REG_W2_DX_ADDR, REG_W2_DY_ADDR);
+    let w0 = w0.bit(31);
+    let w1 = w1.bit(31);
+    let w2 = w2.bit(31);
+
+    let (r, _, _) = drive_interpolant(r, 24, REG_R_MIN_ADDR, REG_R_DX_ADDR, REG_R_DY_ADDR);
+    let (g, _, _) = drive_interpolant(g, 24, REG_G_MIN_ADDR, REG_G_DX_ADDR, REG_G_DY_ADDR);
+    let (b, _, _) = drive_interpolant(b, 24, REG_B_MIN_ADDR, REG_B_DX_ADDR, REG_B_DY_ADDR);
+    let (a, _, _) = drive_interpolant(a, 24, REG_A_MIN_ADDR, REG_A_DX_ADDR, REG_A_DY_ADDR);
+    let r = r.bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
+    let g = g.bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
+    let b = b.bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
+    let a = a.bits(COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1, COLOR_FRACT_BITS);
+
+    let (w_inverse, _, _) = drive_interpolant(w_inverse, 32, REG_W_INVERSE_MIN_ADDR, REG_W_INVERSE_DX_ADDR, REG_W_INVERSE_DY_ADDR);
+
+    let (z, _, _) = drive_interpolant(z, 32, REG_Z_MIN_ADDR, REG_Z_DX_ADDR, REG_Z_DY_ADDR);
+    let z = z.bits(Z_FRACT_BITS - 1, Z_FRACT_BITS - 16);
+
+    let (s, s_dx, s_dy) = drive_interpolant(s, 32, REG_S_MIN_ADDR, REG_S_DX_ADDR, REG_S_DY_ADDR);
+    let (t, t_dx, t_dy) = drive_interpolant(t, 32, REG_T_MIN_ADDR, REG_T_DX_ADDR, REG_T_DY_ADDR);
+    let s = s.bits(31, RESTORED_W_FRACT_BITS);
+    let t = t.bits(31, RESTORED_W_FRACT_BITS);
+
+    //  The per-primitive part of the texture LOD is log2 of the largest s/t derivative (still divided by w; the front
+    //   pipe adds log2(w) per pixel). It's captured from the pending derivatives on start like the other active regs, and
+    //   passed down the pipe with each pixel, since pixels from different primitives can be in flight at once.
+    let abs = |x: &'a Signal<'a>| -> &'a Signal<'a> {
+        x.bit(31).mux(m.lit(0u32, 32) - x, x)
+    };
+    let max = |a: &'a Signal<'a>, b: &'a Signal<'a>| -> &'a Signal<'a> {
+        a.gt(b).mux(a, b)
+    };
+    let tex_lod_base = m.reg("tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+    tex_lod_base.drive_next(if_(start, {
+        log2_approx(max(max(abs(s_dx), abs(s_dy)), max(abs(t_dx), abs(t_dy))), m)
+    }).else_({
+        tex_lod_base.value
+    }));
 
     pixel_pipe.drive_input("in_w0", w0);
     pixel_pipe.drive_input("in_w1", w1);
@@ -303,9 +1091,90 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     pixel_pipe.drive_input("in_s", s);
     pixel_pipe.drive_input("in_t", t);
 
-    m.output("reg_bus_read_data", m.lit(0u32, 31).concat(input_generator_active.value | pixel_pipe.output("active")));
+    pixel_pipe.drive_input("in_tex_lod_base", tex_lod_base.value);
+
+    tile_transfer::generate(c);
+    let tile_transfer = m.instance("tile_transfer", "TileTransfer");
+
+    tile_transfer.drive_input("start", command_processor.output("tile_transfer_start"));
+    tile_transfer.drive_input("start_opcode", command_processor.output("tile_transfer_opcode"));
+    tile_transfer.drive_input("start_tile_buffer", command_processor.output("tile_transfer_tile_buffer"));
+    tile_transfer.drive_input("start_word_addr", command_processor.output("tile_transfer_word_addr"));
+    tile_transfer.drive_input("start_pitch", command_processor.output("tile_transfer_pitch"));
+    let tile_transfer_active = tile_transfer.output("active");
+    command_processor.drive_input("tile_transfer_idle", !tile_transfer_active);
+
+    let rasterizer_active = start_pending.value | input_generator_active.value | pixel_pipe.output("active") | triangle_setup_active | vertex_transform_active | binner_active;
+    command_processor.drive_input("rasterizer_idle", !rasterizer_active);
+
+    let render_bank = command_processor.output("render_bank");
+    let render_bank_busy = start_pending.value | input_generator_active.value | pixel_pipe.output("active");
+    let transfer_bank_busy = tile_transfer_active;
+    let bank_busy = render_bank.mux(render_bank_busy.concat(transfer_bank_busy), transfer_bank_busy.concat(render_bank_busy));
+    m.output("reg_bus_read_data", m.lit(0u32, 28).concat(start_pending.value).concat(bank_busy).concat(rasterizer_active | command_processor_active | tile_transfer_active));
     m.output("reg_bus_read_data_valid", (reg_bus_enable & !reg_bus_write).reg_next_with_default("reg_bus_read_data_valid", false));
 
+    let tile_clear_reg = |name: &str, addr: u32, bit_width: u32| {
+        let reg = m.reg(name, bit_width);
+        reg.drive_next(if_(reg_bus_write_enable & reg_bus_addr.eq(m.lit(addr, REG_BUS_ADDR_BIT_WIDTH)), {
+            reg_bus_write_data.bits(bit_width - 1, 0)
+        }).else_({
+            reg.value
+        }));
+        reg.value
+    };
+
+    tile_transfer.drive_input("tile_clear_color", tile_clear_reg("tile_clear_color", REG_TILE_CLEAR_COLOR_ADDR, 32));
+    tile_transfer.drive_input("tile_clear_depth", tile_clear_reg("tile_clear_depth", REG_TILE_CLEAR_DEPTH_ADDR, 16));
+    tile_transfer.drive_input("tile_clear_stencil", tile_clear_reg("tile_clear_stencil", REG_TILE_CLEAR_STENCIL_ADDR, 8));
+
+    //  Each tile buffer has a mem per bank. The pixel pipe and bus accesses get the ports of the render bank, and tile
+    //   transfers get the ports of the other, transfer bank. Bus accesses only happen while the rasterizer is idle, so
+    //   they take priority over the pixel pipe. Returns the read values for each side.
+    let tile_buffer_banks = |
+        name: &str,
+        address_bit_width: u32,
+        element_bit_width: u32,
+        elements_per_word: u32,
+        bus_enable: &'a Signal<'a>,
+        bus_addr: &'a Signal<'a>,
+        bus_write: &'a Signal<'a>,
+        bus_write_data: &'a Signal<'a>,
+        bus_write_element_enable: &'a Signal<'a>,
+        transfer_enable: &'a Signal<'a>,
+    | {
+        let bus_write_enable = bus_enable & bus_write;
+        let bus_read_enable = bus_enable & !bus_write;
+
+        let render_write_addr = bus_write_enable.mux(bus_addr, pixel_pipe.output(format!("{}_write_port_addr", name)));
+        let render_write_value = bus_write_enable.mux(bus_write_data, pixel_pipe.output(format!("{}_write_port_value", name)));
+        let render_write_enable = bus_write_enable | pixel_pipe.output(format!("{}_write_port_enable", name));
+        let render_write_element_enable = bus_write_enable.mux(bus_write_element_enable, pixel_pipe.output(format!("{}_write_port_word_enable", name)));
+        let render_read_addr = bus_read_enable.mux(bus_addr, pixel_pipe.output(format!("{}_read_port_addr", name)));
+        let render_read_enable = bus_read_enable | pixel_pipe.output(format!("{}_read_port_enable", name));
+
+        let transfer_addr = tile_transfer.output("tile_buffer_addr").bits(address_bit_width - 1, 0);
+        let transfer_write_enable = transfer_enable & tile_transfer.output("tile_buffer_write");
+        let transfer_read_enable = transfer_enable & !tile_transfer.output("tile_buffer_write");
+        let transfer_write_data = tile_transfer.output("tile_buffer_write_data");
+        let transfer_write_element_enable = m.lit(!0u32, 32).bits(elements_per_word - 1, 0);
+
+        let read_values = (0..2).map(|bank| {
+            let is_render_bank = render_bank.eq(m.lit(bank as u32, 1));
+            let mem = WordMem::new(m, format!("{}_bank{}", name, bank), address_bit_width, element_bit_width, elements_per_word);
+            mem.write_port(
+                is_render_bank.mux(render_write_addr, transfer_addr),
+                is_render_bank.mux(render_write_value, transfer_write_data),
+                is_render_bank.mux(render_write_enable, transfer_write_enable),
+                is_render_bank.mux(render_write_element_enable, transfer_write_element_enable));
+            mem.read_port(
+                is_render_bank.mux(render_read_addr, transfer_addr),
+                is_render_bank.mux(render_read_enable, transfer_read_enable))
+        }).collect::<Vec<_>>();
+
+        (render_bank.mux(read_values[1], read_values[0]), render_bank.mux(read_values[0], read_values[1]))
+    };
+
     m.output("color_buffer_bus_ready", m.high());
     let color_buffer_bus_enable = m.input("color_buffer_bus_enable", 1);
     let color_buffer_bus_addr = m.input("color_buffer_bus_addr", TILE_PIXELS_WORDS_BITS);
@@ -321,39 +1190,23 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
         })
     }).unwrap();
 
-    let color_buffer = WordMem::new(m, "color_buffer", TILE_PIXELS_WORDS_BITS, 32, 4);
-    let color_buffer_bus_write_enable = color_buffer_bus_enable & color_buffer_bus_write;
-    color_buffer.write_port(
-        if_(color_buffer_bus_write_enable, {
-            color_buffer_bus_addr
-        }).else_({
-            pixel_pipe.output("color_buffer_write_port_addr")
-        }),
-        if_(color_buffer_bus_write_enable, {
-            color_buffer_bus_write_data
-        }).else_({
-            pixel_pipe.output("color_buffer_write_port_value")
-        }),
-        color_buffer_bus_write_enable | pixel_pipe.output("color_buffer_write_port_enable"),
-        if_(color_buffer_bus_write_enable, {
-            color_buffer_bus_write_word_enable
-        }).else_({
-            pixel_pipe.output("color_buffer_write_port_word_enable")
-        }));
-
-    let color_buffer_bus_read_enable = color_buffer_bus_enable & !color_buffer_bus_write;
-    let color_buffer_read_port_value = color_buffer.read_port(
-        if_(color_buffer_bus_read_enable, {
-            color_buffer_bus_addr
-        }).else_({
-            pixel_pipe.output("color_buffer_read_port_addr")
-        }),
-        color_buffer_bus_read_enable | pixel_pipe.output("color_buffer_read_port_enable"));
+    let (color_buffer_read_port_value, color_buffer_transfer_read_value) = tile_buffer_banks(
+        "color_buffer",
+        TILE_PIXELS_WORDS_BITS,
+        32,
+        4,
+        color_buffer_bus_enable,
+        color_buffer_bus_addr,
+        color_buffer_bus_write,
+        color_buffer_bus_write_data,
+        color_buffer_bus_write_word_enable,
+        tile_transfer.output("color_buffer_enable"));
 
     pixel_pipe.drive_input("color_buffer_read_port_value", color_buffer_read_port_value);
+    tile_transfer.drive_input("color_buffer_read_value", color_buffer_transfer_read_value);
 
     m.output("color_buffer_bus_read_data", color_buffer_read_port_value);
-    m.output("color_buffer_bus_read_data_valid", color_buffer_bus_read_enable.reg_next_with_default("color_buffer_bus_read_data_valid", false));
+    m.output("color_buffer_bus_read_data_valid", (color_buffer_bus_enable & !color_buffer_bus_write).reg_next_with_default("color_buffer_bus_read_data_valid", false));
 
     m.output("depth_buffer_bus_ready", m.high());
     let depth_buffer_bus_enable = m.input("depth_buffer_bus_enable", 1);
@@ -370,46 +1223,105 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
         })
     }).unwrap();
 
-    let depth_buffer = WordMem::new(m, "depth_buffer", TILE_PIXELS_WORDS_BITS - 1, 16, 8);
-    let depth_buffer_bus_write_enable = depth_buffer_bus_enable & depth_buffer_bus_write;
-    depth_buffer.write_port(
-        if_(depth_buffer_bus_write_enable, {
-            depth_buffer_bus_addr
-        }).else_({
-            pixel_pipe.output("depth_buffer_write_port_addr")
-        }),
-        if_(depth_buffer_bus_write_enable, {
-            depth_buffer_bus_write_data
-        }).else_({
-            pixel_pipe.output("depth_buffer_write_port_value")
-        }),
-        depth_buffer_bus_write_enable | pixel_pipe.output("depth_buffer_write_port_enable"),
-        if_(depth_buffer_bus_write_enable, {
-            depth_buffer_bus_write_word_enable
-        }).else_({
-            pixel_pipe.output("depth_buffer_write_port_word_enable")
-        }));
-
-    let depth_buffer_bus_read_enable = depth_buffer_bus_enable & !depth_buffer_bus_write;
-    let depth_buffer_read_port_value = depth_buffer.read_port(
-        if_(depth_buffer_bus_read_enable, {
-            depth_buffer_bus_addr
-        }).else_({
-            pixel_pipe.output("depth_buffer_read_port_addr")
-        }),
-        depth_buffer_bus_read_enable | pixel_pipe.output("depth_buffer_read_port_enable"));
+    let (depth_buffer_read_port_value, depth_buffer_transfer_read_value) = tile_buffer_banks(
+        "depth_buffer",
+        TILE_PIXELS_WORDS_BITS - 1,
+        16,
+        8,
+        depth_buffer_bus_enable,
+        depth_buffer_bus_addr,
+        depth_buffer_bus_write,
+        depth_buffer_bus_write_data,
+        depth_buffer_bus_write_word_enable,
+        tile_transfer.output("depth_buffer_enable"));
 
     pixel_pipe.drive_input("depth_buffer_read_port_value", depth_buffer_read_port_value);
+    tile_transfer.drive_input("depth_buffer_read_value", depth_buffer_transfer_read_value);
 
     m.output("depth_buffer_bus_read_data", depth_buffer_read_port_value);
-    m.output("depth_buffer_bus_read_data_valid", depth_buffer_bus_read_enable.reg_next_with_default("depth_buffer_bus_read_data_valid", false));
+    m.output("depth_buffer_bus_read_data_valid", (depth_buffer_bus_enable & !depth_buffer_bus_write).reg_next_with_default("depth_buffer_bus_read_data_valid", false));
+
+    m.output("stencil_buffer_bus_ready", m.high());
+    let stencil_buffer_bus_enable = m.input("stencil_buffer_bus_enable", 1);
+    let stencil_buffer_bus_addr = m.input("stencil_buffer_bus_addr", TILE_PIXELS_WORDS_BITS - 2);
+    let stencil_buffer_bus_write = m.input("stencil_buffer_bus_write", 1);
+    let stencil_buffer_bus_write_data = m.input("stencil_buffer_bus_write_data", 128);
+    let stencil_buffer_bus_write_byte_enable = m.input("stencil_buffer_bus_write_byte_enable", 16);
+
+    let (stencil_buffer_read_port_value, stencil_buffer_transfer_read_value) = tile_buffer_banks(
+        "stencil_buffer",
+        TILE_PIXELS_WORDS_BITS - 2,
+        8,
+        16,
+        stencil_buffer_bus_enable,
+        stencil_buffer_bus_addr,
+        stencil_buffer_bus_write,
+        stencil_buffer_bus_write_data,
+        stencil_buffer_bus_write_byte_enable,
+        tile_transfer.output("stencil_buffer_enable"));
+
+    pixel_pipe.drive_input("stencil_buffer_read_port_value", stencil_buffer_read_port_value);
+    tile_transfer.drive_input("stencil_buffer_read_value", stencil_buffer_transfer_read_value);
+
+    m.output("stencil_buffer_bus_read_data", stencil_buffer_read_port_value);
+    m.output("stencil_buffer_bus_read_data_valid", (stencil_buffer_bus_enable & !stencil_buffer_bus_write).reg_next_with_default("stencil_buffer_bus_read_data_valid", false));
 
     pixel_pipe.drive_input("tex_cache_invalidate", tex_cache_invalidate);
-    pixel_pipe.drive_input("replica_bus_ready", m.input("replica_bus_ready", 1));
-    m.output("replica_bus_enable", pixel_pipe.output("replica_bus_enable"));
-    m.output("replica_bus_addr", pixel_pipe.output("replica_bus_addr"));
-    pixel_pipe.drive_input("replica_bus_read_data", m.input("replica_bus_read_data", 128));
-    pixel_pipe.drive_input("replica_bus_read_data_valid", m.input("replica_bus_read_data_valid", 1));
+
+    //  The tex cache, command processor, vertex transform unit, binner, and tile transfer unit share the replica bus
+    buster::generate(c, "ReplicaCrossbar", 5, 1, TEX_WORD_ADDR_BITS, 0, 128, 5);
+    let replica_crossbar = m.instance("replica_crossbar", "ReplicaCrossbar");
+    replica_crossbar.drive_input("replica0_bus_ready", m.input("replica_bus_ready", 1));
+    m.output("replica_bus_enable", replica_crossbar.output("replica0_bus_enable"));
+    m.output("replica_bus_addr", replica_crossbar.output("replica0_bus_addr"));
+    m.output("replica_bus_write", replica_crossbar.output("replica0_bus_write"));
+    m.output("replica_bus_write_data", replica_crossbar.output("replica0_bus_write_data"));
+    m.output("replica_bus_write_byte_enable", replica_crossbar.output("replica0_bus_write_byte_enable"));
+    replica_crossbar.drive_input("replica0_bus_read_data", m.input("replica_bus_read_data", 128));
+    replica_crossbar.drive_input("replica0_bus_read_data_valid", m.input("replica_bus_read_data_valid", 1));
+
+    pixel_pipe.drive_input("replica_bus_ready", replica_crossbar.output("primary0_bus_ready"));
+    replica_crossbar.drive_input("primary0_bus_enable", pixel_pipe.output("replica_bus_enable"));
+    replica_crossbar.drive_input("primary0_bus_addr", pixel_pipe.output("replica_bus_addr"));
+    replica_crossbar.drive_input("primary0_bus_write", m.low());
+    replica_crossbar.drive_input("primary0_bus_write_data", m.lit(0u32, 128));
+    replica_crossbar.drive_input("primary0_bus_write_byte_enable", m.lit(0u32, 16));
+    pixel_pipe.drive_input("replica_bus_read_data", replica_crossbar.output("primary0_bus_read_data"));
+    pixel_pipe.drive_input("replica_bus_read_data_valid", replica_crossbar.output("primary0_bus_read_data_valid"));
+
+    command_processor.drive_input("replica_bus_ready", replica_crossbar.output("primary1_bus_ready"));
+    replica_crossbar.drive_input("primary1_bus_enable", command_processor.output("replica_bus_enable"));
+    replica_crossbar.drive_input("primary1_bus_addr", command_processor.output("replica_bus_addr"));
+    replica_crossbar.drive_input("primary1_bus_write", command_processor.output("replica_bus_write"));
+    replica_crossbar.drive_input("primary1_bus_write_data", command_processor.output("replica_bus_write_data"));
+    replica_crossbar.drive_input("primary1_bus_write_byte_enable", command_processor.output("replica_bus_write_byte_enable"));
+    command_processor.drive_input("replica_bus_read_data", replica_crossbar.output("primary1_bus_read_data"));
+    command_processor.drive_input("replica_bus_read_data_valid", replica_crossbar.output("primary1_bus_read_data_valid"));
+
+    vertex_transform.drive_input("replica_bus_ready", replica_crossbar.output("primary2_bus_ready"));
+    replica_crossbar.drive_input("primary2_bus_enable", vertex_transform.output("replica_bus_enable"));
+    replica_crossbar.drive_input("primary2_bus_addr", vertex_transform.output("replica_bus_addr"));
+    replica_crossbar.drive_input("primary2_bus_write", m.low());
+    replica_crossbar.drive_input("primary2_bus_write_data", m.lit(0u32, 128));
+    replica_crossbar.drive_input("primary2_bus_write_byte_enable", m.lit(0u32, 16));
+    vertex_transform.drive_input("replica_bus_read_data", replica_crossbar.output("primary2_bus_read_data"));
+    vertex_transform.drive_input("replica_bus_read_data_valid", replica_crossbar.output("primary2_bus_read_data_valid"));
+
+    binner.drive_input("replica_bus_ready", replica_crossbar.output("primary3_bus_ready"));
+    replica_crossbar.drive_input("primary3_bus_enable", binner.output("replica_bus_enable"));
+    replica_crossbar.drive_input("primary3_bus_addr", binner.output("replica_bus_addr"));
+    replica_crossbar.drive_input("primary3_bus_write", binner.output("replica_bus_write"));
+    replica_crossbar.drive_input("primary3_bus_write_data", binner.output("replica_bus_write_data"));
+    replica_crossbar.drive_input("primary3_bus_write_byte_enable", binner.output("replica_bus_write_byte_enable"));
+
+    tile_transfer.drive_input("replica_bus_ready", replica_crossbar.output("primary4_bus_ready"));
+    replica_crossbar.drive_input("primary4_bus_enable", tile_transfer.output("replica_bus_enable"));
+    replica_crossbar.drive_input("primary4_bus_addr", tile_transfer.output("replica_bus_addr"));
+    replica_crossbar.drive_input("primary4_bus_write", tile_transfer.output("replica_bus_write"));
+    replica_crossbar.drive_input("primary4_bus_write_data", tile_transfer.output("replica_bus_write_data"));
+    replica_crossbar.drive_input("primary4_bus_write_byte_enable", tile_transfer.output("replica_bus_write_byte_enable"));
+    tile_transfer.drive_input("replica_bus_read_data", replica_crossbar.output("primary4_bus_read_data"));
+    tile_transfer.drive_input("replica_bus_read_data_valid", replica_crossbar.output("primary4_bus_read_data_valid"));
 
     m
 }
@@ -418,13 +1330,20 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let m = c.module("PixelPipe");
 
     // Control
-    let start = m.input("start", 1);
-
-    let active = m.reg("active", 1);
-    active.default_value(false);
-    m.output("active", active.value);
-
-    let finished_pixel_acc = m.reg("finished_pixel_acc", TILE_PIXELS_BITS + 1);
+    //  Pixels from different primitives can be in flight at the same time, so the tile addrs of in-flight pixels are kept
+    //   in a small CAM. Each slot is taken when a pixel enters the pipe and freed when it finishes. The pipe is active
+    //   while any slot is taken.
+    let in_flight_slots = (0..PIXEL_PIPE_IN_FLIGHT_SLOTS).map(|i| {
+        let valid = m.reg(format!("in_flight_slot_{}_valid", i), 1);
+        valid.default_value(false);
+        let tile_addr = m.reg(format!("in_flight_slot_{}_tile_addr", i), TILE_PIXELS_BITS);
+        (valid, tile_addr)
+    }).collect::<Vec<_>>();
+    m.output("active", in_flight_slots.iter().fold(m.low(), |acc, (valid, _)| acc | valid.value));
+    let in_flight_full = in_flight_slots.iter().fold(m.high(), |acc, (valid, _)| acc & valid.value);
+    let in_flight_free_slot = in_flight_slots.iter().enumerate().rev().fold(m.lit(0u32, PIXEL_PIPE_IN_FLIGHT_SLOTS_BITS), |acc, (i, (valid, _))| {
+        valid.value.mux(acc, m.lit(i as u32, PIXEL_PIPE_IN_FLIGHT_SLOTS_BITS))
+    });
 
     // Inputs
     let valid = m.input("in_valid", 1);
@@ -438,6 +1357,13 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let edge_test_reject = valid & !edge_test;
     let valid = valid & edge_test;
 
+    //  A pixel waits until any earlier pixel at the same addr has finished, so it sees that pixel's tile buffer writes, and
+    //   until there's a free slot to track it
+    let in_flight_hazard = in_flight_slots.iter().fold(in_flight_full, |acc, (valid, slot_tile_addr)| {
+        acc | (valid.value & slot_tile_addr.value.eq(tile_addr))
+    });
+    let valid = valid & !in_flight_hazard;
+
     let r = m.input("in_r", COLOR_WHOLE_BITS);
     let g = m.input("in_g", COLOR_WHOLE_BITS);
     let b = m.input("in_b", COLOR_WHOLE_BITS);
@@ -450,6 +1376,8 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let s = m.input("in_s", 32 - RESTORED_W_FRACT_BITS);
     let t = m.input("in_t", 32 - RESTORED_W_FRACT_BITS);
 
+    let tex_lod_base = m.input("in_tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+
     // Depth test pipe
     generate_depth_test_pipe(c);
     let mut depth_test_pipe = flow_controlled_pipe::FlowControlledPipe::new(
@@ -460,12 +1388,26 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     //  Aux
     depth_test_pipe.aux_input("depth_test_enable", 1);
+    depth_test_pipe.aux_input("depth_func", REG_DEPTH_SETTINGS_FUNC_BITS);
+
+    depth_test_pipe.aux_input("stencil_test_enable", 1);
+    depth_test_pipe.aux_input("stencil_func", REG_STENCIL_SETTINGS_FUNC_BITS);
+    depth_test_pipe.aux_input("stencil_ref", REG_STENCIL_SETTINGS_REF_BITS);
+    depth_test_pipe.aux_input("stencil_mask", REG_STENCIL_SETTINGS_MASK_BITS);
+    depth_test_pipe.aux_input("stencil_sfail_op", REG_STENCIL_SETTINGS_OP_BITS);
+    depth_test_pipe.aux_input("stencil_dpfail_op", REG_STENCIL_SETTINGS_OP_BITS);
+    depth_test_pipe.aux_input("stencil_dppass_op", REG_STENCIL_SETTINGS_OP_BITS);
 
     depth_test_pipe.aux_output("depth_buffer_read_port_addr");
     depth_test_pipe.aux_output("depth_buffer_read_port_enable");
 
     depth_test_pipe.aux_input("depth_buffer_read_port_value", 128);
 
+    depth_test_pipe.aux_output("stencil_buffer_read_port_addr");
+    depth_test_pipe.aux_output("stencil_buffer_read_port_enable");
+
+    depth_test_pipe.aux_input("stencil_buffer_read_port_value", 128);
+
     //  Inputs
     depth_test_pipe.input("tile_addr", TILE_PIXELS_BITS);
 
@@ -481,6 +1423,8 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     depth_test_pipe.input("s", 32 - RESTORED_W_FRACT_BITS);
     depth_test_pipe.input("t", 32 - RESTORED_W_FRACT_BITS);
 
+    depth_test_pipe.input("tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+
     //  Outputs
     depth_test_pipe.output("tile_addr", TILE_PIXELS_BITS);
 
@@ -496,20 +1440,43 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     depth_test_pipe.output("s", 32 - RESTORED_W_FRACT_BITS);
     depth_test_pipe.output("t", 32 - RESTORED_W_FRACT_BITS);
 
+    depth_test_pipe.output("tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+
     depth_test_pipe.output("depth_test_result", 1);
 
+    depth_test_pipe.output("stencil", 8);
+    depth_test_pipe.output("stencil_test_result", 1);
+    depth_test_pipe.output("stencil_write", 1);
+
     let depth_test_pipe = m.instance("depth_test_pipe", "FlowControlledDepthTestPipe");
 
-    m.output("in_ready", depth_test_pipe.output("in_ready") | edge_test_reject);
+    m.output("in_ready", (depth_test_pipe.output("in_ready") & !in_flight_hazard) | edge_test_reject);
+    let pixel_accept = valid & depth_test_pipe.output("in_ready");
+    let pixel_accept_tile_addr = tile_addr;
 
     //  Aux
     depth_test_pipe.drive_input("depth_test_enable", m.input("depth_test_enable", 1));
+    depth_test_pipe.drive_input("depth_func", m.input("depth_func", REG_DEPTH_SETTINGS_FUNC_BITS));
 
     m.output("depth_buffer_read_port_addr", depth_test_pipe.output("depth_buffer_read_port_addr"));
     m.output("depth_buffer_read_port_enable", depth_test_pipe.output("depth_buffer_read_port_enable"));
 
     depth_test_pipe.drive_input("depth_buffer_read_port_value", m.input("depth_buffer_read_port_value", 128));
 
+    let stencil_test_enable = m.input("stencil_test_enable", 1);
+    depth_test_pipe.drive_input("stencil_test_enable", stencil_test_enable);
+    depth_test_pipe.drive_input("stencil_func", m.input("stencil_func", REG_STENCIL_SETTINGS_FUNC_BITS));
+    depth_test_pipe.drive_input("stencil_ref", m.input("stencil_ref", REG_STENCIL_SETTINGS_REF_BITS));
+    depth_test_pipe.drive_input("stencil_mask", m.input("stencil_mask", REG_STENCIL_SETTINGS_MASK_BITS));
+    depth_test_pipe.drive_input("stencil_sfail_op", m.input("stencil_sfail_op", REG_STENCIL_SETTINGS_OP_BITS));
+    depth_test_pipe.drive_input("stencil_dpfail_op", m.input("stencil_dpfail_op", REG_STENCIL_SETTINGS_OP_BITS));
+    depth_test_pipe.drive_input("stencil_dppass_op", m.input("stencil_dppass_op", REG_STENCIL_SETTINGS_OP_BITS));
+
+    m.output("stencil_buffer_read_port_addr", depth_test_pipe.output("stencil_buffer_read_port_addr"));
+    m.output("stencil_buffer_read_port_enable", depth_test_pipe.output("stencil_buffer_read_port_enable"));
+
+    depth_test_pipe.drive_input("stencil_buffer_read_port_value", m.input("stencil_buffer_read_port_value", 128));
+
     //  Inputs
     depth_test_pipe.drive_input("in_valid", valid);
     depth_test_pipe.drive_input("in_tile_addr", tile_addr);
@@ -526,9 +1493,12 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     depth_test_pipe.drive_input("in_s", s);
     depth_test_pipe.drive_input("in_t", t);
 
+    depth_test_pipe.drive_input("in_tex_lod_base", tex_lod_base);
+
     //  Outputs
     let valid = depth_test_pipe.output("out_valid");
     let tile_addr = depth_test_pipe.output("out_tile_addr");
+    let depth_test_tile_addr = tile_addr;
 
     let r = depth_test_pipe.output("out_r");
     let g = depth_test_pipe.output("out_g");
@@ -542,11 +1512,16 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let s = depth_test_pipe.output("out_s");
     let t = depth_test_pipe.output("out_t");
 
-    let depth_test_result = depth_test_pipe.output("out_depth_test_result");
+    let tex_lod_base = depth_test_pipe.output("out_tex_lod_base");
+
+    let stencil = depth_test_pipe.output("out_stencil");
 
-    // Reject pixel if it doesn't pass depth test before entering the next pipe
-    let depth_test_reject = valid & !depth_test_result;
-    let valid = valid & depth_test_result;
+    //  From here on, depth_test_result covers both the stencil and depth tests
+    let depth_test_result = depth_test_pipe.output("out_depth_test_result") & depth_test_pipe.output("out_stencil_test_result");
+
+    // Reject pixel if it doesn't pass depth test before entering the next pipe, unless it still has to update stencil
+    let depth_test_reject = valid & !depth_test_result & !depth_test_pipe.output("out_stencil_write");
+    let valid = valid & !depth_test_reject;
 
     // Front pipe
     generate_front_pipe(c);
@@ -558,8 +1533,17 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     //  Aux
     front_pipe.aux_input("tex_filter_select", 1);
-    front_pipe.aux_input("tex_dim", 2);
+    front_pipe.aux_input("tex_width", REG_TEXTURE_SETTINGS_DIM_BITS);
+    front_pipe.aux_input("tex_height", REG_TEXTURE_SETTINGS_DIM_BITS);
     front_pipe.aux_input("tex_base", TEX_PIXEL_ADDR_BITS - 8);
+    front_pipe.aux_input("tex_wrap_s", REG_TEXTURE_SETTINGS_WRAP_BITS);
+    front_pipe.aux_input("tex_wrap_t", REG_TEXTURE_SETTINGS_WRAP_BITS);
+    front_pipe.aux_input("tex_mip_mode", REG_TEXTURE_SETTINGS_MIP_MODE_BITS);
+    front_pipe.aux_input("tex_max_level", REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS);
+    front_pipe.aux_input("tex_lod_bias", REG_TEXTURE_LOD_BIAS_BITS);
+    front_pipe.aux_input("tex_format", REG_TEXTURE_SETTINGS_FORMAT_BITS);
+
+    front_pipe.aux_input("fog_coord_select", 1);
 
     //  Inputs
     front_pipe.input("tile_addr", TILE_PIXELS_BITS);
@@ -576,8 +1560,12 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     front_pipe.input("s", 32 - RESTORED_W_FRACT_BITS);
     front_pipe.input("t", 32 - RESTORED_W_FRACT_BITS);
 
+    front_pipe.input("tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+
     front_pipe.input("depth_test_result", 1);
 
+    front_pipe.input("stencil", 8);
+
     //  Outputs
     front_pipe.output("tile_addr", TILE_PIXELS_BITS);
 
@@ -588,17 +1576,26 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     front_pipe.output("z", 16);
 
+    front_pipe.output("fog_coord", FOG_COORD_BITS);
+
     front_pipe.output("depth_test_result", 1);
 
-    front_pipe.output("s_fract", ST_FILTER_FRACT_BITS + 1);
-    front_pipe.output("one_minus_s_fract", ST_FILTER_FRACT_BITS + 1);
-    front_pipe.output("t_fract", ST_FILTER_FRACT_BITS + 1);
-    front_pipe.output("one_minus_t_fract", ST_FILTER_FRACT_BITS + 1);
+    front_pipe.output("stencil", 8);
 
-    front_pipe.output("tex_buffer0_read_addr", TEX_PIXEL_ADDR_BITS);
-    front_pipe.output("tex_buffer1_read_addr", TEX_PIXEL_ADDR_BITS);
-    front_pipe.output("tex_buffer2_read_addr", TEX_PIXEL_ADDR_BITS);
-    front_pipe.output("tex_buffer3_read_addr", TEX_PIXEL_ADDR_BITS);
+    for level in 0..2 {
+        front_pipe.output(format!("level{}_s_fract", level), ST_FILTER_FRACT_BITS + 1);
+        front_pipe.output(format!("level{}_one_minus_s_fract", level), ST_FILTER_FRACT_BITS + 1);
+        front_pipe.output(format!("level{}_t_fract", level), ST_FILTER_FRACT_BITS + 1);
+        front_pipe.output(format!("level{}_one_minus_t_fract", level), ST_FILTER_FRACT_BITS + 1);
+    }
+    front_pipe.output("lod_fract", TEX_LOD_FRACT_BITS + 1);
+    front_pipe.output("one_minus_lod_fract", TEX_LOD_FRACT_BITS + 1);
+
+    for i in 0..8 {
+        front_pipe.output(format!("tex_buffer{}_read_addr", i), TEX_PIXEL_ADDR_BITS);
+    }
+    front_pipe.output("tex_buffer_border", 8);
+    front_pipe.output("tex_buffer_texel_select", 8 * TEX_TEXEL_SELECT_BITS);
 
     let front_pipe = m.instance("front_pipe", "FlowControlledFrontPipe");
 
@@ -606,8 +1603,18 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     //  Aux
     front_pipe.drive_input("tex_filter_select", m.input("tex_filter_select", 1));
-    front_pipe.drive_input("tex_dim", m.input("tex_dim", 2));
+    front_pipe.drive_input("tex_width", m.input("tex_width", REG_TEXTURE_SETTINGS_DIM_BITS));
+    front_pipe.drive_input("tex_height", m.input("tex_height", REG_TEXTURE_SETTINGS_DIM_BITS));
     front_pipe.drive_input("tex_base", m.input("tex_base", TEX_PIXEL_ADDR_BITS - 8));
+    front_pipe.drive_input("tex_wrap_s", m.input("tex_wrap_s", REG_TEXTURE_SETTINGS_WRAP_BITS));
+    front_pipe.drive_input("tex_wrap_t", m.input("tex_wrap_t", REG_TEXTURE_SETTINGS_WRAP_BITS));
+    front_pipe.drive_input("tex_mip_mode", m.input("tex_mip_mode", REG_TEXTURE_SETTINGS_MIP_MODE_BITS));
+    front_pipe.drive_input("tex_max_level", m.input("tex_max_level", REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS));
+    front_pipe.drive_input("tex_lod_bias", m.input("tex_lod_bias", REG_TEXTURE_LOD_BIAS_BITS));
+    let tex_format = m.input("tex_format", REG_TEXTURE_SETTINGS_FORMAT_BITS);
+    front_pipe.drive_input("tex_format", tex_format);
+
+    front_pipe.drive_input("fog_coord_select", m.input("fog_coord_select", 1));
 
     //  Inputs
     front_pipe.drive_input("in_valid", valid);
@@ -625,8 +1632,12 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     front_pipe.drive_input("in_s", s);
     front_pipe.drive_input("in_t", t);
 
+    front_pipe.drive_input("in_tex_lod_base", tex_lod_base);
+
     front_pipe.drive_input("in_depth_test_result", depth_test_result);
 
+    front_pipe.drive_input("in_stencil", stencil);
+
     //  Outputs
     let valid = front_pipe.output("out_valid");
     let tile_addr = front_pipe.output("out_tile_addr");
@@ -638,12 +1649,12 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let z = front_pipe.output("out_z");
 
+    let fog_coord = front_pipe.output("out_fog_coord");
+
     let depth_test_result = front_pipe.output("out_depth_test_result");
 
-    let s_fract = front_pipe.output("out_s_fract");
-    let one_minus_s_fract = front_pipe.output("out_one_minus_s_fract");
-    let t_fract = front_pipe.output("out_t_fract");
-    let one_minus_t_fract = front_pipe.output("out_one_minus_t_fract");
+    let stencil = front_pipe.output("out_stencil");
+
 
     // Tex cache
     tex_cache::generate(c);
@@ -671,14 +1682,24 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     tex_cache.drive_input("in_z", z);
 
+    tex_cache.drive_input("in_fog_coord", fog_coord);
+
     tex_cache.drive_input("in_depth_test_result", depth_test_result);
 
-    tex_cache.drive_input("in_s_fract", s_fract);
-    tex_cache.drive_input("in_one_minus_s_fract", one_minus_s_fract);
-    tex_cache.drive_input("in_t_fract", t_fract);
-    tex_cache.drive_input("in_one_minus_t_fract", one_minus_t_fract);
+    tex_cache.drive_input("in_stencil", stencil);
 
-    for i in 0..4 {
+    for name in ["s_fract", "one_minus_s_fract", "t_fract", "one_minus_t_fract"].iter() {
+        for level in 0..2 {
+            tex_cache.drive_input(format!("in_level{}_{}", level, name), front_pipe.output(format!("out_level{}_{}", level, name)));
+        }
+    }
+    tex_cache.drive_input("in_lod_fract", front_pipe.output("out_lod_fract"));
+    tex_cache.drive_input("in_one_minus_lod_fract", front_pipe.output("out_one_minus_lod_fract"));
+
+    tex_cache.drive_input("in_tex_buffer_border", front_pipe.output("out_tex_buffer_border"));
+    tex_cache.drive_input("in_tex_buffer_texel_select", front_pipe.output("out_tex_buffer_texel_select"));
+
+    for i in 0..8 {
         tex_cache.drive_input(format!("in_tex_buffer{}_read_addr", i), front_pipe.output(format!("out_tex_buffer{}_read_addr", i)));
     }
 
@@ -693,12 +1714,11 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let z = tex_cache.output("out_z");
 
+    let fog_coord = tex_cache.output("out_fog_coord");
+
     let depth_test_result = tex_cache.output("out_depth_test_result");
 
-    let s_fract = tex_cache.output("out_s_fract");
-    let one_minus_s_fract = tex_cache.output("out_one_minus_s_fract");
-    let t_fract = tex_cache.output("out_t_fract");
-    let one_minus_t_fract = tex_cache.output("out_one_minus_t_fract");
+    let stencil = tex_cache.output("out_stencil");
 
     // Back pipe
     generate_back_pipe(c);
@@ -707,8 +1727,32 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     //  Aux
     back_pipe.drive_input("depth_write_mask_enable", m.input("depth_write_mask_enable", 1));
 
+    back_pipe.drive_input("stencil_test_enable", stencil_test_enable);
+
+    back_pipe.drive_input("alpha_test_enable", m.input("alpha_test_enable", 1));
+    back_pipe.drive_input("alpha_test_func", m.input("alpha_test_func", REG_ALPHA_TEST_SETTINGS_FUNC_BITS));
+    back_pipe.drive_input("alpha_test_ref", m.input("alpha_test_ref", REG_ALPHA_TEST_SETTINGS_REF_BITS));
+
+    back_pipe.drive_input("fog_enable", m.input("fog_enable", 1));
+    back_pipe.drive_input("fog_mode", m.input("fog_mode", REG_FOG_SETTINGS_MODE_BITS));
+    back_pipe.drive_input("fog_color", m.input("fog_color", 32));
+    back_pipe.drive_input("fog_start", m.input("fog_start", FOG_COORD_BITS));
+    back_pipe.drive_input("fog_scale", m.input("fog_scale", FOG_COORD_BITS));
+    back_pipe.drive_input("fog_table_write_addr", m.input("fog_table_write_addr", FOG_TABLE_INDEX_BITS));
+    back_pipe.drive_input("fog_table_write_data", m.input("fog_table_write_data", FOG_AMOUNT_BITS));
+    back_pipe.drive_input("fog_table_write_enable", m.input("fog_table_write_enable", 1));
+
+    back_pipe.drive_input("tex_border_color", m.input("tex_border_color", 32));
+    back_pipe.drive_input("tex_format", tex_format);
+    back_pipe.drive_input("tex_combine_mode", m.input("tex_combine_mode", REG_TEXTURE_SETTINGS_COMBINE_MODE_BITS));
+    back_pipe.drive_input("tex_palette_write_addr", m.input("tex_palette_write_addr", TEX_PALETTE_INDEX_BITS));
+    back_pipe.drive_input("tex_palette_write_data", m.input("tex_palette_write_data", 32));
+    back_pipe.drive_input("tex_palette_write_enable", m.input("tex_palette_write_enable", 1));
+
     back_pipe.drive_input("blend_src_factor", m.input("blend_src_factor", REG_BLEND_SETTINGS_SRC_FACTOR_BITS));
     back_pipe.drive_input("blend_dst_factor", m.input("blend_dst_factor", REG_BLEND_SETTINGS_DST_FACTOR_BITS));
+    back_pipe.drive_input("blend_op", m.input("blend_op", REG_BLEND_SETTINGS_OP_BITS));
+    back_pipe.drive_input("blend_constant", m.input("blend_constant", 32));
 
     m.output("color_buffer_read_port_addr", back_pipe.output("color_buffer_read_port_addr"));
     m.output("color_buffer_read_port_enable", back_pipe.output("color_buffer_read_port_enable"));
@@ -725,6 +1769,11 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     m.output("depth_buffer_write_port_enable", back_pipe.output("depth_buffer_write_port_enable"));
     m.output("depth_buffer_write_port_word_enable", back_pipe.output("depth_buffer_write_port_word_enable"));
 
+    m.output("stencil_buffer_write_port_addr", back_pipe.output("stencil_buffer_write_port_addr"));
+    m.output("stencil_buffer_write_port_value", back_pipe.output("stencil_buffer_write_port_value"));
+    m.output("stencil_buffer_write_port_enable", back_pipe.output("stencil_buffer_write_port_enable"));
+    m.output("stencil_buffer_write_port_word_enable", back_pipe.output("stencil_buffer_write_port_word_enable"));
+
     //  Inputs
     back_pipe.drive_input("in_valid", valid);
     back_pipe.drive_input("in_tile_addr", tile_addr);
@@ -736,53 +1785,44 @@ pub fn generate_pixel_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     back_pipe.drive_input("in_z", z);
 
+    back_pipe.drive_input("in_fog_coord", fog_coord);
+
     back_pipe.drive_input("in_depth_test_result", depth_test_result);
 
-    back_pipe.drive_input("in_s_fract", s_fract);
-    back_pipe.drive_input("in_one_minus_s_fract", one_minus_s_fract);
-    back_pipe.drive_input("in_t_fract", t_fract);
-    back_pipe.drive_input("in_one_minus_t_fract", one_minus_t_fract);
+    back_pipe.drive_input("in_stencil", stencil);
+
+    for name in ["s_fract", "one_minus_s_fract", "t_fract", "one_minus_t_fract"].iter() {
+        for level in 0..2 {
+            back_pipe.drive_input(format!("in_level{}_{}", level, name), tex_cache.output(format!("out_level{}_{}", level, name)));
+        }
+    }
+    back_pipe.drive_input("in_lod_fract", tex_cache.output("out_lod_fract"));
+    back_pipe.drive_input("in_one_minus_lod_fract", tex_cache.output("out_one_minus_lod_fract"));
 
-    for i in 0..4 {
+    for i in 0..8 {
         back_pipe.drive_input(format!("in_tex_buffer{}_read_value", i), tex_cache.output(format!("out_tex_buffer{}_read_value", i)));
     }
+    back_pipe.drive_input("in_tex_buffer_border", tex_cache.output("out_tex_buffer_border"));
+    back_pipe.drive_input("in_tex_buffer_texel_select", tex_cache.output("out_tex_buffer_texel_select"));
 
     //  Outputs
     let valid = back_pipe.output("out_valid");
-
-    active.drive_next(if_(start, {
-        m.high()
-    }).else_if(finished_pixel_acc.value.eq(m.lit(TILE_PIXELS, TILE_PIXELS_BITS + 1)), {
-        m.low()
-    }).else_({
-        active.value
-    }));
-
-    // TODO: There's gotta be a simpler way to do this!
-    let finished_pixel_bits = edge_test_reject.concat(depth_test_reject).concat(valid);
-    let finished_pixel_count = if_(finished_pixel_bits.eq(m.lit(0b000u32, 3)), {
-        m.lit(0u32, 2)
-    }).else_if(finished_pixel_bits.eq(m.lit(0b001u32, 3)), {
-        m.lit(1u32, 2)
-    }).else_if(finished_pixel_bits.eq(m.lit(0b010u32, 3)), {
-        m.lit(1u32, 2)
-    }).else_if(finished_pixel_bits.eq(m.lit(0b011u32, 3)), {
-        m.lit(2u32, 2)
-    }).else_if(finished_pixel_bits.eq(m.lit(0b100u32, 3)), {
-        m.lit(1u32, 2)
-    }).else_if(finished_pixel_bits.eq(m.lit(0b101u32, 3)), {
-        m.lit(2u32, 2)
-    }).else_if(finished_pixel_bits.eq(m.lit(0b110u32, 3)), {
-        m.lit(2u32, 2)
-    }).else_({
-        m.lit(3u32, 2)
-    });
-
-    finished_pixel_acc.drive_next(if_(start, {
-        m.lit(0u32, TILE_PIXELS_BITS + 1)
-    }).else_({
-        finished_pixel_acc.value + m.lit(0u32, TILE_PIXELS_BITS - 1).concat(finished_pixel_count)
-    }));
+    let tile_addr = back_pipe.output("out_tile_addr");
+
+    //  Pixels finish when they're rejected by the depth test or written by the back pipe
+    //   Each tile addr is in at most one slot, so slots can be matched by addr alone
+    for (i, (slot_valid, slot_tile_addr)) in in_flight_slots.iter().enumerate() {
+        let take = pixel_accept & in_flight_free_slot.eq(m.lit(i as u32, PIXEL_PIPE_IN_FLIGHT_SLOTS_BITS));
+        let finish = (depth_test_reject & slot_tile_addr.value.eq(depth_test_tile_addr)) | (valid & slot_tile_addr.value.eq(tile_addr));
+        slot_valid.drive_next(if_(take, {
+            m.high()
+        }).else_if(finish, {
+            m.low()
+        }).else_({
+            slot_valid.value
+        }));
+        slot_tile_addr.drive_next(take.mux(pixel_accept_tile_addr, slot_tile_addr.value));
+    }
 
     m
 }
@@ -792,6 +1832,15 @@ pub fn generate_depth_test_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     // Aux inputs
     let depth_test_enable = m.input("depth_test_enable", 1);
+    let depth_func = m.input("depth_func", REG_DEPTH_SETTINGS_FUNC_BITS);
+
+    let stencil_test_enable = m.input("stencil_test_enable", 1);
+    let stencil_func = m.input("stencil_func", REG_STENCIL_SETTINGS_FUNC_BITS);
+    let stencil_ref = m.input("stencil_ref", REG_STENCIL_SETTINGS_REF_BITS);
+    let stencil_mask = m.input("stencil_mask", REG_STENCIL_SETTINGS_MASK_BITS);
+    let stencil_sfail_op = m.input("stencil_sfail_op", REG_STENCIL_SETTINGS_OP_BITS);
+    let stencil_dpfail_op = m.input("stencil_dpfail_op", REG_STENCIL_SETTINGS_OP_BITS);
+    let stencil_dppass_op = m.input("stencil_dppass_op", REG_STENCIL_SETTINGS_OP_BITS);
 
     // Inputs
     let valid = m.input("in_valid", 1);
@@ -809,10 +1858,16 @@ pub fn generate_depth_test_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let s = m.input("in_s", 32 - RESTORED_W_FRACT_BITS);
     let t = m.input("in_t", 32 - RESTORED_W_FRACT_BITS);
 
+    let tex_lod_base = m.input("in_tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+
     //  Issue depth buffer read for prev_depth
     m.output("depth_buffer_read_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 3));
     m.output("depth_buffer_read_port_enable", valid & depth_test_enable);
 
+    //  Issue stencil buffer read for prev_stencil
+    m.output("stencil_buffer_read_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 4));
+    m.output("stencil_buffer_read_port_enable", valid & stencil_test_enable);
+
     // Stage 1
     let valid = valid.reg_next_with_default("stage_1_valid", false);
     let tile_addr = tile_addr.reg_next("stage_1_tile_addr");
@@ -829,6 +1884,8 @@ pub fn generate_depth_test_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let s = s.reg_next("stage_1_s");
     let t = t.reg_next("stage_1_t");
 
+    let tex_lod_base = tex_lod_base.reg_next("stage_1_tex_lod_base");
+
     //  Returned from issue in previous stage
     let prev_depth = m.input("depth_buffer_read_port_value", 128);
     let prev_depth = if_(tile_addr.bits(2, 0).eq(m.lit(0u32, 3)), {
@@ -848,6 +1905,8 @@ pub fn generate_depth_test_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     }).else_({
         prev_depth.bits(127, 112)
     });
+    let prev_stencil = m.input("stencil_buffer_read_port_value", 128);
+    let prev_stencil = (prev_stencil >> tile_addr.bits(3, 0).concat(m.lit(0u32, 3))).bits(7, 0);
 
     // Stage 2
     let valid = valid.reg_next_with_default("stage_2_valid", false);
@@ -865,9 +1924,56 @@ pub fn generate_depth_test_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let s = s.reg_next("stage_2_s");
     let t = t.reg_next("stage_2_t");
 
-    let prev_depth = prev_depth.reg_next("stage_2_prev_depth");
+    let tex_lod_base = tex_lod_base.reg_next("stage_2_tex_lod_base");
 
-    let depth_test_result = z.lt(prev_depth) | !depth_test_enable;
+    let prev_depth = prev_depth.reg_next("stage_2_prev_depth");
+    let prev_stencil = prev_stencil.reg_next("stage_2_prev_stencil");
+
+    let depth_test_result =
+        (depth_func.bit(0) & z.lt(prev_depth)) |
+        (depth_func.bit(1) & z.eq(prev_depth)) |
+        (depth_func.bit(2) & z.gt(prev_depth)) |
+        !depth_test_enable;
+
+    let masked_stencil_ref = stencil_ref & stencil_mask;
+    let masked_prev_stencil = prev_stencil & stencil_mask;
+    let stencil_test_result =
+        (stencil_func.bit(0) & masked_stencil_ref.lt(masked_prev_stencil)) |
+        (stencil_func.bit(1) & masked_stencil_ref.eq(masked_prev_stencil)) |
+        (stencil_func.bit(2) & masked_stencil_ref.gt(masked_prev_stencil)) |
+        !stencil_test_enable;
+
+    let stencil_op = if_(!stencil_test_result, {
+        stencil_sfail_op
+    }).else_if(!depth_test_result, {
+        stencil_dpfail_op
+    }).else_({
+        stencil_dppass_op
+    });
+    let op_is = |op: u32| stencil_op.eq(m.lit(op, REG_STENCIL_SETTINGS_OP_BITS));
+    let stencil_min = prev_stencil.eq(m.lit(0x00u32, 8));
+    let stencil_max = prev_stencil.eq(m.lit(0xffu32, 8));
+    let stencil_incr = prev_stencil + m.lit(1u32, 8);
+    let stencil_decr = prev_stencil - m.lit(1u32, 8);
+    let stencil = if_(op_is(REG_STENCIL_SETTINGS_OP_ZERO), {
+        m.lit(0u32, 8)
+    }).else_if(op_is(REG_STENCIL_SETTINGS_OP_REPLACE), {
+        stencil_ref
+    }).else_if(op_is(REG_STENCIL_SETTINGS_OP_INCR), {
+        stencil_max.mux(prev_stencil, stencil_incr)
+    }).else_if(op_is(REG_STENCIL_SETTINGS_OP_DECR), {
+        stencil_min.mux(prev_stencil, stencil_decr)
+    }).else_if(op_is(REG_STENCIL_SETTINGS_OP_INVERT), {
+        !prev_stencil
+    }).else_if(op_is(REG_STENCIL_SETTINGS_OP_INCR_WRAP), {
+        stencil_incr
+    }).else_if(op_is(REG_STENCIL_SETTINGS_OP_DECR_WRAP), {
+        stencil_decr
+    }).else_({
+        // REG_STENCIL_SETTINGS_OP_KEEP
+        prev_stencil
+    });
+    let stencil_write = stencil_test_enable & stencil.ne(prev_stencil);
 
     // Outputs
     m.output("out_valid", valid);
@@ -885,8 +1991,14 @@ pub fn generate_depth_test_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     m.output("out_s", s);
     m.output("out_t", t);
 
+    m.output("out_tex_lod_base", tex_lod_base);
+
     m.output("out_depth_test_result", depth_test_result);
 
+    m.output("out_stencil", stencil);
+    m.output("out_stencil_test_result", stencil_test_result);
+    m.output("out_stencil_write", stencil_write);
+
     m
 }
 
@@ -895,8 +2007,17 @@ pub fn generate_front_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     // Aux inputs
     let tex_filter_select = m.input("tex_filter_select", 1);
-    let tex_dim = m.input("tex_dim", 2);
+    let tex_width = m.input("tex_width", REG_TEXTURE_SETTINGS_DIM_BITS);
+    let tex_height = m.input("tex_height", REG_TEXTURE_SETTINGS_DIM_BITS);
     let tex_base = m.input("tex_base", TEX_PIXEL_ADDR_BITS - 8);
+    let tex_wrap_s = m.input("tex_wrap_s", REG_TEXTURE_SETTINGS_WRAP_BITS);
+    let tex_wrap_t = m.input("tex_wrap_t", REG_TEXTURE_SETTINGS_WRAP_BITS);
+    let tex_mip_mode = m.input("tex_mip_mode", REG_TEXTURE_SETTINGS_MIP_MODE_BITS);
+    let tex_max_level = m.input("tex_max_level", REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS);
+    let tex_lod_bias = m.input("tex_lod_bias", REG_TEXTURE_LOD_BIAS_BITS);
+    let tex_format = m.input("tex_format", REG_TEXTURE_SETTINGS_FORMAT_BITS);
+
+    let fog_coord_select = m.input("fog_coord_select", 1);
 
     // Inputs
     let mut valid = m.input("in_valid", 1);
@@ -914,8 +2035,12 @@ pub fn generate_front_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let mut s = m.input("in_s", 32 - RESTORED_W_FRACT_BITS);
     let mut t = m.input("in_t", 32 - RESTORED_W_FRACT_BITS);
 
+    let mut tex_lod_base = m.input("in_tex_lod_base", 5 + TEX_LOD_FRACT_BITS);
+
     let mut depth_test_result = m.input("in_depth_test_result", 1);
 
+    let mut stencil = m.input("in_stencil", 8);
+
     approx_reciprocal::generate(c, "WInverseReciprocal", W_INVERSE_FRACT_BITS - RESTORED_W_FRACT_BITS - 3, 4);
     let w_approx_reciprocal = m.instance("w_approx_reciprocal", "WInverseReciprocal");
     w_approx_reciprocal.drive_input("x", w_inverse);
@@ -935,7 +2060,11 @@ pub fn generate_front_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
         s = s.reg_next(format!("stage_{}_s", stage));
         t = t.reg_next(format!("stage_{}_t", stage));
 
+        tex_lod_base = tex_lod_base.reg_next(format!("stage_{}_tex_lod_base", stage));
+
         depth_test_result = depth_test_result.reg_next(format!("stage_{}_depth_test_result", stage));
+
+        stencil = stencil.reg_next(format!("stage_{}_stencil", stage));
     }
 
     //  Returned from issue before stage 1
@@ -955,13 +2084,61 @@ pub fn generate_front_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let s = s.reg_next("stage_14_s");
     let t = t.reg_next("stage_14_t");
 
+    let tex_lod_base = tex_lod_base.reg_next("stage_14_tex_lod_base");
+
     let w = w.reg_next("stage_14_w");
 
     let s = s.mul_signed(w);
     let t = t.mul_signed(w);
 
+    //  Restored w is clamped to the fog coord's range
+    let fog_coord = if_(fog_coord_select.eq(m.lit(REG_FOG_SETTINGS_COORD_SELECT_W, 1)), {
+        w.bits(31, FOG_COORD_BITS).eq(m.lit(0u32, 32 - FOG_COORD_BITS)).mux(w.bits(FOG_COORD_BITS - 1, 0), m.lit((1u32 << FOG_COORD_BITS) - 1, FOG_COORD_BITS))
+    }).else_({
+        // REG_FOG_SETTINGS_COORD_SELECT_Z
+        z
+    });
+
     let depth_test_result = depth_test_result.reg_next("stage_14_depth_test_result");
 
+    let stencil = stencil.reg_next("stage_14_stencil");
+
+    //  LOD is log2 of the screen-space texel derivative, ie. log2(max derivative / w) + log2(w), where w and the
+    //   derivatives carry RESTORED_W_FRACT_BITS and ST_FRACT_BITS fractional bits respectively
+    const LOD_BITS: u32 = 12;
+    let extend = |x: &'a Signal<'a>| -> &'a Signal<'a> {
+        m.lit(0u32, LOD_BITS - x.bit_width()).concat(x)
+    };
+    let lod =
+        extend(tex_lod_base) +
+        extend(log2_approx(w, m)) +
+        tex_lod_bias.bit(REG_TEXTURE_LOD_BIAS_BITS - 1).repeat(LOD_BITS - REG_TEXTURE_LOD_BIAS_BITS).concat(tex_lod_bias) -
+        m.lit((ST_FRACT_BITS + RESTORED_W_FRACT_BITS) << TEX_LOD_FRACT_BITS, LOD_BITS);
+    let max_lod = extend(tex_max_level.concat(m.lit(0u32, TEX_LOD_FRACT_BITS)));
+    let lod = if_(lod.bit(LOD_BITS - 1), {
+        m.lit(0u32, LOD_BITS)
+    }).else_if(lod.gt(max_lod), {
+        max_lod
+    }).else_({
+        lod
+    }).bits(REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS + TEX_LOD_FRACT_BITS - 1, 0);
+
+    let lod_whole = lod.bits(lod.bit_width() - 1, TEX_LOD_FRACT_BITS);
+    let lod_fract = lod.bits(TEX_LOD_FRACT_BITS - 1, 0);
+    let zero_lod_fract = m.lit(0u32, TEX_LOD_FRACT_BITS);
+    let (level0, level1, lod_fract) = if_(tex_mip_mode.eq(m.lit(REG_TEXTURE_SETTINGS_MIP_MODE_NEAREST, REG_TEXTURE_SETTINGS_MIP_MODE_BITS)), {
+        let level = (m.low().concat(lod) + m.lit(1u32 << (TEX_LOD_FRACT_BITS - 1), lod.bit_width() + 1)).bits(lod.bit_width() - 1, TEX_LOD_FRACT_BITS);
+        (level, level, zero_lod_fract)
+    }).else_if(tex_mip_mode.eq(m.lit(REG_TEXTURE_SETTINGS_MIP_MODE_LINEAR, REG_TEXTURE_SETTINGS_MIP_MODE_BITS)), {
+        //  lod is clamped to the max level, so a nonzero fract means there's always a next level
+        let level1 = lod_whole + m.lit(0u32, REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS - 1).concat(lod_fract.ne(zero_lod_fract));
+        (lod_whole, level1, lod_fract)
+    }).else_({
+        // REG_TEXTURE_SETTINGS_MIP_MODE_NONE
+        let level = m.lit(0u32, REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS);
+        (level, level, zero_lod_fract)
+    });
+
     // Stage 15
     let valid = valid.reg_next_with_default("stage_15_valid", false);
     let tile_addr = tile_addr.reg_next("stage_15_tile_addr");
@@ -973,38 +2150,194 @@ pub fn generate_front_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let z = z.reg_next("stage_15_z");
 
+    let fog_coord = fog_coord.reg_next("stage_15_fog_coord");
+
     let s = s.reg_next("stage_15_s");
     let t = t.reg_next("stage_15_t");
 
     let depth_test_result = depth_test_result.reg_next("stage_15_depth_test_result");
 
-    let s_floor = s.bits(31, ST_FRACT_BITS);
-    let t_floor = t.bits(31, ST_FRACT_BITS);
-    let s_fract = m.low().concat(s.bits(ST_FRACT_BITS - 1, ST_FRACT_BITS - ST_FILTER_FRACT_BITS));
-    let t_fract = m.low().concat(t.bits(ST_FRACT_BITS - 1, ST_FRACT_BITS - ST_FILTER_FRACT_BITS));
-    let one_minus_s_fract = m.high().concat(m.lit(0u32, ST_FILTER_FRACT_BITS)) - s_fract;
-    let one_minus_t_fract = m.high().concat(m.lit(0u32, ST_FILTER_FRACT_BITS)) - t_fract;
+    let stencil = stencil.reg_next("stage_15_stencil");
+
+    let level0 = level0.reg_next("stage_15_level0");
+    let level1 = level1.reg_next("stage_15_level1");
+    let lod_fract = m.low().concat(lod_fract.reg_next("stage_15_lod_fract"));
+    let one_minus_lod_fract = m.high().concat(m.lit(0u32, TEX_LOD_FRACT_BITS)) - lod_fract;
+
+    let select = |sel: &'a Signal<'a>, num_values: u32, value: &dyn Fn(u32) -> &'a Signal<'a>| -> &'a Signal<'a> {
+        (1..num_values).fold(value(0), |acc, x| sel.eq(m.lit(x, sel.bit_width())).mux(value(x), acc))
+    };
+
+    //  Wrap texel coords along an axis, returning the wrapped coord and whether it should be replaced with the border color
+    let wrap = |coord: &'a Signal<'a>, tex_dim: &'a Signal<'a>, wrap_mode: &'a Signal<'a>| -> (&'a Signal<'a>, &'a Signal<'a>) {
+        let coord_bits = coord.bit_width();
+        let (mask, mirror) = if_(tex_dim.eq(m.lit(REG_TEXTURE_SETTINGS_DIM_16, REG_TEXTURE_SETTINGS_DIM_BITS)), {
+            (m.lit(0x0fu32, 7), coord.bit(4))
+        }).else_if(tex_dim.eq(m.lit(REG_TEXTURE_SETTINGS_DIM_32, REG_TEXTURE_SETTINGS_DIM_BITS)), {
+            (m.lit(0x1fu32, 7), coord.bit(5))
+        }).else_if(tex_dim.eq(m.lit(REG_TEXTURE_SETTINGS_DIM_64, REG_TEXTURE_SETTINGS_DIM_BITS)), {
+            (m.lit(0x3fu32, 7), coord.bit(6))
+        }).else_({
+            // REG_TEXTURE_SETTINGS_DIM_128
+            (m.lit(0x7fu32, 7), coord.bit(7))
+        });
+
+        let low = coord.bits(6, 0);
+        let out_of_range = coord.bits(coord_bits - 1, 7).ne(m.lit(0u32, coord_bits - 7)) | (low & !mask).ne(m.lit(0u32, 7));
+        let repeat = low & mask;
+
+        let wrapped = if_(wrap_mode.eq(m.lit(REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT, REG_TEXTURE_SETTINGS_WRAP_BITS)), {
+            mirror.mux(!low & mask, repeat)
+        }).else_if(wrap_mode.eq(m.lit(REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE, REG_TEXTURE_SETTINGS_WRAP_BITS)), {
+            out_of_range.mux(coord.bit(coord_bits - 1).mux(m.lit(0u32, 7), mask), low)
+        }).else_({
+            // REG_TEXTURE_SETTINGS_WRAP_REPEAT, REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER
+            repeat
+        });
+        let border = out_of_range & wrap_mode.eq(m.lit(REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER, REG_TEXTURE_SETTINGS_WRAP_BITS));
+
+        (wrapped, border)
+    };
+
+    //  Route each of an axis' two texel coords to the buffers matching their parity, along with their weights.
+    //   Wrapping can map both coords to the same texel (eg. at a clamped edge), in which case that texel takes the
+    //   full weight and the other buffers' reads are ignored.
+    let select_buffers = |coord: &'a Signal<'a>, tex_dim: &'a Signal<'a>, wrap_mode: &'a Signal<'a>, fract: &'a Signal<'a>, one_minus_fract: &'a Signal<'a>| {
+        let (coord0, border0) = wrap(coord, tex_dim, wrap_mode);
+        let (coord1, border1) = wrap(coord + m.lit(1u32, coord.bit_width()), tex_dim, wrap_mode);
 
-    //  Lock weights for nearest filtering
-    let (s_fract, one_minus_s_fract, t_fract, one_minus_t_fract) = if_(!tex_filter_select, {
         let zero = m.low().concat(m.lit(0u32, ST_FILTER_FRACT_BITS));
         let one = m.high().concat(m.lit(0u32, ST_FILTER_FRACT_BITS));
-        (zero, one, zero, one)
-    }).else_({
-        (s_fract, one_minus_s_fract, t_fract, one_minus_t_fract)
-    });
 
-    //  Swap weights depending on pixel offsets
-    let (s_fract, one_minus_s_fract) = if_(!s_floor.bit(0), {
-        (s_fract, one_minus_s_fract)
-    }).else_({
-        (one_minus_s_fract, s_fract)
-    });
-    let (t_fract, one_minus_t_fract) = if_(!t_floor.bit(0), {
-        (t_fract, one_minus_t_fract)
+        let coord0_even = !coord0.bit(0);
+        let coord1_even = !coord1.bit(0);
+        let even_coord = coord0_even.mux(coord0, coord1);
+        let even_border = coord0_even.mux(border0, border1);
+        let even_weight = if_(coord0_even & coord1_even, {
+            one
+        }).else_if(coord0_even, {
+            one_minus_fract
+        }).else_if(coord1_even, {
+            fract
+        }).else_({
+            zero
+        });
+
+        let coord0_odd = coord0.bit(0);
+        let coord1_odd = coord1.bit(0);
+        let odd_coord = coord0_odd.mux(coord0, coord1);
+        let odd_border = coord0_odd.mux(border0, border1);
+        let odd_weight = if_(coord0_odd & coord1_odd, {
+            one
+        }).else_if(coord0_odd, {
+            one_minus_fract
+        }).else_if(coord1_odd, {
+            fract
+        }).else_({
+            zero
+        });
+
+        (even_coord.bits(6, 1), even_border, even_weight, odd_coord.bits(6, 1), odd_border, odd_weight)
+    };
+
+    //  Texel offsets are made up of (level offset, buffer index, t / 2, s / 2), with each field only as wide as the
+    //   level's dims require, and each level packed directly after the previous one. Offsets are in texels, so they're
+    //   scaled down by the number of texels per pixel and added to the base, which is tex_base with the lower bits
+    //   ignored for larger textures. The remaining offset bits select the texel within the pixel.
+    let extend = |field: &'a Signal<'a>| -> &'a Signal<'a> {
+        if field.bit_width() == TEX_PIXEL_ADDR_BITS {
+            field
+        } else {
+            m.lit(0u32, TEX_PIXEL_ADDR_BITS - field.bit_width()).concat(field)
+        }
+    };
+    let format_is = |value: u32| tex_format.eq(m.lit(value, REG_TEXTURE_SETTINGS_FORMAT_BITS));
+    let texels_per_pixel_bits = if_(format_is(REG_TEXTURE_SETTINGS_FORMAT_ARGB8888), {
+        m.lit(0u32, 2)
+    }).else_if(format_is(REG_TEXTURE_SETTINGS_FORMAT_INDEXED8) | format_is(REG_TEXTURE_SETTINGS_FORMAT_VQ), {
+        m.lit(2u32, 2)
+    }).else_if(format_is(REG_TEXTURE_SETTINGS_FORMAT_INDEXED4), {
+        m.lit(3u32, 2)
     }).else_({
-        (one_minus_t_fract, t_fract)
+        // REG_TEXTURE_SETTINGS_FORMAT_RGB565, REG_TEXTURE_SETTINGS_FORMAT_ARGB4444, REG_TEXTURE_SETTINGS_FORMAT_ARGB1555
+        m.lit(1u32, 2)
     });
+    let is_vq = format_is(REG_TEXTURE_SETTINGS_FORMAT_VQ);
+    let tex_dims_sum = m.low().concat(tex_width) + m.low().concat(tex_height);
+    let base = select(tex_dims_sum, 7, &|x| {
+        if x == 0 {
+            tex_base
+        } else {
+            tex_base.bits(6, x).concat(m.lit(0u32, x))
+        }
+    }).concat(m.lit(0u32, 8));
+
+    let mut tex_buffer_border = Vec::new();
+    let mut tex_buffer_texel_select = Vec::new();
+    for (level_index, level) in [level0, level1].iter().enumerate() {
+        let level = *level;
+
+        let s_floor = select(level, 4, &|x| s.bits(31 + x, ST_FRACT_BITS + x));
+        let t_floor = select(level, 4, &|x| t.bits(31 + x, ST_FRACT_BITS + x));
+        let s_fract = m.low().concat(select(level, 4, &|x| s.bits(ST_FRACT_BITS - 1 + x, ST_FRACT_BITS - ST_FILTER_FRACT_BITS + x)));
+        let t_fract = m.low().concat(select(level, 4, &|x| t.bits(ST_FRACT_BITS - 1 + x, ST_FRACT_BITS - ST_FILTER_FRACT_BITS + x)));
+        let one_minus_s_fract = m.high().concat(m.lit(0u32, ST_FILTER_FRACT_BITS)) - s_fract;
+        let one_minus_t_fract = m.high().concat(m.lit(0u32, ST_FILTER_FRACT_BITS)) - t_fract;
+
+        //  Lock weights for nearest filtering
+        let (s_fract, one_minus_s_fract, t_fract, one_minus_t_fract) = if_(!tex_filter_select, {
+            let zero = m.low().concat(m.lit(0u32, ST_FILTER_FRACT_BITS));
+            let one = m.high().concat(m.lit(0u32, ST_FILTER_FRACT_BITS));
+            (zero, one, zero, one)
+        }).else_({
+            (s_fract, one_minus_s_fract, t_fract, one_minus_t_fract)
+        });
+
+        let level_width = tex_width - level;
+        let level_height = tex_height - level;
+
+        let (even_s, even_s_border, one_minus_s_fract, odd_s, odd_s_border, s_fract) = select_buffers(s_floor, level_width, tex_wrap_s, s_fract, one_minus_s_fract);
+        let (even_t, even_t_border, one_minus_t_fract, odd_t, odd_t_border, t_fract) = select_buffers(t_floor, level_height, tex_wrap_t, t_fract, one_minus_t_fract);
+
+        m.output(format!("out_level{}_s_fract", level_index), s_fract);
+        m.output(format!("out_level{}_one_minus_s_fract", level_index), one_minus_s_fract);
+        m.output(format!("out_level{}_t_fract", level_index), t_fract);
+        m.output(format!("out_level{}_one_minus_t_fract", level_index), one_minus_t_fract);
+
+        let level_dims_sum = tex_dims_sum - level.concat(m.low());
+        let level_offset = select(tex_dims_sum, 7, &|x| select(level, 4, &|y| {
+            m.lit((0..y).fold(0u32, |acc, i| acc + (1 << (8 + x - 2 * i))), TEX_PIXEL_ADDR_BITS)
+        }));
+        let read_addr = |s: &'a Signal<'a>, t: &'a Signal<'a>, buffer_index: u32| {
+            let buffer_index = select(level_dims_sum, 7, &|x| m.lit(buffer_index << (6 + x), TEX_PIXEL_ADDR_BITS));
+            let t = select(level_width, 4, &|x| extend(t.concat(m.lit(0u32, 3 + x))));
+            let s = extend(s);
+            //  VQ index maps have a single index per 2x2 block, so they're a quarter of the size and have no buffer index
+            let texel_offset = is_vq.mux(
+                extend(level_offset.bits(TEX_PIXEL_ADDR_BITS - 1, 2)) + (t | s),
+                level_offset + (buffer_index | t | s));
+            let pixel_offset = select(texels_per_pixel_bits, 4, &|x| extend(texel_offset.bits(TEX_PIXEL_ADDR_BITS - 1, x)));
+            let texel_select = select(texels_per_pixel_bits, 4, &|x| match x {
+                0 => m.lit(0u32, TEX_TEXEL_SELECT_BITS),
+                TEX_TEXEL_SELECT_BITS => texel_offset.bits(x - 1, 0),
+                _ => m.lit(0u32, TEX_TEXEL_SELECT_BITS - x).concat(texel_offset.bits(x - 1, 0)),
+            });
+            (base + pixel_offset, texel_select)
+        };
+
+        //  Buffers hold texels with (even s, even t), (odd s, even t), (even s, odd t), and (odd s, odd t) respectively
+        let buffer_offset = level_index as u32 * 4;
+        for (i, &(s, t)) in [(even_s, even_t), (odd_s, even_t), (even_s, odd_t), (odd_s, odd_t)].iter().enumerate() {
+            let (read_addr, texel_select) = read_addr(s, t, i as u32);
+            m.output(format!("out_tex_buffer{}_read_addr", buffer_offset + i as u32), read_addr);
+            tex_buffer_texel_select.push(texel_select);
+        }
+
+        tex_buffer_border.push(even_s_border | even_t_border);
+        tex_buffer_border.push(odd_s_border | even_t_border);
+        tex_buffer_border.push(even_s_border | odd_t_border);
+        tex_buffer_border.push(odd_s_border | odd_t_border);
+    }
 
     // Outputs
     m.output("out_valid", valid);
@@ -1017,52 +2350,17 @@ pub fn generate_front_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     m.output("out_z", z);
 
+    m.output("out_fog_coord", fog_coord);
+
     m.output("out_depth_test_result", depth_test_result);
 
-    m.output("out_s_fract", s_fract);
-    m.output("out_one_minus_s_fract", one_minus_s_fract);
-    m.output("out_t_fract", t_fract);
-    m.output("out_one_minus_t_fract", one_minus_t_fract);
-
-    let buffer0_s = (s_floor.bits(6, 0) + m.lit(1u32, 7)).bits(6, 1);
-    let buffer0_t = (t_floor.bits(6, 0) + m.lit(1u32, 7)).bits(6, 1);
-    let buffer1_s = s_floor.bits(6, 1);
-    let buffer1_t = buffer0_t;
-    let buffer2_s = buffer0_s;
-    let buffer2_t = t_floor.bits(6, 1);
-    let buffer3_s = buffer1_s;
-    let buffer3_t = buffer2_t;
-    let read_addr = |s: &'a Signal<'a>, t: &'a Signal<'a>, buffer_index: u32| {
-        if_(tex_dim.eq(m.lit(REG_TEXTURE_SETTINGS_DIM_16, REG_TEXTURE_SETTINGS_DIM_BITS)), {
-            tex_base
-            .concat(m.lit(buffer_index, 2))
-            .concat(t.bits(2, 0))
-            .concat(s.bits(2, 0))
-        }).else_if(tex_dim.eq(m.lit(REG_TEXTURE_SETTINGS_DIM_32, REG_TEXTURE_SETTINGS_DIM_BITS)), {
-            tex_base
-            .bits(6, 2)
-            .concat(m.lit(buffer_index, 2))
-            .concat(t.bits(3, 0))
-            .concat(s.bits(3, 0))
-        }).else_if(tex_dim.eq(m.lit(REG_TEXTURE_SETTINGS_DIM_64, REG_TEXTURE_SETTINGS_DIM_BITS)), {
-            tex_base
-            .bits(6, 4)
-            .concat(m.lit(buffer_index, 2))
-            .concat(t.bits(4, 0))
-            .concat(s.bits(4, 0))
-        }).else_({
-            // REG_TEXTURE_SETTINGS_DIM_128
-            tex_base
-            .bit(6)
-            .concat(m.lit(buffer_index, 2))
-            .concat(t)
-            .concat(s)
-        })
-    };
-    m.output("out_tex_buffer0_read_addr", read_addr(buffer0_s, buffer0_t, 0));
-    m.output("out_tex_buffer1_read_addr", read_addr(buffer1_s, buffer1_t, 1));
-    m.output("out_tex_buffer2_read_addr", read_addr(buffer2_s, buffer2_t, 2));
-    m.output("out_tex_buffer3_read_addr", read_addr(buffer3_s, buffer3_t, 3));
+    m.output("out_stencil", stencil);
+
+    m.output("out_lod_fract", lod_fract);
+    m.output("out_one_minus_lod_fract", one_minus_lod_fract);
+
+    m.output("out_tex_buffer_border", tex_buffer_border.into_iter().rev().reduce(|acc, x| acc.concat(x)).unwrap());
+    m.output("out_tex_buffer_texel_select", tex_buffer_texel_select.into_iter().rev().reduce(|acc, x| acc.concat(x)).unwrap());
 
     m
 }
@@ -1073,8 +2371,38 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
     // Aux inputs
     let depth_write_mask_enable = m.input("depth_write_mask_enable", 1);
 
+    let stencil_test_enable = m.input("stencil_test_enable", 1);
+
+    let alpha_test_enable = m.input("alpha_test_enable", 1);
+    let alpha_test_func = m.input("alpha_test_func", REG_ALPHA_TEST_SETTINGS_FUNC_BITS);
+    let alpha_test_ref = m.input("alpha_test_ref", REG_ALPHA_TEST_SETTINGS_REF_BITS);
+
+    let fog_enable = m.input("fog_enable", 1);
+    let fog_mode = m.input("fog_mode", REG_FOG_SETTINGS_MODE_BITS);
+    let fog_color = Texel::new(m.input("fog_color", 32));
+    let fog_start = m.input("fog_start", FOG_COORD_BITS);
+    let fog_scale = m.input("fog_scale", FOG_COORD_BITS);
+
+    let fog_table = m.mem("fog_table", FOG_TABLE_INDEX_BITS, FOG_AMOUNT_BITS);
+    fog_table.write_port(
+        m.input("fog_table_write_addr", FOG_TABLE_INDEX_BITS),
+        m.input("fog_table_write_data", FOG_AMOUNT_BITS),
+        m.input("fog_table_write_enable", 1));
+
+    let tex_border_color = m.input("tex_border_color", 32);
+    let tex_format = m.input("tex_format", REG_TEXTURE_SETTINGS_FORMAT_BITS);
+    let tex_combine_mode = m.input("tex_combine_mode", REG_TEXTURE_SETTINGS_COMBINE_MODE_BITS);
+
+    let tex_palette = m.mem("tex_palette", TEX_PALETTE_INDEX_BITS, 32);
+    tex_palette.write_port(
+        m.input("tex_palette_write_addr", TEX_PALETTE_INDEX_BITS),
+        m.input("tex_palette_write_data", 32),
+        m.input("tex_palette_write_enable", 1));
+
     let blend_src_factor = m.input("blend_src_factor", REG_BLEND_SETTINGS_SRC_FACTOR_BITS);
     let blend_dst_factor = m.input("blend_dst_factor", REG_BLEND_SETTINGS_DST_FACTOR_BITS);
+    let blend_op = m.input("blend_op", REG_BLEND_SETTINGS_OP_BITS);
+    let blend_constant = Texel::new(m.input("blend_constant", 32));
 
     // Inputs
     let valid = m.input("in_valid", 1);
@@ -1087,12 +2415,15 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let z = m.input("in_z", 16);
 
+    let fog_coord = m.input("in_fog_coord", FOG_COORD_BITS);
+
     let depth_test_result = m.input("in_depth_test_result", 1);
 
-    let s_fract = m.input("in_s_fract", ST_FILTER_FRACT_BITS + 1);
-    let one_minus_s_fract = m.input("in_one_minus_s_fract", ST_FILTER_FRACT_BITS + 1);
-    let t_fract = m.input("in_t_fract", ST_FILTER_FRACT_BITS + 1);
-    let one_minus_t_fract = m.input("in_one_minus_t_fract", ST_FILTER_FRACT_BITS + 1);
+    let stencil = m.input("in_stencil", 8);
+
+    let lod_fract = m.input("in_lod_fract", TEX_LOD_FRACT_BITS + 1);
+    let one_minus_lod_fract = m.input("in_one_minus_lod_fract", TEX_LOD_FRACT_BITS + 1);
+
     struct Texel<'a> {
         r: &'a Signal<'a>,
         g: &'a Signal<'a>,
@@ -1115,29 +2446,63 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
         }
     }
 
-    let texel0 = Texel::new(m.input("in_tex_buffer0_read_value", 32));
-    let texel1 = Texel::new(m.input("in_tex_buffer1_read_value", 32));
-    let texel2 = Texel::new(m.input("in_tex_buffer2_read_value", 32));
-    let texel3 = Texel::new(m.input("in_tex_buffer3_read_value", 32));
+    let tex_buffer_border = m.input("in_tex_buffer_border", 8);
+    let tex_buffer_texel_select = m.input("in_tex_buffer_texel_select", 8 * TEX_TEXEL_SELECT_BITS);
 
     // Stage 1
-    fn blend_component<'a>(a: &'a Signal<'a>, b: &'a Signal<'a>, a_fract: &'a Signal<'a>, b_fract: &'a Signal<'a>) -> &'a Signal<'a> {
-        (a * a_fract + b * b_fract).bits(8 + ST_FILTER_FRACT_BITS - 1, ST_FILTER_FRACT_BITS)
-    }
+    let format_is = |value: u32| tex_format.eq(m.lit(value, REG_TEXTURE_SETTINGS_FORMAT_BITS));
 
-    fn blend_texels<'a>(a: &Texel<'a>, b: &Texel<'a>, a_fract: &'a Signal<'a>, b_fract: &'a Signal<'a>) -> Texel<'a> {
-        Texel {
-            r: blend_component(a.r, b.r, a_fract, b_fract),
-            g: blend_component(a.g, b.g, a_fract, b_fract),
-            b: blend_component(a.b, b.b, a_fract, b_fract),
-            a: blend_component(a.a, b.a, a_fract, b_fract),
+    //  Expands a component to 8 bits by repeating its upper bits, so that its min and max map to 0 and 255
+    let expand = |comp: &'a Signal<'a>| -> &'a Signal<'a> {
+        let comp_bits = comp.bit_width();
+        if comp_bits == 1 {
+            comp.repeat(8)
+        } else {
+            comp.concat(comp.bits(comp_bits - 1, 2 * comp_bits - 8))
         }
-    }
+    };
+
+    //  Extract each buffer's texel from its pixel and decode it to ARGB8888, issuing palette reads for indexed formats
+    let texels = (0..8).map(|buffer_index| {
+        let pixel = m.input(format!("in_tex_buffer{}_read_value", buffer_index), 32);
+        let texel_select = tex_buffer_texel_select.bits((buffer_index + 1) * TEX_TEXEL_SELECT_BITS - 1, buffer_index * TEX_TEXEL_SELECT_BITS);
+
+        let texel16 = (pixel >> texel_select.bit(0).concat(m.lit(0u32, 4))).bits(15, 0);
+        let texel8 = (pixel >> texel_select.bits(1, 0).concat(m.lit(0u32, 3))).bits(7, 0);
+        let texel4 = (pixel >> texel_select.concat(m.lit(0u32, 2))).bits(3, 0);
+
+        let texel = if_(format_is(REG_TEXTURE_SETTINGS_FORMAT_RGB565), {
+            m.lit(0xffu32, 8).concat(expand(texel16.bits(15, 11))).concat(expand(texel16.bits(10, 5))).concat(expand(texel16.bits(4, 0)))
+        }).else_if(format_is(REG_TEXTURE_SETTINGS_FORMAT_ARGB4444), {
+            expand(texel16.bits(15, 12)).concat(expand(texel16.bits(11, 8))).concat(expand(texel16.bits(7, 4))).concat(expand(texel16.bits(3, 0)))
+        }).else_if(format_is(REG_TEXTURE_SETTINGS_FORMAT_ARGB1555), {
+            expand(texel16.bit(15)).concat(expand(texel16.bits(14, 10))).concat(expand(texel16.bits(9, 5))).concat(expand(texel16.bits(4, 0)))
+        }).else_({
+            // REG_TEXTURE_SETTINGS_FORMAT_ARGB8888 (indexed and VQ formats are replaced with palette entries in the next stage)
+            pixel
+        });
+
+        let palette_index = if_(format_is(REG_TEXTURE_SETTINGS_FORMAT_INDEXED4), {
+            m.lit(0u32, TEX_PALETTE_INDEX_BITS - 4).concat(texel4)
+        }).else_if(format_is(REG_TEXTURE_SETTINGS_FORMAT_VQ), {
+            texel8.concat(m.lit(buffer_index % 4, 2))
+        }).else_({
+            // REG_TEXTURE_SETTINGS_FORMAT_INDEXED8
+            m.lit(0u32, TEX_PALETTE_INDEX_BITS - 8).concat(texel8)
+        });
+        let palette_entry = tex_palette.read_port(palette_index, valid);
+
+        (texel, palette_entry)
+    }).collect::<Vec<_>>();
 
-    let lower = blend_texels(&texel0, &texel1, one_minus_s_fract, s_fract).argb();
-    let upper = blend_texels(&texel2, &texel3, one_minus_s_fract, s_fract).argb();
+    //  Coords before the fog start map to 0
+    let fog_offset = m.low().concat(fog_coord) - m.low().concat(fog_start);
+    let fog_offset = fog_offset.bit(FOG_COORD_BITS).mux(m.lit(0u32, FOG_COORD_BITS), fog_offset.bits(FOG_COORD_BITS - 1, 0));
+    let fog_product = fog_offset * fog_scale;
 
     // Stage 2
+    let is_indexed = format_is(REG_TEXTURE_SETTINGS_FORMAT_INDEXED8) | format_is(REG_TEXTURE_SETTINGS_FORMAT_INDEXED4) | format_is(REG_TEXTURE_SETTINGS_FORMAT_VQ);
+
     let valid = valid.reg_next_with_default("stage_2_valid", false);
     let tile_addr = tile_addr.reg_next("stage_2_tile_addr");
 
@@ -1150,13 +2515,53 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let depth_test_result = depth_test_result.reg_next("stage_2_depth_test_result");
 
-    let t_fract = t_fract.reg_next("stage_2_t_fract");
-    let one_minus_t_fract = one_minus_t_fract.reg_next("stage_2_one_minus_t_fract");
+    let stencil = stencil.reg_next("stage_2_stencil");
+
+    let lod_fract = lod_fract.reg_next("stage_2_lod_fract");
+    let one_minus_lod_fract = one_minus_lod_fract.reg_next("stage_2_one_minus_lod_fract");
+
+    let fog_product = fog_product.reg_next("stage_2_fog_product");
+    let fog_one = m.high().concat(m.lit(0u32, FOG_AMOUNT_BITS - 1));
+    let fog_x = fog_product.bits(2 * FOG_COORD_BITS - 1, FOG_COORD_BITS);
+    let fog_x = fog_x.gt(m.lit(0u32, FOG_COORD_BITS - FOG_AMOUNT_BITS).concat(fog_one)).mux(fog_one, fog_x.bits(FOG_AMOUNT_BITS - 1, 0));
+
+    let tex_buffer_border = tex_buffer_border.reg_next("stage_2_tex_buffer_border");
+    let texel = |buffer_index: u32| -> Texel<'a> {
+        let (texel, palette_entry) = texels[buffer_index as usize];
+        let texel = is_indexed.mux(palette_entry, texel.reg_next(format!("stage_2_tex_buffer{}_texel", buffer_index)));
+        Texel::new(tex_buffer_border.bit(buffer_index).mux(tex_border_color, texel))
+    };
+
+    fn blend_component<'a>(a: &'a Signal<'a>, b: &'a Signal<'a>, a_fract: &'a Signal<'a>, b_fract: &'a Signal<'a>) -> &'a Signal<'a> {
+        (a * a_fract + b * b_fract).bits(8 + ST_FILTER_FRACT_BITS - 1, ST_FILTER_FRACT_BITS)
+    }
+
+    fn blend_texels<'a>(a: &Texel<'a>, b: &Texel<'a>, a_fract: &'a Signal<'a>, b_fract: &'a Signal<'a>) -> Texel<'a> {
+        Texel {
+            r: blend_component(a.r, b.r, a_fract, b_fract),
+            g: blend_component(a.g, b.g, a_fract, b_fract),
+            b: blend_component(a.b, b.b, a_fract, b_fract),
+            a: blend_component(a.a, b.a, a_fract, b_fract),
+        }
+    }
+
+    //  Each mip level is filtered separately, with level 0 and 1 using buffers 0-3 and 4-7 respectively
+    let levels = (0..2).map(|level| {
+        let s_fract = m.input(format!("in_level{}_s_fract", level), ST_FILTER_FRACT_BITS + 1).reg_next(format!("stage_2_level{}_s_fract", level));
+        let one_minus_s_fract = m.input(format!("in_level{}_one_minus_s_fract", level), ST_FILTER_FRACT_BITS + 1).reg_next(format!("stage_2_level{}_one_minus_s_fract", level));
+        let t_fract = m.input(format!("in_level{}_t_fract", level), ST_FILTER_FRACT_BITS + 1).reg_next(format!("stage_2_level{}_t_fract", level));
+        let one_minus_t_fract = m.input(format!("in_level{}_one_minus_t_fract", level), ST_FILTER_FRACT_BITS + 1).reg_next(format!("stage_2_level{}_one_minus_t_fract", level));
+
+        let texel0 = texel(level * 4);
+        let texel1 = texel(level * 4 + 1);
+        let texel2 = texel(level * 4 + 2);
+        let texel3 = texel(level * 4 + 3);
 
-    let lower = Texel::new(lower.reg_next("stage_2_lower"));
-    let upper = Texel::new(upper.reg_next("stage_2_upper"));
+        let lower = blend_texels(&texel0, &texel1, one_minus_s_fract, s_fract).argb();
+        let upper = blend_texels(&texel2, &texel3, one_minus_s_fract, s_fract).argb();
 
-    let texel = blend_texels(&lower, &upper, one_minus_t_fract, t_fract).argb();
+        (lower, upper, t_fract, one_minus_t_fract)
+    }).collect::<Vec<_>>();
 
     // Stage 3
     let valid = valid.reg_next_with_default("stage_3_valid", false);
@@ -1171,20 +2576,28 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let depth_test_result = depth_test_result.reg_next("stage_3_depth_test_result");
 
-    let texel = Texel::new(texel.reg_next("stage_3_texel"));
+    let stencil = stencil.reg_next("stage_3_stencil");
 
-    let scale_comp = |color_comp: &'a Signal<'a>, texel_comp: &'a Signal<'a>| -> &'a Signal<'a> {
-        (color_comp * texel_comp).bits(16, 8)
-    };
+    let lod_fract = lod_fract.reg_next("stage_3_lod_fract");
+    let one_minus_lod_fract = one_minus_lod_fract.reg_next("stage_3_one_minus_lod_fract");
 
-    let r = scale_comp(r, texel.r);
-    let g = scale_comp(g, texel.g);
-    let b = scale_comp(b, texel.b);
-    let a = scale_comp(a, texel.a);
+    let fog_x = fog_x.reg_next("stage_3_fog_x");
+    let fog_x_squared = (fog_x * fog_x).bits(2 * FOG_AMOUNT_BITS - 2, FOG_AMOUNT_BITS - 1);
+    let fog_table_index = fog_mode.eq(m.lit(REG_FOG_SETTINGS_MODE_EXP2, REG_FOG_SETTINGS_MODE_BITS)).mux(fog_x_squared, fog_x);
+    let fog_table_index = fog_table_index.bit(FOG_AMOUNT_BITS - 1).mux(m.lit((1u32 << FOG_TABLE_INDEX_BITS) - 1, FOG_TABLE_INDEX_BITS), fog_table_index.bits(FOG_TABLE_INDEX_BITS - 1, 0));
 
-    //  Issue color buffer read for prev_color
-    m.output("color_buffer_read_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 2));
-    m.output("color_buffer_read_port_enable", valid);
+    //  Issue fog table read
+    let fog_table_value = fog_table.read_port(fog_table_index, valid);
+
+    let levels = levels.into_iter().enumerate().map(|(level, (lower, upper, t_fract, one_minus_t_fract))| {
+        let t_fract = t_fract.reg_next(format!("stage_3_level{}_t_fract", level));
+        let one_minus_t_fract = one_minus_t_fract.reg_next(format!("stage_3_level{}_one_minus_t_fract", level));
+
+        let lower = Texel::new(lower.reg_next(format!("stage_3_level{}_lower", level)));
+        let upper = Texel::new(upper.reg_next(format!("stage_3_level{}_upper", level)));
+
+        blend_texels(&lower, &upper, one_minus_t_fract, t_fract).argb()
+    }).collect::<Vec<_>>();
 
     // Stage 4
     let valid = valid.reg_next_with_default("stage_4_valid", false);
@@ -1199,29 +2612,135 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     let depth_test_result = depth_test_result.reg_next("stage_4_depth_test_result");
 
-    let zero = m.lit(0u32, 9);
-    let one = m.high().concat(m.lit(0u32, 8));
+    let stencil = stencil.reg_next("stage_4_stencil");
+
+    let lod_fract = lod_fract.reg_next("stage_4_lod_fract");
+    let one_minus_lod_fract = one_minus_lod_fract.reg_next("stage_4_one_minus_lod_fract");
 
-    let blend_src_factor = if_(blend_src_factor.eq(m.lit(REG_BLEND_SETTINGS_SRC_FACTOR_ZERO, REG_BLEND_SETTINGS_SRC_FACTOR_BITS)), {
-        zero
-    }).else_if(blend_src_factor.eq(m.lit(REG_BLEND_SETTINGS_SRC_FACTOR_ONE, REG_BLEND_SETTINGS_SRC_FACTOR_BITS)), {
-        one
-    }).else_if(blend_src_factor.eq(m.lit(REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA, REG_BLEND_SETTINGS_SRC_FACTOR_BITS)), {
-        a
+    let fog_x = fog_x.reg_next("stage_4_fog_x");
+
+    //  Table value returned from issue in previous stage
+    let fog_amount = if_(!fog_enable, {
+        m.lit(0u32, FOG_AMOUNT_BITS)
+    }).else_if(fog_mode.eq(m.lit(REG_FOG_SETTINGS_MODE_LINEAR, REG_FOG_SETTINGS_MODE_BITS)), {
+        fog_x
     }).else_({
-        one - a
+        // REG_FOG_SETTINGS_MODE_EXP, REG_FOG_SETTINGS_MODE_EXP2
+        fog_table_value
     });
 
-    let blend_dst_factor = if_(blend_dst_factor.eq(m.lit(REG_BLEND_SETTINGS_DST_FACTOR_ZERO, REG_BLEND_SETTINGS_DST_FACTOR_BITS)), {
-        zero
-    }).else_if(blend_dst_factor.eq(m.lit(REG_BLEND_SETTINGS_DST_FACTOR_ONE, REG_BLEND_SETTINGS_DST_FACTOR_BITS)), {
-        one
-    }).else_if(blend_dst_factor.eq(m.lit(REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA, REG_BLEND_SETTINGS_DST_FACTOR_BITS)), {
-        a
+    let level0 = Texel::new(levels[0].reg_next("stage_4_level0_texel"));
+    let level1 = Texel::new(levels[1].reg_next("stage_4_level1_texel"));
+
+    let texel = blend_texels(&level0, &level1, one_minus_lod_fract, lod_fract).argb();
+
+    // Stage 5
+    let valid = valid.reg_next_with_default("stage_5_valid", false);
+    let tile_addr = tile_addr.reg_next("stage_5_tile_addr");
+
+    let r = r.reg_next("stage_5_r");
+    let g = g.reg_next("stage_5_g");
+    let b = b.reg_next("stage_5_b");
+    let a = a.reg_next("stage_5_a");
+
+    let z = z.reg_next("stage_5_z");
+
+    let depth_test_result = depth_test_result.reg_next("stage_5_depth_test_result");
+
+    let stencil = stencil.reg_next("stage_5_stencil");
+
+    let texel = Texel::new(texel.reg_next("stage_5_texel"));
+
+    let fog_amount = fog_amount.reg_next("stage_5_fog_amount");
+
+    let scale_comp = |color_comp: &'a Signal<'a>, texel_comp: &'a Signal<'a>| -> &'a Signal<'a> {
+        (color_comp * texel_comp).bits(16, 8)
+    };
+
+    //  Decal weights are 1.8 fixed point, like vertex color components
+    let decal_comp = |color_comp: &'a Signal<'a>, texel_comp: &'a Signal<'a>| -> &'a Signal<'a> {
+        let texel_weight = m.low().concat(texel.a);
+        let color_weight = m.high().concat(m.lit(0u32, 8)) - texel_weight;
+        (color_comp * color_weight + m.low().concat(texel_comp) * texel_weight).bits(16, 8)
+    };
+
+    //  Sums saturate at 1.0
+    let add_comp = |color_comp: &'a Signal<'a>, texel_comp: &'a Signal<'a>| -> &'a Signal<'a> {
+        let one = m.high().concat(m.lit(0u32, 8));
+        let sum = m.low().concat(color_comp) + m.lit(0u32, 2).concat(texel_comp);
+        sum.gt(m.low().concat(one)).mux(one, sum.bits(8, 0))
+    };
+
+    let combine_mode_is = |value: u32| tex_combine_mode.eq(m.lit(value, REG_TEXTURE_SETTINGS_COMBINE_MODE_BITS));
+    let (r, g, b, a) = if_(combine_mode_is(REG_TEXTURE_SETTINGS_COMBINE_MODE_REPLACE), {
+        (m.low().concat(texel.r), m.low().concat(texel.g), m.low().concat(texel.b), m.low().concat(texel.a))
+    }).else_if(combine_mode_is(REG_TEXTURE_SETTINGS_COMBINE_MODE_DECAL), {
+        (decal_comp(r, texel.r), decal_comp(g, texel.g), decal_comp(b, texel.b), a)
+    }).else_if(combine_mode_is(REG_TEXTURE_SETTINGS_COMBINE_MODE_ADD), {
+        (add_comp(r, texel.r), add_comp(g, texel.g), add_comp(b, texel.b), scale_comp(a, texel.a))
+    }).else_if(combine_mode_is(REG_TEXTURE_SETTINGS_COMBINE_MODE_DISABLED), {
+        (r, g, b, a)
     }).else_({
-        one - a
+        // REG_TEXTURE_SETTINGS_COMBINE_MODE_MODULATE
+        (scale_comp(r, texel.r), scale_comp(g, texel.g), scale_comp(b, texel.b), scale_comp(a, texel.a))
     });
 
+    //  Alpha is tested as it will be written, ie. clamped to 8 bits
+    let test_alpha = a.bit(8).mux(m.lit(255u32, 8), a.bits(7, 0));
+    let alpha_test_result =
+        (alpha_test_func.bit(0) & test_alpha.lt(alpha_test_ref)) |
+        (alpha_test_func.bit(1) & test_alpha.eq(alpha_test_ref)) |
+        (alpha_test_func.bit(2) & test_alpha.gt(alpha_test_ref)) |
+        !alpha_test_enable;
+
+    // Stage 6
+    let valid = valid.reg_next_with_default("stage_6_valid", false);
+    let tile_addr = tile_addr.reg_next("stage_6_tile_addr");
+
+    let r = r.reg_next("stage_6_r");
+    let g = g.reg_next("stage_6_g");
+    let b = b.reg_next("stage_6_b");
+    let a = a.reg_next("stage_6_a");
+
+    let z = z.reg_next("stage_6_z");
+
+    let depth_test_result = depth_test_result.reg_next("stage_6_depth_test_result");
+
+    let stencil = stencil.reg_next("stage_6_stencil");
+    let alpha_test_result = alpha_test_result.reg_next("stage_6_alpha_test_result");
+
+    let fog_amount = fog_amount.reg_next("stage_6_fog_amount");
+
+    //  Fog weights are 1.8 fixed point, like decal weights
+    let fog_comp = |color_comp: &'a Signal<'a>, fog_color_comp: &'a Signal<'a>| -> &'a Signal<'a> {
+        let color_weight = fog_one - fog_amount;
+        (color_comp * color_weight + m.low().concat(fog_color_comp) * fog_amount).bits(16, 8)
+    };
+
+    let r = fog_comp(r, fog_color.r);
+    let g = fog_comp(g, fog_color.g);
+    let b = fog_comp(b, fog_color.b);
+
+    //  Issue color buffer read for prev_color
+    m.output("color_buffer_read_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 2));
+    m.output("color_buffer_read_port_enable", valid);
+
+    // Stage 7
+    let valid = valid.reg_next_with_default("stage_7_valid", false);
+    let tile_addr = tile_addr.reg_next("stage_7_tile_addr");
+
+    let r = r.reg_next("stage_7_r");
+    let g = g.reg_next("stage_7_g");
+    let b = b.reg_next("stage_7_b");
+    let a = a.reg_next("stage_7_a");
+
+    let z = z.reg_next("stage_7_z");
+
+    let depth_test_result = depth_test_result.reg_next("stage_7_depth_test_result");
+
+    let stencil = stencil.reg_next("stage_7_stencil");
+    let alpha_test_result = alpha_test_result.reg_next("stage_7_alpha_test_result");
+
     //  Returned from issue in previous stage
     let prev_color = m.input("color_buffer_read_port_value", 128);
     let prev_color = if_(tile_addr.bits(1, 0).eq(m.lit(0u32, 2)), {
@@ -1234,60 +2753,142 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
         prev_color.bits(127, 96)
     });
 
-    // Stage 5
-    let valid = valid.reg_next_with_default("stage_5_valid", false);
-    let tile_addr = tile_addr.reg_next("stage_5_tile_addr");
+    let prev_color = Texel::new(prev_color);
 
-    let r = r.reg_next("stage_5_r");
-    let g = g.reg_next("stage_5_g");
-    let b = b.reg_next("stage_5_b");
-    let a = a.reg_next("stage_5_a");
+    let zero = m.lit(0u32, 9);
+    let one = m.high().concat(m.lit(0u32, 8));
 
-    let z = z.reg_next("stage_5_z");
+    //  Factors are 1.8 fixed point; 8-bit dst/constant components are zero-extended to match
+    let blend_factor = |factor: &'a Signal<'a>, src_comp: &'a Signal<'a>, dst_comp: &'a Signal<'a>, constant_comp: &'a Signal<'a>| -> &'a Signal<'a> {
+        let dst_comp = m.low().concat(dst_comp);
+        let dst_a = m.low().concat(prev_color.a);
+        let constant_comp = m.low().concat(constant_comp);
+        let constant_a = m.low().concat(blend_constant.a);
+
+        let factor_is = |value: u32| factor.eq(m.lit(value, REG_BLEND_SETTINGS_SRC_FACTOR_BITS));
+
+        if_(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ZERO), {
+            zero
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ONE), {
+            one
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA), {
+            a
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA), {
+            one - a
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_SRC_COLOR), {
+            src_comp
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_COLOR), {
+            one - src_comp
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_DST_COLOR), {
+            dst_comp
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_COLOR), {
+            one - dst_comp
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_DST_ALPHA), {
+            dst_a
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_ALPHA), {
+            one - dst_a
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_COLOR), {
+            constant_comp
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_COLOR), {
+            one - constant_comp
+        }).else_if(factor_is(REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_ALPHA), {
+            constant_a
+        }).else_({
+            one - constant_a
+        })
+    };
 
-    let depth_test_result = depth_test_result.reg_next("stage_5_depth_test_result");
+    //  Src and dst factor encodings are identical
+    let blend_src_factor_r = blend_factor(blend_src_factor, r, prev_color.r, blend_constant.r);
+    let blend_src_factor_g = blend_factor(blend_src_factor, g, prev_color.g, blend_constant.g);
+    let blend_src_factor_b = blend_factor(blend_src_factor, b, prev_color.b, blend_constant.b);
+
+    let blend_dst_factor_r = blend_factor(blend_dst_factor, r, prev_color.r, blend_constant.r);
+    let blend_dst_factor_g = blend_factor(blend_dst_factor, g, prev_color.g, blend_constant.g);
+    let blend_dst_factor_b = blend_factor(blend_dst_factor, b, prev_color.b, blend_constant.b);
 
-    let blend_src_factor = blend_src_factor.reg_next("stage_5_blend_src_factor");
-    let blend_dst_factor = blend_dst_factor.reg_next("stage_5_blend_dst_factor");
+    // Stage 8
+    let valid = valid.reg_next_with_default("stage_8_valid", false);
+    let tile_addr = tile_addr.reg_next("stage_8_tile_addr");
 
-    let prev_color = prev_color.reg_next("stage_5_prev_color");
+    let r = r.reg_next("stage_8_r");
+    let g = g.reg_next("stage_8_g");
+    let b = b.reg_next("stage_8_b");
+    let a = a.reg_next("stage_8_a");
 
-    let r = (r * blend_src_factor).bits(17, 8);
-    let g = (g * blend_src_factor).bits(17, 8);
-    let b = (b * blend_src_factor).bits(17, 8);
+    let z = z.reg_next("stage_8_z");
 
-    let prev_r = m.lit(0u32, 2).concat((prev_color.bits(23, 16) * blend_dst_factor).bits(16, 9));
-    let prev_g = m.lit(0u32, 2).concat((prev_color.bits(15, 8) * blend_dst_factor).bits(16, 9));
-    let prev_b = m.lit(0u32, 2).concat((prev_color.bits(7, 0) * blend_dst_factor).bits(16, 9));
+    let depth_test_result = depth_test_result.reg_next("stage_8_depth_test_result");
+
+    let stencil = stencil.reg_next("stage_8_stencil");
+    let alpha_test_result = alpha_test_result.reg_next("stage_8_alpha_test_result");
+
+    let blend_src_factor_r = blend_src_factor_r.reg_next("stage_8_blend_src_factor_r");
+    let blend_src_factor_g = blend_src_factor_g.reg_next("stage_8_blend_src_factor_g");
+    let blend_src_factor_b = blend_src_factor_b.reg_next("stage_8_blend_src_factor_b");
+
+    let blend_dst_factor_r = blend_dst_factor_r.reg_next("stage_8_blend_dst_factor_r");
+    let blend_dst_factor_g = blend_dst_factor_g.reg_next("stage_8_blend_dst_factor_g");
+    let blend_dst_factor_b = blend_dst_factor_b.reg_next("stage_8_blend_dst_factor_b");
+
+    let prev_color = Texel::new(prev_color.argb().reg_next("stage_8_prev_color"));
+
+    //  Blend results are 11 bits wide so that sums can't overflow before clamping
+    let blend_comp = |src_comp: &'a Signal<'a>, dst_comp: &'a Signal<'a>, src_factor: &'a Signal<'a>, dst_factor: &'a Signal<'a>| -> &'a Signal<'a> {
+        let weighted_src = m.low().concat((src_comp * src_factor).bits(17, 8));
+        let weighted_dst = m.lit(0u32, 2).concat((dst_comp * dst_factor).bits(16, 8));
+
+        let src = m.lit(0u32, 2).concat(src_comp);
+        let dst = m.lit(0u32, 3).concat(dst_comp);
+
+        let zero = m.lit(0u32, 11);
+
+        let op_is = |value: u32| blend_op.eq(m.lit(value, REG_BLEND_SETTINGS_OP_BITS));
+
+        if_(op_is(REG_BLEND_SETTINGS_OP_ADD), {
+            weighted_src + weighted_dst
+        }).else_if(op_is(REG_BLEND_SETTINGS_OP_SUBTRACT), {
+            weighted_src.ge(weighted_dst).mux(weighted_src - weighted_dst, zero)
+        }).else_if(op_is(REG_BLEND_SETTINGS_OP_REVERSE_SUBTRACT), {
+            weighted_dst.ge(weighted_src).mux(weighted_dst - weighted_src, zero)
+        }).else_if(op_is(REG_BLEND_SETTINGS_OP_MIN), {
+            src.lt(dst).mux(src, dst)
+        }).else_({
+            src.gt(dst).mux(src, dst)
+        })
+    };
 
     let clamp_comp = |comp: &'a Signal<'a>| -> &'a Signal<'a> {
-        if_(comp.bits(9, 8).eq(m.lit(0u32, 2)), {
+        if_(comp.bits(comp.bit_width() - 1, 8).eq(m.lit(0u32, comp.bit_width() - 8)), {
             comp.bits(7, 0)
         }).else_({
             m.lit(255u32, 8)
         })
     };
 
-    let r = clamp_comp(r + prev_r);
-    let g = clamp_comp(g + prev_g);
-    let b = clamp_comp(b + prev_b);
-    let a = clamp_comp(m.low().concat(a));
+    let r = clamp_comp(blend_comp(r, prev_color.r, blend_src_factor_r, blend_dst_factor_r));
+    let g = clamp_comp(blend_comp(g, prev_color.g, blend_src_factor_g, blend_dst_factor_g));
+    let b = clamp_comp(blend_comp(b, prev_color.b, blend_src_factor_b, blend_dst_factor_b));
+    let a = clamp_comp(a);
 
     let color = a.concat(r).concat(g).concat(b);
 
-    // Stage 6
-    let valid = valid.reg_next_with_default("stage_6_valid", false);
-    let tile_addr = tile_addr.reg_next("stage_6_tile_addr");
+    // Stage 9
+    let valid = valid.reg_next_with_default("stage_9_valid", false);
+    let tile_addr = tile_addr.reg_next("stage_9_tile_addr");
 
-    let z = z.reg_next("stage_6_z");
+    let z = z.reg_next("stage_9_z");
 
-    let depth_test_result = depth_test_result.reg_next("stage_6_depth_test_result");
+    let depth_test_result = depth_test_result.reg_next("stage_9_depth_test_result");
+
+    let stencil = stencil.reg_next("stage_9_stencil");
+    let alpha_test_result = alpha_test_result.reg_next("stage_9_alpha_test_result");
 
-    let color = color.reg_next("stage_6_color");
+    let color = color.reg_next("stage_9_color");
 
     m.output("color_buffer_write_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 2));
     m.output("color_buffer_write_port_value", color.repeat(4));
-    m.output("color_buffer_write_port_enable", valid & depth_test_result);
+    m.output("color_buffer_write_port_enable", valid & depth_test_result & alpha_test_result);
     m.output("color_buffer_write_port_word_enable", (0u32..4).fold(None, |acc, x| {
         let word_enable_bit = tile_addr.bits(1, 0).eq(m.lit(x, 2));
         Some(if let Some(acc) = acc {
@@ -1299,7 +2900,7 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     m.output("depth_buffer_write_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 3));
     m.output("depth_buffer_write_port_value", z.repeat(8));
-    m.output("depth_buffer_write_port_enable", valid & depth_test_result & depth_write_mask_enable);
+    m.output("depth_buffer_write_port_enable", valid & depth_test_result & alpha_test_result & depth_write_mask_enable);
     m.output("depth_buffer_write_port_word_enable", (0u32..8).fold(None, |acc, x| {
         let word_enable_bit = tile_addr.bits(2, 0).eq(m.lit(x, 3));
         Some(if let Some(acc) = acc {
@@ -1309,8 +2910,31 @@ pub fn generate_back_pipe<'a>(c: &'a Context<'a>) -> &Module<'a> {
         })
     }).unwrap());
 
+    //  Pixels that failed the stencil or depth test only make it this far if they update stencil
+    m.output("stencil_buffer_write_port_addr", tile_addr.bits(TILE_PIXELS_BITS - 1, 4));
+    m.output("stencil_buffer_write_port_value", stencil.repeat(16));
+    m.output("stencil_buffer_write_port_enable", valid & alpha_test_result & stencil_test_enable);
+    m.output("stencil_buffer_write_port_word_enable", (0u32..16).fold(None, |acc, x| {
+        let word_enable_bit = tile_addr.bits(3, 0).eq(m.lit(x, 4));
+        Some(if let Some(acc) = acc {
+            word_enable_bit.concat(acc)
+        } else {
+            word_enable_bit
+        })
+    }).unwrap());
+
     // Outputs
     m.output("out_valid", valid);
+    m.output("out_tile_addr", tile_addr);
 
     m
 }
+
+//  Approximates log2 of a 32-bit unsigned value in 5.TEX_LOD_FRACT_BITS fixed point, using the leading one's position for
+//   the whole part and the bits just below it (linearly) for the fractional part. Zero maps to zero.
+fn log2_approx<'a>(x: &'a Signal<'a>, m: &'a Module<'a>) -> &'a Signal<'a> {
+    let leading_zeros = approx_reciprocal::leading_zeros(x, m);
+    let normalized_x = x << leading_zeros;
+    let log2 = (m.lit(31u32, 5) - leading_zeros).concat(normalized_x.bits(30, 31 - TEX_LOD_FRACT_BITS));
+    x.eq(m.lit(0u32, 32)).mux(m.lit(0u32, 5 + TEX_LOD_FRACT_BITS), log2)
+}
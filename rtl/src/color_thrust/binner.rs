@@ -0,0 +1,357 @@
+use super::*;
+
+use kaze::*;
+
+// Width of edge function values at tile corners; enough for any triangle with 16-bit vertex positions stepped across the
+//  whole grid
+const EDGE_BITS: u32 = 40;
+// Tile coords computed from vertex positions, before they're clamped to the grid
+const TILE_COORD_BITS: u32 = 17;
+
+pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
+    let m = c.module("Binner");
+
+    let edge_test_enable = m.input("edge_test_enable", 1);
+    let back_face_cull_enable = m.input("back_face_cull_enable", 1);
+    let grid_width = m.input("grid_width", BIN_GRID_DIM_BITS + 1);
+    let grid_height = m.input("grid_height", BIN_GRID_DIM_BITS + 1);
+    let primitive_base = m.input("primitive_base", TEX_WORD_ADDR_BITS);
+    let list_base = m.input("list_base", TEX_WORD_ADDR_BITS);
+    let list_stride = m.input("list_stride", BIN_LIST_STRIDE_BITS);
+
+    let begin = m.input("begin", 1);
+    let triangle = m.input("triangle", 1);
+    let triangle_indices = m.input("triangle_indices", 3 * TRANSFORM_OUTPUT_VERTEX_BITS);
+    let end = m.input("end", 1);
+
+    let vertex_read_value = m.input("vertex_read_value", 32);
+
+    let replica_bus_ready = m.input("replica_bus_ready", 1);
+
+    let state_bit_width = 4;
+    let state_idle = 0u32;
+    let state_clear = 1u32;
+    let state_read_vertices = 2u32;
+    let state_classify = 3u32;
+    let state_edges = 4u32;
+    let state_primitive_read = 5u32;
+    let state_primitive_write = 6u32;
+    let state_tile_test = 7u32;
+    let state_tile_append = 8u32;
+    let state_end_read = 9u32;
+    let state_end_write = 10u32;
+    let state = m.reg("state", state_bit_width);
+    state.default_value(state_idle);
+    m.output("active", !state.value.eq(m.lit(state_idle, state_bit_width)));
+
+    let in_clear = state.value.eq(m.lit(state_clear, state_bit_width));
+    let in_read_vertices = state.value.eq(m.lit(state_read_vertices, state_bit_width));
+    let in_classify = state.value.eq(m.lit(state_classify, state_bit_width));
+    let in_edges = state.value.eq(m.lit(state_edges, state_bit_width));
+    let in_primitive_read = state.value.eq(m.lit(state_primitive_read, state_bit_width));
+    let in_primitive_write = state.value.eq(m.lit(state_primitive_write, state_bit_width));
+    let in_tile_test = state.value.eq(m.lit(state_tile_test, state_bit_width));
+    let in_tile_append = state.value.eq(m.lit(state_tile_append, state_bit_width));
+    let in_end_read = state.value.eq(m.lit(state_end_read, state_bit_width));
+    let in_end_write = state.value.eq(m.lit(state_end_write, state_bit_width));
+
+    let sign_extend = |x: &'a Signal<'a>, bit_width: u32| -> &'a Signal<'a> {
+        x.bit(x.bit_width() - 1).repeat(bit_width - x.bit_width()).concat(x)
+    };
+    let zero_extend = |x: &'a Signal<'a>, bit_width: u32| -> &'a Signal<'a> {
+        m.lit(0u32, bit_width - x.bit_width()).concat(x)
+    };
+    let min_signed = |a: &'a Signal<'a>, b: &'a Signal<'a>| a.lt_signed(b).mux(a, b);
+    let max_signed = |a: &'a Signal<'a>, b: &'a Signal<'a>| a.gt_signed(b).mux(a, b);
+
+    // Tiles
+    //  Tiles are visited by index, either all of them (to clear or terminate lists) or those in a triangle's bounding box
+    let tile_index_bit_width = BIN_GRID_DIM_BITS * 2;
+    let grid_tiles = grid_width * grid_height;
+    let tile_index = m.reg("tile_index", tile_index_bit_width);
+    let tile_index_last = zero_extend(tile_index.value, grid_tiles.bit_width()).eq(grid_tiles - m.lit(1u32, grid_tiles.bit_width()));
+
+    //  Each tile's list length (with an overflow flag in the top bit) is kept here, so appends don't need to read RAM
+    let list_entry_bit_width = BIN_LIST_STRIDE_BITS + 2;
+    let list_lengths = m.mem("list_lengths", tile_index_bit_width, list_entry_bit_width + 1);
+    let list_length_read_value = list_lengths.read_port(tile_index.value, in_tile_test | in_end_read);
+    let list_length = list_length_read_value.bits(list_entry_bit_width - 1, 0);
+    let list_overflow = list_length_read_value.bit(list_entry_bit_width);
+    //  The last slot is reserved for the terminator
+    let list_full = list_length.eq(list_stride.concat(m.lit(0u32, 2)) - m.lit(1u32, list_entry_bit_width));
+
+    // Vertices
+    //  The XY and clip flag words of each vertex are read from the transform output buffer in turn
+    let indices = m.reg("indices", 3 * TRANSFORM_OUTPUT_VERTEX_BITS);
+    indices.drive_next(triangle.mux(triangle_indices, indices.value));
+    let index = |i: &'a Signal<'a>| -> &'a Signal<'a> {
+        if_(i.eq(m.lit(0u32, 2)), {
+            indices.value.bits(TRANSFORM_OUTPUT_VERTEX_BITS - 1, 0)
+        }).else_if(i.eq(m.lit(1u32, 2)), {
+            indices.value.bits(TRANSFORM_OUTPUT_VERTEX_BITS * 2 - 1, TRANSFORM_OUTPUT_VERTEX_BITS)
+        }).else_({
+            indices.value.bits(TRANSFORM_OUTPUT_VERTEX_BITS * 3 - 1, TRANSFORM_OUTPUT_VERTEX_BITS * 2)
+        })
+    };
+
+    let read_counter = m.reg("read_counter", 3);
+    let read_counter_last = read_counter.value.eq(m.lit(6u32, 3));
+    read_counter.drive_next(in_read_vertices.mux(read_counter.value + m.lit(1u32, 3), m.lit(0u32, 3)));
+    //  Reads alternate between XY and clip flags, so the low bit of the counter selects the word
+    let read_vertex = read_counter.value.bits(2, 1);
+    let read_word = read_counter.value.bit(0).mux(m.lit(TRANSFORM_OUTPUT_WORD_CLIP_FLAGS, SETUP_VERTEX_WORD_BITS), m.lit(SETUP_VERTEX_WORD_XY, SETUP_VERTEX_WORD_BITS));
+    let captured_counter = read_counter.value - m.lit(1u32, 3);
+    let capture = in_read_vertices & !read_counter.value.eq(m.lit(0u32, 3));
+
+    let xy = (0..3u32).map(|i| {
+        let xy = m.reg(format!("xy_{}", i), 32);
+        xy.drive_next((capture & captured_counter.eq(m.lit(i * 2, 3))).mux(vertex_read_value, xy.value));
+        xy.value
+    }).collect::<Vec<_>>();
+    let clip_flags = (0..3u32).map(|i| {
+        let clip_flags = m.reg(format!("clip_flags_{}", i), 6);
+        clip_flags.drive_next((capture & captured_counter.eq(m.lit(i * 2 + 1, 3))).mux(vertex_read_value.bits(5, 0), clip_flags.value));
+        clip_flags.value
+    }).collect::<Vec<_>>();
+
+    // Classification
+    //  Vertex positions are signed with SETUP_XY_FRACT_BITS fractional bits, so their products are exact
+    let x = xy.iter().map(|xy| sign_extend(xy.bits(15, 0), 17)).collect::<Vec<_>>();
+    let y = xy.iter().map(|xy| sign_extend(xy.bits(31, 16), 17)).collect::<Vec<_>>();
+    let area = (x[1] - x[0]).mul_signed(y[2] - y[0]) - (y[1] - y[0]).mul_signed(x[2] - x[0]);
+    let area_negative = area.bit(area.bit_width() - 1);
+    let area_zero = area.eq(m.lit(0u32, area.bit_width()));
+
+    let near_far_flags = m.lit((1u32 << TRANSFORM_CLIP_FLAG_NEAR_BIT) | (1u32 << TRANSFORM_CLIP_FLAG_FAR_BIT), 6);
+    let outside = !(clip_flags[0] & clip_flags[1] & clip_flags[2]).eq(m.lit(0u32, 6));
+    let needs_clipping = !((clip_flags[0] | clip_flags[1] | clip_flags[2]) & near_far_flags).eq(m.lit(0u32, 6));
+
+    //  The bounding box is tightened to the pixel centers it contains before it's converted to tiles
+    let half_pixel = 1u32 << (SETUP_XY_FRACT_BITS - 1);
+    let to_tile = |x: &'a Signal<'a>| x.shr_arithmetic(m.lit(TILE_DIM_BITS + SETUP_XY_FRACT_BITS, 5));
+    let tile_min = |v: &[&'a Signal<'a>]| to_tile(min_signed(min_signed(v[0], v[1]), v[2]) + m.lit(half_pixel - 1, TILE_COORD_BITS));
+    let tile_max = |v: &[&'a Signal<'a>]| to_tile(max_signed(max_signed(v[0], v[1]), v[2]) - m.lit(half_pixel, TILE_COORD_BITS));
+    let tile_min_x = tile_min(&x);
+    let tile_max_x = tile_max(&x);
+    let tile_min_y = tile_min(&y);
+    let tile_max_y = tile_max(&y);
+    let grid_max_x = zero_extend(grid_width, TILE_COORD_BITS) - m.lit(1u32, TILE_COORD_BITS);
+    let grid_max_y = zero_extend(grid_height, TILE_COORD_BITS) - m.lit(1u32, TILE_COORD_BITS);
+    let zero = m.lit(0u32, TILE_COORD_BITS);
+    let bounds_empty =
+        tile_max_x.lt_signed(zero) | tile_min_x.gt_signed(grid_max_x) | tile_min_x.gt_signed(tile_max_x) |
+        tile_max_y.lt_signed(zero) | tile_min_y.gt_signed(grid_max_y) | tile_min_y.gt_signed(tile_max_y);
+    let clamp = |x: &'a Signal<'a>, max: &'a Signal<'a>| min_signed(max_signed(x, zero), max).bits(BIN_GRID_DIM_BITS - 1, 0);
+
+    let cull = outside | needs_clipping | area_zero | (back_face_cull_enable & area_negative) | bounds_empty;
+
+    //  Vertices 0 and 1 are swapped for triangles with negative area, so that binned primitives always have positive area
+    let swap = m.reg("swap", 1);
+    swap.drive_next(in_classify.mux(area_negative, swap.value));
+    let vertex_order = |i: &'a Signal<'a>| -> &'a Signal<'a> {
+        (swap.value & !i.bit(1)).mux(m.low().concat(!i.bit(0)), i)
+    };
+    let ordered = |v: &[&'a Signal<'a>], i: u32| -> &'a Signal<'a> {
+        match i {
+            0 => swap.value.mux(v[1], v[0]),
+            1 => swap.value.mux(v[0], v[1]),
+            _ => v[2],
+        }
+    };
+
+    let bounds = [
+        ("tile_min_x", tile_min_x, grid_max_x),
+        ("tile_max_x", tile_max_x, grid_max_x),
+        ("tile_min_y", tile_min_y, grid_max_y),
+        ("tile_max_y", tile_max_y, grid_max_y),
+    ].iter().map(|&(name, x, max)| {
+        let reg = m.reg(name, BIN_GRID_DIM_BITS);
+        reg.drive_next(in_classify.mux(clamp(x, max), reg.value));
+        reg
+    }).collect::<Vec<_>>();
+    let (tile_min_x, tile_max_x, tile_min_y, tile_max_y) = (bounds[0].value, bounds[1].value, bounds[2].value, bounds[3].value);
+
+    // Edges
+    //  Edge k is opposite vertex k, so it runs from vertex k + 1 to vertex k + 2, matching setup. Each edge is evaluated
+    //   at the pixel center in each tile where it's largest, which is the same corner for every tile, so a tile can only
+    //   contain pixel centers inside the triangle if all three values are non-negative.
+    let tile_x = m.reg("tile_x", BIN_GRID_DIM_BITS);
+    let tile_y = m.reg("tile_y", BIN_GRID_DIM_BITS);
+    let tile_row_index = m.reg("tile_row_index", tile_index_bit_width);
+    let tile_x_last = tile_x.value.eq(tile_max_x);
+    let tile_y_last = tile_y.value.eq(tile_max_y);
+
+    let tile_origin = |tile: &'a Signal<'a>| m.lit(0u32, 1).concat(tile).concat(m.lit(0u32, TILE_DIM_BITS + SETUP_XY_FRACT_BITS));
+    let near_pixel = m.lit(half_pixel, TILE_DIM_BITS + SETUP_XY_FRACT_BITS);
+    let far_pixel = m.lit(((TILE_DIM - 1) << SETUP_XY_FRACT_BITS) + half_pixel, TILE_DIM_BITS + SETUP_XY_FRACT_BITS);
+    let corner = |tile: &'a Signal<'a>, far: &'a Signal<'a>| tile_origin(tile) + zero_extend(far.mux(far_pixel, near_pixel), BIN_GRID_DIM_BITS + 1 + TILE_DIM_BITS + SETUP_XY_FRACT_BITS);
+
+    let edges = (0..3u32).map(|k| {
+        let a = (k + 1) % 3;
+        let b = (k + 2) % 3;
+        let (ax, ay) = (ordered(&x, a), ordered(&y, a));
+        let (bx, by) = (ordered(&x, b), ordered(&y, b));
+        let dx = ay - by;
+        let dy = bx - ax;
+        let step_x = sign_extend(dx.concat(m.lit(0u32, TILE_DIM_BITS + SETUP_XY_FRACT_BITS)), EDGE_BITS).reg_next(format!("edge_{}_step_x", k));
+        let step_y = sign_extend(dy.concat(m.lit(0u32, TILE_DIM_BITS + SETUP_XY_FRACT_BITS)), EDGE_BITS).reg_next(format!("edge_{}_step_y", k));
+
+        //  Tile corners are non-negative, so they're zero-extended before subtracting vertex positions
+        let cx = zero_extend(corner(tile_min_x, !dx.bit(16)), 18) - sign_extend(ax, 18);
+        let cy = zero_extend(corner(tile_min_y, !dy.bit(16)), 18) - sign_extend(ay, 18);
+        let edge_min = sign_extend(dy.mul_signed(cy) + dx.mul_signed(cx), EDGE_BITS);
+
+        let edge_row = m.reg(format!("edge_{}_row", k), EDGE_BITS);
+        let edge = m.reg(format!("edge_{}", k), EDGE_BITS);
+        (edge_min, step_x, step_y, edge_row, edge)
+    }).collect::<Vec<_>>();
+    let tile_passes = !edge_test_enable | edges.iter().fold(m.high(), |acc, (_, _, _, _, edge)| acc & !edge.value.bit(EDGE_BITS - 1));
+
+    // Control
+    let primitive_index = m.reg("primitive_index", TEX_WORD_ADDR_BITS);
+
+    let primitive_vertex = m.reg("primitive_vertex", 2);
+    let primitive_word = m.reg("primitive_word", SETUP_VERTEX_WORD_BITS);
+    let primitive_value_index = m.reg("primitive_value_index", 5);
+    let primitive_value_last = primitive_value_index.value.eq(m.lit(3 * SETUP_VERTEX_WORDS - 1, 5));
+
+    let tile_done = (in_tile_test & !tile_passes) | (in_tile_append & (list_full | replica_bus_ready));
+    let tile_advance_x = tile_done & !tile_x_last;
+    let tile_advance_y = tile_done & tile_x_last & !tile_y_last;
+    let triangle_done = tile_done & tile_x_last & tile_y_last;
+
+    let next_state = if_(begin, {
+        m.lit(state_clear, state_bit_width)
+    }).else_if(triangle, {
+        m.lit(state_read_vertices, state_bit_width)
+    }).else_if(end, {
+        m.lit(state_end_read, state_bit_width)
+    }).else_if(in_clear & tile_index_last, {
+        m.lit(state_idle, state_bit_width)
+    }).else_if(in_read_vertices & read_counter_last, {
+        m.lit(state_classify, state_bit_width)
+    }).else_if(in_classify, {
+        cull.mux(m.lit(state_idle, state_bit_width), m.lit(state_edges, state_bit_width))
+    }).else_if(in_edges, {
+        m.lit(state_primitive_read, state_bit_width)
+    }).else_if(in_primitive_read, {
+        m.lit(state_primitive_write, state_bit_width)
+    }).else_if(in_primitive_write & replica_bus_ready, {
+        primitive_value_last.mux(m.lit(state_tile_test, state_bit_width), m.lit(state_primitive_read, state_bit_width))
+    }).else_if(in_tile_test & tile_passes, {
+        m.lit(state_tile_append, state_bit_width)
+    }).else_if(triangle_done, {
+        m.lit(state_idle, state_bit_width)
+    }).else_if(tile_done, {
+        m.lit(state_tile_test, state_bit_width)
+    }).else_if(in_end_read, {
+        m.lit(state_end_write, state_bit_width)
+    }).else_if(in_end_write & replica_bus_ready, {
+        tile_index_last.mux(m.lit(state_idle, state_bit_width), m.lit(state_end_read, state_bit_width))
+    }).else_({
+        state.value
+    });
+    state.drive_next(next_state);
+
+    primitive_index.drive_next(if_(begin, {
+        m.lit(0u32, TEX_WORD_ADDR_BITS)
+    }).else_if(triangle_done, {
+        primitive_index.value + m.lit(1u32, TEX_WORD_ADDR_BITS)
+    }).else_({
+        primitive_index.value
+    }));
+
+    let (next_primitive_vertex, next_primitive_word, next_primitive_value_index) = if_(in_edges, {
+        (m.lit(0u32, 2), m.lit(0u32, SETUP_VERTEX_WORD_BITS), m.lit(0u32, 5))
+    }).else_if(in_primitive_write & replica_bus_ready, {
+        let primitive_word_last = primitive_word.value.eq(m.lit(SETUP_VERTEX_WORDS - 1, SETUP_VERTEX_WORD_BITS));
+        (
+            primitive_word_last.mux(primitive_vertex.value + m.lit(1u32, 2), primitive_vertex.value),
+            primitive_word_last.mux(m.lit(0u32, SETUP_VERTEX_WORD_BITS), primitive_word.value + m.lit(1u32, SETUP_VERTEX_WORD_BITS)),
+            primitive_value_index.value + m.lit(1u32, 5),
+        )
+    }).else_({
+        (primitive_vertex.value, primitive_word.value, primitive_value_index.value)
+    });
+    primitive_vertex.drive_next(next_primitive_vertex);
+    primitive_word.drive_next(next_primitive_word);
+    primitive_value_index.drive_next(next_primitive_value_index);
+
+    let tile_min_index = (tile_min_y * grid_width).bits(tile_index_bit_width - 1, 0) + zero_extend(tile_min_x, tile_index_bit_width);
+    let grid_width_index = zero_extend(grid_width, tile_index_bit_width);
+    let (next_tile_x, next_tile_y, next_tile_row_index, next_tile_index) = if_(begin | end, {
+        (tile_x.value, tile_y.value, tile_row_index.value, m.lit(0u32, tile_index_bit_width))
+    }).else_if(in_edges, {
+        (tile_min_x, tile_min_y, tile_min_index, tile_min_index)
+    }).else_if(tile_advance_x, {
+        (tile_x.value + m.lit(1u32, BIN_GRID_DIM_BITS), tile_y.value, tile_row_index.value, tile_index.value + m.lit(1u32, tile_index_bit_width))
+    }).else_if(tile_advance_y, {
+        let next_tile_row_index = tile_row_index.value + grid_width_index;
+        (tile_min_x, tile_y.value + m.lit(1u32, BIN_GRID_DIM_BITS), next_tile_row_index, next_tile_row_index)
+    }).else_if(in_clear | (in_end_write & replica_bus_ready), {
+        (tile_x.value, tile_y.value, tile_row_index.value, tile_index.value + m.lit(1u32, tile_index_bit_width))
+    }).else_({
+        (tile_x.value, tile_y.value, tile_row_index.value, tile_index.value)
+    });
+    tile_x.drive_next(next_tile_x);
+    tile_y.drive_next(next_tile_y);
+    tile_row_index.drive_next(next_tile_row_index);
+    tile_index.drive_next(next_tile_index);
+
+    for (edge_min, step_x, step_y, edge_row, edge) in edges.into_iter() {
+        let (next_edge_row, next_edge) = if_(in_edges, {
+            (edge_min, edge_min)
+        }).else_if(tile_advance_x, {
+            (edge_row.value, edge.value + step_x)
+        }).else_if(tile_advance_y, {
+            let next_edge_row = edge_row.value + step_y;
+            (next_edge_row, next_edge_row)
+        }).else_({
+            (edge_row.value, edge.value)
+        });
+        edge_row.drive_next(next_edge_row);
+        edge.drive_next(next_edge);
+    }
+
+    // List lengths
+    let list_append = in_tile_append & !list_full & replica_bus_ready;
+    list_lengths.write_port(
+        tile_index.value,
+        if_(in_clear, {
+            m.lit(0u32, list_entry_bit_width + 1)
+        }).else_if(list_append, {
+            m.low().concat(list_length + m.lit(1u32, list_entry_bit_width))
+        }).else_({
+            m.high().concat(list_length)
+        }),
+        in_clear | (in_tile_append & list_full) | list_append);
+
+    // Output buffer reads
+    m.output("vertex_read_addr", if_(in_read_vertices, {
+        index(read_vertex).concat(read_word)
+    }).else_({
+        index(vertex_order(primitive_vertex.value)).concat(primitive_word.value)
+    }));
+    m.output("vertex_read_enable", (in_read_vertices & !read_counter_last) | in_primitive_read);
+
+    // RAM writes
+    //  Each write covers a single 32-bit value, selected with byte enables
+    let list_addr = list_base + (tile_index.value * list_stride).bits(TEX_WORD_ADDR_BITS - 1, 0) + zero_extend(list_length.bits(list_entry_bit_width - 1, 2), TEX_WORD_ADDR_BITS);
+    let primitive_addr = primitive_base + (primitive_index.value * m.lit(BIN_PRIMITIVE_WORDS, 3)).bits(TEX_WORD_ADDR_BITS - 1, 0) + zero_extend(primitive_value_index.value.bits(4, 2), TEX_WORD_ADDR_BITS);
+    let (write_addr, write_lane, write_value) = if_(in_primitive_write, {
+        (primitive_addr, primitive_value_index.value.bits(1, 0), vertex_read_value)
+    }).else_if(in_tile_append, {
+        (list_addr, list_length.bits(1, 0), zero_extend(primitive_index.value, 32))
+    }).else_({
+        (list_addr, list_length.bits(1, 0), list_overflow.mux(m.lit(BIN_LIST_OVERFLOW, 32), m.lit(BIN_LIST_END, 32)))
+    });
+    m.output("replica_bus_enable", in_primitive_write | (in_tile_append & !list_full) | in_end_write);
+    m.output("replica_bus_addr", write_addr);
+    m.output("replica_bus_write", m.high());
+    m.output("replica_bus_write_data", write_value.repeat(4));
+    m.output("replica_bus_write_byte_enable", (0..4u32).fold(m.lit(0u32, 16), |acc, lane| {
+        write_lane.eq(m.lit(lane, 2)).mux(m.lit(0xfu32 << (lane * 4), 16), acc)
+    }));
+
+    m
+}
@@ -0,0 +1,119 @@
+use super::*;
+
+use kaze::*;
+
+pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
+    let m = c.module("CommandProcessor");
+
+    let start = m.input("start", 1);
+    let start_addr = m.input("start_addr", TEX_WORD_ADDR_BITS);
+
+    let rasterizer_idle = m.input("rasterizer_idle", 1);
+    let start_pending = m.input("start_pending", 1);
+    let tile_transfer_idle = m.input("tile_transfer_idle", 1);
+
+    let replica_bus_ready = m.input("replica_bus_ready", 1);
+    let replica_bus_read_data = m.input("replica_bus_read_data", 128);
+    let replica_bus_read_data_valid = m.input("replica_bus_read_data_valid", 1);
+
+    let state_bit_width = 2;
+    let state_idle = 0u32;
+    let state_fetch = 1u32;
+    let state_fetch_wait = 2u32;
+    let state_execute = 3u32;
+    let state = m.reg("state", state_bit_width);
+    state.default_value(state_idle);
+    m.output("active", !state.value.eq(m.lit(state_idle, state_bit_width)));
+
+    let in_fetch = state.value.eq(m.lit(state_fetch, state_bit_width));
+    let in_fetch_wait = state.value.eq(m.lit(state_fetch_wait, state_bit_width));
+    let in_execute = state.value.eq(m.lit(state_execute, state_bit_width));
+
+    let pc = m.reg("pc", TEX_WORD_ADDR_BITS);
+    let command_word = m.reg("command_word", 128);
+    command_word.drive_next(if_(in_fetch_wait & replica_bus_read_data_valid, {
+        replica_bus_read_data
+    }).else_({
+        command_word.value
+    }));
+    //  Selects which of the two commands in the current word is executing
+    let half = m.reg("half", 1);
+
+    let command = half.value.mux(command_word.value.bits(127, COMMAND_BITS), command_word.value.bits(COMMAND_BITS - 1, 0));
+    let opcode = command.bits(COMMAND_OPCODE_BITS - 1, 0);
+    let reg_addr = command.bits(COMMAND_ARG_BIT_OFFSET + REG_BUS_ADDR_BIT_WIDTH - 1, COMMAND_ARG_BIT_OFFSET);
+    let data = command.bits(COMMAND_DATA_BIT_OFFSET + 31, COMMAND_DATA_BIT_OFFSET);
+    let word_addr = command.bits(COMMAND_DATA_BIT_OFFSET + TEX_WORD_ADDR_BITS - 1, COMMAND_DATA_BIT_OFFSET);
+
+    let is_opcode = |x: u32| opcode.eq(m.lit(x, COMMAND_OPCODE_BITS));
+    let is_end = is_opcode(COMMAND_OPCODE_END);
+    let is_write_reg = is_opcode(COMMAND_OPCODE_WRITE_REG);
+    let is_start = is_opcode(COMMAND_OPCODE_START);
+    let is_wait_idle = is_opcode(COMMAND_OPCODE_WAIT_IDLE);
+    let is_wait_start = is_opcode(COMMAND_OPCODE_WAIT_START);
+    let is_tile_transfer = is_opcode(COMMAND_OPCODE_TILE_LOAD) | is_opcode(COMMAND_OPCODE_TILE_STORE) | is_opcode(COMMAND_OPCODE_TILE_CLEAR);
+    let is_jump = is_opcode(COMMAND_OPCODE_JUMP);
+    let is_tile_swap = is_opcode(COMMAND_OPCODE_TILE_SWAP);
+
+    // Reg writes
+    m.output("reg_write_enable", in_execute & (is_write_reg | is_start));
+    m.output("reg_write_addr", is_start.mux(m.lit(REG_START_ADDR, REG_BUS_ADDR_BIT_WIDTH), reg_addr));
+    m.output("reg_write_data", data);
+
+    // Tile transfers
+    //  Transfers are handed off to the tile transfer unit, which works on the transfer bank in the background
+    m.output("tile_transfer_start", in_execute & is_tile_transfer & tile_transfer_idle);
+    m.output("tile_transfer_opcode", opcode);
+    m.output("tile_transfer_tile_buffer", command.bits(COMMAND_ARG_BIT_OFFSET + COMMAND_TILE_BUFFER_BITS - 1, COMMAND_ARG_BIT_OFFSET));
+    m.output("tile_transfer_word_addr", word_addr);
+    m.output("tile_transfer_pitch", command.bits(COMMAND_TILE_PITCH_BIT_OFFSET + TEX_WORD_ADDR_BITS - 1, COMMAND_TILE_PITCH_BIT_OFFSET));
+
+    // Tile buffer banks
+    let render_bank = m.reg("render_bank", 1);
+    render_bank.default_value(false);
+    m.output("render_bank", render_bank.value);
+    let tile_swap = in_execute & is_tile_swap & rasterizer_idle & tile_transfer_idle;
+    render_bank.drive_next(tile_swap.mux(!render_bank.value, render_bank.value));
+
+    // Replica bus
+    m.output("replica_bus_enable", in_fetch);
+    m.output("replica_bus_addr", pc.value);
+    m.output("replica_bus_write", m.low());
+    m.output("replica_bus_write_data", m.lit(0u32, 128));
+    m.output("replica_bus_write_byte_enable", m.lit(0u32, 16));
+
+    // Control
+    //  Moves on to the other command in the current word, or fetches the next word if both have been executed
+    let (advance_state, advance_pc, advance_half) = if_(half.value, {
+        (m.lit(state_fetch, state_bit_width), pc.value + m.lit(1u32, TEX_WORD_ADDR_BITS), m.low())
+    }).else_({
+        (m.lit(state_execute, state_bit_width), pc.value, m.high())
+    });
+
+    let (next_state, next_pc, next_half) = if_(start, {
+        (m.lit(state_fetch, state_bit_width), start_addr, m.low())
+    }).else_if(in_fetch & replica_bus_ready, {
+        (m.lit(state_fetch_wait, state_bit_width), pc.value, half.value)
+    }).else_if(in_fetch_wait & replica_bus_read_data_valid, {
+        (m.lit(state_execute, state_bit_width), pc.value, half.value)
+    }).else_if(in_execute, {
+        if_(is_end, {
+            (m.lit(state_idle, state_bit_width), pc.value, half.value)
+        }).else_if(is_jump, {
+            (m.lit(state_fetch, state_bit_width), word_addr, m.low())
+        }).else_if((is_wait_idle & !rasterizer_idle) | (is_wait_start & start_pending) | (is_tile_transfer & !tile_transfer_idle) | (is_tile_swap & !tile_swap), {
+            (state.value, pc.value, half.value)
+        }).else_({
+            // Write reg, start, and unrecognized commands all complete immediately, and the rest once they're done waiting
+            (advance_state, advance_pc, advance_half)
+        })
+    }).else_({
+        (state.value, pc.value, half.value)
+    });
+
+    state.drive_next(next_state);
+    pc.drive_next(next_pc);
+    half.drive_next(next_half);
+
+    m
+}
@@ -14,7 +14,7 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let issue_buffer_occupied = m.reg("issue_buffer_occupied", 1);
     issue_buffer_occupied.default_value(false);
 
-    buster::generate(c, "BlockCacheCrossbar", 4, 1, TEX_WORD_ADDR_BITS, 0, 128, 5);
+    buster::generate(c, "BlockCacheCrossbar", 8, 1, TEX_WORD_ADDR_BITS, 0, 128, 5);
     let block_cache_crossbar = m.instance("block_cache_crossbar", "BlockCacheCrossbar");
     block_cache_crossbar.drive_input("replica0_bus_ready", m.input("replica_bus_ready", 1));
     m.output("replica_bus_enable", block_cache_crossbar.output("replica0_bus_enable"));
@@ -24,7 +24,7 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     generate_block_cache(c, "BlockCache");
     let mut acc = None;
-    let block_caches = (0..4).map(|i| {
+    let block_caches = (0..8).map(|i| {
         let block_cache = m.instance(format!("block_cache{}", i), "BlockCache");
         block_cache.drive_input("invalidate", invalidate);
         let addr = m.input(format!("in_tex_buffer{}_read_addr", i), TEX_PIXEL_ADDR_BITS);
@@ -88,12 +88,26 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
         ("z", 16),
 
+        ("fog_coord", FOG_COORD_BITS),
+
         ("depth_test_result", 1),
 
-        ("s_fract", ST_FILTER_FRACT_BITS + 1),
-        ("one_minus_s_fract", ST_FILTER_FRACT_BITS + 1),
-        ("t_fract", ST_FILTER_FRACT_BITS + 1),
-        ("one_minus_t_fract", ST_FILTER_FRACT_BITS + 1),
+        ("stencil", 8),
+
+        ("level0_s_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level0_one_minus_s_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level0_t_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level0_one_minus_t_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level1_s_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level1_one_minus_s_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level1_t_fract", ST_FILTER_FRACT_BITS + 1),
+        ("level1_one_minus_t_fract", ST_FILTER_FRACT_BITS + 1),
+
+        ("lod_fract", TEX_LOD_FRACT_BITS + 1),
+        ("one_minus_lod_fract", TEX_LOD_FRACT_BITS + 1),
+
+        ("tex_buffer_border", 8),
+        ("tex_buffer_texel_select", 8 * TEX_TEXEL_SELECT_BITS),
     ].iter() {
         let in_ = m.input(format!("in_{}", name), *bit_width);
         let reg = m.reg(format!("{}_forward", name), *bit_width);
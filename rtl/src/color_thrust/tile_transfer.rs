@@ -0,0 +1,161 @@
+use super::*;
+
+use kaze::*;
+
+pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
+    let m = c.module("TileTransfer");
+
+    //  Transfer args are latched on start, so the command processor can move on while the transfer runs
+    let start = m.input("start", 1);
+    let start_opcode = m.input("start_opcode", COMMAND_OPCODE_BITS);
+    let start_tile_buffer = m.input("start_tile_buffer", COMMAND_TILE_BUFFER_BITS);
+    let start_word_addr = m.input("start_word_addr", TEX_WORD_ADDR_BITS);
+    //  Only the low bits of the pitch are needed, since addresses wrap
+    let start_pitch = m.input("start_pitch", TEX_WORD_ADDR_BITS);
+
+    let tile_clear_color = m.input("tile_clear_color", 32);
+    let tile_clear_depth = m.input("tile_clear_depth", 16);
+    let tile_clear_stencil = m.input("tile_clear_stencil", 8);
+
+    let replica_bus_ready = m.input("replica_bus_ready", 1);
+    let replica_bus_read_data = m.input("replica_bus_read_data", 128);
+    let replica_bus_read_data_valid = m.input("replica_bus_read_data_valid", 1);
+
+    let state_bit_width = 3;
+    let state_idle = 0u32;
+    let state_load_read = 1u32;
+    let state_load_wait = 2u32;
+    let state_store_read = 3u32;
+    let state_store_write = 4u32;
+    let state_clear = 5u32;
+    let state = m.reg("state", state_bit_width);
+    state.default_value(state_idle);
+    m.output("active", !state.value.eq(m.lit(state_idle, state_bit_width)));
+
+    let in_load_read = state.value.eq(m.lit(state_load_read, state_bit_width));
+    let in_load_wait = state.value.eq(m.lit(state_load_wait, state_bit_width));
+    let in_store_read = state.value.eq(m.lit(state_store_read, state_bit_width));
+    let in_store_write = state.value.eq(m.lit(state_store_write, state_bit_width));
+    let in_clear = state.value.eq(m.lit(state_clear, state_bit_width));
+
+    let is_start_opcode = |x: u32| start_opcode.eq(m.lit(x, COMMAND_OPCODE_BITS));
+    let is_start_color_buffer = start_tile_buffer.eq(m.lit(COMMAND_TILE_BUFFER_COLOR, COMMAND_TILE_BUFFER_BITS));
+    let is_start_depth_buffer = start_tile_buffer.eq(m.lit(COMMAND_TILE_BUFFER_DEPTH, COMMAND_TILE_BUFFER_BITS));
+
+    //  Transfers walk the selected tile buffer one word at a time, so they're simple rather than fast. Clears work the
+    //   same way, writing the buffer's clear value (latched on start, like the other args) to each word in turn.
+    let tile_buffer = m.reg("tile_buffer", COMMAND_TILE_BUFFER_BITS);
+    tile_buffer.drive_next(start.mux(start_tile_buffer, tile_buffer.value));
+    let pitch = m.reg("pitch", TEX_WORD_ADDR_BITS);
+    pitch.drive_next(start.mux(start_pitch, pitch.value));
+    let clear_word = m.reg("clear_word", 128);
+    clear_word.drive_next(start.mux(if_(is_start_color_buffer, {
+        tile_clear_color.repeat(4)
+    }).else_if(is_start_depth_buffer, {
+        tile_clear_depth.repeat(8)
+    }).else_({
+        tile_clear_stencil.repeat(16)
+    }), clear_word.value));
+
+    let tile_index = m.reg("tile_index", TILE_PIXELS_WORDS_BITS);
+    let is_color_buffer = tile_buffer.value.eq(m.lit(COMMAND_TILE_BUFFER_COLOR, COMMAND_TILE_BUFFER_BITS));
+    let is_depth_buffer = tile_buffer.value.eq(m.lit(COMMAND_TILE_BUFFER_DEPTH, COMMAND_TILE_BUFFER_BITS));
+    let is_stencil_buffer = tile_buffer.value.eq(m.lit(COMMAND_TILE_BUFFER_STENCIL, COMMAND_TILE_BUFFER_BITS));
+    let tile_index_last = if_(is_color_buffer, {
+        tile_index.value.eq(m.lit((1u32 << TILE_PIXELS_WORDS_BITS) - 1, TILE_PIXELS_WORDS_BITS))
+    }).else_if(is_depth_buffer, {
+        tile_index.value.eq(m.lit((1u32 << (TILE_PIXELS_WORDS_BITS - 1)) - 1, TILE_PIXELS_WORDS_BITS))
+    }).else_({
+        tile_index.value.eq(m.lit((1u32 << (TILE_PIXELS_WORDS_BITS - 2)) - 1, TILE_PIXELS_WORDS_BITS))
+    });
+    //  Rows are 16 pixels, which is 4 color words, 2 depth words, or 1 stencil word
+    let (tile_col, tile_row_last) = if_(is_color_buffer, {
+        let col = tile_index.value.bits(1, 0);
+        (col, col.eq(m.lit(3u32, 2)))
+    }).else_if(is_depth_buffer, {
+        let col = m.low().concat(tile_index.value.bit(0));
+        (col, col.eq(m.lit(1u32, 2)))
+    }).else_({
+        (m.lit(0u32, 2), m.high())
+    });
+
+    let tile_buffer_write_enable = (in_load_wait & replica_bus_read_data_valid) | in_clear;
+    let tile_buffer_read_enable = in_store_read;
+    let tile_buffer_enable = tile_buffer_write_enable | tile_buffer_read_enable;
+    m.output("color_buffer_enable", tile_buffer_enable & is_color_buffer);
+    m.output("depth_buffer_enable", tile_buffer_enable & is_depth_buffer);
+    m.output("stencil_buffer_enable", tile_buffer_enable & is_stencil_buffer);
+    m.output("tile_buffer_addr", tile_index.value);
+    m.output("tile_buffer_write", tile_buffer_write_enable);
+    m.output("tile_buffer_write_data", in_clear.mux(clear_word.value, replica_bus_read_data));
+
+    //  Tile buffer read values hold until the next read, so a store can wait on the replica bus for as long as it needs to
+    let color_buffer_read_value = m.input("color_buffer_read_value", 128);
+    let depth_buffer_read_value = m.input("depth_buffer_read_value", 128);
+    let stencil_buffer_read_value = m.input("stencil_buffer_read_value", 128);
+    let tile_buffer_read_value = if_(is_color_buffer, {
+        color_buffer_read_value
+    }).else_if(is_depth_buffer, {
+        depth_buffer_read_value
+    }).else_({
+        stencil_buffer_read_value
+    });
+
+    // Replica bus
+    let tile_row_addr = m.reg("tile_row_addr", TEX_WORD_ADDR_BITS);
+    let tile_ram_addr = tile_row_addr.value + m.lit(0u32, TEX_WORD_ADDR_BITS - 2).concat(tile_col);
+    m.output("replica_bus_enable", in_load_read | in_store_write);
+    m.output("replica_bus_addr", tile_ram_addr);
+    m.output("replica_bus_write", in_store_write);
+    m.output("replica_bus_write_data", tile_buffer_read_value);
+    m.output("replica_bus_write_byte_enable", m.lit(0xffffu32, 16));
+
+    // Control
+    let word_done = (in_load_wait & replica_bus_read_data_valid) | (in_store_write & replica_bus_ready) | in_clear;
+
+    tile_row_addr.drive_next(if_(start, {
+        start_word_addr
+    }).else_if(word_done & tile_row_last, {
+        tile_row_addr.value + pitch.value
+    }).else_({
+        tile_row_addr.value
+    }));
+
+    let (next_state, next_tile_index) = if_(start, {
+        let next_state = if_(is_start_opcode(COMMAND_OPCODE_TILE_LOAD), {
+            m.lit(state_load_read, state_bit_width)
+        }).else_if(is_start_opcode(COMMAND_OPCODE_TILE_STORE), {
+            m.lit(state_store_read, state_bit_width)
+        }).else_if(is_start_opcode(COMMAND_OPCODE_TILE_CLEAR), {
+            m.lit(state_clear, state_bit_width)
+        }).else_({
+            m.lit(state_idle, state_bit_width)
+        });
+        (next_state, m.lit(0u32, TILE_PIXELS_WORDS_BITS))
+    }).else_if(in_load_read & replica_bus_ready, {
+        (m.lit(state_load_wait, state_bit_width), tile_index.value)
+    }).else_if(word_done, {
+        if_(tile_index_last, {
+            (m.lit(state_idle, state_bit_width), tile_index.value)
+        }).else_({
+            let next_tile_index = tile_index.value + m.lit(1u32, TILE_PIXELS_WORDS_BITS);
+            let next_state = if_(in_load_wait, {
+                m.lit(state_load_read, state_bit_width)
+            }).else_if(in_clear, {
+                m.lit(state_clear, state_bit_width)
+            }).else_({
+                m.lit(state_store_read, state_bit_width)
+            });
+            (next_state, next_tile_index)
+        })
+    }).else_if(in_store_read, {
+        (m.lit(state_store_write, state_bit_width), tile_index.value)
+    }).else_({
+        (state.value, tile_index.value)
+    });
+
+    state.drive_next(next_state);
+    tile_index.drive_next(next_tile_index);
+
+    m
+}
@@ -0,0 +1,337 @@
+use super::*;
+
+use kaze::*;
+
+// Width of the area (and normalized area) fed to the reciprocal; enough for any triangle with 16-bit vertex positions
+const AREA_BITS: u32 = 33;
+// Width of edge function values at the tile origin
+const EDGE_MIN_BITS: u32 = 40;
+// Significant bits of 1 / area; one more than the reg width keeps interpolants within 2 LSBs even when the difference from
+//  vertex 0's value is close to 2^32
+const RECIPROCAL_BITS: u32 = 33;
+
+pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
+    let m = c.module("TriangleSetup");
+
+    let start = m.input("start", 1);
+    let tile_min_x = m.input("tile_min_x", 16);
+    let tile_min_y = m.input("tile_min_y", 16);
+
+    let vertex_write_addr = m.input("vertex_write_addr", 2 + SETUP_VERTEX_WORD_BITS);
+    let vertex_write_data = m.input("vertex_write_data", 32);
+    let vertex_write_enable = m.input("vertex_write_enable", 1);
+
+    let vertex_copy_start = m.input("vertex_copy_start", 1);
+    let vertex_copy_indices = m.input("vertex_copy_indices", 3 * TRANSFORM_OUTPUT_VERTEX_BITS);
+    let vertex_copy_read_value = m.input("vertex_copy_read_value", 32);
+
+    let state_bit_width = 3;
+    let state_idle = 0u32;
+    let state_edges = 1u32;
+    let state_normalize = 2u32;
+    let state_divide = 3u32;
+    let state_attributes = 4u32;
+    let state = m.reg("state", state_bit_width);
+    state.default_value(state_idle);
+
+    let in_idle = state.value.eq(m.lit(state_idle, state_bit_width));
+    let in_edges = state.value.eq(m.lit(state_edges, state_bit_width));
+    let in_normalize = state.value.eq(m.lit(state_normalize, state_bit_width));
+    let in_divide = state.value.eq(m.lit(state_divide, state_bit_width));
+    let in_attributes = state.value.eq(m.lit(state_attributes, state_bit_width));
+
+    let sign_extend = |x: &'a Signal<'a>, bit_width: u32| -> &'a Signal<'a> {
+        x.bit(x.bit_width() - 1).repeat(bit_width - x.bit_width()).concat(x)
+    };
+    let saturate = |x: &'a Signal<'a>| -> &'a Signal<'a> {
+        let high_bits = x.bits(x.bit_width() - 1, 31);
+        let in_range = high_bits.eq(m.lit(0u32, high_bits.bit_width())) | (!high_bits).eq(m.lit(0u32, high_bits.bit_width()));
+        if_(in_range, {
+            x.bits(31, 0)
+        }).else_if(x.bit(x.bit_width() - 1), {
+            m.lit(0x80000000u32, 32)
+        }).else_({
+            m.lit(0x7fffffffu32, 32)
+        })
+    };
+
+    // Vertex copy
+    //  Reads each setup word of the three source entries from the transform output buffer in turn, writing them to the
+    //   vertex mems a cycle later when the read data arrives
+    let copy_active = m.reg("copy_active", 1);
+    copy_active.default_value(false);
+    let copy_indices = m.reg("copy_indices", 3 * TRANSFORM_OUTPUT_VERTEX_BITS);
+    let copy_vertex = m.reg("copy_vertex", 2);
+    let copy_word = m.reg("copy_word", SETUP_VERTEX_WORD_BITS);
+    let copy_word_last = copy_word.value.eq(m.lit(SETUP_VERTEX_WORDS - 1, SETUP_VERTEX_WORD_BITS));
+    let copy_vertex_last = copy_vertex.value.eq(m.lit(2u32, 2));
+    copy_indices.drive_next(vertex_copy_start.mux(vertex_copy_indices, copy_indices.value));
+    let (next_copy_active, next_copy_vertex, next_copy_word) = if_(vertex_copy_start, {
+        (m.high(), m.lit(0u32, 2), m.lit(0u32, SETUP_VERTEX_WORD_BITS))
+    }).else_if(copy_active.value & copy_word_last, {
+        (!copy_vertex_last, copy_vertex.value + m.lit(1u32, 2), m.lit(0u32, SETUP_VERTEX_WORD_BITS))
+    }).else_if(copy_active.value, {
+        (m.high(), copy_vertex.value, copy_word.value + m.lit(1u32, SETUP_VERTEX_WORD_BITS))
+    }).else_({
+        (copy_active.value, copy_vertex.value, copy_word.value)
+    });
+    copy_active.drive_next(next_copy_active);
+    copy_vertex.drive_next(next_copy_vertex);
+    copy_word.drive_next(next_copy_word);
+
+    let copy_index = if_(copy_vertex.value.eq(m.lit(0u32, 2)), {
+        copy_indices.value.bits(TRANSFORM_OUTPUT_VERTEX_BITS - 1, 0)
+    }).else_if(copy_vertex.value.eq(m.lit(1u32, 2)), {
+        copy_indices.value.bits(TRANSFORM_OUTPUT_VERTEX_BITS * 2 - 1, TRANSFORM_OUTPUT_VERTEX_BITS)
+    }).else_({
+        copy_indices.value.bits(TRANSFORM_OUTPUT_VERTEX_BITS * 3 - 1, TRANSFORM_OUTPUT_VERTEX_BITS * 2)
+    });
+    m.output("vertex_copy_read_addr", copy_index.concat(copy_word.value));
+    m.output("vertex_copy_read_enable", copy_active.value);
+
+    let copy_write_enable = copy_active.value.reg_next_with_default("copy_write_enable", false);
+    let copy_write_addr = copy_vertex.value.concat(copy_word.value).reg_next("copy_write_addr");
+    let vertex_write_addr = copy_write_enable.mux(copy_write_addr, vertex_write_addr);
+    let vertex_write_data = copy_write_enable.mux(vertex_copy_read_value, vertex_write_data);
+    let vertex_write_enable = copy_write_enable | vertex_write_enable;
+
+    // Vertex mems
+    //  Each vertex has its own mem so that all three values of an attribute can be read at once
+    let attribute_index = m.reg("attribute_index", 3);
+    let vertex_read_addr = in_attributes.mux(m.lit(SETUP_VERTEX_WORD_ATTRIBUTES, SETUP_VERTEX_WORD_BITS) + m.lit(0u32, SETUP_VERTEX_WORD_BITS - 3).concat(attribute_index.value), m.lit(SETUP_VERTEX_WORD_XY, SETUP_VERTEX_WORD_BITS));
+    let vertex_read_enable = start | in_attributes;
+    let vertex_values = (0..3u32).map(|i| {
+        let vertex = m.mem(format!("vertex_{}", i), SETUP_VERTEX_WORD_BITS, 32);
+        vertex.write_port(
+            vertex_write_addr.bits(SETUP_VERTEX_WORD_BITS - 1, 0),
+            vertex_write_data,
+            vertex_write_enable & vertex_write_addr.bits(SETUP_VERTEX_WORD_BITS + 1, SETUP_VERTEX_WORD_BITS).eq(m.lit(i, 2)));
+        vertex.read_port(vertex_read_addr, vertex_read_enable)
+    }).collect::<Vec<_>>();
+
+    // Edges
+    //  Edge k is opposite vertex k, so it runs from vertex k + 1 to vertex k + 2. Products of positions have
+    //   2 * SETUP_XY_FRACT_BITS = EDGE_FRACT_BITS fractional bits, so everything here is exact.
+    let x = vertex_values.iter().map(|v| sign_extend(v.bits(15, 0), 17)).collect::<Vec<_>>();
+    let y = vertex_values.iter().map(|v| sign_extend(v.bits(31, 16), 17)).collect::<Vec<_>>();
+
+    //  Sample at pixel centers
+    let tile_min_x_reg = m.reg("tile_min_x", 16);
+    tile_min_x_reg.drive_next(start.mux(tile_min_x, tile_min_x_reg.value));
+    let tile_min_y_reg = m.reg("tile_min_y", 16);
+    tile_min_y_reg.drive_next(start.mux(tile_min_y, tile_min_y_reg.value));
+    let p_x = m.lit(0u32, 2).concat(tile_min_x_reg.value).concat(m.lit(1u32 << (SETUP_XY_FRACT_BITS - 1), SETUP_XY_FRACT_BITS));
+    let p_y = m.lit(0u32, 2).concat(tile_min_y_reg.value).concat(m.lit(1u32 << (SETUP_XY_FRACT_BITS - 1), SETUP_XY_FRACT_BITS));
+
+    let mut edge_dx = Vec::new();
+    let mut edge_dy = Vec::new();
+    let mut edge_min = Vec::new();
+    let mut area = None;
+    for k in 0..3 {
+        let a = (k + 1) % 3;
+        let b = (k + 2) % 3;
+        let dx = y[a] - y[b];
+        let dy = x[b] - x[a];
+        let min = sign_extend(dy.mul_signed(p_y - sign_extend(y[a], 22)), EDGE_MIN_BITS) + sign_extend(dx.mul_signed(p_x - sign_extend(x[a], 22)), EDGE_MIN_BITS);
+
+        //  Twice the triangle's area, which is edge 2's function evaluated at vertex 2. Only positive areas are supported.
+        if k == 2 {
+            area = Some((sign_extend(dy.mul_signed(y[k] - y[a]), 35) + sign_extend(dx.mul_signed(x[k] - x[a]), 35)).bits(AREA_BITS - 1, 0));
+        }
+
+        let dx_reg = m.reg(format!("edge_{}_dx", k), 17);
+        dx_reg.drive_next(in_edges.mux(dx, dx_reg.value));
+        edge_dx.push(dx_reg.value);
+        let dy_reg = m.reg(format!("edge_{}_dy", k), 17);
+        dy_reg.drive_next(in_edges.mux(dy, dy_reg.value));
+        edge_dy.push(dy_reg.value);
+        let min_reg = m.reg(format!("edge_{}_min", k), EDGE_MIN_BITS);
+        min_reg.drive_next(in_edges.mux(min, min_reg.value));
+        edge_min.push(min_reg.value);
+    }
+
+    let area_reg = m.reg("area", AREA_BITS);
+    area_reg.drive_next(in_edges.mux(area.unwrap(), area_reg.value));
+
+    //  Edge gradients have SETUP_XY_FRACT_BITS fractional bits internally
+    let edge_gradient = |x: &'a Signal<'a>, bit_width: u32| -> &'a Signal<'a> {
+        sign_extend(x, bit_width - SETUP_XY_FRACT_BITS).concat(m.lit(0u32, SETUP_XY_FRACT_BITS))
+    };
+
+    // Reciprocal
+    //  The area is normalized so its top bit is set, and then a long division computes the top RECIPROCAL_BITS significant
+    //   bits of its reciprocal, one bit per cycle: reciprocal = (2^(AREA_BITS + RECIPROCAL_BITS - 1) - 1) / normalized_area,
+    //   so 1 / area ~= reciprocal >> shift, where shift = AREA_BITS + RECIPROCAL_BITS - 1 - leading zeros
+    let leading_zeros = leading_zeros(area_reg.value, m);
+    let normalized_area = m.reg("normalized_area", AREA_BITS);
+    normalized_area.drive_next(in_normalize.mux(area_reg.value << leading_zeros, normalized_area.value));
+    let shift = m.reg("shift", 7);
+    shift.drive_next(in_normalize.mux(m.lit(AREA_BITS + RECIPROCAL_BITS - 1, 7) - m.lit(0u32, 1).concat(leading_zeros), shift.value));
+
+    let divide_counter = m.reg("divide_counter", 6);
+    let divide_counter_last = divide_counter.value.eq(m.lit(RECIPROCAL_BITS - 1, 6));
+    divide_counter.drive_next(if_(in_divide, {
+        divide_counter.value + m.lit(1u32, 6)
+    }).else_({
+        m.lit(0u32, 6)
+    }));
+
+    //  The dividend is all ones; its top AREA_BITS - 1 bits make up the initial remainder, which is always less than the
+    //   normalized area
+    let remainder = m.reg("remainder", AREA_BITS + 1);
+    let reciprocal = m.reg("reciprocal", RECIPROCAL_BITS);
+    let next_remainder = remainder.value.bits(AREA_BITS - 1, 0).concat(m.high());
+    let divisor = m.lit(0u32, 1).concat(normalized_area.value);
+    let quotient_bit = next_remainder.ge(divisor);
+    let (next_remainder, next_reciprocal) = if_(in_normalize, {
+        (m.lit((1u64 << (AREA_BITS - 1)) - 1, AREA_BITS + 1), reciprocal.value)
+    }).else_if(in_divide, {
+        (quotient_bit.mux(next_remainder - divisor, next_remainder), reciprocal.value.bits(RECIPROCAL_BITS - 2, 0).concat(quotient_bit))
+    }).else_({
+        (remainder.value, reciprocal.value)
+    });
+    remainder.drive_next(next_remainder);
+    reciprocal.drive_next(next_reciprocal);
+
+    // Attributes
+    //  Outputs are issued in interpolant reg order (min, dx, dy for each attribute), one per cycle, and each attribute is
+    //   computed as base + (d1 * e1 + d2 * e2) / area, where dk is the difference between vertex k's value and vertex 0's,
+    //   ek is the corresponding edge value (min, dx, or dy), and base is vertex 0's value for min and 0 otherwise
+    let component_index = m.reg("component_index", 2);
+    let component_index_last = component_index.value.eq(m.lit(2u32, 2));
+    let attribute_index_last = attribute_index.value.eq(m.lit(SETUP_VERTEX_ATTRIBUTES - 1, 3));
+    let issue_addr = m.reg("issue_addr", REG_BUS_ADDR_BIT_WIDTH);
+    let (next_component_index, next_attribute_index, next_issue_addr) = if_(in_attributes, {
+        let next_issue_addr = issue_addr.value + m.lit(1u32, REG_BUS_ADDR_BIT_WIDTH);
+        if_(component_index_last, {
+            (m.lit(0u32, 2), attribute_index.value + m.lit(1u32, 3), next_issue_addr)
+        }).else_({
+            (component_index.value + m.lit(1u32, 2), attribute_index.value, next_issue_addr)
+        })
+    }).else_({
+        (m.lit(0u32, 2), m.lit(0u32, 3), m.lit(REG_R_MIN_ADDR, REG_BUS_ADDR_BIT_WIDTH))
+    });
+    component_index.drive_next(next_component_index);
+    attribute_index.drive_next(next_attribute_index);
+    issue_addr.drive_next(next_issue_addr);
+
+    //  Read stage
+    let mut pipeline_valids = Vec::new();
+    let valid = in_attributes.reg_next_with_default("read_stage_valid", false);
+    pipeline_valids.push(valid);
+    let addr = issue_addr.value.reg_next("read_stage_addr");
+    let component_index = component_index.value.reg_next("read_stage_component_index");
+
+    //  Difference stage
+    let a0 = sign_extend(vertex_values[0], 33);
+    let d1 = sign_extend(vertex_values[1], 33) - a0;
+    let d2 = sign_extend(vertex_values[2], 33) - a0;
+    let (e1, e2, base) = if_(component_index.eq(m.lit(0u32, 2)), {
+        (edge_min[1], edge_min[2], vertex_values[0])
+    }).else_if(component_index.eq(m.lit(1u32, 2)), {
+        (edge_gradient(edge_dx[1], EDGE_MIN_BITS), edge_gradient(edge_dx[2], EDGE_MIN_BITS), m.lit(0u32, 32))
+    }).else_({
+        (edge_gradient(edge_dy[1], EDGE_MIN_BITS), edge_gradient(edge_dy[2], EDGE_MIN_BITS), m.lit(0u32, 32))
+    });
+    let valid = valid.reg_next_with_default("difference_stage_valid", false);
+    pipeline_valids.push(valid);
+    let addr = addr.reg_next("difference_stage_addr");
+    let d1 = d1.reg_next("difference_stage_d1");
+    let d2 = d2.reg_next("difference_stage_d2");
+    let e1 = e1.reg_next("difference_stage_e1");
+    let e2 = e2.reg_next("difference_stage_e2");
+    let mut base = base.reg_next("difference_stage_base");
+
+    //  Product stage
+    let mut valid = valid;
+    let mut addr = addr;
+    let mut p1 = d1.mul_signed(e1);
+    let mut p2 = d2.mul_signed(e2);
+    //  Buffer/pipeline regs to meet timing for multiplies
+    for i in 0..2 {
+        valid = valid.reg_next_with_default(format!("product_stage_buffer_{}_valid", i), false);
+        pipeline_valids.push(valid);
+        addr = addr.reg_next(format!("product_stage_buffer_{}_addr", i));
+        p1 = p1.reg_next(format!("product_stage_buffer_{}_p1", i));
+        p2 = p2.reg_next(format!("product_stage_buffer_{}_p2", i));
+        base = base.reg_next(format!("product_stage_buffer_{}_base", i));
+    }
+
+    //  Sum stage
+    let sum = sign_extend(p1, p1.bit_width() + 1) + sign_extend(p2, p2.bit_width() + 1);
+    let mut valid = valid.reg_next_with_default("sum_stage_valid", false);
+    pipeline_valids.push(valid);
+    let mut addr = addr.reg_next("sum_stage_addr");
+    let sum = sum.reg_next("sum_stage_sum");
+    let mut base = base.reg_next("sum_stage_base");
+
+    //  Scale stage
+    let mut scaled = sum.mul_signed(m.lit(0u32, 1).concat(reciprocal.value));
+    for i in 0..2 {
+        valid = valid.reg_next_with_default(format!("scale_stage_buffer_{}_valid", i), false);
+        pipeline_valids.push(valid);
+        addr = addr.reg_next(format!("scale_stage_buffer_{}_addr", i));
+        scaled = scaled.reg_next(format!("scale_stage_buffer_{}_scaled", i));
+        base = base.reg_next(format!("scale_stage_buffer_{}_base", i));
+    }
+
+    //  Output stage
+    //   The shift is at least RECIPROCAL_BITS, so the shifted value always fits in sum's width
+    let result = scaled.shr_arithmetic(shift.value).bits(sum.bit_width() - 1, 0) + sign_extend(base, sum.bit_width());
+    let attribute_write_enable = valid.reg_next_with_default("output_stage_valid", false);
+    pipeline_valids.push(attribute_write_enable);
+    let attribute_write_addr = addr.reg_next("output_stage_addr");
+    //  Interpolants are stepped with wrapping adds, so truncating is exact where saturating wouldn't be
+    let attribute_write_data = result.bits(31, 0).reg_next("output_stage_data");
+
+    // Reg writes
+    //  Edge regs are written while the reciprocal is computed, and attribute regs as they come out of the pipeline
+    let edge_write_enable = in_divide & divide_counter.value.lt(m.lit(9u32, 6));
+    let edge_write_data = (0..9u32).fold(m.lit(0u32, 32), |acc, i| {
+        let k = (i / 3) as usize;
+        let value = match i % 3 {
+            0 => saturate(edge_min[k]),
+            1 => edge_gradient(edge_dx[k], 32),
+            _ => edge_gradient(edge_dy[k], 32),
+        };
+        divide_counter.value.eq(m.lit(i, 6)).mux(value, acc)
+    });
+    m.output("reg_write_enable", edge_write_enable | attribute_write_enable);
+    m.output("reg_write_addr", edge_write_enable.mux(m.lit(REG_W0_MIN_ADDR, REG_BUS_ADDR_BIT_WIDTH) + m.lit(0u32, REG_BUS_ADDR_BIT_WIDTH - divide_counter.value.bit_width()).concat(divide_counter.value), attribute_write_addr));
+    m.output("reg_write_data", edge_write_enable.mux(edge_write_data, attribute_write_data));
+
+    // Control
+    let next_state = if_(in_idle & start, {
+        m.lit(state_edges, state_bit_width)
+    }).else_if(in_edges, {
+        m.lit(state_normalize, state_bit_width)
+    }).else_if(in_normalize, {
+        m.lit(state_divide, state_bit_width)
+    }).else_if(in_divide & divide_counter_last, {
+        m.lit(state_attributes, state_bit_width)
+    }).else_if(in_attributes & attribute_index_last & component_index_last, {
+        m.lit(state_idle, state_bit_width)
+    }).else_({
+        state.value
+    });
+    state.drive_next(next_state);
+
+    //  Attributes are still in flight for a few cycles after the last one is issued
+    m.output("active", pipeline_valids.into_iter().fold(!in_idle | copy_active.value | copy_write_enable, |acc, valid| acc | valid));
+
+    m
+}
+
+fn leading_zeros<'a>(x: &'a Signal<'a>, m: &'a Module<'a>) -> &'a Signal<'a> {
+    let mut ret = m.lit(0u32, 6);
+
+    for i in 0..x.bit_width() {
+        ret = if_(x.bit(i), {
+            m.lit(x.bit_width() - 1 - i, 6)
+        }).else_({
+            ret
+        });
+    }
+
+    ret
+}
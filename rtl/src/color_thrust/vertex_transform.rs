@@ -0,0 +1,670 @@
+use crate::approx_reciprocal;
+use super::*;
+
+use kaze::*;
+
+// Fractional bits of clip coordinates, which are stored like positions
+const CLIP_FRACT_BITS: u32 = TRANSFORM_POSITION_FRACT_BITS;
+// Fractional bits of normalized device coordinates
+const NDC_FRACT_BITS: u32 = 24;
+// Fractional bits of lighting intermediates (transformed normals, diffuse terms, and lit colors)
+const LIGHTING_FRACT_BITS: u32 = 16;
+// Fractional bits of color channels expanded from 8 bits, so that 255 maps to exactly 1
+const COLOR_CHANNEL_FRACT_BITS: u32 = 8;
+
+// The reciprocal of w is computed as (2^RECIPROCAL_DIVIDEND_BITS - 1) / (w << leading zeros), so a product with it needs
+//  to be shifted by RECIPROCAL_DIVIDEND_BITS - leading zeros (less the product's desired fractional bits) to divide by w
+const RECIPROCAL_BITS: u32 = 32;
+const RECIPROCAL_DIVIDEND_BITS: u32 = RECIPROCAL_BITS * 2 - 1;
+
+const OPERAND_BITS: u32 = 33;
+const ACCUMULATOR_BITS: u32 = OPERAND_BITS * 2 + 2;
+const PRODUCT_BUFFER_STAGES: u32 = 2;
+// Cycles from when a step is issued to when a temp it stores can be read by another step
+const STORE_LATENCY: usize = PRODUCT_BUFFER_STAGES as usize + 2;
+
+const TEMP_CLIP_X: u32 = 0;
+const TEMP_CLIP_Y: u32 = 1;
+const TEMP_CLIP_Z: u32 = 2;
+const TEMP_CLIP_W: u32 = 3;
+const TEMP_NDC: u32 = 4; // x, y, z
+const TEMP_WINDOW_X: u32 = 7;
+const TEMP_WINDOW_Y: u32 = 8;
+const TEMP_Z: u32 = 9;
+const TEMP_W_INVERSE: u32 = 10;
+const TEMP_S_SCALED: u32 = 11;
+const TEMP_T_SCALED: u32 = 12;
+const TEMP_S: u32 = 13;
+const TEMP_T: u32 = 14;
+const TEMP_NORMAL_X: u32 = 15;
+const TEMP_NORMAL_Y: u32 = 16;
+const TEMP_NORMAL_Z: u32 = 17;
+const TEMP_DIFFUSE: u32 = 18; // One per light
+const TEMP_LIT: u32 = TEMP_DIFFUSE + TRANSFORM_LIGHTS; // r, g, b
+const TEMP_R: u32 = TEMP_LIT + 3;
+const TEMP_G: u32 = TEMP_R + 1;
+const TEMP_B: u32 = TEMP_R + 2;
+const TEMP_A: u32 = TEMP_R + 3;
+const NUM_TEMPS: u32 = TEMP_A + 1;
+const TEMP_INDEX_BITS: u32 = 5;
+
+#[derive(Clone, Copy)]
+enum Channel {
+    R,
+    G,
+    B,
+    A,
+}
+
+impl Channel {
+    // Byte index within an ARGB8888 color
+    fn byte_index(&self) -> u32 {
+        match self {
+            Channel::R => 2,
+            Channel::G => 1,
+            Channel::B => 0,
+            Channel::A => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum OperandA {
+    Temp(u32),
+    PositionX,
+    PositionY,
+    PositionZ,
+    TexCoordS,
+    TexCoordT,
+    NormalX,
+    NormalY,
+    NormalZ,
+    Const(u32),
+}
+
+impl OperandA {
+    fn select(&self) -> u32 {
+        match *self {
+            OperandA::Temp(temp) => temp,
+            OperandA::PositionX => NUM_TEMPS,
+            OperandA::PositionY => NUM_TEMPS + 1,
+            OperandA::PositionZ => NUM_TEMPS + 2,
+            OperandA::TexCoordS => NUM_TEMPS + 3,
+            OperandA::TexCoordT => NUM_TEMPS + 4,
+            OperandA::NormalX => NUM_TEMPS + 5,
+            OperandA::NormalY => NUM_TEMPS + 6,
+            OperandA::NormalZ => NUM_TEMPS + 7,
+            OperandA::Const(_) => NUM_TEMPS + 8,
+        }
+    }
+}
+
+const OPERAND_A_SELECT_BITS: u32 = 6;
+
+#[derive(Clone, Copy)]
+enum OperandB {
+    Param(u32),
+    // Expanded to COLOR_CHANNEL_FRACT_BITS, and forced to 1 if lighting is disabled
+    AmbientColor(Channel),
+    // Expanded to COLOR_CHANNEL_FRACT_BITS, and forced to 0 if lighting or the light is disabled
+    LightColor(u32, Channel),
+    Reciprocal,
+    // Expanded to COLOR_CHANNEL_FRACT_BITS
+    VertexColor(Channel),
+}
+
+const OPERAND_B_SELECT_PARAM: u32 = 0;
+const OPERAND_B_SELECT_PARAM_COLOR: u32 = 1;
+const OPERAND_B_SELECT_RECIPROCAL: u32 = 2;
+const OPERAND_B_SELECT_VERTEX_COLOR: u32 = 3;
+const OPERAND_B_SELECT_BITS: u32 = 2;
+
+#[derive(Clone, Copy)]
+enum Shift {
+    Const(u32),
+    // Shifts by RECIPROCAL_DIVIDEND_BITS - fract bits - w's leading zeros, dividing by w
+    Reciprocal(u32),
+}
+
+#[derive(Clone, Copy)]
+enum Clamp {
+    None,
+    Zero,
+    // [0, 1] with LIGHTING_FRACT_BITS
+    Unit,
+    I16,
+}
+
+impl Clamp {
+    fn range(&self) -> (i64, i64) {
+        match self {
+            Clamp::None => (i32::MIN as _, i32::MAX as _),
+            Clamp::Zero => (0, i32::MAX as _),
+            Clamp::Unit => (0, 1 << LIGHTING_FRACT_BITS),
+            Clamp::I16 => (i16::MIN as _, i16::MAX as _),
+        }
+    }
+}
+
+const CLAMPS: [Clamp; 4] = [Clamp::None, Clamp::Zero, Clamp::Unit, Clamp::I16];
+
+#[derive(Clone, Copy)]
+struct Store {
+    temp: u32,
+    shift: Shift,
+    clamp: Clamp,
+}
+
+#[derive(Clone, Copy)]
+enum Step {
+    // acc = (accumulate ? acc : 0) + a * b, optionally storing clamp(acc >> shift) to a temp
+    MultiplyAdd {
+        a: OperandA,
+        b: OperandB,
+        accumulate: bool,
+        store: Option<Store>,
+    },
+    // Computes the reciprocal of a temp (w), stalling issue until it's done
+    Reciprocal(u32),
+    Nop,
+}
+
+impl Step {
+    fn temp_read(&self) -> Option<u32> {
+        match *self {
+            Step::MultiplyAdd { a: OperandA::Temp(temp), .. } => Some(temp),
+            Step::Reciprocal(temp) => Some(temp),
+            _ => None,
+        }
+    }
+
+    fn temp_written(&self) -> Option<u32> {
+        match *self {
+            Step::MultiplyAdd { store: Some(store), .. } => Some(store.temp),
+            _ => None,
+        }
+    }
+}
+
+fn sum_of_products(steps: &mut Vec<Step>, terms: &[(OperandA, OperandB)], store: Store) {
+    for (i, &(a, b)) in terms.iter().enumerate() {
+        steps.push(Step::MultiplyAdd {
+            a,
+            b,
+            accumulate: i > 0,
+            store: if i == terms.len() - 1 { Some(store) } else { None },
+        });
+    }
+}
+
+fn store(temp: u32, shift: Shift) -> Store {
+    Store {
+        temp,
+        shift,
+        clamp: Clamp::None,
+    }
+}
+
+fn store_clamped(temp: u32, shift: Shift, clamp: Clamp) -> Store {
+    Store {
+        temp,
+        shift,
+        clamp,
+    }
+}
+
+fn program() -> Vec<Step> {
+    use OperandA::*;
+    use OperandB::*;
+
+    let mut steps = Vec::new();
+
+    // Clip coordinates
+    for row in 0..4 {
+        let param = TRANSFORM_PARAM_MATRIX + row * 4;
+        sum_of_products(&mut steps, &[
+            (PositionX, Param(param)),
+            (PositionY, Param(param + 1)),
+            (PositionZ, Param(param + 2)),
+            (Const(1 << TRANSFORM_POSITION_FRACT_BITS), Param(param + 3)),
+        ], store(TEMP_CLIP_X + row, Shift::Const(TRANSFORM_PARAM_FRACT_BITS)));
+    }
+
+    // Scaled/biased tex coords
+    for (i, &(tex_coord, temp)) in [(TexCoordS, TEMP_S_SCALED), (TexCoordT, TEMP_T_SCALED)].iter().enumerate() {
+        sum_of_products(&mut steps, &[
+            (tex_coord, Param(TRANSFORM_PARAM_TEX_COORD_SCALE + i as u32)),
+            (Const(1 << TRANSFORM_TEX_COORD_FRACT_BITS), Param(TRANSFORM_PARAM_TEX_COORD_BIAS + i as u32)),
+        ], store(temp, Shift::Const(TRANSFORM_PARAM_FRACT_BITS)));
+    }
+
+    // Lighting
+    //  This doesn't depend on w, so it's done first
+    for row in 0..3 {
+        let param = TRANSFORM_PARAM_NORMAL_MATRIX + row * 3;
+        sum_of_products(&mut steps, &[
+            (NormalX, Param(param)),
+            (NormalY, Param(param + 1)),
+            (NormalZ, Param(param + 2)),
+        ], store(TEMP_NORMAL_X + row, Shift::Const(TRANSFORM_NORMAL_FRACT_BITS + TRANSFORM_PARAM_FRACT_BITS - LIGHTING_FRACT_BITS)));
+    }
+    for light in 0..TRANSFORM_LIGHTS {
+        let param = TRANSFORM_PARAM_LIGHTS + light * TRANSFORM_PARAM_LIGHT_WORDS;
+        sum_of_products(&mut steps, &[
+            (Temp(TEMP_NORMAL_X), Param(param)),
+            (Temp(TEMP_NORMAL_Y), Param(param + 1)),
+            (Temp(TEMP_NORMAL_Z), Param(param + 2)),
+        ], store_clamped(TEMP_DIFFUSE + light, Shift::Const(TRANSFORM_PARAM_FRACT_BITS), Clamp::Zero));
+    }
+    for (i, &channel) in [Channel::R, Channel::G, Channel::B].iter().enumerate() {
+        let mut terms = vec![(Const(1 << LIGHTING_FRACT_BITS), AmbientColor(channel))];
+        terms.extend((0..TRANSFORM_LIGHTS).map(|light| (Temp(TEMP_DIFFUSE + light), LightColor(light, channel))));
+        sum_of_products(&mut steps, &terms, store_clamped(TEMP_LIT + i as u32, Shift::Const(COLOR_CHANNEL_FRACT_BITS), Clamp::Unit));
+    }
+    let color_fract_bits = COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1;
+    sum_of_products(&mut steps, &[
+        (Const(1 << (color_fract_bits - COLOR_CHANNEL_FRACT_BITS)), VertexColor(Channel::A)),
+    ], store(TEMP_A, Shift::Const(0)));
+    for (i, &channel) in [Channel::R, Channel::G, Channel::B].iter().enumerate() {
+        sum_of_products(&mut steps, &[
+            (Temp(TEMP_LIT + i as u32), VertexColor(channel)),
+        ], store(TEMP_R + i as u32, Shift::Const(LIGHTING_FRACT_BITS + COLOR_CHANNEL_FRACT_BITS - color_fract_bits)));
+    }
+
+    // Perspective divide
+    steps.push(Step::Reciprocal(TEMP_CLIP_W));
+    for i in 0..3 {
+        sum_of_products(&mut steps, &[
+            (Temp(TEMP_CLIP_X + i), Reciprocal),
+        ], store(TEMP_NDC + i, Shift::Reciprocal(NDC_FRACT_BITS)));
+    }
+    sum_of_products(&mut steps, &[
+        (Const(1 << CLIP_FRACT_BITS), Reciprocal),
+    ], store(TEMP_W_INVERSE, Shift::Reciprocal(W_INVERSE_FRACT_BITS)));
+    for &(scaled, temp) in [(TEMP_S_SCALED, TEMP_S), (TEMP_T_SCALED, TEMP_T)].iter() {
+        sum_of_products(&mut steps, &[
+            (Temp(scaled), Reciprocal),
+        ], store(temp, Shift::Reciprocal(ST_FRACT_BITS + CLIP_FRACT_BITS - TRANSFORM_TEX_COORD_FRACT_BITS)));
+    }
+
+    // Viewport
+    for &(i, temp, fract_bits, clamp) in [(0, TEMP_WINDOW_X, SETUP_XY_FRACT_BITS, Clamp::I16), (1, TEMP_WINDOW_Y, SETUP_XY_FRACT_BITS, Clamp::I16), (2, TEMP_Z, Z_FRACT_BITS, Clamp::None)].iter() {
+        sum_of_products(&mut steps, &[
+            (Temp(TEMP_NDC + i), Param(TRANSFORM_PARAM_VIEWPORT_SCALE + i)),
+            (Const(1 << NDC_FRACT_BITS), Param(TRANSFORM_PARAM_VIEWPORT_BIAS + i)),
+        ], store_clamped(temp, Shift::Const(NDC_FRACT_BITS + TRANSFORM_PARAM_FRACT_BITS - fract_bits), clamp));
+    }
+
+    // Nothing can read a temp until its store has made it through the pipeline, so pad with nops where needed
+    let mut ret: Vec<Step> = Vec::new();
+    for step in steps {
+        if let Some(temp) = step.temp_read() {
+            while ret.iter().rev().take(STORE_LATENCY - 1).any(|step| step.temp_written() == Some(temp)) {
+                ret.push(Step::Nop);
+            }
+        }
+        ret.push(step);
+    }
+    ret
+}
+
+pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
+    let m = c.module("VertexTransform");
+
+    let param_write_addr = m.input("param_write_addr", TRANSFORM_PARAM_INDEX_BITS);
+    let param_write_data = m.input("param_write_data", 32);
+    let param_write_enable = m.input("param_write_enable", 1);
+
+    let lighting_enable = m.input("lighting_enable", 1);
+    let light_mask = m.input("light_mask", TRANSFORM_LIGHTS);
+
+    let start = m.input("start", 1);
+    let source_addr = m.input("source_addr", TEX_WORD_ADDR_BITS);
+    let vertex_count = m.input("vertex_count", TRANSFORM_OUTPUT_VERTEX_BITS + 1);
+
+    let replica_bus_ready = m.input("replica_bus_ready", 1);
+    let replica_bus_read_data = m.input("replica_bus_read_data", 128);
+    let replica_bus_read_data_valid = m.input("replica_bus_read_data_valid", 1);
+
+    let state_bit_width = 3;
+    let state_idle = 0u32;
+    let state_fetch = 1u32;
+    let state_fetch_wait = 2u32;
+    let state_program = 3u32;
+    let state_divide = 4u32;
+    let state_flush = 5u32;
+    let state_output = 6u32;
+    let state = m.reg("state", state_bit_width);
+    state.default_value(state_idle);
+    m.output("active", !state.value.eq(m.lit(state_idle, state_bit_width)));
+
+    let in_fetch = state.value.eq(m.lit(state_fetch, state_bit_width));
+    let in_fetch_wait = state.value.eq(m.lit(state_fetch_wait, state_bit_width));
+    let in_program = state.value.eq(m.lit(state_program, state_bit_width));
+    let in_divide = state.value.eq(m.lit(state_divide, state_bit_width));
+    let in_flush = state.value.eq(m.lit(state_flush, state_bit_width));
+    let in_output = state.value.eq(m.lit(state_output, state_bit_width));
+
+    let sign_extend = |x: &'a Signal<'a>, bit_width: u32| -> &'a Signal<'a> {
+        x.bit(x.bit_width() - 1).repeat(bit_width - x.bit_width()).concat(x)
+    };
+    let zero_extend = |x: &'a Signal<'a>, bit_width: u32| -> &'a Signal<'a> {
+        m.lit(0u32, bit_width - x.bit_width()).concat(x)
+    };
+    //  Expands an 8-bit color channel to 1.COLOR_CHANNEL_FRACT_BITS, mapping 255 to 1
+    let expand_channel = |color: &'a Signal<'a>, channel: &'a Signal<'a>| -> &'a Signal<'a> {
+        let x = if_(channel.eq(m.lit(0u32, 2)), {
+            color.bits(7, 0)
+        }).else_if(channel.eq(m.lit(1u32, 2)), {
+            color.bits(15, 8)
+        }).else_if(channel.eq(m.lit(2u32, 2)), {
+            color.bits(23, 16)
+        }).else_({
+            color.bits(31, 24)
+        });
+        zero_extend(x, 9) + zero_extend(x.bit(7), 9)
+    };
+
+    // Source vertices
+    let vertex_index = m.reg("vertex_index", TRANSFORM_OUTPUT_VERTEX_BITS);
+    let vertex_count_reg = m.reg("vertex_count", TRANSFORM_OUTPUT_VERTEX_BITS + 1);
+    vertex_count_reg.drive_next(start.mux(vertex_count, vertex_count_reg.value));
+    let vertex_index_last = zero_extend(vertex_index.value, TRANSFORM_OUTPUT_VERTEX_BITS + 1).eq(vertex_count_reg.value - m.lit(1u32, TRANSFORM_OUTPUT_VERTEX_BITS + 1));
+    let vertex_addr = m.reg("vertex_addr", TEX_WORD_ADDR_BITS);
+    let fetch_word = m.reg("fetch_word", 1);
+    let vertex_words = (0..TRANSFORM_VERTEX_WORDS).map(|i| {
+        let word = m.reg(format!("vertex_word_{}", i), 128);
+        word.drive_next(if_(in_fetch_wait & replica_bus_read_data_valid & fetch_word.value.eq(m.lit(i, 1)), {
+            replica_bus_read_data
+        }).else_({
+            word.value
+        }));
+        word.value
+    }).collect::<Vec<_>>();
+    let position_x = vertex_words[0].bits(31, 0);
+    let position_y = vertex_words[0].bits(63, 32);
+    let position_z = vertex_words[0].bits(95, 64);
+    let vertex_color = vertex_words[0].bits(127, 96);
+    let tex_coord_s = vertex_words[1].bits(31, 0);
+    let tex_coord_t = vertex_words[1].bits(63, 32);
+    let normal_x = vertex_words[1].bits(79, 64);
+    let normal_y = vertex_words[1].bits(95, 80);
+    let normal_z = vertex_words[1].bits(111, 96);
+
+    m.output("replica_bus_enable", in_fetch);
+    m.output("replica_bus_addr", vertex_addr.value + zero_extend(fetch_word.value, TEX_WORD_ADDR_BITS));
+
+    // Program
+    //  Steps are issued one per cycle from a table generated from the program, except for the reciprocal step, which
+    //   stalls issue while it's computed
+    let program = program();
+    let pc_bit_width = 7;
+    if program.len() > 1 << pc_bit_width {
+        panic!("Vertex transform program has {} steps, but the pc only has {} bits.", program.len(), pc_bit_width);
+    }
+    let pc = m.reg("pc", pc_bit_width);
+    let step_field = |bit_width: u32, f: &dyn Fn(&Step) -> u32| -> &'a Signal<'a> {
+        program.iter().enumerate().fold(m.lit(0u32, bit_width), |acc, (i, step)| {
+            let value = f(step);
+            if value == 0 {
+                acc
+            } else {
+                pc.value.eq(m.lit(i as u32, pc_bit_width)).mux(m.lit(value, bit_width), acc)
+            }
+        })
+    };
+    let step_is_multiply_add = step_field(1, &|step| match step { Step::MultiplyAdd { .. } => 1, _ => 0 });
+    let step_is_reciprocal = step_field(1, &|step| match step { Step::Reciprocal(_) => 1, _ => 0 });
+    let step_a_select = step_field(OPERAND_A_SELECT_BITS, &|step| match *step {
+        Step::MultiplyAdd { a, .. } => a.select(),
+        Step::Reciprocal(temp) => temp,
+        Step::Nop => 0,
+    });
+    let step_a_const = step_field(32, &|step| match *step { Step::MultiplyAdd { a: OperandA::Const(value), .. } => value, _ => 0 });
+    let step_b_select = step_field(OPERAND_B_SELECT_BITS, &|step| match *step {
+        Step::MultiplyAdd { b: OperandB::AmbientColor(_), .. } | Step::MultiplyAdd { b: OperandB::LightColor(_, _), .. } => OPERAND_B_SELECT_PARAM_COLOR,
+        Step::MultiplyAdd { b: OperandB::Reciprocal, .. } => OPERAND_B_SELECT_RECIPROCAL,
+        Step::MultiplyAdd { b: OperandB::VertexColor(_), .. } => OPERAND_B_SELECT_VERTEX_COLOR,
+        _ => OPERAND_B_SELECT_PARAM,
+    });
+    let step_b_param = step_field(TRANSFORM_PARAM_INDEX_BITS, &|step| match *step {
+        Step::MultiplyAdd { b: OperandB::Param(param), .. } => param,
+        Step::MultiplyAdd { b: OperandB::AmbientColor(_), .. } => TRANSFORM_PARAM_AMBIENT_COLOR,
+        Step::MultiplyAdd { b: OperandB::LightColor(light, _), .. } => TRANSFORM_PARAM_LIGHTS + light * TRANSFORM_PARAM_LIGHT_WORDS + 3,
+        _ => 0,
+    });
+    let step_b_channel = step_field(2, &|step| match *step {
+        Step::MultiplyAdd { b: OperandB::AmbientColor(channel), .. } | Step::MultiplyAdd { b: OperandB::LightColor(_, channel), .. } | Step::MultiplyAdd { b: OperandB::VertexColor(channel), .. } => channel.byte_index(),
+        _ => 0,
+    });
+    //  0 for the ambient color, otherwise 1 + light index
+    let step_b_light = step_field(3, &|step| match *step { Step::MultiplyAdd { b: OperandB::LightColor(light, _), .. } => 1 + light, _ => 0 });
+    let step_accumulate = step_field(1, &|step| match *step { Step::MultiplyAdd { accumulate: true, .. } => 1, _ => 0 });
+    let step_store = step_field(1, &|step| match *step { Step::MultiplyAdd { store: Some(_), .. } => 1, _ => 0 });
+    let step_store_temp = step_field(TEMP_INDEX_BITS, &|step| match *step { Step::MultiplyAdd { store: Some(store), .. } => store.temp, _ => 0 });
+    let step_store_shift = step_field(7, &|step| match *step {
+        Step::MultiplyAdd { store: Some(Store { shift: Shift::Const(shift), .. }), .. } => shift,
+        Step::MultiplyAdd { store: Some(Store { shift: Shift::Reciprocal(fract_bits), .. }), .. } => RECIPROCAL_DIVIDEND_BITS - fract_bits,
+        _ => 0,
+    });
+    let step_store_shift_reciprocal = step_field(1, &|step| match *step { Step::MultiplyAdd { store: Some(Store { shift: Shift::Reciprocal(_), .. }), .. } => 1, _ => 0 });
+    let step_store_clamp = step_field(2, &|step| match *step {
+        Step::MultiplyAdd { store: Some(store), .. } => store.clamp as u32,
+        _ => 0,
+    });
+    let pc_last = pc.value.eq(m.lit(program.len() as u32 - 1, pc_bit_width));
+
+    let temps = (0..NUM_TEMPS).map(|i| m.reg(format!("temp_{}", i), 32)).collect::<Vec<_>>();
+
+    //  Issue stage
+    let a_sources = temps.iter().map(|temp| sign_extend(temp.value, OPERAND_BITS)).chain(vec![
+        sign_extend(position_x, OPERAND_BITS),
+        sign_extend(position_y, OPERAND_BITS),
+        sign_extend(position_z, OPERAND_BITS),
+        sign_extend(tex_coord_s, OPERAND_BITS),
+        sign_extend(tex_coord_t, OPERAND_BITS),
+        sign_extend(normal_x, OPERAND_BITS),
+        sign_extend(normal_y, OPERAND_BITS),
+        sign_extend(normal_z, OPERAND_BITS),
+        zero_extend(step_a_const, OPERAND_BITS),
+    ].into_iter()).collect::<Vec<_>>();
+    let a = a_sources.iter().enumerate().fold(m.lit(0u32, OPERAND_BITS), |acc, (i, &source)| {
+        step_a_select.eq(m.lit(i as u32, OPERAND_A_SELECT_BITS)).mux(source, acc)
+    });
+
+    let params = m.mem("params", TRANSFORM_PARAM_INDEX_BITS, 32);
+    params.write_port(param_write_addr, param_write_data, param_write_enable);
+    let param = params.read_port(step_b_param, in_program);
+
+    let issue_valid = in_program & step_is_multiply_add;
+    let mut valid = issue_valid.reg_next_with_default("operand_stage_valid", false);
+    let mut pipeline_valids = vec![valid];
+    let a = a.reg_next("operand_stage_a");
+    let b_select = step_b_select.reg_next("operand_stage_b_select");
+    let b_channel = step_b_channel.reg_next("operand_stage_b_channel");
+    let b_light = step_b_light.reg_next("operand_stage_b_light");
+    let mut accumulate = step_accumulate.reg_next("operand_stage_accumulate");
+    let mut store = step_store.reg_next("operand_stage_store");
+    let mut store_temp = step_store_temp.reg_next("operand_stage_store_temp");
+    let mut store_shift = step_store_shift.reg_next("operand_stage_store_shift");
+    let mut store_shift_reciprocal = step_store_shift_reciprocal.reg_next("operand_stage_store_shift_reciprocal");
+    let mut store_clamp = step_store_clamp.reg_next("operand_stage_store_clamp");
+
+    //  Reciprocal
+    //   w is normalized so its top bit is set, and then a long division computes its reciprocal, one bit per cycle
+    let w = a_sources.iter().enumerate().fold(m.lit(0u32, 32), |acc, (i, &source)| {
+        step_a_select.eq(m.lit(i as u32, OPERAND_A_SELECT_BITS)).mux(source.bits(31, 0), acc)
+    });
+    let start_reciprocal = in_program & step_is_reciprocal;
+    let w_leading_zeros = m.reg("w_leading_zeros", 5);
+    w_leading_zeros.drive_next(start_reciprocal.mux(approx_reciprocal::leading_zeros(w, m), w_leading_zeros.value));
+    let divisor = m.reg("divisor", 32);
+    divisor.drive_next(start_reciprocal.mux(w << approx_reciprocal::leading_zeros(w, m), divisor.value));
+
+    let divide_counter = m.reg("divide_counter", 5);
+    let divide_counter_last = divide_counter.value.eq(m.lit(RECIPROCAL_BITS - 1, 5));
+    divide_counter.drive_next(in_divide.mux(divide_counter.value + m.lit(1u32, 5), m.lit(0u32, 5)));
+
+    //  The dividend is all ones; its top RECIPROCAL_BITS - 1 bits make up the initial remainder, which is always less
+    //   than the normalized divisor
+    let remainder = m.reg("remainder", RECIPROCAL_BITS + 1);
+    let reciprocal = m.reg("reciprocal", RECIPROCAL_BITS);
+    let next_remainder = remainder.value.bits(RECIPROCAL_BITS - 1, 0).concat(m.high());
+    let quotient_bit = next_remainder.ge(m.lit(0u32, 1).concat(divisor.value));
+    let (next_remainder, next_reciprocal) = if_(start_reciprocal, {
+        (m.lit((1u64 << (RECIPROCAL_BITS - 1)) - 1, RECIPROCAL_BITS + 1), reciprocal.value)
+    }).else_if(in_divide, {
+        (quotient_bit.mux(next_remainder - m.lit(0u32, 1).concat(divisor.value), next_remainder), reciprocal.value.bits(RECIPROCAL_BITS - 2, 0).concat(quotient_bit))
+    }).else_({
+        (remainder.value, reciprocal.value)
+    });
+    remainder.drive_next(next_remainder);
+    reciprocal.drive_next(next_reciprocal);
+
+    //  Multiply stage
+    let light_enable = (0..TRANSFORM_LIGHTS).fold(lighting_enable & b_light.eq(m.lit(0u32, 3)), |acc, light| {
+        acc | (lighting_enable & light_mask.bit(light) & b_light.eq(m.lit(1 + light, 3)))
+    });
+    let param_color = if_(light_enable, {
+        expand_channel(param, b_channel)
+    }).else_if(b_light.eq(m.lit(0u32, 3)), {
+        m.lit(1u32 << COLOR_CHANNEL_FRACT_BITS, 9)
+    }).else_({
+        m.lit(0u32, 9)
+    });
+    let b = if_(b_select.eq(m.lit(OPERAND_B_SELECT_PARAM, OPERAND_B_SELECT_BITS)), {
+        sign_extend(param, OPERAND_BITS)
+    }).else_if(b_select.eq(m.lit(OPERAND_B_SELECT_PARAM_COLOR, OPERAND_B_SELECT_BITS)), {
+        zero_extend(param_color, OPERAND_BITS)
+    }).else_if(b_select.eq(m.lit(OPERAND_B_SELECT_RECIPROCAL, OPERAND_B_SELECT_BITS)), {
+        zero_extend(reciprocal.value, OPERAND_BITS)
+    }).else_({
+        zero_extend(expand_channel(vertex_color, b_channel), OPERAND_BITS)
+    });
+    let mut product = a.mul_signed(b);
+    //  Buffer/pipeline regs to meet timing for multiplies
+    for i in 0..PRODUCT_BUFFER_STAGES {
+        valid = valid.reg_next_with_default(format!("product_stage_buffer_{}_valid", i), false);
+        pipeline_valids.push(valid);
+        product = product.reg_next(format!("product_stage_buffer_{}_product", i));
+        accumulate = accumulate.reg_next(format!("product_stage_buffer_{}_accumulate", i));
+        store = store.reg_next(format!("product_stage_buffer_{}_store", i));
+        store_temp = store_temp.reg_next(format!("product_stage_buffer_{}_store_temp", i));
+        store_shift = store_shift.reg_next(format!("product_stage_buffer_{}_store_shift", i));
+        store_shift_reciprocal = store_shift_reciprocal.reg_next(format!("product_stage_buffer_{}_store_shift_reciprocal", i));
+        store_clamp = store_clamp.reg_next(format!("product_stage_buffer_{}_store_clamp", i));
+    }
+
+    //  Accumulate stage
+    let accumulator = m.reg("accumulator", ACCUMULATOR_BITS);
+    let next_accumulator = accumulate.mux(accumulator.value, m.lit(0u32, ACCUMULATOR_BITS)) + sign_extend(product, ACCUMULATOR_BITS);
+    accumulator.drive_next(valid.mux(next_accumulator, accumulator.value));
+
+    let shift = store_shift_reciprocal.mux(store_shift - zero_extend(w_leading_zeros.value, 7), store_shift);
+    let shifted = next_accumulator.shr_arithmetic(shift);
+    let clamp_lit = |x: i64| m.lit((x as i128 as u128) & ((1u128 << ACCUMULATOR_BITS) - 1), ACCUMULATOR_BITS);
+    let (clamp_min, clamp_max) = CLAMPS.iter().fold((clamp_lit(0), clamp_lit(0)), |(acc_min, acc_max), &clamp| {
+        let (min, max) = clamp.range();
+        let is_clamp = store_clamp.eq(m.lit(clamp as u32, 2));
+        (is_clamp.mux(clamp_lit(min), acc_min), is_clamp.mux(clamp_lit(max), acc_max))
+    });
+    let result = if_(shifted.lt_signed(clamp_min), {
+        clamp_min.bits(31, 0)
+    }).else_if(shifted.gt_signed(clamp_max), {
+        clamp_max.bits(31, 0)
+    }).else_({
+        shifted.bits(31, 0)
+    });
+    let store_enable = valid & store;
+    for (i, temp) in temps.iter().enumerate() {
+        temp.drive_next((store_enable & store_temp.eq(m.lit(i as u32, TEMP_INDEX_BITS))).mux(result, temp.value));
+    }
+
+    // Output
+    //  Words are written in setup vertex order, followed by the clip flags
+    let output_word = m.reg("output_word", SETUP_VERTEX_WORD_BITS);
+    let output_word_last = output_word.value.eq(m.lit(TRANSFORM_OUTPUT_WORD_CLIP_FLAGS, SETUP_VERTEX_WORD_BITS));
+    let clip = |temp: u32| sign_extend(temps[temp as usize].value, 33);
+    let clip_w = clip(TEMP_CLIP_W);
+    let clip_flags = [
+        (TRANSFORM_CLIP_FLAG_LEFT_BIT, clip(TEMP_CLIP_X) + clip_w),
+        (TRANSFORM_CLIP_FLAG_RIGHT_BIT, clip_w - clip(TEMP_CLIP_X)),
+        (TRANSFORM_CLIP_FLAG_BOTTOM_BIT, clip(TEMP_CLIP_Y) + clip_w),
+        (TRANSFORM_CLIP_FLAG_TOP_BIT, clip_w - clip(TEMP_CLIP_Y)),
+        (TRANSFORM_CLIP_FLAG_NEAR_BIT, clip(TEMP_CLIP_Z) + clip_w),
+        (TRANSFORM_CLIP_FLAG_FAR_BIT, clip_w - clip(TEMP_CLIP_Z)),
+    ].iter().fold(m.lit(0u32, 32), |acc, &(bit, distance)| {
+        // Outside when the distance to the plane is negative
+        acc | zero_extend(distance.bit(32), 32) << m.lit(bit, 5)
+    });
+    let output_words = [
+        temps[TEMP_WINDOW_Y as usize].value.bits(15, 0).concat(temps[TEMP_WINDOW_X as usize].value.bits(15, 0)),
+        temps[TEMP_R as usize].value,
+        temps[TEMP_G as usize].value,
+        temps[TEMP_B as usize].value,
+        temps[TEMP_A as usize].value,
+        temps[TEMP_W_INVERSE as usize].value,
+        temps[TEMP_Z as usize].value,
+        temps[TEMP_S as usize].value,
+        temps[TEMP_T as usize].value,
+        clip_flags,
+    ];
+    let output_data = output_words.iter().enumerate().fold(m.lit(0u32, 32), |acc, (i, &word)| {
+        output_word.value.eq(m.lit(i as u32, SETUP_VERTEX_WORD_BITS)).mux(word, acc)
+    });
+    let output_buffer = m.mem("output_buffer", TRANSFORM_OUTPUT_VERTEX_BITS + SETUP_VERTEX_WORD_BITS, 32);
+    output_buffer.write_port(vertex_index.value.concat(output_word.value), output_data, in_output);
+    m.output("output_read_value", output_buffer.read_port(
+        m.input("output_read_addr", TRANSFORM_OUTPUT_VERTEX_BITS + SETUP_VERTEX_WORD_BITS),
+        m.input("output_read_enable", 1)));
+    m.output("bin_read_value", output_buffer.read_port(
+        m.input("bin_read_addr", TRANSFORM_OUTPUT_VERTEX_BITS + SETUP_VERTEX_WORD_BITS),
+        m.input("bin_read_enable", 1)));
+
+    // Control
+    let pipeline_empty = !pipeline_valids.into_iter().fold(m.low(), |acc, valid| acc | valid);
+
+    let (next_state, next_vertex_index, next_vertex_addr, next_fetch_word) = if_(start, {
+        (vertex_count.eq(m.lit(0u32, TRANSFORM_OUTPUT_VERTEX_BITS + 1)).mux(m.lit(state_idle, state_bit_width), m.lit(state_fetch, state_bit_width)), m.lit(0u32, TRANSFORM_OUTPUT_VERTEX_BITS), source_addr, m.low())
+    }).else_if(in_fetch & replica_bus_ready, {
+        (m.lit(state_fetch_wait, state_bit_width), vertex_index.value, vertex_addr.value, fetch_word.value)
+    }).else_if(in_fetch_wait & replica_bus_read_data_valid, {
+        if_(fetch_word.value, {
+            (m.lit(state_program, state_bit_width), vertex_index.value, vertex_addr.value, m.low())
+        }).else_({
+            (m.lit(state_fetch, state_bit_width), vertex_index.value, vertex_addr.value, m.high())
+        })
+    }).else_if(in_program & step_is_reciprocal, {
+        (m.lit(state_divide, state_bit_width), vertex_index.value, vertex_addr.value, fetch_word.value)
+    }).else_if(in_program & pc_last, {
+        (m.lit(state_flush, state_bit_width), vertex_index.value, vertex_addr.value, fetch_word.value)
+    }).else_if(in_divide & divide_counter_last, {
+        (m.lit(state_program, state_bit_width), vertex_index.value, vertex_addr.value, fetch_word.value)
+    }).else_if(in_flush & pipeline_empty, {
+        (m.lit(state_output, state_bit_width), vertex_index.value, vertex_addr.value, fetch_word.value)
+    }).else_if(in_output & output_word_last, {
+        if_(vertex_index_last, {
+            (m.lit(state_idle, state_bit_width), vertex_index.value, vertex_addr.value, fetch_word.value)
+        }).else_({
+            (m.lit(state_fetch, state_bit_width), vertex_index.value + m.lit(1u32, TRANSFORM_OUTPUT_VERTEX_BITS), vertex_addr.value + m.lit(TRANSFORM_VERTEX_WORDS, TEX_WORD_ADDR_BITS), fetch_word.value)
+        })
+    }).else_({
+        (state.value, vertex_index.value, vertex_addr.value, fetch_word.value)
+    });
+    state.drive_next(next_state);
+    vertex_index.drive_next(next_vertex_index);
+    vertex_addr.drive_next(next_vertex_addr);
+    fetch_word.drive_next(next_fetch_word);
+
+    pc.drive_next(if_(in_fetch_wait, {
+        m.lit(0u32, pc_bit_width)
+    }).else_if((in_program & !step_is_reciprocal) | (in_divide & divide_counter_last), {
+        pc.value + m.lit(1u32, pc_bit_width)
+    }).else_({
+        pc.value
+    }));
+
+    output_word.drive_next(in_output.mux(output_word.value + m.lit(1u32, SETUP_VERTEX_WORD_BITS), m.lit(0u32, SETUP_VERTEX_WORD_BITS)));
+
+    m
+}
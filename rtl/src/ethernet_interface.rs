@@ -0,0 +1,336 @@
+use crate::word_mem::*;
+
+use kaze::*;
+
+// Register addresses (in bus words)
+pub const REG_STATUS: u32 = 0;
+pub const REG_MAC_ADDR_LOW: u32 = 1;
+pub const REG_MAC_ADDR_HIGH: u32 = 2;
+pub const REG_RX_LENGTH: u32 = 3;
+pub const REG_RX_RELEASE: u32 = 4;
+pub const REG_TX_LENGTH: u32 = 5;
+pub const REG_RX_DROPPED: u32 = 6;
+
+// Packet buffer windows (in bus words)
+pub const RX_BUFFER_BASE: u32 = 0x100;
+pub const TX_BUFFER_BASE: u32 = 0x200;
+
+const BUFFER_ADDR_BIT_WIDTH: u32 = 7;
+
+// Including FCS; shorter frames are padded on TX and dropped on RX
+pub const MIN_FRAME_SIZE: u32 = 64;
+const FCS_SIZE: u32 = 4;
+const PREAMBLE_SIZE: u32 = 7;
+const SFD: u32 = 0xd5;
+const IFG_SIZE: u32 = 12;
+
+// Reflected CRC-32 as used for the Ethernet FCS; running the CRC over a frame including a valid FCS always
+//  leaves CRC_RESIDUE in the (non-inverted) CRC register
+const CRC_POLY: u32 = 0xedb88320;
+const CRC_RESIDUE: u32 = 0xdebb20e3;
+
+const TX_PHASE_IDLE: u32 = 0;
+const TX_PHASE_PREAMBLE: u32 = 1;
+const TX_PHASE_DATA: u32 = 2;
+const TX_PHASE_FCS: u32 = 3;
+const TX_PHASE_IFG: u32 = 4;
+
+fn crc32_update<'a>(m: &'a Module<'a>, crc: &'a Signal<'a>, data: &'a Signal<'a>) -> &'a Signal<'a> {
+    (0..8).fold(crc, |crc, i| {
+        let shifted = m.lit(0u32, 1).concat(crc.bits(31, 1));
+        (crc.bit(0) ^ data.bit(i)).mux(shifted ^ m.lit(CRC_POLY, 32), shifted)
+    })
+}
+
+// Selects byte `index` of `word`
+fn select_byte<'a>(m: &'a Module<'a>, word: &'a Signal<'a>, index: &'a Signal<'a>) -> &'a Signal<'a> {
+    let num_bytes = word.bit_width() / 8;
+    (1..num_bytes).fold(word.bits(7, 0), |acc, i| if_(index.eq(m.lit(i, index.bit_width())), {
+        word.bits(i * 8 + 7, i * 8)
+    }).else_({
+        acc
+    }))
+}
+
+// Ethernet MAC with single RX/TX packet buffers
+//  The PHY side is a GMII-style byte stream in the system clock domain; each strobe input marks a cycle where the
+//  PHY adapter transfers one byte, so the MAC works for any link rate up to one byte per system clock cycle.
+//  Preamble/SFD, padding, and FCS generation/checking are handled here; everything from the destination MAC
+//  address through the payload lives in the packet buffers. Received frames are filtered by destination address
+//  (our address or broadcast), and while a received frame is held in the RX buffer, further frames are dropped.
+pub fn generate<'a>(c: &'a Context<'a>) -> &'a Module<'a> {
+    let m = c.module("EthernetInterface");
+
+    let bus_enable = m.input("bus_enable", 1);
+    let bus_addr = m.input("bus_addr", 20);
+    let bus_write = m.input("bus_write", 1);
+    let bus_write_data = m.input("bus_write_data", 128);
+    let bus_write_byte_enable = m.input("bus_write_byte_enable", 16);
+    m.output("bus_ready", m.high());
+
+    // 0: regs, 1: RX buffer, 2: TX buffer
+    let bus_region = bus_addr.bits(9, 8);
+    let is_region = |addr: u32| bus_region.eq(m.lit(addr >> 8, 2));
+    let reg_addr = bus_addr.bits(2, 0);
+    let buffer_addr = bus_addr.bits(BUFFER_ADDR_BIT_WIDTH - 1, 0);
+    let reg_write = |reg: u32| bus_enable & bus_write & is_region(0) & reg_addr.eq(m.lit(reg, 3));
+
+    let mac_addr_low = m.reg("mac_addr_low", 32);
+    mac_addr_low.default_value(0u32);
+    mac_addr_low.drive_next(if_(reg_write(REG_MAC_ADDR_LOW), {
+        bus_write_data.bits(31, 0)
+    }).else_({
+        mac_addr_low.value
+    }));
+
+    let mac_addr_high = m.reg("mac_addr_high", 16);
+    mac_addr_high.default_value(0u32);
+    mac_addr_high.drive_next(if_(reg_write(REG_MAC_ADDR_HIGH), {
+        bus_write_data.bits(15, 0)
+    }).else_({
+        mac_addr_high.value
+    }));
+
+    // RX
+    let rx_strobe = m.input("rx_strobe", 1);
+    let rx_dv = m.input("rx_dv", 1);
+    let rx_er = m.input("rx_er", 1);
+    let rx_data = m.input("rx_data", 8);
+
+    let rx_active = m.reg("rx_active", 1);
+    rx_active.default_value(false);
+
+    // One extra bit to detect overlong frames
+    let rx_counter = m.reg("rx_counter", 12);
+    rx_counter.default_value(0u32);
+
+    let rx_crc = m.reg("rx_crc", 32);
+    rx_crc.default_value(0u32);
+
+    let rx_error = m.reg("rx_error", 1);
+    rx_error.default_value(false);
+
+    let rx_dropping = m.reg("rx_dropping", 1);
+    rx_dropping.default_value(false);
+
+    let rx_unicast_match = m.reg("rx_unicast_match", 1);
+    rx_unicast_match.default_value(false);
+
+    let rx_broadcast_match = m.reg("rx_broadcast_match", 1);
+    rx_broadcast_match.default_value(false);
+
+    let rx_frame_available = m.reg("rx_frame_available", 1);
+    rx_frame_available.default_value(false);
+
+    let rx_length = m.reg("rx_length", 12);
+    rx_length.default_value(0u32);
+
+    let rx_dropped = m.reg("rx_dropped", 16);
+    rx_dropped.default_value(0u32);
+
+    let rx_start = !rx_active.value & rx_strobe & rx_dv & rx_data.eq(m.lit(SFD, 8));
+    let rx_byte = rx_active.value & rx_strobe & rx_dv;
+    let rx_end = rx_active.value & rx_strobe & !rx_dv;
+    let rx_overflow = rx_counter.value.bit(11);
+
+    rx_active.drive_next(if_(rx_start, {
+        m.high()
+    }).else_if(rx_end, {
+        m.low()
+    }).else_({
+        rx_active.value
+    }));
+
+    rx_counter.drive_next(if_(rx_start, {
+        m.lit(0u32, 12)
+    }).else_if(rx_byte & !rx_overflow, {
+        rx_counter.value + m.lit(1u32, 12)
+    }).else_({
+        rx_counter.value
+    }));
+
+    rx_crc.drive_next(if_(rx_start, {
+        m.lit(0xffffffffu32, 32)
+    }).else_if(rx_byte, {
+        crc32_update(m, rx_crc.value, rx_data)
+    }).else_({
+        rx_crc.value
+    }));
+
+    rx_error.drive_next(if_(rx_start, {
+        m.low()
+    }).else_if(rx_byte & (rx_er | rx_overflow), {
+        m.high()
+    }).else_({
+        rx_error.value
+    }));
+
+    rx_dropping.drive_next(if_(rx_start, {
+        rx_frame_available.value
+    }).else_({
+        rx_dropping.value
+    }));
+
+    let mac_addr = mac_addr_high.value.concat(mac_addr_low.value);
+    let rx_expected_byte = select_byte(m, mac_addr, rx_counter.value.bits(2, 0));
+    let rx_in_dest_addr = rx_counter.value.lt(m.lit(6u32, 12));
+    rx_unicast_match.drive_next(if_(rx_start, {
+        m.high()
+    }).else_if(rx_byte & rx_in_dest_addr, {
+        rx_unicast_match.value & rx_data.eq(rx_expected_byte)
+    }).else_({
+        rx_unicast_match.value
+    }));
+    rx_broadcast_match.drive_next(if_(rx_start, {
+        m.high()
+    }).else_if(rx_byte & rx_in_dest_addr, {
+        rx_broadcast_match.value & rx_data.eq(m.lit(0xffu32, 8))
+    }).else_({
+        rx_broadcast_match.value
+    }));
+
+    let rx_addr_match = rx_unicast_match.value | rx_broadcast_match.value;
+    let rx_good =
+        !rx_error.value &
+        !rx_dropping.value &
+        rx_crc.value.eq(m.lit(CRC_RESIDUE, 32)) &
+        rx_counter.value.ge(m.lit(MIN_FRAME_SIZE, 12)) &
+        rx_addr_match;
+
+    rx_frame_available.drive_next(if_(rx_end & rx_good, {
+        m.high()
+    }).else_if(reg_write(REG_RX_RELEASE), {
+        m.low()
+    }).else_({
+        rx_frame_available.value
+    }));
+
+    rx_length.drive_next(if_(rx_end & rx_good, {
+        rx_counter.value - m.lit(FCS_SIZE, 12)
+    }).else_({
+        rx_length.value
+    }));
+
+    rx_dropped.drive_next(if_(rx_end & rx_addr_match & !rx_good, {
+        rx_dropped.value + m.lit(1u32, 16)
+    }).else_({
+        rx_dropped.value
+    }));
+
+    let rx_buffer = WordMem::new(m, "rx_buffer", BUFFER_ADDR_BIT_WIDTH, 8, 16);
+    let rx_lane = rx_counter.value.bits(3, 0);
+    let rx_lane_enable = (0..16)
+        .map(|i| rx_lane.eq(m.lit(i as u32, 4)))
+        .rev()
+        .reduce(|acc, x| acc.concat(x))
+        .unwrap();
+    rx_buffer.write_port(
+        rx_counter.value.bits(10, 4),
+        rx_data.repeat(16),
+        rx_byte & !rx_dropping.value & !rx_overflow,
+        rx_lane_enable);
+    let rx_buffer_read_data = rx_buffer.read_port(buffer_addr, bus_enable & !bus_write & is_region(RX_BUFFER_BASE));
+
+    // TX
+    let tx_strobe = m.input("tx_strobe", 1);
+
+    let tx_phase = m.reg("tx_phase", 3);
+    tx_phase.default_value(TX_PHASE_IDLE);
+    let is_tx_phase = |phase: u32| tx_phase.value.eq(m.lit(phase, 3));
+
+    let tx_counter = m.reg("tx_counter", 11);
+    tx_counter.default_value(0u32);
+
+    let tx_length = m.reg("tx_length", 11);
+    tx_length.default_value(0u32);
+
+    let tx_crc = m.reg("tx_crc", 32);
+    tx_crc.default_value(0u32);
+
+    let tx_start = reg_write(REG_TX_LENGTH) & is_tx_phase(TX_PHASE_IDLE);
+
+    let tx_counter_inc = tx_counter.value + m.lit(1u32, 11);
+    let tx_counter_is = |value: u32| tx_counter.value.eq(m.lit(value, 11));
+    let tx_preamble_end = tx_counter_is(PREAMBLE_SIZE);
+    let tx_data_end = tx_counter_inc.ge(tx_length.value) & tx_counter_inc.ge(m.lit(MIN_FRAME_SIZE - FCS_SIZE, 11));
+    let tx_fcs_end = tx_counter_is(FCS_SIZE - 1);
+    let tx_ifg_end = tx_counter_is(IFG_SIZE - 1);
+
+    let (next_tx_phase, next_tx_counter) = if_(tx_start, {
+        (m.lit(TX_PHASE_PREAMBLE, 3), m.lit(0u32, 11))
+    }).else_if(tx_strobe & is_tx_phase(TX_PHASE_PREAMBLE) & tx_preamble_end, {
+        (m.lit(TX_PHASE_DATA, 3), m.lit(0u32, 11))
+    }).else_if(tx_strobe & is_tx_phase(TX_PHASE_DATA) & tx_data_end, {
+        (m.lit(TX_PHASE_FCS, 3), m.lit(0u32, 11))
+    }).else_if(tx_strobe & is_tx_phase(TX_PHASE_FCS) & tx_fcs_end, {
+        (m.lit(TX_PHASE_IFG, 3), m.lit(0u32, 11))
+    }).else_if(tx_strobe & is_tx_phase(TX_PHASE_IFG) & tx_ifg_end, {
+        (m.lit(TX_PHASE_IDLE, 3), m.lit(0u32, 11))
+    }).else_if(tx_strobe & !is_tx_phase(TX_PHASE_IDLE), {
+        (tx_phase.value, tx_counter_inc)
+    }).else_({
+        (tx_phase.value, tx_counter.value)
+    });
+    tx_phase.drive_next(next_tx_phase);
+    tx_counter.drive_next(next_tx_counter);
+
+    tx_length.drive_next(if_(tx_start, {
+        bus_write_data.bits(10, 0)
+    }).else_({
+        tx_length.value
+    }));
+
+    // The buffer read is addressed with the next counter value so that the word for the current byte is always
+    //  available
+    let tx_buffer = WordMem::new(m, "tx_buffer", BUFFER_ADDR_BIT_WIDTH, 8, 16);
+    tx_buffer.write_port(buffer_addr, bus_write_data, bus_enable & bus_write & is_region(TX_BUFFER_BASE), bus_write_byte_enable);
+    let tx_buffer_word = tx_buffer.read_port(next_tx_counter.bits(10, 4), m.high());
+    let tx_buffer_byte = select_byte(m, tx_buffer_word, tx_counter.value.bits(3, 0));
+    let tx_payload_byte = if_(tx_counter.value.lt(tx_length.value), {
+        tx_buffer_byte
+    }).else_({
+        m.lit(0u32, 8)
+    });
+
+    tx_crc.drive_next(if_(tx_start, {
+        m.lit(0xffffffffu32, 32)
+    }).else_if(tx_strobe & is_tx_phase(TX_PHASE_DATA), {
+        crc32_update(m, tx_crc.value, tx_payload_byte)
+    }).else_({
+        tx_crc.value
+    }));
+
+    let tx_data = if_(is_tx_phase(TX_PHASE_PREAMBLE), {
+        tx_preamble_end.mux(m.lit(SFD, 8), m.lit(0x55u32, 8))
+    }).else_if(is_tx_phase(TX_PHASE_DATA), {
+        tx_payload_byte
+    }).else_({
+        select_byte(m, !tx_crc.value, tx_counter.value.bits(1, 0))
+    });
+    m.output("tx_en", is_tx_phase(TX_PHASE_PREAMBLE) | is_tx_phase(TX_PHASE_DATA) | is_tx_phase(TX_PHASE_FCS));
+    m.output("tx_data", tx_data);
+
+    let bus_read_return_region = bus_region.reg_next("bus_read_return_region");
+    let bus_read_return_addr = reg_addr.reg_next("bus_read_return_addr");
+    let read_reg = |reg: u32| bus_read_return_addr.eq(m.lit(reg, 3));
+    m.output("bus_read_data", if_(bus_read_return_region.eq(m.lit(RX_BUFFER_BASE >> 8, 2)), {
+        rx_buffer_read_data
+    }).else_({
+        m.lit(0u32, 96).concat(if_(read_reg(REG_STATUS), {
+            m.lit(0u32, 30).concat(!is_tx_phase(TX_PHASE_IDLE)).concat(rx_frame_available.value)
+        }).else_if(read_reg(REG_MAC_ADDR_LOW), {
+            mac_addr_low.value
+        }).else_if(read_reg(REG_MAC_ADDR_HIGH), {
+            m.lit(0u32, 16).concat(mac_addr_high.value)
+        }).else_if(read_reg(REG_RX_LENGTH), {
+            m.lit(0u32, 20).concat(rx_length.value)
+        }).else_if(read_reg(REG_RX_DROPPED), {
+            m.lit(0u32, 16).concat(rx_dropped.value)
+        }).else_({
+            m.lit(0u32, 32)
+        }))
+    }));
+    m.output("bus_read_data_valid", (bus_enable & !bus_write).reg_next_with_default("bus_read_data_valid", false));
+
+    m
+}
@@ -0,0 +1,200 @@
+use kaze::*;
+
+pub const NUM_BUTTONS: u32 = 4;
+pub const NUM_SWITCHES: u32 = 8;
+pub const PAD_BITS: u32 = 16;
+
+// Register addresses (in bus words)
+pub const REG_BUTTONS: u32 = 0;
+pub const REG_BUTTONS_PRESSED: u32 = 1;
+pub const REG_BUTTONS_RELEASED: u32 = 2;
+pub const REG_SWITCHES: u32 = 3;
+pub const REG_PAD: u32 = 4;
+pub const REG_PAD_PRESSED: u32 = 5;
+pub const REG_PAD_RELEASED: u32 = 6;
+
+// Buttons/switches are sampled at this rate, and a new value is only accepted once it's been stable for
+//  DEBOUNCE_SAMPLES consecutive samples (so ~4ms with the defaults)
+const DEBOUNCE_SAMPLE_RATE: u32 = 1000;
+const DEBOUNCE_SAMPLES: u32 = 4;
+
+// Rate of pad latch/clock half-periods; NES/SNES pads are typically clocked at ~83khz (6us half-periods), but
+//  they're fine being clocked a bit slower
+const PAD_TICK_RATE: u32 = 100000;
+
+pub fn generate<'a>(c: &'a Context<'a>, clock_freq: u32) -> &'a Module<'a> {
+    let m = c.module("InputInterface");
+
+    // Requires external sync FF's
+    let buttons = m.input("buttons", NUM_BUTTONS);
+    let switches = m.input("switches", NUM_SWITCHES);
+    let pad_data = m.input("pad_data", 1);
+
+    let clocks_per_sample = (clock_freq / DEBOUNCE_SAMPLE_RATE).max(1);
+    let sample_counter = m.reg("sample_counter", 32);
+    sample_counter.default_value(0u32);
+    let sample_tick = sample_counter.value.eq(m.lit(clocks_per_sample - 1, 32));
+    sample_counter.drive_next(sample_tick.mux(m.lit(0u32, 32), sample_counter.value + m.lit(1u32, 32)));
+
+    let debounce = |name: &str, raw: &'a Signal<'a>| -> &'a Signal<'a> {
+        let history = m.reg(format!("{}_history", name), DEBOUNCE_SAMPLES);
+        history.default_value(0u32);
+        history.drive_next(if_(sample_tick, {
+            history.value.bits(DEBOUNCE_SAMPLES - 2, 0).concat(raw)
+        }).else_({
+            history.value
+        }));
+
+        let stable = m.reg(format!("{}_stable", name), 1);
+        stable.default_value(false);
+        stable.drive_next(if_(history.value.eq(m.lit((1u32 << DEBOUNCE_SAMPLES) - 1, DEBOUNCE_SAMPLES)), {
+            m.high()
+        }).else_if(history.value.eq(m.lit(0u32, DEBOUNCE_SAMPLES)), {
+            m.low()
+        }).else_({
+            stable.value
+        }));
+
+        stable.value
+    };
+
+    let debounce_bits = |name: &str, raw: &'a Signal<'a>| -> &'a Signal<'a> {
+        (0..raw.bit_width())
+            .map(|i| debounce(&format!("{}{}", name, i), raw.bit(i)))
+            .fold(None, |acc: Option<&'a Signal<'a>>, x| Some(match acc {
+                Some(acc) => x.concat(acc),
+                _ => x
+            }))
+            .unwrap()
+    };
+
+    let buttons_state = debounce_bits("button", buttons);
+    let switches_state = debounce_bits("switch", switches);
+
+    // Serial pad
+    //  A poll is kicked off on each sample tick; the latch is pulsed high for one pad tick, after which each bit
+    //  is shifted in while the clock is low and the clock is pulsed high to advance to the next bit. The pad's
+    //  data line is active-low, so we invert it to get 1 = pressed.
+    let clocks_per_pad_tick = (clock_freq / PAD_TICK_RATE).max(1);
+    let pad_tick_counter = m.reg("pad_tick_counter", 32);
+    pad_tick_counter.default_value(0u32);
+    let pad_tick = pad_tick_counter.value.eq(m.lit(clocks_per_pad_tick - 1, 32));
+    pad_tick_counter.drive_next(pad_tick.mux(m.lit(0u32, 32), pad_tick_counter.value + m.lit(1u32, 32)));
+
+    let pad_state_bit_width = 2;
+    let pad_state_idle = 0u32;
+    let pad_state_latch = 1u32;
+    let pad_state_clock_low = 2u32;
+    let pad_state_clock_high = 3u32;
+    let pad_state = m.reg("pad_state", pad_state_bit_width);
+    pad_state.default_value(pad_state_idle);
+
+    let pad_bit_counter_bit_width = 4;
+    let pad_bit_counter = m.reg("pad_bit_counter", pad_bit_counter_bit_width);
+    pad_bit_counter.default_value(0u32);
+
+    let pad_shift = m.reg("pad_shift", PAD_BITS);
+    pad_shift.default_value(0u32);
+
+    let pad_buttons = m.reg("pad_buttons", PAD_BITS);
+    pad_buttons.default_value(0u32);
+
+    let in_idle = pad_state.value.eq(m.lit(pad_state_idle, pad_state_bit_width));
+    let in_latch = pad_state.value.eq(m.lit(pad_state_latch, pad_state_bit_width));
+    let in_clock_low = pad_state.value.eq(m.lit(pad_state_clock_low, pad_state_bit_width));
+    let in_clock_high = pad_state.value.eq(m.lit(pad_state_clock_high, pad_state_bit_width));
+    let last_bit = pad_bit_counter.value.eq(m.lit(PAD_BITS - 1, pad_bit_counter_bit_width));
+
+    pad_state.drive_next(if_(in_idle & sample_tick, {
+        m.lit(pad_state_latch, pad_state_bit_width)
+    }).else_if(pad_tick & in_latch, {
+        m.lit(pad_state_clock_low, pad_state_bit_width)
+    }).else_if(pad_tick & in_clock_low, {
+        m.lit(pad_state_clock_high, pad_state_bit_width)
+    }).else_if(pad_tick & in_clock_high, {
+        last_bit.mux(m.lit(pad_state_idle, pad_state_bit_width), m.lit(pad_state_clock_low, pad_state_bit_width))
+    }).else_({
+        pad_state.value
+    }));
+
+    pad_bit_counter.drive_next(if_(pad_tick & in_latch, {
+        m.lit(0u32, pad_bit_counter_bit_width)
+    }).else_if(pad_tick & in_clock_high, {
+        pad_bit_counter.value + m.lit(1u32, pad_bit_counter_bit_width)
+    }).else_({
+        pad_bit_counter.value
+    }));
+
+    let next_pad_shift = (!pad_data).concat(pad_shift.value.bits(PAD_BITS - 1, 1));
+    pad_shift.drive_next(if_(pad_tick & in_clock_low, {
+        next_pad_shift
+    }).else_({
+        pad_shift.value
+    }));
+
+    pad_buttons.drive_next(if_(pad_tick & in_clock_high & last_bit, {
+        pad_shift.value
+    }).else_({
+        pad_buttons.value
+    }));
+
+    m.output("pad_latch", in_latch);
+    m.output("pad_clock", in_clock_high);
+
+    // Bus
+    let bus_enable = m.input("bus_enable", 1);
+    let bus_addr = m.input("bus_addr", 20);
+    let bus_write = m.input("bus_write", 1);
+    let bus_write_data = m.input("bus_write_data", 128);
+    let _bus_write_byte_enable = m.input("bus_write_byte_enable", 16);
+    m.output("bus_ready", m.high());
+
+    let reg_addr = bus_addr.bits(2, 0);
+    let reg_write = |reg: u32| bus_enable & bus_write & reg_addr.eq(m.lit(reg, 3));
+
+    // Edge registers are sticky; bits are set on the corresponding edge and cleared by writing 1's to them
+    let edge = |name: &str, state: &'a Signal<'a>, pressed_reg: u32, released_reg: u32| -> (&'a Signal<'a>, &'a Signal<'a>) {
+        let bit_width = state.bit_width();
+        let prev = state.reg_next_with_default(format!("{}_prev", name), 0u32);
+
+        let edge_reg = |suffix: &str, edges: &'a Signal<'a>, reg: u32| {
+            let r = m.reg(format!("{}_{}", name, suffix), bit_width);
+            r.default_value(0u32);
+            let clear = if_(reg_write(reg), {
+                bus_write_data.bits(bit_width - 1, 0)
+            }).else_({
+                m.lit(0u32, bit_width)
+            });
+            r.drive_next((r.value & !clear) | edges);
+            r.value
+        };
+
+        (edge_reg("pressed", state & !prev, pressed_reg), edge_reg("released", !state & prev, released_reg))
+    };
+
+    let (buttons_pressed, buttons_released) = edge("buttons", buttons_state, REG_BUTTONS_PRESSED, REG_BUTTONS_RELEASED);
+    let (pad_pressed, pad_released) = edge("pad", pad_buttons.value, REG_PAD_PRESSED, REG_PAD_RELEASED);
+
+    let bus_read_return_addr = reg_addr.reg_next("bus_read_return_addr");
+    let read_reg = |reg: u32| bus_read_return_addr.eq(m.lit(reg, 3));
+    m.output("bus_read_data", m.lit(0u32, 128 - PAD_BITS).concat(if_(read_reg(REG_BUTTONS), {
+        m.lit(0u32, PAD_BITS - NUM_BUTTONS).concat(buttons_state)
+    }).else_if(read_reg(REG_BUTTONS_PRESSED), {
+        m.lit(0u32, PAD_BITS - NUM_BUTTONS).concat(buttons_pressed)
+    }).else_if(read_reg(REG_BUTTONS_RELEASED), {
+        m.lit(0u32, PAD_BITS - NUM_BUTTONS).concat(buttons_released)
+    }).else_if(read_reg(REG_SWITCHES), {
+        m.lit(0u32, PAD_BITS - NUM_SWITCHES).concat(switches_state)
+    }).else_if(read_reg(REG_PAD), {
+        pad_buttons.value
+    }).else_if(read_reg(REG_PAD_PRESSED), {
+        pad_pressed
+    }).else_if(read_reg(REG_PAD_RELEASED), {
+        pad_released
+    }).else_({
+        m.lit(0u32, PAD_BITS)
+    })));
+    m.output("bus_read_data_valid", (bus_enable & !bus_write).reg_next_with_default("bus_read_data_valid", false));
+
+    m
+}
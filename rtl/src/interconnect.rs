@@ -32,9 +32,9 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
 
     mem.drive_input("primary1_bus_enable", m.input("color_thrust_replica_bus_enable", 1));
     mem.drive_input("primary1_bus_addr", m.input("color_thrust_replica_bus_addr", 13));
-    mem.drive_input("primary1_bus_write", m.low());
-    mem.drive_input("primary1_bus_write_data", m.lit(0u32, 128));
-    mem.drive_input("primary1_bus_write_byte_enable", m.lit(0u32, 16));
+    mem.drive_input("primary1_bus_write", m.input("color_thrust_replica_bus_write", 1));
+    mem.drive_input("primary1_bus_write_data", m.input("color_thrust_replica_bus_write_data", 128));
+    mem.drive_input("primary1_bus_write_byte_enable", m.input("color_thrust_replica_bus_write_byte_enable", 16));
     m.output("color_thrust_replica_bus_ready", mem.output("primary1_bus_ready"));
     m.output("color_thrust_replica_bus_read_data", mem.output("primary1_bus_read_data"));
     m.output("color_thrust_replica_bus_read_data_valid", mem.output("primary1_bus_read_data_valid"));
@@ -48,7 +48,7 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     mem.drive_input("replica0_bus_read_data", m.input("ddr3_interface_bus_read_data", 128));
     mem.drive_input("replica0_bus_read_data_valid", m.input("ddr3_interface_bus_read_data_valid", 1));
 
-    buster::generate(c, "Sys", 1, 7, 24, 4, 128, 5);
+    buster::generate(c, "Sys", 1, 13, 24, 4, 128, 5);
     let sys = m.instance("sys", "Sys");
 
     sys.drive_input("primary0_bus_enable", cpu.output("replica0_bus_enable"));
@@ -123,5 +123,59 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     sys.drive_input("replica6_bus_read_data", m.input("color_thrust_depth_buffer_bus_read_data", 128));
     sys.drive_input("replica6_bus_read_data_valid", m.input("color_thrust_depth_buffer_bus_read_data_valid", 1));
 
+    m.output("input_interface_bus_enable", sys.output("replica7_bus_enable"));
+    m.output("input_interface_bus_addr", sys.output("replica7_bus_addr"));
+    m.output("input_interface_bus_write", sys.output("replica7_bus_write"));
+    m.output("input_interface_bus_write_data", sys.output("replica7_bus_write_data"));
+    m.output("input_interface_bus_write_byte_enable", sys.output("replica7_bus_write_byte_enable"));
+    sys.drive_input("replica7_bus_ready", m.input("input_interface_bus_ready", 1));
+    sys.drive_input("replica7_bus_read_data", m.input("input_interface_bus_read_data", 128));
+    sys.drive_input("replica7_bus_read_data_valid", m.input("input_interface_bus_read_data_valid", 1));
+
+    m.output("spi_flash_interface_bus_enable", sys.output("replica8_bus_enable"));
+    m.output("spi_flash_interface_bus_addr", sys.output("replica8_bus_addr"));
+    m.output("spi_flash_interface_bus_write", sys.output("replica8_bus_write"));
+    m.output("spi_flash_interface_bus_write_data", sys.output("replica8_bus_write_data"));
+    m.output("spi_flash_interface_bus_write_byte_enable", sys.output("replica8_bus_write_byte_enable"));
+    sys.drive_input("replica8_bus_ready", m.input("spi_flash_interface_bus_ready", 1));
+    sys.drive_input("replica8_bus_read_data", m.input("spi_flash_interface_bus_read_data", 128));
+    sys.drive_input("replica8_bus_read_data_valid", m.input("spi_flash_interface_bus_read_data_valid", 1));
+
+    m.output("spi_flash_xip_bus_enable", sys.output("replica9_bus_enable"));
+    m.output("spi_flash_xip_bus_addr", sys.output("replica9_bus_addr"));
+    m.output("spi_flash_xip_bus_write", sys.output("replica9_bus_write"));
+    m.output("spi_flash_xip_bus_write_data", sys.output("replica9_bus_write_data"));
+    m.output("spi_flash_xip_bus_write_byte_enable", sys.output("replica9_bus_write_byte_enable"));
+    sys.drive_input("replica9_bus_ready", m.input("spi_flash_xip_bus_ready", 1));
+    sys.drive_input("replica9_bus_read_data", m.input("spi_flash_xip_bus_read_data", 128));
+    sys.drive_input("replica9_bus_read_data_valid", m.input("spi_flash_xip_bus_read_data_valid", 1));
+
+    m.output("sd_interface_bus_enable", sys.output("replica10_bus_enable"));
+    m.output("sd_interface_bus_addr", sys.output("replica10_bus_addr"));
+    m.output("sd_interface_bus_write", sys.output("replica10_bus_write"));
+    m.output("sd_interface_bus_write_data", sys.output("replica10_bus_write_data"));
+    m.output("sd_interface_bus_write_byte_enable", sys.output("replica10_bus_write_byte_enable"));
+    sys.drive_input("replica10_bus_ready", m.input("sd_interface_bus_ready", 1));
+    sys.drive_input("replica10_bus_read_data", m.input("sd_interface_bus_read_data", 128));
+    sys.drive_input("replica10_bus_read_data_valid", m.input("sd_interface_bus_read_data_valid", 1));
+
+    m.output("ethernet_interface_bus_enable", sys.output("replica11_bus_enable"));
+    m.output("ethernet_interface_bus_addr", sys.output("replica11_bus_addr"));
+    m.output("ethernet_interface_bus_write", sys.output("replica11_bus_write"));
+    m.output("ethernet_interface_bus_write_data", sys.output("replica11_bus_write_data"));
+    m.output("ethernet_interface_bus_write_byte_enable", sys.output("replica11_bus_write_byte_enable"));
+    sys.drive_input("replica11_bus_ready", m.input("ethernet_interface_bus_ready", 1));
+    sys.drive_input("replica11_bus_read_data", m.input("ethernet_interface_bus_read_data", 128));
+    sys.drive_input("replica11_bus_read_data_valid", m.input("ethernet_interface_bus_read_data_valid", 1));
+
+    m.output("color_thrust_stencil_buffer_bus_enable", sys.output("replica12_bus_enable"));
+    m.output("color_thrust_stencil_buffer_bus_addr", sys.output("replica12_bus_addr"));
+    m.output("color_thrust_stencil_buffer_bus_write", sys.output("replica12_bus_write"));
+    m.output("color_thrust_stencil_buffer_bus_write_data", sys.output("replica12_bus_write_data"));
+    m.output("color_thrust_stencil_buffer_bus_write_byte_enable", sys.output("replica12_bus_write_byte_enable"));
+    sys.drive_input("replica12_bus_ready", m.input("color_thrust_stencil_buffer_bus_ready", 1));
+    sys.drive_input("replica12_bus_read_data", m.input("color_thrust_stencil_buffer_bus_read_data", 128));
+    sys.drive_input("replica12_bus_read_data_valid", m.input("color_thrust_stencil_buffer_bus_read_data_valid", 1));
+
     m
 }
@@ -1,27 +1,172 @@
 use kaze::*;
 
-pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
+pub const NUM_LEDS: u32 = 8;
+pub const NUM_DIGITS: u32 = 4;
+
+// Register addresses (in bus words)
+pub const REG_LEDS: u32 = 0;
+pub const REG_LED_BRIGHTNESS_BASE: u32 = 1; // One reg per LED, so 1-8
+pub const REG_DISPLAY_CONTROL: u32 = 9;
+pub const REG_DISPLAY_DATA: u32 = 10;
+pub const REG_DISPLAY_BRIGHTNESS: u32 = 11;
+
+pub const DISPLAY_CONTROL_RAW_BIT: u32 = 4;
+
+// Brightness is a duty cycle of (value / PWM_STEPS), so 0 is off and 255 is fully on
+pub const PWM_STEPS: u32 = 255;
+// LED PWM frequency; each display digit is also driven for one full PWM period, so the whole display is
+//  refreshed at PWM_FREQ / NUM_DIGITS (250hz)
+pub const PWM_FREQ: u32 = 1000;
+
+pub const fn clocks_per_pwm_step(clock_freq: u32) -> u32 {
+    let ret = clock_freq / (PWM_FREQ * PWM_STEPS);
+    if ret > 0 { ret } else { 1 }
+}
+
+// Segment bits are gfedcba
+const HEX_SEGMENTS: [u32; 16] = [
+    0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07,
+    0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e, 0x79, 0x71,
+];
+
+pub fn generate<'a>(c: &'a Context<'a>, clock_freq: u32) -> &'a Module<'a> {
     let m = c.module("LedInterface");
 
-    let leds = m.reg("leds", 8);
+    let leds = m.reg("leds", NUM_LEDS);
     leds.default_value(0u32);
 
+    let led_brightness = (0..NUM_LEDS).map(|i| {
+        let brightness = m.reg(format!("led{}_brightness", i), 8);
+        brightness.default_value(PWM_STEPS);
+        brightness
+    }).collect::<Vec<_>>();
+
+    // Bits 0-3: digit enable, bit 4: raw segment mode (otherwise hex)
+    let display_control = m.reg("display_control", 5);
+    display_control.default_value(0u32);
+
+    let display_data = m.reg("display_data", 32);
+    display_data.default_value(0u32);
+
+    let display_brightness = m.reg("display_brightness", 8);
+    display_brightness.default_value(PWM_STEPS);
+
     let bus_enable = m.input("bus_enable", 1);
-    let _bus_addr = m.input("bus_addr", 20);
+    let bus_addr = m.input("bus_addr", 20);
     let bus_write = m.input("bus_write", 1);
     let bus_write_data = m.input("bus_write_data", 128);
     let _bus_write_byte_enable = m.input("bus_write_byte_enable", 16);
     m.output("bus_ready", m.high());
-    m.output("bus_read_data", m.lit(0u32, 120).concat(leds.value));
-    m.output("bus_read_data_valid", (bus_enable & !bus_write).reg_next_with_default("bus_read_data_valid", false));
 
-    leds.drive_next(if_(bus_enable & bus_write, {
-        bus_write_data.bits(7, 0)
+    let reg_addr = bus_addr.bits(3, 0);
+    let reg_write = |reg: u32| bus_enable & bus_write & reg_addr.eq(m.lit(reg, 4));
+
+    let drive_reg = |reg: &'a Register<'a>, addr: u32| {
+        reg.drive_next(if_(reg_write(addr), {
+            bus_write_data.bits(reg.value.bit_width() - 1, 0)
+        }).else_({
+            reg.value
+        }));
+    };
+    drive_reg(leds, REG_LEDS);
+    for (i, brightness) in led_brightness.iter().enumerate() {
+        drive_reg(brightness, REG_LED_BRIGHTNESS_BASE + i as u32);
+    }
+    drive_reg(display_control, REG_DISPLAY_CONTROL);
+    drive_reg(display_data, REG_DISPLAY_DATA);
+    drive_reg(display_brightness, REG_DISPLAY_BRIGHTNESS);
+
+    // PWM/multiplex timing
+    let clocks_per_pwm_step = clocks_per_pwm_step(clock_freq);
+    let pwm_step_counter = m.reg("pwm_step_counter", 32);
+    pwm_step_counter.default_value(0u32);
+    let pwm_step_tick = pwm_step_counter.value.eq(m.lit(clocks_per_pwm_step - 1, 32));
+    pwm_step_counter.drive_next(pwm_step_tick.mux(m.lit(0u32, 32), pwm_step_counter.value + m.lit(1u32, 32)));
+
+    let pwm_counter = m.reg("pwm_counter", 8);
+    pwm_counter.default_value(0u32);
+    let pwm_period_tick = pwm_step_tick & pwm_counter.value.eq(m.lit(PWM_STEPS - 1, 8));
+    pwm_counter.drive_next(if_(pwm_period_tick, {
+        m.lit(0u32, 8)
+    }).else_if(pwm_step_tick, {
+        pwm_counter.value + m.lit(1u32, 8)
     }).else_({
-        leds.value
+        pwm_counter.value
     }));
 
-    m.output("leds", leds.value);
+    let pwm = |brightness: &'a Signal<'a>| pwm_counter.value.lt(brightness);
+
+    let led_outputs = (0..NUM_LEDS)
+        .map(|i| leds.value.bit(i) & pwm(led_brightness[i as usize].value))
+        .rev()
+        .reduce(|acc, x| acc.concat(x))
+        .unwrap();
+    m.output("leds", led_outputs);
+
+    // Seven-segment display
+    let digit = m.reg("digit", 2);
+    digit.default_value(0u32);
+    digit.drive_next(if_(pwm_period_tick, {
+        digit.value + m.lit(1u32, 2)
+    }).else_({
+        digit.value
+    }));
+
+    let select_digit = |width: u32, bits: &dyn Fn(u32) -> &'a Signal<'a>| -> &'a Signal<'a> {
+        (1..NUM_DIGITS).fold(bits(0), |acc, i| if_(digit.value.eq(m.lit(i, 2)), {
+            bits(i)
+        }).else_({
+            acc
+        }))
+        .bits(width - 1, 0)
+    };
+
+    let hex_value = select_digit(4, &|i| display_data.value.bits(i * 4 + 3, i * 4));
+    let hex_segments = (1..HEX_SEGMENTS.len() as u32).fold(m.lit(HEX_SEGMENTS[0], 7), |acc, i| if_(hex_value.eq(m.lit(i, 4)), {
+        m.lit(HEX_SEGMENTS[i as usize], 7)
+    }).else_({
+        acc
+    }));
+    let hex_decimal_point = select_digit(1, &|i| display_data.value.bit(16 + i));
+    let raw_segments = select_digit(8, &|i| display_data.value.bits(i * 8 + 7, i * 8));
+
+    let segments = if_(display_control.value.bit(DISPLAY_CONTROL_RAW_BIT), {
+        raw_segments
+    }).else_({
+        hex_decimal_point.concat(hex_segments)
+    });
+
+    let digit_enable = select_digit(1, &|i| display_control.value.bit(i));
+    let digit_on = digit_enable & pwm(display_brightness.value);
+    let digits = (0..NUM_DIGITS)
+        .map(|i| digit_on & digit.value.eq(m.lit(i, 2)))
+        .rev()
+        .reduce(|acc, x| acc.concat(x))
+        .unwrap();
+
+    // Both active-high; board-specific polarity is handled at the top level
+    m.output("display_digits", digits);
+    m.output("display_segments", segments);
+
+    let bus_read_return_addr = reg_addr.reg_next("bus_read_return_addr");
+    let read_reg = |reg: u32| bus_read_return_addr.eq(m.lit(reg, 4));
+    let bus_read_data = (0..NUM_LEDS).fold(
+        if_(read_reg(REG_DISPLAY_CONTROL), {
+            m.lit(0u32, 27).concat(display_control.value)
+        }).else_if(read_reg(REG_DISPLAY_DATA), {
+            display_data.value
+        }).else_if(read_reg(REG_DISPLAY_BRIGHTNESS), {
+            m.lit(0u32, 24).concat(display_brightness.value)
+        }).else_({
+            m.lit(0u32, 24).concat(leds.value)
+        }),
+        |acc, i| if_(read_reg(REG_LED_BRIGHTNESS_BASE + i), {
+            m.lit(0u32, 24).concat(led_brightness[i as usize].value)
+        }).else_({
+            acc
+        }));
+    m.output("bus_read_data", m.lit(0u32, 96).concat(bus_read_data));
+    m.output("bus_read_data_valid", (bus_enable & !bus_write).reg_next_with_default("bus_read_data_valid", false));
 
     m
 }
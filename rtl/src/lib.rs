@@ -1,8 +1,10 @@
 pub mod approx_reciprocal;
 pub mod buster;
 pub mod color_thrust;
+pub mod ethernet_interface;
 pub mod fifo;
 pub mod flow_controlled_pipe;
+pub mod input_interface;
 pub mod interconnect;
 pub mod led_interface;
 pub mod marv;
@@ -10,6 +12,8 @@ pub mod marv_interconnect_bridge;
 pub mod mimas_a7;
 pub mod peek_buffer;
 pub mod read_cache;
+pub mod spi;
+pub mod spi_interface;
 pub mod uart;
 pub mod uart_interface;
 pub mod word_mem;
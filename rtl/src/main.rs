@@ -1,8 +1,10 @@
 mod approx_reciprocal;
 mod buster;
 mod color_thrust;
+mod ethernet_interface;
 mod fifo;
 mod flow_controlled_pipe;
+mod input_interface;
 mod interconnect;
 mod led_interface;
 mod marv;
@@ -10,6 +12,8 @@ mod marv_interconnect_bridge;
 mod mimas_a7;
 mod peek_buffer;
 mod read_cache;
+mod spi;
+mod spi_interface;
 mod uart;
 mod uart_interface;
 mod word_mem;
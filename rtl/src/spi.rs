@@ -0,0 +1,89 @@
+use kaze::*;
+
+// SPI mode 0 master (sck idles low, data is sampled on rising edges and shifted out on falling edges), MSB first.
+//  sck half-period is (clock_div + 1) clock cycles.
+pub fn generate_master<'a, S: Into<String>>(c: &'a Context<'a>, mod_name: S) -> &'a Module<'a> {
+    let m = c.module(mod_name);
+
+    let clock_div = m.input("clock_div", 8);
+    let write_enable = m.input("write_enable", 1);
+    let write_data = m.input("write_data", 8);
+    let miso = m.input("miso", 1);
+
+    let busy = m.reg("busy", 1);
+    busy.default_value(false);
+
+    let sck = m.reg("sck", 1);
+    sck.default_value(false);
+
+    let div_counter = m.reg("div_counter", 8);
+    div_counter.default_value(0u32);
+
+    let bit_counter = m.reg("bit_counter", 3);
+    bit_counter.default_value(0u32);
+
+    let shift = m.reg("shift", 8);
+    shift.default_value(0u32);
+
+    let miso_sample = m.reg("miso_sample", 1);
+    miso_sample.default_value(false);
+
+    let start = write_enable & !busy.value;
+    let tick = busy.value & div_counter.value.eq(clock_div);
+    let rising_edge = tick & !sck.value;
+    let falling_edge = tick & sck.value;
+    let last_bit = falling_edge & bit_counter.value.eq(m.lit(7u32, 3));
+
+    busy.drive_next(if_(start, {
+        m.high()
+    }).else_if(last_bit, {
+        m.low()
+    }).else_({
+        busy.value
+    }));
+
+    div_counter.drive_next(if_(start | tick, {
+        m.lit(0u32, 8)
+    }).else_if(busy.value, {
+        div_counter.value + m.lit(1u32, 8)
+    }).else_({
+        div_counter.value
+    }));
+
+    sck.drive_next(if_(tick, {
+        !sck.value
+    }).else_({
+        sck.value
+    }));
+
+    miso_sample.drive_next(if_(rising_edge, {
+        miso
+    }).else_({
+        miso_sample.value
+    }));
+
+    shift.drive_next(if_(start, {
+        write_data
+    }).else_if(falling_edge, {
+        shift.value.bits(6, 0).concat(miso_sample.value)
+    }).else_({
+        shift.value
+    }));
+
+    bit_counter.drive_next(if_(start, {
+        m.lit(0u32, 3)
+    }).else_if(falling_edge, {
+        bit_counter.value + m.lit(1u32, 3)
+    }).else_({
+        bit_counter.value
+    }));
+
+    m.output("ready", !busy.value);
+    m.output("read_data", shift.value);
+    m.output("read_data_valid", last_bit.reg_next_with_default("read_data_valid", false));
+
+    m.output("sck", sck.value);
+    m.output("mosi", shift.value.bit(7));
+
+    m
+}
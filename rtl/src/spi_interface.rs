@@ -0,0 +1,172 @@
+use crate::spi;
+
+use kaze::*;
+
+// Register addresses (in bus words)
+pub const REG_STATUS: u32 = 0;
+pub const REG_CHIP_SELECT: u32 = 1;
+pub const REG_DATA: u32 = 2;
+pub const REG_CLOCK_DIV: u32 = 3;
+
+pub const CLOCK_DIV_DEFAULT: u32 = 3;
+
+// Standard (single-bit, no dummy cycles) flash read command, supported by effectively all SPI NOR flashes
+const XIP_READ_COMMAND: u32 = 0x03;
+// Command byte + 3 address bytes + 16 data bytes
+const XIP_TRANSFER_BYTES: u32 = 20;
+
+pub fn generate<'a, S: Into<String>>(c: &'a Context<'a>, mod_name: S, xip: bool) -> &'a Module<'a> {
+    let mod_name = mod_name.into();
+
+    let m = c.module(&mod_name);
+
+    let master_mod_name = format!("{}Master", mod_name);
+    spi::generate_master(c, &master_mod_name);
+    let master = m.instance("master", &master_mod_name);
+
+    let master_ready = master.output("ready");
+    let master_read_data = master.output("read_data");
+    let master_read_data_valid = master.output("read_data_valid");
+
+    master.drive_input("miso", m.input("miso", 1));
+    m.output("sck", master.output("sck"));
+    m.output("mosi", master.output("mosi"));
+
+    let chip_select = m.reg("chip_select", 1);
+    chip_select.default_value(false);
+
+    let clock_div = m.reg("clock_div", 8);
+    clock_div.default_value(CLOCK_DIV_DEFAULT);
+    master.drive_input("clock_div", clock_div.value);
+
+    let bus_enable = m.input("bus_enable", 1);
+    let bus_addr = m.input("bus_addr", 20);
+    let bus_write = m.input("bus_write", 1);
+    let bus_write_data = m.input("bus_write_data", 128);
+    let _bus_write_byte_enable = m.input("bus_write_byte_enable", 16);
+    m.output("bus_ready", m.high());
+
+    let reg_addr = bus_addr.bits(1, 0);
+    let reg_write = |reg: u32| bus_enable & bus_write & reg_addr.eq(m.lit(reg, 2));
+
+    chip_select.drive_next(if_(reg_write(REG_CHIP_SELECT), {
+        bus_write_data.bit(0)
+    }).else_({
+        chip_select.value
+    }));
+
+    clock_div.drive_next(if_(reg_write(REG_CLOCK_DIV), {
+        bus_write_data.bits(7, 0)
+    }).else_({
+        clock_div.value
+    }));
+
+    let software_write = reg_write(REG_DATA) & master_ready;
+
+    let (xip_active, xip_write_enable, xip_write_data) = if xip {
+        // XIP window
+        //  Each bus read issues a complete read command for the addressed 16-byte word with its own chip select
+        //  assertion. Reads stall while software has chip select asserted.
+        let xip_bus_enable = m.input("xip_bus_enable", 1);
+        let xip_bus_addr = m.input("xip_bus_addr", 20);
+        let xip_bus_write = m.input("xip_bus_write", 1);
+        let _xip_bus_write_data = m.input("xip_bus_write_data", 128);
+        let _xip_bus_write_byte_enable = m.input("xip_bus_write_byte_enable", 16);
+
+        let xip_active = m.reg("xip_active", 1);
+        xip_active.default_value(false);
+
+        let xip_addr = m.reg("xip_addr", 20);
+        xip_addr.default_value(0u32);
+
+        let xip_byte_counter = m.reg("xip_byte_counter", 5);
+        xip_byte_counter.default_value(0u32);
+
+        let xip_data = m.reg("xip_data", 128);
+        xip_data.default_value(0u32);
+
+        let xip_bus_ready = !xip_active.value & !chip_select.value & master_ready & !software_write;
+        m.output("xip_bus_ready", xip_bus_ready);
+
+        // Writes are accepted but ignored
+        let xip_start = xip_bus_enable & xip_bus_ready & !xip_bus_write;
+        let xip_issue = xip_active.value & master_ready & xip_byte_counter.value.ne(m.lit(XIP_TRANSFER_BYTES, 5));
+        let xip_byte_valid = xip_active.value & master_read_data_valid;
+        let xip_done = xip_byte_valid & xip_byte_counter.value.eq(m.lit(XIP_TRANSFER_BYTES, 5));
+
+        xip_active.drive_next(if_(xip_start, {
+            m.high()
+        }).else_if(xip_done, {
+            m.low()
+        }).else_({
+            xip_active.value
+        }));
+
+        xip_addr.drive_next(if_(xip_start, {
+            xip_bus_addr
+        }).else_({
+            xip_addr.value
+        }));
+
+        xip_byte_counter.drive_next(if_(xip_start, {
+            m.lit(0u32, 5)
+        }).else_if(xip_issue, {
+            xip_byte_counter.value + m.lit(1u32, 5)
+        }).else_({
+            xip_byte_counter.value
+        }));
+
+        // Received bytes are shifted in from the top so that after the last byte, the first data byte ends up in
+        //  the low byte (the bytes received during the command/address phase fall off the bottom)
+        let next_xip_data = master_read_data.concat(xip_data.value.bits(127, 8));
+        xip_data.drive_next(if_(xip_byte_valid, {
+            next_xip_data
+        }).else_({
+            xip_data.value
+        }));
+
+        m.output("xip_bus_read_data", next_xip_data);
+        m.output("xip_bus_read_data_valid", xip_done);
+
+        let xip_write_data = if_(xip_byte_counter.value.eq(m.lit(0u32, 5)), {
+            m.lit(XIP_READ_COMMAND, 8)
+        }).else_if(xip_byte_counter.value.eq(m.lit(1u32, 5)), {
+            xip_addr.value.bits(19, 12)
+        }).else_if(xip_byte_counter.value.eq(m.lit(2u32, 5)), {
+            xip_addr.value.bits(11, 4)
+        }).else_if(xip_byte_counter.value.eq(m.lit(3u32, 5)), {
+            xip_addr.value.bits(3, 0).concat(m.lit(0u32, 4))
+        }).else_({
+            m.lit(0u32, 8)
+        });
+
+        (xip_active.value, xip_issue, xip_write_data)
+    } else {
+        (m.low(), m.low(), m.lit(0u32, 8))
+    };
+
+    // Software transfers are ignored while an XIP read is in progress
+    master.drive_input("write_enable", (software_write & !xip_active) | xip_write_enable);
+    master.drive_input("write_data", if_(xip_active, {
+        xip_write_data
+    }).else_({
+        bus_write_data.bits(7, 0)
+    }));
+
+    m.output("cs_n", !(chip_select.value | xip_active));
+
+    let bus_read_return_addr = reg_addr.reg_next("bus_read_return_addr");
+    let read_reg = |reg: u32| bus_read_return_addr.eq(m.lit(reg, 2));
+    m.output("bus_read_data", m.lit(0u32, 120).concat(if_(read_reg(REG_STATUS), {
+        m.lit(0u32, 7).concat(master_ready & !xip_active)
+    }).else_if(read_reg(REG_CHIP_SELECT), {
+        m.lit(0u32, 7).concat(chip_select.value)
+    }).else_if(read_reg(REG_DATA), {
+        master_read_data
+    }).else_({
+        clock_div.value
+    })));
+    m.output("bus_read_data_valid", (bus_enable & !bus_write).reg_next_with_default("bus_read_data_valid", false));
+
+    m
+}
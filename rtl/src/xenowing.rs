@@ -1,14 +1,19 @@
 use crate::color_thrust;
+use crate::ethernet_interface;
+use crate::input_interface;
 use crate::interconnect;
 use crate::led_interface;
 use crate::marv;
 use crate::marv_interconnect_bridge;
+use crate::spi_interface;
 use crate::uart;
 use crate::uart_interface;
 use crate::word_mem::*;
 
 use kaze::*;
 
+pub const CLOCK_FREQ: u32 = 100000000;
+
 pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let m = c.module("Xenowing");
 
@@ -84,7 +89,7 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     interconnect.drive_input("program_ram_bus_read_data", program_ram_mem.read_port(program_ram_bus_addr, program_ram_bus_enable & !program_ram_bus_write));
     interconnect.drive_input("program_ram_bus_read_data_valid", (program_ram_bus_enable & !program_ram_bus_write).reg_next_with_default("program_ram_bus_read_data_valid", false));
 
-    led_interface::generate(c);
+    led_interface::generate(c, CLOCK_FREQ);
     let led_interface = m.instance("led_interface", "LedInterface");
 
     led_interface.drive_input("bus_enable", interconnect.output("led_interface_bus_enable"));
@@ -97,12 +102,14 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     interconnect.drive_input("led_interface_bus_read_data_valid", led_interface.output("bus_read_data_valid"));
 
     m.output("leds", led_interface.output("leds"));
+    m.output("display_digits", led_interface.output("display_digits"));
+    m.output("display_segments", led_interface.output("display_segments"));
 
-    uart::generate_tx(c, 100000000, 460800);
+    uart::generate_tx(c, CLOCK_FREQ, 460800);
     let uart_tx = m.instance("uart_tx", "UartTx");
     m.output("tx", uart_tx.output("tx"));
 
-    uart::generate_rx(c, 100000000, 460800);
+    uart::generate_rx(c, CLOCK_FREQ, 460800);
     let uart_rx = m.instance("uart_rx", "UartRx");
     uart_rx.drive_input("rx", m.input("rx", 1));
 
@@ -125,6 +132,89 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     uart_interface.drive_input("rx_data", uart_rx.output("data"));
     uart_interface.drive_input("rx_data_valid", uart_rx.output("data_valid"));
 
+    input_interface::generate(c, CLOCK_FREQ);
+    let input_interface = m.instance("input_interface", "InputInterface");
+
+    input_interface.drive_input("bus_enable", interconnect.output("input_interface_bus_enable"));
+    input_interface.drive_input("bus_addr", interconnect.output("input_interface_bus_addr"));
+    input_interface.drive_input("bus_write", interconnect.output("input_interface_bus_write"));
+    input_interface.drive_input("bus_write_data", interconnect.output("input_interface_bus_write_data"));
+    input_interface.drive_input("bus_write_byte_enable", interconnect.output("input_interface_bus_write_byte_enable"));
+    interconnect.drive_input("input_interface_bus_ready", input_interface.output("bus_ready"));
+    interconnect.drive_input("input_interface_bus_read_data", input_interface.output("bus_read_data"));
+    interconnect.drive_input("input_interface_bus_read_data_valid", input_interface.output("bus_read_data_valid"));
+
+    input_interface.drive_input("buttons", m.input("buttons", input_interface::NUM_BUTTONS));
+    input_interface.drive_input("switches", m.input("switches", input_interface::NUM_SWITCHES));
+    input_interface.drive_input("pad_data", m.input("pad_data", 1));
+    m.output("pad_latch", input_interface.output("pad_latch"));
+    m.output("pad_clock", input_interface.output("pad_clock"));
+
+    spi_interface::generate(c, "SpiFlashInterface", true);
+    let spi_flash_interface = m.instance("spi_flash_interface", "SpiFlashInterface");
+
+    spi_flash_interface.drive_input("bus_enable", interconnect.output("spi_flash_interface_bus_enable"));
+    spi_flash_interface.drive_input("bus_addr", interconnect.output("spi_flash_interface_bus_addr"));
+    spi_flash_interface.drive_input("bus_write", interconnect.output("spi_flash_interface_bus_write"));
+    spi_flash_interface.drive_input("bus_write_data", interconnect.output("spi_flash_interface_bus_write_data"));
+    spi_flash_interface.drive_input("bus_write_byte_enable", interconnect.output("spi_flash_interface_bus_write_byte_enable"));
+    interconnect.drive_input("spi_flash_interface_bus_ready", spi_flash_interface.output("bus_ready"));
+    interconnect.drive_input("spi_flash_interface_bus_read_data", spi_flash_interface.output("bus_read_data"));
+    interconnect.drive_input("spi_flash_interface_bus_read_data_valid", spi_flash_interface.output("bus_read_data_valid"));
+
+    spi_flash_interface.drive_input("xip_bus_enable", interconnect.output("spi_flash_xip_bus_enable"));
+    spi_flash_interface.drive_input("xip_bus_addr", interconnect.output("spi_flash_xip_bus_addr"));
+    spi_flash_interface.drive_input("xip_bus_write", interconnect.output("spi_flash_xip_bus_write"));
+    spi_flash_interface.drive_input("xip_bus_write_data", interconnect.output("spi_flash_xip_bus_write_data"));
+    spi_flash_interface.drive_input("xip_bus_write_byte_enable", interconnect.output("spi_flash_xip_bus_write_byte_enable"));
+    interconnect.drive_input("spi_flash_xip_bus_ready", spi_flash_interface.output("xip_bus_ready"));
+    interconnect.drive_input("spi_flash_xip_bus_read_data", spi_flash_interface.output("xip_bus_read_data"));
+    interconnect.drive_input("spi_flash_xip_bus_read_data_valid", spi_flash_interface.output("xip_bus_read_data_valid"));
+
+    m.output("spi_flash_cs_n", spi_flash_interface.output("cs_n"));
+    m.output("spi_flash_sck", spi_flash_interface.output("sck"));
+    m.output("spi_flash_mosi", spi_flash_interface.output("mosi"));
+    // miso is launched on our own sck falling edges and sampled well after, so it doesn't need a sync chain
+    spi_flash_interface.drive_input("miso", m.input("spi_flash_miso", 1));
+
+    spi_interface::generate(c, "SdInterface", false);
+    let sd_interface = m.instance("sd_interface", "SdInterface");
+
+    sd_interface.drive_input("bus_enable", interconnect.output("sd_interface_bus_enable"));
+    sd_interface.drive_input("bus_addr", interconnect.output("sd_interface_bus_addr"));
+    sd_interface.drive_input("bus_write", interconnect.output("sd_interface_bus_write"));
+    sd_interface.drive_input("bus_write_data", interconnect.output("sd_interface_bus_write_data"));
+    sd_interface.drive_input("bus_write_byte_enable", interconnect.output("sd_interface_bus_write_byte_enable"));
+    interconnect.drive_input("sd_interface_bus_ready", sd_interface.output("bus_ready"));
+    interconnect.drive_input("sd_interface_bus_read_data", sd_interface.output("bus_read_data"));
+    interconnect.drive_input("sd_interface_bus_read_data_valid", sd_interface.output("bus_read_data_valid"));
+
+    m.output("sd_cs_n", sd_interface.output("cs_n"));
+    m.output("sd_sck", sd_interface.output("sck"));
+    m.output("sd_mosi", sd_interface.output("mosi"));
+    sd_interface.drive_input("miso", m.input("sd_miso", 1));
+
+    ethernet_interface::generate(c);
+    let ethernet_interface = m.instance("ethernet_interface", "EthernetInterface");
+
+    ethernet_interface.drive_input("bus_enable", interconnect.output("ethernet_interface_bus_enable"));
+    ethernet_interface.drive_input("bus_addr", interconnect.output("ethernet_interface_bus_addr"));
+    ethernet_interface.drive_input("bus_write", interconnect.output("ethernet_interface_bus_write"));
+    ethernet_interface.drive_input("bus_write_data", interconnect.output("ethernet_interface_bus_write_data"));
+    ethernet_interface.drive_input("bus_write_byte_enable", interconnect.output("ethernet_interface_bus_write_byte_enable"));
+    interconnect.drive_input("ethernet_interface_bus_ready", ethernet_interface.output("bus_ready"));
+    interconnect.drive_input("ethernet_interface_bus_read_data", ethernet_interface.output("bus_read_data"));
+    interconnect.drive_input("ethernet_interface_bus_read_data_valid", ethernet_interface.output("bus_read_data_valid"));
+
+    // Byte streams in the system clock domain; the PHY adapter provides the strobes
+    ethernet_interface.drive_input("tx_strobe", m.input("eth_tx_strobe", 1));
+    m.output("eth_tx_en", ethernet_interface.output("tx_en"));
+    m.output("eth_tx_data", ethernet_interface.output("tx_data"));
+    ethernet_interface.drive_input("rx_strobe", m.input("eth_rx_strobe", 1));
+    ethernet_interface.drive_input("rx_dv", m.input("eth_rx_dv", 1));
+    ethernet_interface.drive_input("rx_er", m.input("eth_rx_er", 1));
+    ethernet_interface.drive_input("rx_data", m.input("eth_rx_data", 8));
+
     color_thrust::generate(c);
     let color_thrust = m.instance("color_thrust", "ColorThrust");
 
@@ -155,8 +245,20 @@ pub fn generate<'a>(c: &'a Context<'a>) -> &Module<'a> {
     interconnect.drive_input("color_thrust_depth_buffer_bus_read_data", color_thrust.output("depth_buffer_bus_read_data"));
     interconnect.drive_input("color_thrust_depth_buffer_bus_read_data_valid", color_thrust.output("depth_buffer_bus_read_data_valid"));
 
+    color_thrust.drive_input("stencil_buffer_bus_enable", interconnect.output("color_thrust_stencil_buffer_bus_enable"));
+    color_thrust.drive_input("stencil_buffer_bus_addr", interconnect.output("color_thrust_stencil_buffer_bus_addr").bits(color_thrust::TILE_PIXELS_WORDS_BITS - 3, 0));
+    color_thrust.drive_input("stencil_buffer_bus_write", interconnect.output("color_thrust_stencil_buffer_bus_write"));
+    color_thrust.drive_input("stencil_buffer_bus_write_data", interconnect.output("color_thrust_stencil_buffer_bus_write_data"));
+    color_thrust.drive_input("stencil_buffer_bus_write_byte_enable", interconnect.output("color_thrust_stencil_buffer_bus_write_byte_enable"));
+    interconnect.drive_input("color_thrust_stencil_buffer_bus_ready", color_thrust.output("stencil_buffer_bus_ready"));
+    interconnect.drive_input("color_thrust_stencil_buffer_bus_read_data", color_thrust.output("stencil_buffer_bus_read_data"));
+    interconnect.drive_input("color_thrust_stencil_buffer_bus_read_data_valid", color_thrust.output("stencil_buffer_bus_read_data_valid"));
+
     interconnect.drive_input("color_thrust_replica_bus_enable", color_thrust.output("replica_bus_enable"));
     interconnect.drive_input("color_thrust_replica_bus_addr", color_thrust.output("replica_bus_addr"));
+    interconnect.drive_input("color_thrust_replica_bus_write", color_thrust.output("replica_bus_write"));
+    interconnect.drive_input("color_thrust_replica_bus_write_data", color_thrust.output("replica_bus_write_data"));
+    interconnect.drive_input("color_thrust_replica_bus_write_byte_enable", color_thrust.output("replica_bus_write_byte_enable"));
     color_thrust.drive_input("replica_bus_ready", interconnect.output("color_thrust_replica_bus_ready"));
     color_thrust.drive_input("replica_bus_read_data", interconnect.output("color_thrust_replica_bus_read_data"));
     color_thrust.drive_input("replica_bus_read_data_valid", interconnect.output("color_thrust_replica_bus_read_data_valid"));
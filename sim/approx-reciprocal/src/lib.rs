@@ -18,6 +18,45 @@ mod tests {
         assert_eq!(expected_quotient, div_shiz.quotient);
     }
 
+    // Mirrors strugl's model device
+    fn model_quotient(x: u32) -> u32 {
+        const FRACT_BITS: u32 = 30 - 8 - 3;
+
+        let shl = x.leading_zeros() & 31;
+        let normalized_x = x << shl;
+        let shr = (64 - 2 * FRACT_BITS - shl) & 31;
+
+        let mut e = !normalized_x;
+        let mut q = e;
+        for _ in 0..4 {
+            q += (((q as u64) * (e as u64)) >> 32) as u32;
+            e = (((e as u64) * (e as u64)) >> 32) as u32;
+        }
+
+        (q >> shr) | 1u32.checked_shl(32 - shr).unwrap_or(0)
+    }
+
+    #[test]
+    fn top_bit_set_matches_model() {
+        let mut div_shiz = ApproxReciprocal::new();
+
+        for &x in &[0x80000000, 0x80000001, 0x9d2c5f37, 0xc0000000, 0xfadebabe, 0xffffffff] {
+            test(x, model_quotient(x), &mut div_shiz);
+        }
+    }
+
+    #[test]
+    fn matches_model() {
+        let mut div_shiz = ApproxReciprocal::new();
+
+        for shift in 0..32 {
+            for &pattern in &[0xffffffffu32, 0x80000000, 0xa5a5a5a5, 0x0a4e6be0] {
+                let x = pattern >> shift;
+                test(x, model_quotient(x), &mut div_shiz);
+            }
+        }
+    }
+
     #[test]
     fn random_rasterizer_reference_values() {
         let mut div_shiz = ApproxReciprocal::new();
@@ -0,0 +1,287 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    use rtl::color_thrust::*;
+
+    use rand::{Rng, SeedableRng};
+
+    const RAM_WORDS: usize = 1 << TEX_WORD_ADDR_BITS;
+
+    const PRIMITIVE_BASE: u32 = 0x0100;
+    const LIST_BASE: u32 = 0x1000;
+
+    const VERTEX_WORDS: usize = TRANSFORM_OUTPUT_WORD_CLIP_FLAGS as usize + 1;
+
+    struct Settings {
+        edge_test_enable: bool,
+        back_face_cull_enable: bool,
+        grid_width: u32,
+        grid_height: u32,
+        list_stride: u32,
+    }
+
+    struct Vertex {
+        x: i32,
+        y: i32,
+        words: [u32; VERTEX_WORDS],
+    }
+
+    fn random_vertex(rng: &mut impl Rng, settings: &Settings) -> Vertex {
+        let one = 1 << SETUP_XY_FRACT_BITS;
+        let tile = one * TILE_DIM as i32;
+        // Mostly within a tile or two of the grid, with the occasional huge triangle
+        let (x, y) = if rng.gen_range(0, 16) == 0 {
+            (rng.gen_range(-32768, 32768), rng.gen_range(-32768, 32768))
+        } else {
+            (
+                rng.gen_range(-2 * tile, (settings.grid_width as i32 + 2) * tile),
+                rng.gen_range(-2 * tile, (settings.grid_height as i32 + 2) * tile),
+            )
+        };
+        let mut words = [0; VERTEX_WORDS];
+        for word in words.iter_mut() {
+            *word = rng.gen();
+        }
+        words[SETUP_VERTEX_WORD_XY as usize] = ((y as u32 & 0xffff) << 16) | (x as u32 & 0xffff);
+        words[TRANSFORM_OUTPUT_WORD_CLIP_FLAGS as usize] = if rng.gen_range(0, 16) == 0 {
+            1 << rng.gen_range(0, 6)
+        } else {
+            0
+        };
+        Vertex {
+            x,
+            y,
+            words,
+        }
+    }
+
+    // Same edge function the rasterizer uses; non-negative inside triangles with positive area
+    fn edge(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+        (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+    }
+
+    struct Primitive {
+        words: Vec<u32>,
+        tiles: Vec<(u32, u32)>,
+    }
+
+    fn bin(settings: &Settings, vertices: &[Vertex], indices: [usize; 3]) -> Option<Primitive> {
+        let v = indices.iter().map(|&i| &vertices[i]).collect::<Vec<_>>();
+        let p = v.iter().map(|v| (v.x as i64, v.y as i64)).collect::<Vec<_>>();
+
+        let clip_flags = v.iter().map(|v| v.words[TRANSFORM_OUTPUT_WORD_CLIP_FLAGS as usize]).collect::<Vec<_>>();
+        let near_far_flags = (1 << TRANSFORM_CLIP_FLAG_NEAR_BIT) | (1 << TRANSFORM_CLIP_FLAG_FAR_BIT);
+        if clip_flags[0] & clip_flags[1] & clip_flags[2] != 0 || (clip_flags[0] | clip_flags[1] | clip_flags[2]) & near_far_flags != 0 {
+            return None;
+        }
+
+        let area = edge(p[0], p[1], p[2]);
+        if area == 0 || (settings.back_face_cull_enable && area < 0) {
+            return None;
+        }
+        let order = if area < 0 { [1, 0, 2] } else { [0, 1, 2] };
+        let p = order.iter().map(|&i| p[i]).collect::<Vec<_>>();
+
+        let half_pixel = 1 << (SETUP_XY_FRACT_BITS - 1);
+        let tile_shift = TILE_DIM_BITS + SETUP_XY_FRACT_BITS;
+        let min_x = (p.iter().map(|p| p.0).min().unwrap() + half_pixel - 1) >> tile_shift;
+        let max_x = (p.iter().map(|p| p.0).max().unwrap() - half_pixel) >> tile_shift;
+        let min_y = (p.iter().map(|p| p.1).min().unwrap() + half_pixel - 1) >> tile_shift;
+        let max_y = (p.iter().map(|p| p.1).max().unwrap() - half_pixel) >> tile_shift;
+        let (grid_width, grid_height) = (settings.grid_width as i64, settings.grid_height as i64);
+        if max_x < 0 || min_x >= grid_width || min_x > max_x || max_y < 0 || min_y >= grid_height || min_y > max_y {
+            return None;
+        }
+
+        let words = order.iter().flat_map(|&i| v[i].words[..SETUP_VERTEX_WORDS as usize].iter().cloned()).collect();
+
+        let mut tiles = Vec::new();
+        for tile_y in min_y.max(0)..=max_y.min(grid_height - 1) {
+            for tile_x in min_x.max(0)..=max_x.min(grid_width - 1) {
+                // Each edge is tested at the pixel center in the tile where it's largest
+                let passes = (0..3).all(|k| {
+                    let (a, b) = (p[(k + 1) % 3], p[(k + 2) % 3]);
+                    let corner = |tile: i64, far: bool| (tile << tile_shift) + if far { ((TILE_DIM as i64 - 1) << SETUP_XY_FRACT_BITS) + half_pixel } else { half_pixel };
+                    let corner = (corner(tile_x, a.1 >= b.1), corner(tile_y, b.0 >= a.0));
+                    edge(a, b, corner) >= 0
+                });
+                if !settings.edge_test_enable || passes {
+                    tiles.push((tile_x as u32, tile_y as u32));
+                }
+            }
+        }
+
+        Some(Primitive {
+            words,
+            tiles,
+        })
+    }
+
+    // Whether any pixel center in the tile is inside all three edges
+    fn tile_covered(primitive: &Primitive, tile: (u32, u32)) -> bool {
+        let xy = (0..3).map(|i| primitive.words[i * SETUP_VERTEX_WORDS as usize + SETUP_VERTEX_WORD_XY as usize]).collect::<Vec<_>>();
+        let p = xy.iter().map(|&xy| ((xy & 0xffff) as i16 as i64, (xy >> 16) as i16 as i64)).collect::<Vec<_>>();
+        let half_pixel = 1 << (SETUP_XY_FRACT_BITS - 1);
+        (0..TILE_DIM as i64).any(|y| (0..TILE_DIM as i64).any(|x| {
+            let center = (((((tile.0 as i64) << TILE_DIM_BITS) + x) << SETUP_XY_FRACT_BITS) + half_pixel, ((((tile.1 as i64) << TILE_DIM_BITS) + y) << SETUP_XY_FRACT_BITS) + half_pixel);
+            (0..3).all(|k| edge(p[(k + 1) % 3], p[(k + 2) % 3], center) >= 0)
+        }))
+    }
+
+    fn run(m: &mut Binner, rng: &mut impl Rng, vertices: &[Vertex], ram: &mut [u128]) -> u32 {
+        let mut num_cycles = 0;
+        m.prop();
+        while m.active {
+            // Serve output buffer reads with one cycle of latency, and stall RAM writes now and then
+            let read_value = if m.vertex_read_enable {
+                let index = (m.vertex_read_addr >> SETUP_VERTEX_WORD_BITS) as usize;
+                let word = (m.vertex_read_addr & ((1 << SETUP_VERTEX_WORD_BITS) - 1)) as usize;
+                Some(vertices[index].words[word])
+            } else {
+                None
+            };
+            m.replica_bus_ready = rng.gen_range(0, 4) != 0;
+            m.prop();
+            if m.replica_bus_enable && m.replica_bus_ready {
+                assert!(m.replica_bus_write);
+                let word = &mut ram[m.replica_bus_addr as usize];
+                for i in 0..16 {
+                    if (m.replica_bus_write_byte_enable >> i) & 1 != 0 {
+                        let mask = 0xffu128 << (i * 8);
+                        *word = (*word & !mask) | (m.replica_bus_write_data & mask);
+                    }
+                }
+            }
+            m.posedge_clk();
+            if let Some(read_value) = read_value {
+                m.vertex_read_value = read_value;
+            }
+            m.prop();
+
+            num_cycles += 1;
+            assert!(num_cycles < 1000000);
+        }
+        num_cycles
+    }
+
+    fn read_value(ram: &[u128], addr: u32, index: u32) -> u32 {
+        (ram[(addr + index / 4) as usize] >> ((index % 4) * 32)) as u32
+    }
+
+    #[test]
+    fn random_triangles() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0xfadebabe);
+
+        let mut m = Binner::new();
+
+        m.reset();
+        m.prop();
+
+        let mut num_primitives = 0;
+        let mut num_overflows = 0;
+        for _ in 0..32 {
+            let settings = Settings {
+                edge_test_enable: rng.gen(),
+                back_face_cull_enable: rng.gen(),
+                grid_width: rng.gen_range(1, 21),
+                grid_height: rng.gen_range(1, 16),
+                list_stride: rng.gen_range(1, 9),
+            };
+            let vertices = (0..TRANSFORM_OUTPUT_VERTICES).map(|_| random_vertex(&mut rng, &settings)).collect::<Vec<_>>();
+
+            // Start from garbage to make sure everything the binner reports was actually written
+            let mut ram = (0..RAM_WORDS).map(|_| rng.gen()).collect::<Vec<u128>>();
+
+            m.edge_test_enable = settings.edge_test_enable;
+            m.back_face_cull_enable = settings.back_face_cull_enable;
+            m.grid_width = settings.grid_width;
+            m.grid_height = settings.grid_height;
+            m.primitive_base = PRIMITIVE_BASE;
+            m.list_base = LIST_BASE;
+            m.list_stride = settings.list_stride;
+
+            m.begin = true;
+            m.prop();
+            m.posedge_clk();
+            m.begin = false;
+            run(&mut m, &mut rng, &vertices, &mut ram);
+
+            let mut primitives = Vec::new();
+            for _ in 0..rng.gen_range(1, 64) {
+                let indices = [
+                    rng.gen_range(0, vertices.len()),
+                    rng.gen_range(0, vertices.len()),
+                    rng.gen_range(0, vertices.len()),
+                ];
+                m.triangle = true;
+                m.triangle_indices = indices.iter().rev().fold(0, |acc, &i| (acc << TRANSFORM_OUTPUT_VERTEX_BITS) | i as u32);
+                m.prop();
+                m.posedge_clk();
+                m.triangle = false;
+                run(&mut m, &mut rng, &vertices, &mut ram);
+
+                if let Some(primitive) = bin(&settings, &vertices, indices) {
+                    primitives.push(primitive);
+                }
+            }
+
+            m.end = true;
+            m.prop();
+            m.posedge_clk();
+            m.end = false;
+            run(&mut m, &mut rng, &vertices, &mut ram);
+
+            for (i, primitive) in primitives.iter().enumerate() {
+                let addr = PRIMITIVE_BASE + i as u32 * BIN_PRIMITIVE_WORDS;
+                for (j, &word) in primitive.words.iter().enumerate() {
+                    assert_eq!(read_value(&ram, addr, j as _), word, "primitive {} value {}", i, j);
+                }
+            }
+
+            let capacity = settings.list_stride as usize * 4 - 1;
+            for tile_y in 0..settings.grid_height {
+                for tile_x in 0..settings.grid_width {
+                    let expected = primitives.iter().enumerate().filter(|(_, primitive)| primitive.tiles.contains(&(tile_x, tile_y))).map(|(i, _)| i as u32).collect::<Vec<_>>();
+
+                    let addr = LIST_BASE + (tile_y * settings.grid_width + tile_x) * settings.list_stride;
+                    let mut list = Vec::new();
+                    let terminator = loop {
+                        let value = read_value(&ram, addr, list.len() as _);
+                        if value == BIN_LIST_END || value == BIN_LIST_OVERFLOW {
+                            break value;
+                        }
+                        list.push(value);
+                        assert!(list.len() <= capacity, "list for tile ({}, {}) isn't terminated", tile_x, tile_y);
+                    };
+
+                    if expected.len() > capacity {
+                        assert_eq!(terminator, BIN_LIST_OVERFLOW);
+                        assert_eq!(list, &expected[..capacity]);
+                        num_overflows += 1;
+                    } else {
+                        assert_eq!(terminator, BIN_LIST_END);
+                        assert_eq!(list, expected, "list for tile ({}, {})", tile_x, tile_y);
+                    }
+
+                    // The edge test must never drop a tile that has covered pixels
+                    for primitive in primitives.iter() {
+                        if !primitive.tiles.contains(&(tile_x, tile_y)) {
+                            assert!(!tile_covered(primitive, (tile_x, tile_y)));
+                        }
+                    }
+                }
+            }
+
+            num_primitives += primitives.len();
+        }
+
+        println!("binned {} primitives, {} lists overflowed", num_primitives, num_overflows);
+        assert!(num_primitives > 0);
+        assert!(num_overflows > 0);
+    }
+}
@@ -16,6 +16,7 @@ fn main() -> Result<()> {
     sim::generate(buster::generate(&c, "Buster1x2", 1, 2, 17, 1, 32, 2), sim::GenerationOptions::default(), &mut file)?;
     sim::generate(buster::generate(&c, "Buster2x1", 2, 1, 16, 0, 32, 2), sim::GenerationOptions::default(), &mut file)?;
     sim::generate(buster::generate(&c, "Buster2x2", 2, 2, 17, 1, 128, 4), sim::GenerationOptions::default(), &mut file)?;
+    sim::generate(buster::generate(&c, "Buster3x1", 3, 1, 16, 0, 32, 2), sim::GenerationOptions::default(), &mut file)?;
 
     Ok(())
 }
@@ -1049,4 +1049,106 @@ mod tests {
             m.posedge_clk();
         }
     }
+
+    #[test]
+    fn buster3x1_single_read_primary2() {
+        let mut m = Buster3x1::new();
+
+        m.reset();
+
+        m.primary0_bus_enable = false;
+        m.primary0_bus_addr = 0;
+        m.primary1_bus_enable = false;
+        m.primary1_bus_addr = 0;
+        m.primary2_bus_enable = true;
+        m.primary2_bus_write = false;
+        m.primary2_bus_addr = 0x0babe;
+        m.replica0_bus_ready = true;
+        m.replica0_bus_read_data = 0xffffffff;
+        m.replica0_bus_read_data_valid = false;
+
+        m.prop();
+
+        assert_eq!(m.primary2_bus_ready, true);
+        assert_eq!(m.replica0_bus_enable, true);
+        assert_eq!(m.replica0_bus_addr, 0xbabe);
+
+        m.posedge_clk();
+
+        m.primary2_bus_enable = false;
+        m.replica0_bus_read_data = 0xdeadbeef;
+        m.replica0_bus_read_data_valid = true;
+
+        m.prop();
+
+        while !m.primary2_bus_read_data_valid {
+            assert_eq!(m.primary0_bus_read_data_valid, false);
+            assert_eq!(m.primary1_bus_read_data_valid, false);
+
+            m.posedge_clk();
+            m.prop();
+        }
+
+        assert_eq!(m.primary2_bus_read_data, 0xdeadbeef);
+
+        assert_eq!(m.primary0_bus_read_data_valid, false);
+        assert_eq!(m.primary1_bus_read_data_valid, false);
+    }
+
+    #[test]
+    fn buster3x1_read_all_primaries() {
+        let mut m = Buster3x1::new();
+
+        m.reset();
+
+        m.primary0_bus_enable = true;
+        m.primary0_bus_write = false;
+        m.primary0_bus_addr = 0;
+        m.primary1_bus_enable = true;
+        m.primary1_bus_write = false;
+        m.primary1_bus_addr = 1;
+        m.primary2_bus_enable = true;
+        m.primary2_bus_write = false;
+        m.primary2_bus_addr = 2;
+        m.replica0_bus_ready = true;
+        m.replica0_bus_read_data_valid = false;
+
+        // Replica returns each read's addr as its data the cycle after it's issued
+        let mut pending_read_addr = None;
+        let mut primary_read_data = [None, None, None];
+        for _ in 0..32 {
+            m.replica0_bus_read_data_valid = pending_read_addr.is_some();
+            m.replica0_bus_read_data = pending_read_addr.unwrap_or(0xffffffff);
+
+            m.prop();
+
+            pending_read_addr = if m.replica0_bus_enable { Some(m.replica0_bus_addr) } else { None };
+            if m.primary0_bus_ready {
+                m.primary0_bus_enable = false;
+            }
+            if m.primary1_bus_ready {
+                m.primary1_bus_enable = false;
+            }
+            if m.primary2_bus_ready {
+                m.primary2_bus_enable = false;
+            }
+
+            if m.primary0_bus_read_data_valid {
+                assert_eq!(primary_read_data[0], None);
+                primary_read_data[0] = Some(m.primary0_bus_read_data);
+            }
+            if m.primary1_bus_read_data_valid {
+                assert_eq!(primary_read_data[1], None);
+                primary_read_data[1] = Some(m.primary1_bus_read_data);
+            }
+            if m.primary2_bus_read_data_valid {
+                assert_eq!(primary_read_data[2], None);
+                primary_read_data[2] = Some(m.primary2_bus_read_data);
+            }
+
+            m.posedge_clk();
+        }
+
+        assert_eq!(primary_read_data, [Some(0), Some(1), Some(2)]);
+    }
 }
@@ -0,0 +1,334 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    const REG_STATUS: u32 = 0;
+    const REG_MAC_ADDR_LOW: u32 = 1;
+    const REG_MAC_ADDR_HIGH: u32 = 2;
+    const REG_RX_LENGTH: u32 = 3;
+    const REG_RX_RELEASE: u32 = 4;
+    const REG_TX_LENGTH: u32 = 5;
+    const REG_RX_DROPPED: u32 = 6;
+
+    const RX_BUFFER_BASE: u32 = 0x100;
+    const TX_BUFFER_BASE: u32 = 0x200;
+
+    const STATUS_RX_FRAME_AVAILABLE: u32 = 1 << 0;
+    const STATUS_TX_BUSY: u32 = 1 << 1;
+
+    const MAC_ADDR: [u8; 6] = [0x02, 0x58, 0x57, 0x00, 0x00, 0x01];
+    const OTHER_MAC_ADDR: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+    const BROADCAST_MAC_ADDR: [u8; 6] = [0xff; 6];
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = 0xffffffff;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    fn frame(dest: [u8; 6], payload_len: usize) -> Vec<u8> {
+        let mut ret = dest.to_vec();
+        ret.extend_from_slice(&OTHER_MAC_ADDR);
+        ret.extend_from_slice(&[0x08, 0x00]);
+        ret.extend((0..payload_len).map(|x| (x * 13 + 7) as u8));
+        ret
+    }
+
+    // Preamble, SFD, and FCS around a frame
+    fn wire_bytes(frame: &[u8]) -> Vec<u8> {
+        let mut ret = vec![0x55; 7];
+        ret.push(0xd5);
+        ret.extend_from_slice(frame);
+        ret.extend_from_slice(&crc32(frame).to_le_bytes());
+        ret
+    }
+
+    fn cycle(m: &mut EthernetInterface) {
+        m.prop();
+        m.posedge_clk();
+        m.prop();
+    }
+
+    fn read_reg(m: &mut EthernetInterface, addr: u32) -> u128 {
+        m.bus_enable = true;
+        m.bus_addr = addr;
+        m.bus_write = false;
+        cycle(m);
+        m.bus_enable = false;
+        m.prop();
+        assert_eq!(m.bus_read_data_valid, true);
+        m.bus_read_data
+    }
+
+    fn write_reg(m: &mut EthernetInterface, addr: u32, value: u128) {
+        m.bus_enable = true;
+        m.bus_addr = addr;
+        m.bus_write = true;
+        m.bus_write_data = value;
+        m.bus_write_byte_enable = 0xffff;
+        cycle(m);
+        m.bus_enable = false;
+        m.bus_write = false;
+        m.prop();
+    }
+
+    fn write_tx_buffer(m: &mut EthernetInterface, data: &[u8]) {
+        for (i, chunk) in data.chunks(16).enumerate() {
+            let mut word = 0;
+            for (j, &byte) in chunk.iter().enumerate() {
+                word |= (byte as u128) << (j * 8);
+            }
+            write_reg(m, TX_BUFFER_BASE + i as u32, word);
+        }
+    }
+
+    fn read_rx_buffer(m: &mut EthernetInterface, len: usize) -> Vec<u8> {
+        let mut ret = Vec::new();
+        for i in 0..(len + 15) / 16 {
+            let word = read_reg(m, RX_BUFFER_BASE + i as u32);
+            for j in 0..16 {
+                ret.push((word >> (j * 8)) as u8);
+            }
+        }
+        ret.truncate(len);
+        ret
+    }
+
+    // Starts a transmission and returns the bytes on the wire (with one strobe per cycle)
+    fn transmit(m: &mut EthernetInterface, len: u32) -> Vec<u8> {
+        write_reg(m, REG_TX_LENGTH, len as _);
+
+        let mut ret = Vec::new();
+        m.tx_strobe = true;
+        for _ in 0..10000 {
+            m.prop();
+            if m.tx_en {
+                ret.push(m.tx_data as u8);
+            } else if !ret.is_empty() {
+                break;
+            }
+            cycle(m);
+        }
+        m.tx_strobe = false;
+        m.prop();
+        ret
+    }
+
+    fn receive(m: &mut EthernetInterface, wire_bytes: &[u8]) {
+        m.rx_strobe = true;
+        for &byte in wire_bytes {
+            m.rx_dv = true;
+            m.rx_data = byte as _;
+            cycle(m);
+        }
+        m.rx_dv = false;
+        m.rx_data = 0;
+        for _ in 0..12 {
+            cycle(m);
+        }
+        m.rx_strobe = false;
+        m.prop();
+    }
+
+    fn new() -> EthernetInterface {
+        let mut m = EthernetInterface::new();
+
+        m.reset();
+        m.prop();
+
+        let mac_addr_low = u32::from_le_bytes([MAC_ADDR[0], MAC_ADDR[1], MAC_ADDR[2], MAC_ADDR[3]]);
+        let mac_addr_high = u16::from_le_bytes([MAC_ADDR[4], MAC_ADDR[5]]);
+        write_reg(&mut m, REG_MAC_ADDR_LOW, mac_addr_low as _);
+        write_reg(&mut m, REG_MAC_ADDR_HIGH, mac_addr_high as _);
+
+        m
+    }
+
+    #[test]
+    fn reset_state() {
+        let mut m = new();
+
+        assert_eq!(m.tx_en, false);
+        assert_eq!(read_reg(&mut m, REG_STATUS), 0);
+        assert_eq!(read_reg(&mut m, REG_RX_DROPPED), 0);
+        assert_eq!(read_reg(&mut m, REG_MAC_ADDR_LOW), 0x00575802);
+        assert_eq!(read_reg(&mut m, REG_MAC_ADDR_HIGH), 0x0100);
+    }
+
+    #[test]
+    fn tx_frame() {
+        let mut m = new();
+
+        let frame = frame(OTHER_MAC_ADDR, 100);
+        write_tx_buffer(&mut m, &frame);
+        assert_eq!(transmit(&mut m, frame.len() as _), wire_bytes(&frame));
+    }
+
+    #[test]
+    fn tx_pads_short_frames() {
+        let mut m = new();
+
+        let frame = frame(OTHER_MAC_ADDR, 6);
+        write_tx_buffer(&mut m, &frame);
+        // Stale buffer contents past the frame length must not leak into the padding
+        write_reg(&mut m, TX_BUFFER_BASE + 2, !0);
+        write_reg(&mut m, TX_BUFFER_BASE + 3, !0);
+
+        let mut padded_frame = frame.clone();
+        padded_frame.resize(60, 0);
+        assert_eq!(transmit(&mut m, frame.len() as _), wire_bytes(&padded_frame));
+    }
+
+    #[test]
+    fn tx_busy_through_ifg() {
+        let mut m = new();
+
+        write_tx_buffer(&mut m, &frame(OTHER_MAC_ADDR, 50));
+        write_reg(&mut m, REG_TX_LENGTH, 64);
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_TX_BUSY, STATUS_TX_BUSY);
+
+        // Preamble/SFD + data + FCS + IFG
+        m.tx_strobe = true;
+        for _ in 0..8 + 64 + 4 + 12 - 1 {
+            cycle(&mut m);
+        }
+        m.tx_strobe = false;
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_TX_BUSY, STATUS_TX_BUSY);
+        m.tx_strobe = true;
+        cycle(&mut m);
+        m.tx_strobe = false;
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_TX_BUSY, 0);
+    }
+
+    #[test]
+    fn tx_only_advances_on_strobe() {
+        let mut m = new();
+
+        let frame = frame(OTHER_MAC_ADDR, 60);
+        write_tx_buffer(&mut m, &frame);
+        write_reg(&mut m, REG_TX_LENGTH, frame.len() as _);
+
+        let mut wire = Vec::new();
+        for i in 0..10000 {
+            m.tx_strobe = i % 3 == 0;
+            m.prop();
+            if m.tx_strobe {
+                if m.tx_en {
+                    wire.push(m.tx_data as u8);
+                } else if !wire.is_empty() {
+                    break;
+                }
+            }
+            cycle(&mut m);
+        }
+
+        assert_eq!(wire, wire_bytes(&frame));
+    }
+
+    #[test]
+    fn rx_frames() {
+        for &(dest, payload_len) in [(MAC_ADDR, 46), (MAC_ADDR, 1000), (BROADCAST_MAC_ADDR, 46), (MAC_ADDR, 2048 - 18)].iter() {
+            let mut m = new();
+
+            let frame = frame(dest, payload_len);
+            receive(&mut m, &wire_bytes(&frame));
+
+            assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_RX_FRAME_AVAILABLE, STATUS_RX_FRAME_AVAILABLE);
+            assert_eq!(read_reg(&mut m, REG_RX_LENGTH) as usize, frame.len());
+            assert_eq!(read_rx_buffer(&mut m, frame.len()), frame);
+
+            write_reg(&mut m, REG_RX_RELEASE, 1);
+            assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_RX_FRAME_AVAILABLE, 0);
+            assert_eq!(read_reg(&mut m, REG_RX_DROPPED), 0);
+        }
+    }
+
+    #[test]
+    fn rx_filters_other_addresses() {
+        let mut m = new();
+
+        receive(&mut m, &wire_bytes(&frame(OTHER_MAC_ADDR, 46)));
+
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_RX_FRAME_AVAILABLE, 0);
+        assert_eq!(read_reg(&mut m, REG_RX_DROPPED), 0);
+    }
+
+    #[test]
+    fn rx_drops_bad_frames() {
+        let mut m = new();
+
+        // Bad FCS
+        let mut wire = wire_bytes(&frame(MAC_ADDR, 46));
+        *wire.last_mut().unwrap() ^= 0x01;
+        receive(&mut m, &wire);
+
+        // Runt
+        receive(&mut m, &wire_bytes(&frame(MAC_ADDR, 20)));
+
+        // Too long
+        receive(&mut m, &wire_bytes(&frame(MAC_ADDR, 2048)));
+
+        // PHY-signalled error
+        m.rx_er = true;
+        receive(&mut m, &wire_bytes(&frame(MAC_ADDR, 46)));
+        m.rx_er = false;
+
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_RX_FRAME_AVAILABLE, 0);
+        assert_eq!(read_reg(&mut m, REG_RX_DROPPED), 4);
+    }
+
+    #[test]
+    fn rx_drops_frames_while_buffer_is_held() {
+        let mut m = new();
+
+        let first = frame(MAC_ADDR, 46);
+        let second = frame(BROADCAST_MAC_ADDR, 100);
+
+        receive(&mut m, &wire_bytes(&first));
+        receive(&mut m, &wire_bytes(&second));
+
+        assert_eq!(read_reg(&mut m, REG_RX_DROPPED), 1);
+        assert_eq!(read_reg(&mut m, REG_RX_LENGTH) as usize, first.len());
+        assert_eq!(read_rx_buffer(&mut m, first.len()), first);
+
+        write_reg(&mut m, REG_RX_RELEASE, 1);
+        receive(&mut m, &wire_bytes(&second));
+
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32 & STATUS_RX_FRAME_AVAILABLE, STATUS_RX_FRAME_AVAILABLE);
+        assert_eq!(read_reg(&mut m, REG_RX_LENGTH) as usize, second.len());
+        assert_eq!(read_rx_buffer(&mut m, second.len()), second);
+    }
+
+    #[test]
+    fn loopback() {
+        let mut m = new();
+
+        let frame = frame(MAC_ADDR, 500);
+        write_tx_buffer(&mut m, &frame);
+        write_reg(&mut m, REG_TX_LENGTH, frame.len() as _);
+
+        m.tx_strobe = true;
+        m.rx_strobe = true;
+        for _ in 0..1000 {
+            m.prop();
+            m.rx_dv = m.tx_en;
+            m.rx_data = m.tx_data;
+            cycle(&mut m);
+        }
+        m.tx_strobe = false;
+        m.rx_strobe = false;
+
+        assert_eq!(read_reg(&mut m, REG_STATUS) as u32, STATUS_RX_FRAME_AVAILABLE);
+        assert_eq!(read_reg(&mut m, REG_RX_LENGTH) as usize, frame.len());
+        assert_eq!(read_rx_buffer(&mut m, frame.len()), frame);
+    }
+}
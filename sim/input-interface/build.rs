@@ -0,0 +1,18 @@
+use kaze::*;
+use rtl::*;
+
+use std::env;
+use std::fs::File;
+use std::io::Result;
+use std::path::Path;
+
+fn main() -> Result<()> {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("modules.rs");
+    let file = File::create(&dest_path).unwrap();
+
+    let c = Context::new();
+
+    // Low clock freq so debounce samples/pad polls happen every 100 cycles and pad ticks happen every cycle
+    sim::generate(input_interface::generate(&c, 100000), sim::GenerationOptions::default(), file)
+}
@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    const REG_BUTTONS: u32 = 0;
+    const REG_BUTTONS_PRESSED: u32 = 1;
+    const REG_BUTTONS_RELEASED: u32 = 2;
+    const REG_SWITCHES: u32 = 3;
+    const REG_PAD: u32 = 4;
+    const REG_PAD_PRESSED: u32 = 5;
+    const REG_PAD_RELEASED: u32 = 6;
+
+    // Behavioral NES/SNES-style pad: parallel-loads while latch is high, shifts on clock rising edges, active-low data
+    #[derive(Default)]
+    struct Pad {
+        buttons: u16,
+        shift: u16,
+        prev_clock: bool,
+    }
+
+    fn cycle(m: &mut InputInterface, pad: &mut Pad) {
+        m.pad_data = (pad.shift & 1) == 0;
+        m.prop();
+        if m.pad_latch {
+            pad.shift = pad.buttons;
+        }
+        if m.pad_clock && !pad.prev_clock {
+            pad.shift >>= 1;
+        }
+        pad.prev_clock = m.pad_clock;
+        m.posedge_clk();
+    }
+
+    fn cycles(m: &mut InputInterface, pad: &mut Pad, n: u32) {
+        for _ in 0..n {
+            cycle(m, pad);
+        }
+    }
+
+    fn read_reg(m: &mut InputInterface, pad: &mut Pad, reg: u32) -> u32 {
+        m.bus_enable = true;
+        m.bus_addr = reg;
+        m.bus_write = false;
+        cycle(m, pad);
+        m.bus_enable = false;
+        m.prop();
+        assert_eq!(m.bus_read_data_valid, true);
+        m.bus_read_data as u32
+    }
+
+    fn write_reg(m: &mut InputInterface, pad: &mut Pad, reg: u32, value: u32) {
+        m.bus_enable = true;
+        m.bus_addr = reg;
+        m.bus_write = true;
+        m.bus_write_data = value as _;
+        m.bus_write_byte_enable = 0xffff;
+        cycle(m, pad);
+        m.bus_enable = false;
+        m.bus_write = false;
+    }
+
+    fn new() -> (InputInterface, Pad) {
+        let mut m = InputInterface::new();
+
+        m.reset();
+        m.prop();
+
+        (m, Pad::default())
+    }
+
+    #[test]
+    fn reset_all_clear() {
+        let (mut m, mut pad) = new();
+
+        for reg in 0..7 {
+            assert_eq!(read_reg(&mut m, &mut pad, reg), 0);
+        }
+    }
+
+    #[test]
+    fn buttons_debounced() {
+        let (mut m, mut pad) = new();
+
+        m.buttons = 0b0101;
+        cycles(&mut m, &mut pad, 250);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS), 0);
+
+        cycles(&mut m, &mut pad, 250);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS), 0b0101);
+    }
+
+    #[test]
+    fn buttons_glitch_rejected() {
+        let (mut m, mut pad) = new();
+
+        for _ in 0..10 {
+            m.buttons = 0b1111;
+            cycles(&mut m, &mut pad, 150);
+            m.buttons = 0b0000;
+            cycles(&mut m, &mut pad, 150);
+            assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS), 0);
+        }
+
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_PRESSED), 0);
+    }
+
+    #[test]
+    fn buttons_edges_sticky() {
+        let (mut m, mut pad) = new();
+
+        m.buttons = 0b0010;
+        cycles(&mut m, &mut pad, 500);
+        m.buttons = 0b0000;
+        cycles(&mut m, &mut pad, 500);
+
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS), 0);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_PRESSED), 0b0010);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_RELEASED), 0b0010);
+
+        // Writing 0's doesn't clear anything
+        write_reg(&mut m, &mut pad, REG_BUTTONS_PRESSED, 0b1101);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_PRESSED), 0b0010);
+
+        write_reg(&mut m, &mut pad, REG_BUTTONS_PRESSED, 0b0010);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_PRESSED), 0);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_RELEASED), 0b0010);
+
+        write_reg(&mut m, &mut pad, REG_BUTTONS_RELEASED, 0b1111);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_BUTTONS_RELEASED), 0);
+    }
+
+    #[test]
+    fn switches_debounced() {
+        let (mut m, mut pad) = new();
+
+        m.switches = 0xa5;
+        cycles(&mut m, &mut pad, 500);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_SWITCHES), 0xa5);
+
+        m.switches = 0x3c;
+        cycles(&mut m, &mut pad, 500);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_SWITCHES), 0x3c);
+    }
+
+    #[test]
+    fn pad_read() {
+        let (mut m, mut pad) = new();
+
+        for &buttons in [0x0001, 0x8000, 0xbeef, 0x0000, 0xffff].iter() {
+            pad.buttons = buttons;
+            cycles(&mut m, &mut pad, 300);
+            assert_eq!(read_reg(&mut m, &mut pad, REG_PAD), buttons as u32);
+        }
+    }
+
+    #[test]
+    fn pad_edges_sticky() {
+        let (mut m, mut pad) = new();
+
+        pad.buttons = 0x0180;
+        cycles(&mut m, &mut pad, 300);
+        pad.buttons = 0x0100;
+        cycles(&mut m, &mut pad, 300);
+
+        assert_eq!(read_reg(&mut m, &mut pad, REG_PAD_PRESSED), 0x0180);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_PAD_RELEASED), 0x0080);
+
+        write_reg(&mut m, &mut pad, REG_PAD_PRESSED, 0xffff);
+        write_reg(&mut m, &mut pad, REG_PAD_RELEASED, 0xffff);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_PAD_PRESSED), 0);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_PAD_RELEASED), 0);
+        assert_eq!(read_reg(&mut m, &mut pad, REG_PAD), 0x0100);
+    }
+}
@@ -0,0 +1,18 @@
+use kaze::*;
+use rtl::*;
+
+use std::env;
+use std::fs::File;
+use std::io::Result;
+use std::path::Path;
+
+fn main() -> Result<()> {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("modules.rs");
+    let file = File::create(&dest_path).unwrap();
+
+    let c = Context::new();
+
+    // Clock freq chosen so that PWM steps happen every cycle
+    sim::generate(led_interface::generate(&c, led_interface::PWM_FREQ * led_interface::PWM_STEPS), sim::GenerationOptions::default(), file)
+}
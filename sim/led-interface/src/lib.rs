@@ -0,0 +1,225 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    const REG_LEDS: u32 = 0;
+    const REG_LED_BRIGHTNESS_BASE: u32 = 1;
+    const REG_DISPLAY_CONTROL: u32 = 9;
+    const REG_DISPLAY_DATA: u32 = 10;
+    const REG_DISPLAY_BRIGHTNESS: u32 = 11;
+
+    const DISPLAY_CONTROL_RAW: u32 = 1 << 4;
+
+    const PWM_STEPS: u32 = 255;
+
+    const HEX_SEGMENTS: [u32; 16] = [
+        0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07,
+        0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e, 0x79, 0x71,
+    ];
+
+    fn read_reg(m: &mut LedInterface, reg: u32) -> u32 {
+        m.bus_enable = true;
+        m.bus_addr = reg;
+        m.bus_write = false;
+        m.prop();
+        m.posedge_clk();
+        m.bus_enable = false;
+        m.prop();
+        assert_eq!(m.bus_read_data_valid, true);
+        m.bus_read_data as u32
+    }
+
+    fn write_reg(m: &mut LedInterface, reg: u32, value: u32) {
+        m.bus_enable = true;
+        m.bus_addr = reg;
+        m.bus_write = true;
+        m.bus_write_data = value as _;
+        m.bus_write_byte_enable = 0xffff;
+        m.prop();
+        m.posedge_clk();
+        m.bus_enable = false;
+        m.bus_write = false;
+        m.prop();
+    }
+
+    fn new() -> LedInterface {
+        let mut m = LedInterface::new();
+
+        m.reset();
+        m.prop();
+
+        m
+    }
+
+    // Waits for the start of the next PWM period (which is also when the next display digit is selected). Within
+    //  a period, LEDs only ever turn off, so any LED turning on also marks the start of a new period.
+    fn wait_for_pwm_period(m: &mut LedInterface) {
+        let prev_digits = m.display_digits;
+        let mut prev_leds = m.leds;
+        for _ in 0..PWM_STEPS + 1 {
+            m.posedge_clk();
+            m.prop();
+            if m.display_digits != prev_digits || (m.leds & !prev_leds) != 0 {
+                return;
+            }
+            prev_leds = m.leds;
+        }
+    }
+
+    // Counts the number of cycles each LED is on over a full PWM period
+    fn led_on_cycles(m: &mut LedInterface) -> [u32; 8] {
+        let mut ret = [0; 8];
+        for _ in 0..PWM_STEPS {
+            for i in 0..8 {
+                if (m.leds >> i) & 1 != 0 {
+                    ret[i] += 1;
+                }
+            }
+            m.posedge_clk();
+            m.prop();
+        }
+        ret
+    }
+
+    #[test]
+    fn reset_state() {
+        let mut m = new();
+
+        assert_eq!(m.leds, 0);
+        assert_eq!(m.display_digits, 0);
+        assert_eq!(read_reg(&mut m, REG_LEDS), 0);
+        for i in 0..8 {
+            assert_eq!(read_reg(&mut m, REG_LED_BRIGHTNESS_BASE + i), 255);
+        }
+        assert_eq!(read_reg(&mut m, REG_DISPLAY_CONTROL), 0);
+        assert_eq!(read_reg(&mut m, REG_DISPLAY_DATA), 0);
+        assert_eq!(read_reg(&mut m, REG_DISPLAY_BRIGHTNESS), 255);
+    }
+
+    #[test]
+    fn reg_readback() {
+        let mut m = new();
+
+        write_reg(&mut m, REG_LEDS, 0xa5);
+        for i in 0..8 {
+            write_reg(&mut m, REG_LED_BRIGHTNESS_BASE + i, i * 17);
+        }
+        write_reg(&mut m, REG_DISPLAY_CONTROL, 0x1a);
+        write_reg(&mut m, REG_DISPLAY_DATA, 0xdeadbeef);
+        write_reg(&mut m, REG_DISPLAY_BRIGHTNESS, 0x42);
+
+        assert_eq!(read_reg(&mut m, REG_LEDS), 0xa5);
+        for i in 0..8 {
+            assert_eq!(read_reg(&mut m, REG_LED_BRIGHTNESS_BASE + i), i * 17);
+        }
+        assert_eq!(read_reg(&mut m, REG_DISPLAY_CONTROL), 0x1a);
+        assert_eq!(read_reg(&mut m, REG_DISPLAY_DATA), 0xdeadbeef);
+        assert_eq!(read_reg(&mut m, REG_DISPLAY_BRIGHTNESS), 0x42);
+    }
+
+    #[test]
+    fn full_brightness_leds_are_static() {
+        let mut m = new();
+
+        write_reg(&mut m, REG_LEDS, 0x5a);
+        for _ in 0..PWM_STEPS * 4 {
+            assert_eq!(m.leds, 0x5a);
+            m.posedge_clk();
+            m.prop();
+        }
+    }
+
+    #[test]
+    fn led_pwm_duty_cycle() {
+        let mut m = new();
+
+        let brightness = [0, 1, 64, 127, 128, 200, 254, 255];
+        write_reg(&mut m, REG_LEDS, 0xff);
+        for i in 0..8 {
+            write_reg(&mut m, REG_LED_BRIGHTNESS_BASE + i as u32, brightness[i]);
+        }
+        wait_for_pwm_period(&mut m);
+
+        let on_cycles = led_on_cycles(&mut m);
+        for i in 0..8 {
+            assert_eq!(on_cycles[i], brightness[i]);
+        }
+
+        // Disabled LEDs stay off regardless of brightness
+        write_reg(&mut m, REG_LEDS, 0x00);
+        assert_eq!(led_on_cycles(&mut m), [0; 8]);
+    }
+
+    #[test]
+    fn display_multiplexes_hex_digits() {
+        let mut m = new();
+
+        write_reg(&mut m, REG_DISPLAY_DATA, 0x0005_c0de);
+        write_reg(&mut m, REG_DISPLAY_CONTROL, 0xf);
+        wait_for_pwm_period(&mut m);
+
+        let mut seen_digits = 0;
+        for _ in 0..4 {
+            let digits = m.display_digits;
+            assert_eq!(digits.count_ones(), 1);
+            let digit = digits.trailing_zeros();
+            seen_digits |= digits;
+
+            let nibble = (0xc0de >> (digit * 4)) & 0xf;
+            let decimal_point = (0x5 >> digit) & 1;
+            let expected_segments = (decimal_point << 7) | HEX_SEGMENTS[nibble as usize];
+            for _ in 0..PWM_STEPS {
+                assert_eq!(m.display_digits, digits);
+                assert_eq!(m.display_segments as u32, expected_segments);
+                m.posedge_clk();
+                m.prop();
+            }
+        }
+
+        assert_eq!(seen_digits, 0xf);
+    }
+
+    #[test]
+    fn display_raw_segments() {
+        let mut m = new();
+
+        write_reg(&mut m, REG_DISPLAY_DATA, 0x8040_2001);
+        write_reg(&mut m, REG_DISPLAY_CONTROL, DISPLAY_CONTROL_RAW | 0xf);
+        wait_for_pwm_period(&mut m);
+
+        for _ in 0..4 {
+            let digit = m.display_digits.trailing_zeros();
+            assert_eq!(m.display_segments as u32, (0x8040_2001 >> (digit * 8)) & 0xff);
+            for _ in 0..PWM_STEPS {
+                m.posedge_clk();
+                m.prop();
+            }
+        }
+    }
+
+    #[test]
+    fn display_digit_enable_and_brightness() {
+        let mut m = new();
+
+        write_reg(&mut m, REG_DISPLAY_CONTROL, 0x5);
+        write_reg(&mut m, REG_DISPLAY_BRIGHTNESS, 100);
+
+        let mut on_cycles = [0; 4];
+        for _ in 0..PWM_STEPS * 4 * 2 {
+            m.posedge_clk();
+            m.prop();
+            for i in 0..4 {
+                if (m.display_digits >> i) & 1 != 0 {
+                    on_cycles[i] += 1;
+                }
+            }
+        }
+
+        // Two full display refreshes
+        assert_eq!(on_cycles, [200, 0, 200, 0]);
+    }
+}
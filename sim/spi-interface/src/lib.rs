@@ -0,0 +1,276 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    const REG_STATUS: u32 = 0;
+    const REG_CHIP_SELECT: u32 = 1;
+    const REG_DATA: u32 = 2;
+    const REG_CLOCK_DIV: u32 = 3;
+
+    const FLASH_ID: [u8; 3] = [0xef, 0x40, 0x18];
+
+    // Behavioral SPI flash (mode 0) supporting READ (0x03) and READ ID (0x9f)
+    struct Flash {
+        data: Vec<u8>,
+
+        prev_cs_n: bool,
+        prev_sck: bool,
+
+        in_shift: u8,
+        in_bits: u32,
+        in_bytes: Vec<u8>,
+
+        out_shift: u8,
+        pending_out: Option<u8>,
+
+        sck_rising_edges: u32,
+    }
+
+    impl Flash {
+        fn new() -> Flash {
+            Flash {
+                data: (0..0x1000).map(|x| (x * 7 + (x >> 8)) as u8).collect(),
+
+                prev_cs_n: true,
+                prev_sck: false,
+
+                in_shift: 0,
+                in_bits: 0,
+                in_bytes: Vec::new(),
+
+                out_shift: 0xff,
+                pending_out: None,
+
+                sck_rising_edges: 0,
+            }
+        }
+
+        fn next_out(&self) -> u8 {
+            match self.in_bytes[0] {
+                0x03 if self.in_bytes.len() >= 4 => {
+                    let addr = ((self.in_bytes[1] as usize) << 16) | ((self.in_bytes[2] as usize) << 8) | (self.in_bytes[3] as usize);
+                    self.data[(addr + self.in_bytes.len() - 4) % self.data.len()]
+                }
+                0x9f if self.in_bytes.len() <= FLASH_ID.len() => FLASH_ID[self.in_bytes.len() - 1],
+                _ => 0xff,
+            }
+        }
+
+        fn update(&mut self, cs_n: bool, sck: bool, mosi: bool) {
+            if cs_n {
+                self.in_bits = 0;
+                self.in_bytes.clear();
+                self.out_shift = 0xff;
+                self.pending_out = None;
+            } else {
+                if sck && !self.prev_sck {
+                    self.sck_rising_edges += 1;
+                    self.in_shift = (self.in_shift << 1) | (mosi as u8);
+                    self.in_bits += 1;
+                    if self.in_bits == 8 {
+                        self.in_bits = 0;
+                        self.in_bytes.push(self.in_shift);
+                        self.pending_out = Some(self.next_out());
+                    }
+                }
+                if !sck && self.prev_sck {
+                    self.out_shift = match self.pending_out.take() {
+                        Some(value) => value,
+                        _ => self.out_shift << 1,
+                    };
+                }
+            }
+
+            self.prev_cs_n = cs_n;
+            self.prev_sck = sck;
+        }
+
+        fn miso(&self) -> bool {
+            (self.out_shift & 0x80) != 0
+        }
+    }
+
+    fn cycle(m: &mut SpiInterface, flash: &mut Flash) {
+        m.prop();
+        flash.update(m.cs_n, m.sck, m.mosi);
+        m.miso = flash.miso();
+        m.prop();
+        m.posedge_clk();
+    }
+
+    fn read_reg(m: &mut SpiInterface, flash: &mut Flash, reg: u32) -> u32 {
+        m.bus_enable = true;
+        m.bus_addr = reg;
+        m.bus_write = false;
+        cycle(m, flash);
+        m.bus_enable = false;
+        m.prop();
+        assert_eq!(m.bus_read_data_valid, true);
+        m.bus_read_data as u32
+    }
+
+    fn write_reg(m: &mut SpiInterface, flash: &mut Flash, reg: u32, value: u32) {
+        m.bus_enable = true;
+        m.bus_addr = reg;
+        m.bus_write = true;
+        m.bus_write_data = value as _;
+        m.bus_write_byte_enable = 0xffff;
+        cycle(m, flash);
+        m.bus_enable = false;
+        m.bus_write = false;
+    }
+
+    fn transfer(m: &mut SpiInterface, flash: &mut Flash, value: u8) -> u8 {
+        write_reg(m, flash, REG_DATA, value as _);
+        while read_reg(m, flash, REG_STATUS) & 1 == 0 {
+            // Wait for transfer to complete
+        }
+        read_reg(m, flash, REG_DATA) as _
+    }
+
+    fn xip_read(m: &mut SpiInterface, flash: &mut Flash, addr: u32) -> u128 {
+        m.xip_bus_enable = true;
+        m.xip_bus_addr = addr;
+        m.xip_bus_write = false;
+        loop {
+            m.prop();
+            let accepted = m.xip_bus_ready;
+            cycle(m, flash);
+            if accepted {
+                break;
+            }
+        }
+        m.xip_bus_enable = false;
+
+        for _ in 0..10000 {
+            m.prop();
+            if m.xip_bus_read_data_valid {
+                let ret = m.xip_bus_read_data;
+                cycle(m, flash);
+                return ret;
+            }
+            cycle(m, flash);
+        }
+
+        panic!("XIP read timed out");
+    }
+
+    fn new() -> (SpiInterface, Flash) {
+        let mut m = SpiInterface::new();
+
+        m.reset();
+        m.prop();
+
+        (m, Flash::new())
+    }
+
+    #[test]
+    fn reset_state() {
+        let (mut m, mut flash) = new();
+
+        assert_eq!(m.cs_n, true);
+        assert_eq!(m.sck, false);
+        assert_eq!(read_reg(&mut m, &mut flash, REG_STATUS), 1);
+        assert_eq!(read_reg(&mut m, &mut flash, REG_CHIP_SELECT), 0);
+        assert_eq!(read_reg(&mut m, &mut flash, REG_CLOCK_DIV), 3);
+    }
+
+    #[test]
+    fn chip_select() {
+        let (mut m, mut flash) = new();
+
+        write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 1);
+        m.prop();
+        assert_eq!(m.cs_n, false);
+        assert_eq!(read_reg(&mut m, &mut flash, REG_CHIP_SELECT), 1);
+
+        write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 0);
+        m.prop();
+        assert_eq!(m.cs_n, true);
+    }
+
+    #[test]
+    fn read_id() {
+        for &clock_div in [0, 1, 3, 10].iter() {
+            let (mut m, mut flash) = new();
+
+            write_reg(&mut m, &mut flash, REG_CLOCK_DIV, clock_div);
+            write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 1);
+            transfer(&mut m, &mut flash, 0x9f);
+            for &id in FLASH_ID.iter() {
+                assert_eq!(transfer(&mut m, &mut flash, 0x00), id);
+            }
+            write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 0);
+
+            assert_eq!(flash.sck_rising_edges, 32);
+        }
+    }
+
+    #[test]
+    fn clock_div_transfer_time() {
+        for &clock_div in [0, 1, 3, 10].iter() {
+            let (mut m, mut flash) = new();
+
+            write_reg(&mut m, &mut flash, REG_CLOCK_DIV, clock_div);
+            write_reg(&mut m, &mut flash, REG_DATA, 0x55);
+            let mut cycles = 1;
+            while read_reg(&mut m, &mut flash, REG_STATUS) & 1 == 0 {
+                cycles += 1;
+            }
+
+            assert_eq!(cycles, 16 * (clock_div + 1));
+        }
+    }
+
+    #[test]
+    fn software_read() {
+        let (mut m, mut flash) = new();
+
+        write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 1);
+        for &byte in [0x03, 0x00, 0x01, 0x23].iter() {
+            transfer(&mut m, &mut flash, byte);
+        }
+        for i in 0..32 {
+            assert_eq!(transfer(&mut m, &mut flash, 0x00), flash.data[0x123 + i]);
+        }
+        write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 0);
+    }
+
+    #[test]
+    fn xip_reads() {
+        let (mut m, mut flash) = new();
+
+        write_reg(&mut m, &mut flash, REG_CLOCK_DIV, 0);
+        for &addr in [0x00, 0x01, 0x2a, 0xff, 0x2a].iter() {
+            let value = xip_read(&mut m, &mut flash, addr);
+            let mut expected = 0;
+            for i in 0..16 {
+                expected |= (flash.data[(addr * 16 + i) as usize] as u128) << (i * 8);
+            }
+            assert_eq!(value, expected);
+
+            m.prop();
+            assert_eq!(m.cs_n, true);
+        }
+    }
+
+    #[test]
+    fn xip_waits_for_software_chip_select() {
+        let (mut m, mut flash) = new();
+
+        write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 1);
+        for _ in 0..100 {
+            m.prop();
+            assert_eq!(m.xip_bus_ready, false);
+            cycle(&mut m, &mut flash);
+        }
+        write_reg(&mut m, &mut flash, REG_CHIP_SELECT, 0);
+
+        m.prop();
+        assert_eq!(m.xip_bus_ready, true);
+    }
+}
@@ -0,0 +1,416 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    use rtl::color_thrust::*;
+
+    use rand::{Rng, SeedableRng};
+
+    const NUM_REGS: usize = (REG_T_DY_ADDR - REG_W0_MIN_ADDR + 1) as usize;
+
+    const SCREEN_WIDTH: u32 = 320;
+    const SCREEN_HEIGHT: u32 = 240;
+
+    struct Vertex {
+        x: i32,
+        y: i32,
+        attributes: [i32; SETUP_VERTEX_ATTRIBUTES as usize],
+    }
+
+    // Same conversion strugl uses for reg values
+    fn to_fixed(x: f32, fract_bits: u32) -> i32 {
+        let bits = x.to_bits() as i32;
+        let exponent = ((bits >> 23) & 0xff) - 127 - 23 + (fract_bits as i32);
+        let mut result = (bits & 0x7fffff) | 0x800000;
+        if exponent < 0 {
+            if exponent > -32 {
+                result >>= -exponent;
+            } else {
+                result = 0;
+            }
+        } else {
+            if exponent < 32 {
+                result <<= exponent;
+            } else {
+                result = 0x7fffffff;
+            }
+        }
+        if ((bits as u32) & 0x80000000) != 0 {
+            result = -result;
+        }
+        result
+    }
+
+    fn attribute_fract_bits(attribute: usize) -> u32 {
+        match attribute {
+            0..=3 => COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1,
+            4 => W_INVERSE_FRACT_BITS,
+            5 => Z_FRACT_BITS,
+            _ => ST_FRACT_BITS,
+        }
+    }
+
+    fn random_vertex(rng: &mut impl Rng) -> Vertex {
+        let x = rng.gen_range(-64 << SETUP_XY_FRACT_BITS, (SCREEN_WIDTH as i32 + 64) << SETUP_XY_FRACT_BITS);
+        let y = rng.gen_range(-64 << SETUP_XY_FRACT_BITS, (SCREEN_HEIGHT as i32 + 64) << SETUP_XY_FRACT_BITS);
+        let mut attributes = [0; SETUP_VERTEX_ATTRIBUTES as usize];
+        for (i, attribute) in attributes.iter_mut().enumerate() {
+            let value: f32 = match i {
+                0..=3 => rng.gen_range(0.0, 1.0),
+                4 => rng.gen_range(0.05, 1.0),
+                5 => rng.gen_range(0.0, 1.0),
+                _ => rng.gen_range(-64.0, 64.0),
+            };
+            // Keep values exactly representable as f32 so both paths see the same inputs
+            *attribute = to_fixed(value, attribute_fract_bits(i)) & !0xff;
+        }
+        Vertex { x, y, attributes }
+    }
+
+    fn orient2d(a: &Vertex, b: &Vertex, c: (i64, i64)) -> i64 {
+        (b.x - a.x) as i64 * (c.1 - a.y as i64) - (b.y - a.y) as i64 * (c.0 - a.x as i64)
+    }
+
+    fn vertex_words(vert: &Vertex) -> Vec<u32> {
+        let mut words = vec![((vert.y as u32) << 16) | ((vert.x as u32) & 0xffff)];
+        words.extend(vert.attributes.iter().map(|&x| x as u32));
+        words
+    }
+
+    fn setup(m: &mut TriangleSetup, verts: &[Vertex; 3], tile_min_x: u32, tile_min_y: u32) -> [u32; NUM_REGS] {
+        for (i, vert) in verts.iter().enumerate() {
+            for (j, &word) in vertex_words(vert).iter().enumerate() {
+                m.vertex_write_addr = ((i as u32) << SETUP_VERTEX_WORD_BITS) | j as u32;
+                m.vertex_write_data = word;
+                m.vertex_write_enable = true;
+                m.prop();
+                m.posedge_clk();
+            }
+        }
+        m.vertex_write_enable = false;
+
+        run_setup(m, tile_min_x, tile_min_y)
+    }
+
+    // Copies vertices from a transform output buffer (with the same read latency as the real one) instead
+    fn setup_from_output_buffer(m: &mut TriangleSetup, output_buffer: &[u32], indices: [u32; 3], tile_min_x: u32, tile_min_y: u32) -> [u32; NUM_REGS] {
+        m.vertex_copy_start = true;
+        m.vertex_copy_indices = (indices[2] << (TRANSFORM_OUTPUT_VERTEX_BITS * 2)) | (indices[1] << TRANSFORM_OUTPUT_VERTEX_BITS) | indices[0];
+        m.prop();
+        m.posedge_clk();
+        m.vertex_copy_start = false;
+        m.prop();
+
+        let mut num_cycles = 0;
+        while m.active {
+            let read_value = if m.vertex_copy_read_enable {
+                Some(output_buffer[m.vertex_copy_read_addr as usize])
+            } else {
+                None
+            };
+            m.posedge_clk();
+            if let Some(read_value) = read_value {
+                m.vertex_copy_read_value = read_value;
+            }
+            m.prop();
+
+            num_cycles += 1;
+            assert!(num_cycles < 100);
+        }
+
+        run_setup(m, tile_min_x, tile_min_y)
+    }
+
+    fn run_setup(m: &mut TriangleSetup, tile_min_x: u32, tile_min_y: u32) -> [u32; NUM_REGS] {
+        m.start = true;
+        m.tile_min_x = tile_min_x;
+        m.tile_min_y = tile_min_y;
+        m.prop();
+        m.posedge_clk();
+        m.start = false;
+        m.prop();
+
+        let mut regs = [None; NUM_REGS];
+        let mut num_cycles = 0;
+        while m.active {
+            if m.reg_write_enable {
+                let index = (m.reg_write_addr - REG_W0_MIN_ADDR) as usize;
+                assert_eq!(regs[index], None);
+                regs[index] = Some(m.reg_write_data);
+            }
+            m.posedge_clk();
+            m.prop();
+
+            num_cycles += 1;
+            assert!(num_cycles < 1000);
+        }
+
+        let mut ret = [0; NUM_REGS];
+        for (i, reg) in regs.iter().enumerate() {
+            ret[i] = reg.expect("Reg not written");
+        }
+        ret
+    }
+
+    // strugl's f32 path for the same (already snapped) vertices
+    fn reference_setup(verts: &[Vertex; 3], tile_min_x: u32, tile_min_y: u32) -> [i32; NUM_REGS] {
+        let x = |i: usize| verts[i].x as f32 / (1 << SETUP_XY_FRACT_BITS) as f32;
+        let y = |i: usize| verts[i].y as f32 / (1 << SETUP_XY_FRACT_BITS) as f32;
+        let orient2d = |a: usize, b: usize, c: (f32, f32)| (x(b) - x(a)) * (c.1 - y(a)) - (y(b) - y(a)) * (c.0 - x(a));
+        let scaled_area = orient2d(0, 1, (x(2), y(2)));
+        let p = (tile_min_x as f32 + 0.5, tile_min_y as f32 + 0.5);
+
+        let mut ret = [0; NUM_REGS];
+        let mut w_min = [0.0; 3];
+        let mut w_dx = [0.0; 3];
+        let mut w_dy = [0.0; 3];
+        for k in 0..3 {
+            let a = (k + 1) % 3;
+            let b = (k + 2) % 3;
+            w_min[k] = orient2d(a, b, p);
+            w_dx[k] = y(a) - y(b);
+            w_dy[k] = x(b) - x(a);
+            ret[k * 3 + 0] = to_fixed(w_min[k], EDGE_FRACT_BITS);
+            ret[k * 3 + 1] = to_fixed(w_dx[k], EDGE_FRACT_BITS);
+            ret[k * 3 + 2] = to_fixed(w_dy[k], EDGE_FRACT_BITS);
+        }
+        for i in 0..SETUP_VERTEX_ATTRIBUTES as usize {
+            let fract_bits = attribute_fract_bits(i);
+            let a = |k: usize| verts[k].attributes[i] as f32 / (1u64 << fract_bits) as f32;
+            let interpolate = |w: &[f32; 3]| a(0) * (w[0] / scaled_area) + a(1) * (w[1] / scaled_area) + a(2) * (w[2] / scaled_area);
+            ret[9 + i * 3 + 0] = to_fixed(interpolate(&w_min), fract_bits);
+            ret[9 + i * 3 + 1] = to_fixed(interpolate(&w_dx), fract_bits);
+            ret[9 + i * 3 + 2] = to_fixed(interpolate(&w_dy), fract_bits);
+        }
+        ret
+    }
+
+    // Same math as strugl's bit-exact model of the setup unit (ModelDevice::setup_triangle)
+    fn model_setup(verts: &[Vertex; 3], tile_min_x: u32, tile_min_y: u32) -> [u32; NUM_REGS] {
+        const AREA_BITS: u32 = 33;
+        const RECIPROCAL_BITS: u32 = 33;
+
+        fn saturate(x: i128) -> u32 {
+            x.max(i32::MIN as i128).min(i32::MAX as i128) as i32 as u32
+        }
+
+        let x = verts.iter().map(|vert| vert.x as i16 as i64).collect::<Vec<_>>();
+        let y = verts.iter().map(|vert| vert.y as i16 as i64).collect::<Vec<_>>();
+
+        let p_x = ((tile_min_x as i64) << SETUP_XY_FRACT_BITS) | (1 << (SETUP_XY_FRACT_BITS - 1));
+        let p_y = ((tile_min_y as i64) << SETUP_XY_FRACT_BITS) | (1 << (SETUP_XY_FRACT_BITS - 1));
+
+        let mut ret = [0; NUM_REGS];
+        let mut edge_min = [0; 3];
+        let mut edge_dx = [0; 3];
+        let mut edge_dy = [0; 3];
+        for k in 0..3 {
+            let a = (k + 1) % 3;
+            let b = (k + 2) % 3;
+            edge_dx[k] = (y[a] - y[b]) << SETUP_XY_FRACT_BITS;
+            edge_dy[k] = (x[b] - x[a]) << SETUP_XY_FRACT_BITS;
+            edge_min[k] = (x[b] - x[a]) * (p_y - y[a]) + (y[a] - y[b]) * (p_x - x[a]);
+            ret[k * 3] = saturate(edge_min[k] as _);
+            ret[k * 3 + 1] = edge_dx[k] as _;
+            ret[k * 3 + 2] = edge_dy[k] as _;
+        }
+
+        let area = (((x[1] - x[0]) * (y[2] - y[0]) - (y[1] - y[0]) * (x[2] - x[0])) as u64) & ((1 << AREA_BITS) - 1);
+        let leading_zeros = area.leading_zeros() - (64 - AREA_BITS);
+        let normalized_area = (area << leading_zeros) as u128;
+        let reciprocal = ((1 << (AREA_BITS + RECIPROCAL_BITS - 1)) - 1) / normalized_area;
+        let shift = AREA_BITS + RECIPROCAL_BITS - 1 - leading_zeros;
+
+        for i in 0..SETUP_VERTEX_ATTRIBUTES as usize {
+            let a = |k: usize| verts[k].attributes[i] as i128;
+            let d1 = a(1) - a(0);
+            let d2 = a(2) - a(0);
+            let components = [
+                (edge_min[1], edge_min[2], a(0)),
+                (edge_dx[1], edge_dx[2], 0),
+                (edge_dy[1], edge_dy[2], 0),
+            ];
+            for (j, &(e1, e2, base)) in components.iter().enumerate() {
+                let sum = d1 * e1 as i128 + d2 * e2 as i128;
+                ret[9 + i * 3 + j] = (((sum * reciprocal as i128) >> shift) + base) as u32;
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn random_triangles() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0xfadebabe);
+
+        let mut m = TriangleSetup::new();
+
+        m.reset();
+        m.prop();
+
+        let mut num_triangles = 0;
+        while num_triangles < 1000 {
+            let mut verts = [random_vertex(&mut rng), random_vertex(&mut rng), random_vertex(&mut rng)];
+            let area = orient2d(&verts[0], &verts[1], (verts[2].x as i64, verts[2].y as i64));
+            if area == 0 {
+                continue;
+            }
+            if area < 0 {
+                verts.swap(0, 1);
+            }
+            let area = area.abs();
+
+            let tile_min_x = rng.gen_range(0, SCREEN_WIDTH / TILE_DIM) * TILE_DIM;
+            let tile_min_y = rng.gen_range(0, SCREEN_HEIGHT / TILE_DIM) * TILE_DIM;
+
+            let regs = setup(&mut m, &verts, tile_min_x, tile_min_y);
+            assert_eq!(regs, model_setup(&verts, tile_min_x, tile_min_y));
+            let reference_regs = reference_setup(&verts, tile_min_x, tile_min_y);
+
+            // Exact edge values, with EDGE_FRACT_BITS = 2 * SETUP_XY_FRACT_BITS fractional bits
+            let p = ((tile_min_x as i64) << SETUP_XY_FRACT_BITS | 0x8, (tile_min_y as i64) << SETUP_XY_FRACT_BITS | 0x8);
+            let mut edge_min = [0; 3];
+            let mut edge_dx = [0; 3];
+            let mut edge_dy = [0; 3];
+            for k in 0..3 {
+                let a = &verts[(k + 1) % 3];
+                let b = &verts[(k + 2) % 3];
+                edge_min[k] = orient2d(a, b, p);
+                edge_dx[k] = ((a.y - b.y) as i64) << SETUP_XY_FRACT_BITS;
+                edge_dy[k] = ((b.x - a.x) as i64) << SETUP_XY_FRACT_BITS;
+                assert_eq!(regs[k * 3 + 0] as i32 as i64, edge_min[k]);
+                assert_eq!(regs[k * 3 + 1] as i32 as i64, edge_dx[k]);
+                assert_eq!(regs[k * 3 + 2] as i32 as i64, edge_dy[k]);
+            }
+
+            for i in 0..SETUP_VERTEX_ATTRIBUTES as usize {
+                let a0 = verts[0].attributes[i] as i128;
+                let d1 = verts[1].attributes[i] as i128 - a0;
+                let d2 = verts[2].attributes[i] as i128 - a0;
+                for (j, (base, e)) in [(a0, &edge_min), (0, &edge_dx), (0, &edge_dy)].iter().enumerate() {
+                    let index = 9 + i * 3 + j;
+
+                    let value = regs[index] as i32 as i128;
+
+                    // Within 2 LSBs of the exact value, base + (d1 * e1 + d2 * e2) / area, if that's in range (out of range
+                    //  values are only truncated, and are covered by the model comparison below)
+                    let numerator = d1 * e[1] as i128 + d2 * e[2] as i128;
+                    let exact = base + numerator.div_euclid(area as i128);
+                    if exact < i32::MIN as i128 || exact > i32::MAX as i128 {
+                        continue;
+                    }
+                    let error = (value - base) * area as i128 - numerator;
+                    assert!(error.abs() < 2 * area as i128, "Attribute {} reg {} out of range: {:#010x}", i, j, value);
+
+                    // Within f32 precision of the software path, which loses up to ~18 significant bits to cancellation in its
+                    //  edge functions
+                    let magnitude = (0..3).map(|k| {
+                        let a = verts[k].attributes[i] as f64;
+                        let b = e[k] as f64 / area as f64;
+                        (a * b).abs()
+                    }).sum::<f64>();
+                    let tolerance = 2.0 + magnitude / (1 << 16) as f64;
+                    let reference_value = reference_regs[index] as i128;
+                    assert!(((value - reference_value) as f64).abs() <= tolerance, "Attribute {} reg {} differs from software path: {:#010x} vs {:#010x}", i, j, value, reference_value);
+                }
+            }
+
+            num_triangles += 1;
+        }
+    }
+
+    #[test]
+    fn sliver_far_from_tile_origin() {
+        let mut m = TriangleSetup::new();
+
+        m.reset();
+        m.prop();
+
+        // A long, thin triangle in the far corner of the screen, set up for the tile at the origin, so its interpolants
+        //  are extrapolated well outside of the i32 range
+        let vertex = |x: i32, y: i32, w_inverse: f32, z: f32, s: f32, t: f32| {
+            let mut attributes = [0; SETUP_VERTEX_ATTRIBUTES as usize];
+            for (i, &value) in [1.0, 0.5, 0.25, 1.0, w_inverse, z, s, t].iter().enumerate() {
+                attributes[i] = to_fixed(value, attribute_fract_bits(i)) & !0xff;
+            }
+            Vertex { x: x << SETUP_XY_FRACT_BITS, y: y << SETUP_XY_FRACT_BITS, attributes }
+        };
+        let verts = [
+            vertex(200, 150, 0.9, 0.9, -60.0, -60.0),
+            vertex(300, 230, 0.1, 0.1, 60.0, 60.0),
+            vertex(299, 230, 0.95, 0.95, -60.0, 60.0),
+        ];
+        assert!(orient2d(&verts[0], &verts[1], (verts[2].x as i64, verts[2].y as i64)) > 0);
+
+        let regs = setup(&mut m, &verts, 0, 0);
+        let model_regs = model_setup(&verts, 0, 0);
+        assert_eq!(regs, model_regs);
+
+        // Make sure the case is actually covered
+        let w_inverse_index = 9 + 4 * 3;
+        let exact_value = |index: usize| {
+            let i = (index - 9) / 3;
+            let a = |k: usize| verts[k].attributes[i] as f64 / (1u64 << attribute_fract_bits(i)) as f64;
+            let x = |k: usize| verts[k].x as f64 / (1 << SETUP_XY_FRACT_BITS) as f64;
+            let y = |k: usize| verts[k].y as f64 / (1 << SETUP_XY_FRACT_BITS) as f64;
+            let orient2d = |a: usize, b: usize, c: (f64, f64)| (x(b) - x(a)) * (c.1 - y(a)) - (y(b) - y(a)) * (c.0 - x(a));
+            let area = orient2d(0, 1, (x(2), y(2)));
+            let p = (0.5, 0.5);
+            (a(0) * orient2d(1, 2, p) + a(1) * orient2d(2, 0, p) + a(2) * orient2d(0, 1, p)) / area * (1u64 << attribute_fract_bits(i)) as f64
+        };
+        let exact = exact_value(w_inverse_index);
+        assert!(exact.abs() > i32::MAX as f64, "w inverse min {} is in range", exact);
+    }
+
+    #[test]
+    fn copy_from_output_buffer() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0xfadebabe);
+
+        let mut m = TriangleSetup::new();
+
+        m.reset();
+        m.prop();
+
+        let mut output_buffer = vec![0; (TRANSFORM_OUTPUT_VERTICES << SETUP_VERTEX_WORD_BITS) as usize];
+        let mut num_triangles = 0;
+        while num_triangles < 100 {
+            let mut verts = [random_vertex(&mut rng), random_vertex(&mut rng), random_vertex(&mut rng)];
+            let area = orient2d(&verts[0], &verts[1], (verts[2].x as i64, verts[2].y as i64));
+            if area == 0 {
+                continue;
+            }
+            if area < 0 {
+                verts.swap(0, 1);
+            }
+
+            // Fill the whole buffer so that any stray reads show up as mismatches
+            for word in output_buffer.iter_mut() {
+                *word = rng.gen();
+            }
+            let mut indices = [0; 3];
+            for (i, vert) in verts.iter().enumerate() {
+                indices[i] = loop {
+                    let index = rng.gen_range(0, TRANSFORM_OUTPUT_VERTICES);
+                    if !indices[..i].contains(&index) {
+                        break index;
+                    }
+                };
+                for (j, &word) in vertex_words(vert).iter().enumerate() {
+                    output_buffer[((indices[i] << SETUP_VERTEX_WORD_BITS) as usize) + j] = word;
+                }
+            }
+
+            let tile_min_x = rng.gen_range(0, SCREEN_WIDTH / TILE_DIM) * TILE_DIM;
+            let tile_min_y = rng.gen_range(0, SCREEN_HEIGHT / TILE_DIM) * TILE_DIM;
+
+            let regs = setup_from_output_buffer(&mut m, &output_buffer, indices, tile_min_x, tile_min_y);
+            let reference_regs = setup(&mut m, &verts, tile_min_x, tile_min_y);
+            assert_eq!(regs, reference_regs);
+
+            num_triangles += 1;
+        }
+    }
+}
@@ -0,0 +1,407 @@
+#[cfg(test)]
+mod tests {
+    mod modules {
+        include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+    }
+
+    use modules::*;
+
+    use rtl::color_thrust::*;
+
+    use rand::{Rng, SeedableRng};
+
+    use std::f64::consts::PI;
+
+    const RAM_WORDS: usize = 1 << 12;
+
+    type Matrix = [[f64; 4]; 4];
+
+    fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut ret = [[0.0; 4]; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                ret[i][j] = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        ret
+    }
+
+    fn rotation(rng: &mut impl Rng) -> Matrix {
+        let (yaw, pitch, roll) = (rng.gen_range(0.0, 2.0 * PI), rng.gen_range(0.0, 2.0 * PI), rng.gen_range(0.0, 2.0 * PI));
+        let y = [
+            [yaw.cos(), 0.0, yaw.sin(), 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-yaw.sin(), 0.0, yaw.cos(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let x = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, pitch.cos(), -pitch.sin(), 0.0],
+            [0.0, pitch.sin(), pitch.cos(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let z = [
+            [roll.cos(), -roll.sin(), 0.0, 0.0],
+            [roll.sin(), roll.cos(), 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        multiply(&y, &multiply(&x, &z))
+    }
+
+    fn perspective(fov_y: f64, aspect: f64, near: f64, far: f64) -> Matrix {
+        let f = 1.0 / (fov_y / 2.0).tan();
+        [
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, (far + near) / (near - far), 2.0 * far * near / (near - far)],
+            [0.0, 0.0, -1.0, 0.0],
+        ]
+    }
+
+    fn to_fixed(x: f64, fract_bits: u32) -> i32 {
+        (x * (1u64 << fract_bits) as f64).round() as i32
+    }
+
+    fn from_fixed(x: i32, fract_bits: u32) -> f64 {
+        x as f64 / (1u64 << fract_bits) as f64
+    }
+
+    // Same 8-bit channel expansion the hardware uses, so that 255 maps to exactly 1
+    fn expand_channel(color: u32, byte_index: u32) -> f64 {
+        let x = (color >> (byte_index * 8)) & 0xff;
+        (x + (x >> 7)) as f64 / 256.0
+    }
+
+    struct Vertex {
+        position: [i32; 3],
+        color: u32,
+        tex_coord: [i32; 2],
+        normal: [i16; 3],
+    }
+
+    impl Vertex {
+        fn words(&self) -> [u128; TRANSFORM_VERTEX_WORDS as usize] {
+            [
+                (self.position[0] as u32 as u128) | ((self.position[1] as u32 as u128) << 32) | ((self.position[2] as u32 as u128) << 64) | ((self.color as u128) << 96),
+                (self.tex_coord[0] as u32 as u128) | ((self.tex_coord[1] as u32 as u128) << 32) | ((self.normal[0] as u16 as u128) << 64) | ((self.normal[1] as u16 as u128) << 80) | ((self.normal[2] as u16 as u128) << 96),
+            ]
+        }
+    }
+
+    fn random_vertex(rng: &mut impl Rng) -> Vertex {
+        let mut position = [0; 3];
+        for x in position.iter_mut() {
+            *x = to_fixed(rng.gen_range(-1.5, 1.5), TRANSFORM_POSITION_FRACT_BITS);
+        }
+        let mut tex_coord = [0; 2];
+        for x in tex_coord.iter_mut() {
+            *x = to_fixed(rng.gen_range(-2.0, 2.0), TRANSFORM_TEX_COORD_FRACT_BITS);
+        }
+        let normal = loop {
+            let n = [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)];
+            let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2] as f64).sqrt();
+            if len > 0.1 && len <= 1.0 {
+                break [
+                    to_fixed(n[0] / len, TRANSFORM_NORMAL_FRACT_BITS) as i16,
+                    to_fixed(n[1] / len, TRANSFORM_NORMAL_FRACT_BITS) as i16,
+                    to_fixed(n[2] / len, TRANSFORM_NORMAL_FRACT_BITS) as i16,
+                ];
+            }
+        };
+        Vertex {
+            position,
+            color: rng.gen(),
+            tex_coord,
+            normal,
+        }
+    }
+
+    struct Scene {
+        params: [u32; 1 << TRANSFORM_PARAM_INDEX_BITS],
+        lighting_enable: bool,
+        light_mask: u32,
+    }
+
+    impl Scene {
+        fn param(&self, index: u32) -> f64 {
+            from_fixed(self.params[index as usize] as i32, TRANSFORM_PARAM_FRACT_BITS)
+        }
+    }
+
+    fn random_scene(rng: &mut impl Rng) -> Scene {
+        let mut params = [0; 1 << TRANSFORM_PARAM_INDEX_BITS];
+        let mut set_param = |index: u32, x: f64| params[index as usize] = to_fixed(x, TRANSFORM_PARAM_FRACT_BITS) as u32;
+
+        let model_view = {
+            let mut m = rotation(rng);
+            m[0][3] = rng.gen_range(-1.5, 1.5);
+            m[1][3] = rng.gen_range(-1.5, 1.5);
+            m[2][3] = rng.gen_range(-6.0, -3.0);
+            m
+        };
+        let matrix = multiply(&perspective(PI / 3.0, 4.0 / 3.0, 1.0, 10.0), &model_view);
+        for i in 0..4 {
+            for j in 0..4 {
+                set_param(TRANSFORM_PARAM_MATRIX + (i * 4 + j) as u32, matrix[i][j]);
+            }
+        }
+        for i in 0..3 {
+            for j in 0..3 {
+                set_param(TRANSFORM_PARAM_NORMAL_MATRIX + (i * 3 + j) as u32, model_view[i][j]);
+            }
+        }
+
+        for (i, &(scale, bias)) in [(160.0, 160.0), (-120.0, 120.0), (0.5, 0.5)].iter().enumerate() {
+            set_param(TRANSFORM_PARAM_VIEWPORT_SCALE + i as u32, scale);
+            set_param(TRANSFORM_PARAM_VIEWPORT_BIAS + i as u32, bias);
+        }
+        for i in 0..2 {
+            set_param(TRANSFORM_PARAM_TEX_COORD_SCALE + i, 64.0);
+            set_param(TRANSFORM_PARAM_TEX_COORD_BIAS + i, -0.5);
+        }
+
+        params[TRANSFORM_PARAM_AMBIENT_COLOR as usize] = rng.gen::<u32>() & 0x3f3f3f;
+        for light in 0..TRANSFORM_LIGHTS {
+            let param = TRANSFORM_PARAM_LIGHTS + light * TRANSFORM_PARAM_LIGHT_WORDS;
+            let dir = loop {
+                let d = [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0f64)];
+                let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+                if len > 0.1 && len <= 1.0 {
+                    break [d[0] / len, d[1] / len, d[2] / len];
+                }
+            };
+            for i in 0..3 {
+                params[(param + i) as usize] = to_fixed(dir[i as usize], TRANSFORM_PARAM_FRACT_BITS) as u32;
+            }
+            params[(param + 3) as usize] = rng.gen::<u32>() & 0xffffff;
+        }
+
+        Scene {
+            params,
+            lighting_enable: rng.gen(),
+            light_mask: rng.gen_range(0, 1 << TRANSFORM_LIGHTS),
+        }
+    }
+
+    struct Transformed {
+        clip: [i32; 4],
+        // Window x, y, r, g, b, a, 1 / w, z, s, t in the same units as the output words
+        values: [f64; NUM_VALUES],
+    }
+
+    const NUM_VALUES: usize = 10;
+    const VALUE_NAMES: [&str; NUM_VALUES] = ["x", "y", "r", "g", "b", "a", "w_inverse", "z", "s", "t"];
+
+    fn transform(scene: &Scene, vertex: &Vertex) -> Transformed {
+        // Clip coordinates are the same truncated values the hardware computes, as they're the transform's precision
+        //  limit (and they determine the clip flags); everything derived from them is ideal
+        let mut clip = [0; 4];
+        for (i, c) in clip.iter_mut().enumerate() {
+            let param = |j: u32| scene.params[(TRANSFORM_PARAM_MATRIX + i as u32 * 4 + j) as usize] as i32 as i64;
+            let sum = (0..3).map(|j| vertex.position[j as usize] as i64 * param(j)).sum::<i64>() + (param(3) << TRANSFORM_POSITION_FRACT_BITS);
+            *c = (sum >> TRANSFORM_PARAM_FRACT_BITS).max(i32::MIN as i64).min(i32::MAX as i64) as i32;
+        }
+        let clip_value = |i: usize| from_fixed(clip[i], TRANSFORM_POSITION_FRACT_BITS);
+        let w = clip_value(3);
+        let window = |i: usize| (clip_value(i) / w) * scene.param(TRANSFORM_PARAM_VIEWPORT_SCALE + i as u32) + scene.param(TRANSFORM_PARAM_VIEWPORT_BIAS + i as u32);
+        let tex_coord = |i: usize| (from_fixed(vertex.tex_coord[i], TRANSFORM_TEX_COORD_FRACT_BITS) * scene.param(TRANSFORM_PARAM_TEX_COORD_SCALE + i as u32) + scene.param(TRANSFORM_PARAM_TEX_COORD_BIAS + i as u32)) / w;
+
+        let mut lit = [1.0; 3];
+        if scene.lighting_enable {
+            let normal = (0..3).map(|i| {
+                (0..3).map(|j| from_fixed(vertex.normal[j] as i32, TRANSFORM_NORMAL_FRACT_BITS) * scene.param(TRANSFORM_PARAM_NORMAL_MATRIX + i * 3 + j as u32)).sum::<f64>()
+            }).collect::<Vec<_>>();
+            for (channel, lit) in lit.iter_mut().enumerate() {
+                // r, g, b byte indices
+                let byte_index = 2 - channel as u32;
+                *lit = expand_channel(scene.params[TRANSFORM_PARAM_AMBIENT_COLOR as usize], byte_index);
+                for light in 0..TRANSFORM_LIGHTS {
+                    if (scene.light_mask & (1 << light)) == 0 {
+                        continue;
+                    }
+                    let param = TRANSFORM_PARAM_LIGHTS + light * TRANSFORM_PARAM_LIGHT_WORDS;
+                    let diffuse = (0..3).map(|i| normal[i as usize] * scene.param(param + i)).sum::<f64>().max(0.0);
+                    *lit += diffuse * expand_channel(scene.params[(param + 3) as usize], byte_index);
+                }
+                *lit = lit.min(1.0);
+            }
+        }
+
+        let color_scale = (1u64 << (COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1)) as f64;
+        Transformed {
+            clip,
+            values: [
+                window(0) * (1 << SETUP_XY_FRACT_BITS) as f64,
+                window(1) * (1 << SETUP_XY_FRACT_BITS) as f64,
+                lit[0] * expand_channel(vertex.color, 2) * color_scale,
+                lit[1] * expand_channel(vertex.color, 1) * color_scale,
+                lit[2] * expand_channel(vertex.color, 0) * color_scale,
+                expand_channel(vertex.color, 3) * color_scale,
+                (1u64 << W_INVERSE_FRACT_BITS) as f64 / w,
+                window(2) * (1u64 << Z_FRACT_BITS) as f64,
+                tex_coord(0) * (1u64 << ST_FRACT_BITS) as f64,
+                tex_coord(1) * (1u64 << ST_FRACT_BITS) as f64,
+            ],
+        }
+    }
+
+    fn clip_flags(clip: &[i32; 4]) -> u32 {
+        let [x, y, z, w] = [clip[0] as i64, clip[1] as i64, clip[2] as i64, clip[3] as i64];
+        let mut flags = 0;
+        for &(bit, outside) in [
+            (TRANSFORM_CLIP_FLAG_LEFT_BIT, x < -w),
+            (TRANSFORM_CLIP_FLAG_RIGHT_BIT, x > w),
+            (TRANSFORM_CLIP_FLAG_BOTTOM_BIT, y < -w),
+            (TRANSFORM_CLIP_FLAG_TOP_BIT, y > w),
+            (TRANSFORM_CLIP_FLAG_NEAR_BIT, z < -w),
+            (TRANSFORM_CLIP_FLAG_FAR_BIT, z > w),
+        ].iter() {
+            if outside {
+                flags |= 1 << bit;
+            }
+        }
+        flags
+    }
+
+    fn run(m: &mut VertexTransform, scene: &Scene, ram: &[u128], source_addr: u32, vertex_count: u32) -> Vec<[u32; TRANSFORM_OUTPUT_WORD_CLIP_FLAGS as usize + 1]> {
+        for (i, &param) in scene.params.iter().enumerate() {
+            m.param_write_addr = i as _;
+            m.param_write_data = param;
+            m.param_write_enable = true;
+            m.prop();
+            m.posedge_clk();
+        }
+        m.param_write_enable = false;
+
+        m.lighting_enable = scene.lighting_enable;
+        m.light_mask = scene.light_mask;
+
+        m.start = true;
+        m.source_addr = source_addr;
+        m.vertex_count = vertex_count;
+        m.prop();
+        m.posedge_clk();
+        m.start = false;
+
+        m.replica_bus_ready = true;
+        m.replica_bus_read_data_valid = false;
+        m.prop();
+
+        let mut num_cycles = 0;
+        while m.active {
+            let read_data = if m.replica_bus_enable {
+                Some(ram[m.replica_bus_addr as usize])
+            } else {
+                None
+            };
+            m.posedge_clk();
+            m.replica_bus_read_data_valid = read_data.is_some();
+            m.replica_bus_read_data = read_data.unwrap_or(0);
+            m.prop();
+
+            num_cycles += 1;
+            assert!(num_cycles < 1000 * vertex_count);
+        }
+        m.replica_bus_read_data_valid = false;
+
+        (0..vertex_count).map(|i| {
+            let mut words = [0; TRANSFORM_OUTPUT_WORD_CLIP_FLAGS as usize + 1];
+            for (j, word) in words.iter_mut().enumerate() {
+                m.output_read_addr = (i << SETUP_VERTEX_WORD_BITS) | j as u32;
+                m.output_read_enable = true;
+                m.prop();
+                m.posedge_clk();
+                m.output_read_enable = false;
+                m.prop();
+                *word = m.output_read_value;
+            }
+            words
+        }).collect()
+    }
+
+    #[test]
+    fn random_vertices() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0xfadebabe);
+
+        let mut m = VertexTransform::new();
+
+        m.reset();
+        m.prop();
+
+        let mut ram = vec![0; RAM_WORDS];
+
+        let mut num_vertices = 0;
+        let mut num_clipped_vertices = 0;
+        let mut max_errors = [0.0f64; NUM_VALUES];
+        let mut total_errors = [0.0f64; NUM_VALUES];
+        for _ in 0..32 {
+            let scene = random_scene(&mut rng);
+            let vertex_count = rng.gen_range(1, TRANSFORM_OUTPUT_VERTICES + 1);
+            let source_addr = rng.gen_range(0, RAM_WORDS as u32 - vertex_count * TRANSFORM_VERTEX_WORDS);
+            let vertices = (0..vertex_count).map(|_| random_vertex(&mut rng)).collect::<Vec<_>>();
+            for (i, vertex) in vertices.iter().enumerate() {
+                for (j, &word) in vertex.words().iter().enumerate() {
+                    ram[source_addr as usize + i * TRANSFORM_VERTEX_WORDS as usize + j] = word;
+                }
+            }
+
+            let outputs = run(&mut m, &scene, &ram, source_addr, vertex_count);
+
+            for (vertex, words) in vertices.iter().zip(outputs.iter()) {
+                let reference = transform(&scene, vertex);
+
+                let flags = clip_flags(&reference.clip);
+                assert_eq!(words[TRANSFORM_OUTPUT_WORD_CLIP_FLAGS as usize], flags);
+                // Everything else is only defined for vertices inside the view volume
+                if flags != 0 {
+                    num_clipped_vertices += 1;
+                    continue;
+                }
+
+                let values = [
+                    (words[SETUP_VERTEX_WORD_XY as usize] as i16) as f64,
+                    ((words[SETUP_VERTEX_WORD_XY as usize] >> 16) as i16) as f64,
+                    words[1] as i32 as f64,
+                    words[2] as i32 as f64,
+                    words[3] as i32 as f64,
+                    words[4] as i32 as f64,
+                    words[5] as i32 as f64,
+                    words[6] as i32 as f64,
+                    words[7] as i32 as f64,
+                    words[8] as i32 as f64,
+                ];
+                for i in 0..NUM_VALUES {
+                    let error = (values[i] - reference.values[i]).abs();
+                    max_errors[i] = max_errors[i].max(error);
+                    total_errors[i] += error;
+                }
+                num_vertices += 1;
+            }
+        }
+
+        println!("{} vertices compared, {} clipped", num_vertices, num_clipped_vertices);
+        println!("Error (LSBs):");
+        for i in 0..NUM_VALUES {
+            println!("  {:>9}: max {:.3}, mean {:.3}", VALUE_NAMES[i], max_errors[i], total_errors[i] / num_vertices as f64);
+        }
+
+        assert!(num_vertices >= 500);
+        // Results are truncated, so they should be within a few LSBs except where an intermediate has fewer bits
+        for &i in [0, 1].iter() {
+            assert!(max_errors[i] < 1.5, "{} error too large", VALUE_NAMES[i]);
+        }
+        // Lighting is done with 16 fractional bits, which is still several bits more than an 8-bit color channel needs
+        for &i in [2, 3, 4].iter() {
+            assert!(max_errors[i] < (1 << (COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1 - 12)) as f64, "{} error too large", VALUE_NAMES[i]);
+        }
+        assert!(max_errors[5] < 1.0, "a error too large");
+        assert!(max_errors[6] < 4.0, "w_inverse error too large");
+        // Normalized device coords have 24 fractional bits, so the low bits of z aren't significant; that's still well
+        //  below a depth buffer LSB
+        assert!(max_errors[7] < 64.0, "z error too large");
+        for &i in [8, 9].iter() {
+            assert!(max_errors[i] < 4.0, "{} error too large", VALUE_NAMES[i]);
+        }
+    }
+}
@@ -1,13 +1,33 @@
 #include <xw/xw.h>
 
 #define PROGRAM_RAM ((volatile uint8_t *)0x01000000)
+#define PROGRAM_RAM_SIZE (0x4000)
+
+// Program images live well past the FPGA bitstream in the config flash. Format: magic ("XWPG"), u32 length, program bytes.
+#define PROGRAM_FLASH_OFFSET (0x00800000)
+#define PROGRAM_FLASH_MAGIC (0x47505758)
+
+// Long enough for input debouncing to settle (~6ms at 100mhz)
+#define INPUT_SETTLE_CYCLES (600000)
 
 typedef void (*program_ram_entry)();
 
-int main()
+static bool load_program_from_flash()
 {
-    xw_puts("xw online");
+    uint32_t header[2];
+    xw_flash_read(PROGRAM_FLASH_OFFSET, (uint8_t *)header, sizeof(header));
+    uint32_t magic = header[0];
+    uint32_t len = header[1];
+    if (magic != PROGRAM_FLASH_MAGIC || len > PROGRAM_RAM_SIZE)
+        return false;
+
+    xw_flash_read(PROGRAM_FLASH_OFFSET + sizeof(header), (uint8_t *)PROGRAM_RAM, len);
 
+    return true;
+}
+
+static void load_program_from_host()
+{
     // TODO: Proper command
     xw_uart_write(0x01);
     // TODO: Proper filename
@@ -32,8 +52,23 @@ int main()
         xw_set_leds(byte);
         PROGRAM_RAM[i] = byte;
     }
+}
 
-    xw_puts("program RAM read successful");
+int main()
+{
+    xw_puts("xw online");
+
+    // Holding button 0 during boot skips the flash image and loads from the host instead
+    xw_sleep_cycles(INPUT_SETTLE_CYCLES);
+    if (!(xw_buttons() & XW_BUTTON_0) && load_program_from_flash())
+    {
+        xw_puts("program loaded from flash");
+    }
+    else
+    {
+        load_program_from_host();
+        xw_puts("program RAM read successful");
+    }
 
     ((program_ram_entry)PROGRAM_RAM)();
 
@@ -5,6 +5,9 @@
 #include "bool.h"
 
 #include "cpu.h"
+// Built from the program's sources (see the Makefile)
+#include <xw/flash.h>
+#include <xw/input.h>
 #include "leds.h"
 #include "uart.h"
 
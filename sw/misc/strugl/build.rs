@@ -45,6 +45,15 @@ fn main() -> Result<()> {
     m.output("depth_buffer_bus_read_data", color_thrust.output("depth_buffer_bus_read_data"));
     m.output("depth_buffer_bus_read_data_valid", color_thrust.output("depth_buffer_bus_read_data_valid"));
 
+    color_thrust.drive_input("stencil_buffer_bus_enable", m.input("stencil_buffer_bus_enable", 1));
+    color_thrust.drive_input("stencil_buffer_bus_addr", m.input("stencil_buffer_bus_addr", color_thrust::TILE_PIXELS_WORDS_BITS - 2));
+    color_thrust.drive_input("stencil_buffer_bus_write", m.input("stencil_buffer_bus_write", 1));
+    color_thrust.drive_input("stencil_buffer_bus_write_data", m.input("stencil_buffer_bus_write_data", 128));
+    color_thrust.drive_input("stencil_buffer_bus_write_byte_enable", m.input("stencil_buffer_bus_write_byte_enable", 16));
+    m.output("stencil_buffer_bus_ready", color_thrust.output("stencil_buffer_bus_ready"));
+    m.output("stencil_buffer_bus_read_data", color_thrust.output("stencil_buffer_bus_read_data"));
+    m.output("stencil_buffer_bus_read_data_valid", color_thrust.output("stencil_buffer_bus_read_data_valid"));
+
     // TODO: Better name?
     buster::generate(&c, "MemCrossbar", 2, 1, 13, 0, 128, 5);
     let mem = m.instance("mem", "MemCrossbar");
@@ -72,9 +81,9 @@ fn main() -> Result<()> {
 
     mem.drive_input("primary1_bus_enable", color_thrust.output("replica_bus_enable"));
     mem.drive_input("primary1_bus_addr", color_thrust.output("replica_bus_addr"));
-    mem.drive_input("primary1_bus_write", m.low());
-    mem.drive_input("primary1_bus_write_data", m.lit(0u32, 128));
-    mem.drive_input("primary1_bus_write_byte_enable", m.lit(0u32, 16));
+    mem.drive_input("primary1_bus_write", color_thrust.output("replica_bus_write"));
+    mem.drive_input("primary1_bus_write_data", color_thrust.output("replica_bus_write_data"));
+    mem.drive_input("primary1_bus_write_byte_enable", color_thrust.output("replica_bus_write_byte_enable"));
     color_thrust.drive_input("replica_bus_ready", mem.output("primary1_bus_ready"));
     color_thrust.drive_input("replica_bus_read_data", mem.output("primary1_bus_read_data"));
     color_thrust.drive_input("replica_bus_read_data_valid", mem.output("primary1_bus_read_data_valid"));
@@ -0,0 +1,165 @@
+use crate::device::*;
+use crate::matrix::*;
+use crate::Vertex;
+
+use rtl::color_thrust::*;
+
+// Scratch RAM for bin debugging, before the command list (overlapping the depth buffer, which isn't used when debugging bins)
+const SOURCE_ADDR: u32 = (1 << TEX_WORD_ADDR_BITS) / 8 * 6;
+const PRIMITIVE_BASE: u32 = SOURCE_ADDR + TRANSFORM_OUTPUT_VERTICES * TRANSFORM_VERTEX_WORDS;
+const LIST_BASE: u32 = SOURCE_ADDR + (1 << TEX_WORD_ADDR_BITS) / 16;
+const LIST_STRIDE: u32 = 4;
+
+pub struct Primitive {
+    // Setup vertex words, in the order they're written to the setup vertex data reg
+    pub vertex_words: [u32; 3 * SETUP_VERTEX_WORDS as usize],
+}
+
+pub struct List {
+    pub primitives: Vec<u32>,
+    pub overflow: bool,
+}
+
+// Decodes binned primitives and per-tile lists, as laid out by the binning unit
+pub struct Bins {
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub primitives: Vec<Primitive>,
+    pub lists: Vec<List>,
+}
+
+impl Bins {
+    pub fn read<D: Device>(device: &mut D, primitive_base: u32, list_base: u32, list_stride: u32, grid_width: u32, grid_height: u32) -> Bins {
+        let mut read_value = |addr: u32, index: u32| (device.read_tex_buffer_word(addr + index / 4) >> ((index % 4) * 32)) as u32;
+
+        let lists = (0..grid_width * grid_height).map(|tile| {
+            let addr = list_base + tile * list_stride;
+            let mut primitives = Vec::new();
+            loop {
+                match read_value(addr, primitives.len() as _) {
+                    BIN_LIST_END => break List { primitives, overflow: false },
+                    BIN_LIST_OVERFLOW => break List { primitives, overflow: true },
+                    primitive => primitives.push(primitive),
+                }
+                if primitives.len() as u32 >= list_stride * 4 {
+                    panic!("List for tile {} isn't terminated", tile);
+                }
+            }
+        }).collect::<Vec<_>>();
+
+        //  Primitives aren't counted anywhere, so assume only those that are referenced were written
+        let num_primitives = lists.iter().flat_map(|list| list.primitives.iter()).max().map_or(0, |&max| max + 1);
+        let primitives = (0..num_primitives).map(|primitive| {
+            let mut vertex_words = [0; 3 * SETUP_VERTEX_WORDS as usize];
+            for (i, word) in vertex_words.iter_mut().enumerate() {
+                *word = read_value(primitive_base + primitive * BIN_PRIMITIVE_WORDS, i as _);
+            }
+            Primitive {
+                vertex_words,
+            }
+        }).collect();
+
+        Bins {
+            grid_width,
+            grid_height,
+            primitives,
+            lists,
+        }
+    }
+
+    pub fn dump(&self) {
+        println!("{} primitives", self.primitives.len());
+        for (i, primitive) in self.primitives.iter().enumerate() {
+            let xy = (0..3).map(|vertex| {
+                let xy = primitive.vertex_words[(vertex * SETUP_VERTEX_WORDS + SETUP_VERTEX_WORD_XY) as usize];
+                let to_float = |x: u32| x as i16 as f32 / (1 << SETUP_XY_FRACT_BITS) as f32;
+                format!("({}, {})", to_float(xy & 0xffff), to_float(xy >> 16))
+            }).collect::<Vec<_>>();
+            println!("  {}: {}", i, xy.join(" "));
+        }
+
+        println!("{}x{} tile lists", self.grid_width, self.grid_height);
+        for tile_y in 0..self.grid_height {
+            let row = (0..self.grid_width).map(|tile_x| {
+                let list = &self.lists[(tile_y * self.grid_width + tile_x) as usize];
+                format!("{:>3}{}", list.primitives.len(), if list.overflow { "+" } else { " " })
+            }).collect::<String>();
+            println!("  {}", row);
+        }
+        for (tile, list) in self.lists.iter().enumerate() {
+            if !list.primitives.is_empty() {
+                let tile = tile as u32;
+                println!("  ({}, {}): {:?}{}", tile % self.grid_width, tile / self.grid_width, list.primitives, if list.overflow { " (overflow)" } else { "" });
+            }
+        }
+    }
+}
+
+// Transforms and bins a triangle list with the hardware units, then reads back the results
+pub fn bin<D: Device>(device: &mut D, model_view: Matrix, projection: Matrix, viewport_width: u32, viewport_height: u32, verts: &[Vertex]) -> Bins {
+    assert!(verts.len() as u32 <= TRANSFORM_OUTPUT_VERTICES);
+
+    let to_fixed = |x: f32, fract_bits: u32| (x * (1 << fract_bits) as f32).round() as i32 as u32;
+
+    let matrix = projection * model_view;
+    device.write_reg(REG_TRANSFORM_PARAM_INDEX_ADDR, 0);
+    let mut params = vec![0; 1 << TRANSFORM_PARAM_INDEX_BITS];
+    for row in 0..4 {
+        for col in 0..4 {
+            params[(TRANSFORM_PARAM_MATRIX + row * 4 + col) as usize] = to_fixed(matrix.get(row as _, col as _), TRANSFORM_PARAM_FRACT_BITS);
+        }
+    }
+    //  Same viewport strugl's own assembly uses
+    let viewport = [(viewport_width as f32 / 2.0, viewport_width as f32 / 2.0), (viewport_height as f32 / 2.0, viewport_height as f32 / 2.0), (0.5, 0.5)];
+    for (i, &(scale, bias)) in viewport.iter().enumerate() {
+        params[TRANSFORM_PARAM_VIEWPORT_SCALE as usize + i] = to_fixed(scale, TRANSFORM_PARAM_FRACT_BITS);
+        params[TRANSFORM_PARAM_VIEWPORT_BIAS as usize + i] = to_fixed(bias, TRANSFORM_PARAM_FRACT_BITS);
+    }
+    for i in 0..2 {
+        params[(TRANSFORM_PARAM_TEX_COORD_SCALE + i) as usize] = to_fixed(1.0, TRANSFORM_PARAM_FRACT_BITS);
+    }
+    for param in params {
+        device.write_reg(REG_TRANSFORM_PARAM_DATA_ADDR, param);
+    }
+    device.write_reg(REG_TRANSFORM_SETTINGS_ADDR, 0);
+
+    for (i, vert) in verts.iter().enumerate() {
+        let position = [vert.position.x(), vert.position.y(), vert.position.z()].iter().enumerate().fold(0, |acc, (i, &x)| acc | ((to_fixed(x, TRANSFORM_POSITION_FRACT_BITS) as u128) << (i * 32)));
+        let to_channel = |x: f32| (x.max(0.0).min(1.0) * 255.0).round() as u128;
+        let color = (to_channel(vert.color.w()) << 24) | (to_channel(vert.color.x()) << 16) | (to_channel(vert.color.y()) << 8) | to_channel(vert.color.z());
+        let tex_coord = (to_fixed(vert.tex_coord.x(), TRANSFORM_TEX_COORD_FRACT_BITS) as u128) | ((to_fixed(vert.tex_coord.y(), TRANSFORM_TEX_COORD_FRACT_BITS) as u128) << 32);
+        let addr = SOURCE_ADDR + i as u32 * TRANSFORM_VERTEX_WORDS;
+        device.write_tex_buffer_word(addr, position | (color << 96));
+        device.write_tex_buffer_word(addr + 1, tex_coord);
+    }
+    device.write_reg(REG_TRANSFORM_SOURCE_ADDR, SOURCE_ADDR);
+    device.write_reg(REG_TRANSFORM_START_ADDR, verts.len() as _);
+    wait_idle(device);
+
+    let grid_width = viewport_width / TILE_DIM;
+    let grid_height = viewport_height / TILE_DIM;
+    device.write_reg(REG_BIN_SETTINGS_ADDR,
+        (1 << REG_BIN_EDGE_TEST_ENABLE_BIT) |
+        (grid_width << REG_BIN_SETTINGS_GRID_WIDTH_BIT_OFFSET) |
+        (grid_height << REG_BIN_SETTINGS_GRID_HEIGHT_BIT_OFFSET));
+    device.write_reg(REG_BIN_PRIMITIVE_BASE_ADDR, PRIMITIVE_BASE);
+    device.write_reg(REG_BIN_LIST_BASE_ADDR, LIST_BASE);
+    device.write_reg(REG_BIN_LIST_STRIDE_ADDR, LIST_STRIDE);
+    //  Each write starts a new operation, so wait for the last one to finish first
+    device.write_reg(REG_BIN_BEGIN_ADDR, 0);
+    wait_idle(device);
+    for i in (0..verts.len() as u32).step_by(3) {
+        device.write_reg(REG_BIN_TRIANGLE_ADDR, ((i + 2) << 16) | ((i + 1) << 8) | i);
+        wait_idle(device);
+    }
+    device.write_reg(REG_BIN_END_ADDR, 0);
+    wait_idle(device);
+
+    Bins::read(device, PRIMITIVE_BASE, LIST_BASE, LIST_STRIDE, grid_width, grid_height)
+}
+
+fn wait_idle<D: Device>(device: &mut D) {
+    while device.read_reg(REG_STATUS_ADDR) != 0 {
+        // Do nothing
+    }
+}
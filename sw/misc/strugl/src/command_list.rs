@@ -0,0 +1,97 @@
+use rtl::color_thrust::*;
+
+#[derive(Clone, Copy)]
+pub enum TileBuffer {
+    Color,
+    Depth,
+    Stencil,
+}
+
+impl TileBuffer {
+    fn to_arg(&self) -> u32 {
+        match self {
+            TileBuffer::Color => COMMAND_TILE_BUFFER_COLOR,
+            TileBuffer::Depth => COMMAND_TILE_BUFFER_DEPTH,
+            TileBuffer::Stencil => COMMAND_TILE_BUFFER_STENCIL,
+        }
+    }
+}
+
+pub struct CommandList {
+    commands: Vec<u64>,
+}
+
+impl CommandList {
+    pub fn new() -> CommandList {
+        CommandList {
+            commands: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, opcode: u32, arg: u32, data: u32) {
+        self.commands.push((opcode as u64) | ((arg as u64) << COMMAND_ARG_BIT_OFFSET) | ((data as u64) << COMMAND_DATA_BIT_OFFSET));
+    }
+
+    pub fn write_reg(&mut self, addr: u32, data: u32) {
+        self.push(COMMAND_OPCODE_WRITE_REG, addr, data);
+    }
+
+    pub fn start(&mut self) {
+        self.push(COMMAND_OPCODE_START, 0, 0);
+    }
+
+    #[allow(unused)]
+    pub fn wait_idle(&mut self) {
+        self.push(COMMAND_OPCODE_WAIT_IDLE, 0, 0);
+    }
+
+    pub fn wait_start(&mut self) {
+        self.push(COMMAND_OPCODE_WAIT_START, 0, 0);
+    }
+
+    // Pitch is the signed distance between the start of each tile row in RAM, in words
+    pub fn tile_load(&mut self, buffer: TileBuffer, addr: u32, pitch: i32) {
+        self.push(COMMAND_OPCODE_TILE_LOAD, buffer.to_arg() | tile_pitch_arg(pitch), addr);
+    }
+
+    pub fn tile_store(&mut self, buffer: TileBuffer, addr: u32, pitch: i32) {
+        self.push(COMMAND_OPCODE_TILE_STORE, buffer.to_arg() | tile_pitch_arg(pitch), addr);
+    }
+
+    pub fn tile_clear(&mut self, buffer: TileBuffer) {
+        self.push(COMMAND_OPCODE_TILE_CLEAR, buffer.to_arg(), 0);
+    }
+
+    pub fn tile_swap(&mut self) {
+        self.push(COMMAND_OPCODE_TILE_SWAP, 0, 0);
+    }
+
+    #[allow(unused)]
+    pub fn jump(&mut self, addr: u32) {
+        self.push(COMMAND_OPCODE_JUMP, 0, addr);
+    }
+
+    pub fn end(&mut self) {
+        self.push(COMMAND_OPCODE_END, 0, 0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    // Packs commands two per word, starting with the least significant half. An odd trailing command is paired with an
+    //  end command.
+    pub fn words(&self) -> Vec<u128> {
+        self.commands.chunks(2).map(|chunk| {
+            chunk.iter().enumerate().fold(0, |acc, (i, &command)| acc | ((command as u128) << (i as u32 * COMMAND_BITS)))
+        }).collect()
+    }
+}
+
+fn tile_pitch_arg(pitch: i32) -> u32 {
+    (pitch as u32 & ((1 << COMMAND_TILE_PITCH_BITS) - 1)) << (COMMAND_TILE_PITCH_BIT_OFFSET - COMMAND_ARG_BIT_OFFSET)
+}
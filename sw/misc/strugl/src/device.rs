@@ -1,3 +1,5 @@
+use rtl::color_thrust::*;
+
 pub trait Device {
     fn write_reg(&mut self, addr: u32, data: u32);
     fn read_reg(&mut self, addr: u32) -> u32;
@@ -5,7 +7,18 @@ pub trait Device {
     fn read_color_buffer_word(&mut self, addr: u32) -> u128;
     fn write_depth_buffer_word(&mut self, addr: u32, data: u128);
     fn read_depth_buffer_word(&mut self, addr: u32) -> u128;
+    fn write_stencil_buffer_word(&mut self, addr: u32, data: u128);
+    fn read_stencil_buffer_word(&mut self, addr: u32) -> u128;
     fn write_tex_buffer_word(&mut self, addr: u32, data: u128);
+    fn read_tex_buffer_word(&mut self, addr: u32) -> u128;
+
+    // Copies a command list into memory starting at the given word address, then starts processing it from there
+    fn submit_command_list(&mut self, addr: u32, words: &[u128]) {
+        for (i, &word) in words.iter().enumerate() {
+            self.write_tex_buffer_word(addr + i as u32, word);
+        }
+        self.write_reg(REG_COMMAND_LIST_BASE_ADDR, addr);
+    }
 }
 
 impl<D: Device + ?Sized> Device for &mut D {
@@ -39,8 +52,28 @@ impl<D: Device + ?Sized> Device for &mut D {
         (**self).read_depth_buffer_word(addr)
     }
 
+    #[inline]
+    fn write_stencil_buffer_word(&mut self, addr: u32, data: u128) {
+        (**self).write_stencil_buffer_word(addr, data);
+    }
+
+    #[inline]
+    fn read_stencil_buffer_word(&mut self, addr: u32) -> u128 {
+        (**self).read_stencil_buffer_word(addr)
+    }
+
     #[inline]
     fn write_tex_buffer_word(&mut self, addr: u32, data: u128) {
         (**self).write_tex_buffer_word(addr, data);
     }
+
+    #[inline]
+    fn read_tex_buffer_word(&mut self, addr: u32) -> u128 {
+        (**self).read_tex_buffer_word(addr)
+    }
+
+    #[inline]
+    fn submit_command_list(&mut self, addr: u32, words: &[u128]) {
+        (**self).submit_command_list(addr, words);
+    }
 }
@@ -1,3 +1,5 @@
+mod bins;
+mod command_list;
 mod device;
 mod matrix;
 mod model_device;
@@ -8,7 +10,9 @@ mod sim_device;
 mod vec2;
 mod vec3;
 mod vec4;
+mod vq;
 
+use command_list::*;
 use device::*;
 use matrix::*;
 use vec2::*;
@@ -21,14 +25,35 @@ use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg32;
 use rtl::color_thrust::*;
 
+use std::collections::HashMap;
 use std::env;
 use std::mem;
+use std::process;
 use std::time::Instant;
 
 const WIDTH: usize = 16 * 8;//320;
 const HEIGHT: usize = 16 * 8;//240;
 const PIXELS: usize = WIDTH * HEIGHT;
 
+// Textures come first in memory, followed by the color, depth, and stencil buffers (which are stored top-down like the
+//  window), and command lists are built in the last thirty-second. Textures get whatever's left, which is enough for a
+//  64x64 16-bit or 128x128 4-bit mip chain (but only a 32x32 argb8888 one).
+const MEM_WORDS: u32 = 1 << TEX_WORD_ADDR_BITS;
+const COMMAND_LIST_MAX_WORDS: u32 = MEM_WORDS / 32;
+const TEXTURE_MAX_WORDS: u32 = MEM_WORDS - (PIXELS / 4 + PIXELS / 8 + PIXELS / 16) as u32 - COMMAND_LIST_MAX_WORDS;
+const COLOR_BUFFER_ADDR: u32 = TEXTURE_MAX_WORDS;
+const DEPTH_BUFFER_ADDR: u32 = COLOR_BUFFER_ADDR + (PIXELS / 4) as u32;
+const STENCIL_BUFFER_ADDR: u32 = DEPTH_BUFFER_ADDR + (PIXELS / 8) as u32;
+const COMMAND_LIST_ADDR: u32 = MEM_WORDS - COMMAND_LIST_MAX_WORDS;
+const COMMAND_LIST_MAX_COMMANDS: usize = COMMAND_LIST_MAX_WORDS as usize * 2;
+// Wait start, setup vertex index and data writes, setup start, and start
+const PRIMITIVE_COMMANDS: usize = 1 + 3 * SETUP_VERTEX_WORDS as usize + 3;
+// Color, depth, and stencil loads or clears
+const TILE_LOAD_COMMANDS: usize = 3;
+// Color, depth, and stencil stores
+const TILE_STORE_COMMANDS: usize = 3;
+const TILES: usize = PIXELS / TILE_PIXELS as usize;
+
 #[derive(Clone, Copy)]
 struct Vertex {
     position: Vec4,
@@ -36,6 +61,101 @@ struct Vertex {
     tex_coord: Vec2,
 }
 
+#[allow(unused)]
+#[derive(Clone)]
+enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+#[allow(unused)]
+#[derive(Clone)]
+enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Incr,
+    Decr,
+    Invert,
+    IncrWrap,
+    DecrWrap,
+}
+
+impl StencilOp {
+    fn to_reg(&self) -> u32 {
+        match self {
+            StencilOp::Keep => REG_STENCIL_SETTINGS_OP_KEEP,
+            StencilOp::Zero => REG_STENCIL_SETTINGS_OP_ZERO,
+            StencilOp::Replace => REG_STENCIL_SETTINGS_OP_REPLACE,
+            StencilOp::Incr => REG_STENCIL_SETTINGS_OP_INCR,
+            StencilOp::Decr => REG_STENCIL_SETTINGS_OP_DECR,
+            StencilOp::Invert => REG_STENCIL_SETTINGS_OP_INVERT,
+            StencilOp::IncrWrap => REG_STENCIL_SETTINGS_OP_INCR_WRAP,
+            StencilOp::DecrWrap => REG_STENCIL_SETTINGS_OP_DECR_WRAP,
+        }
+    }
+}
+
+// Exp and exp2 fog share a single table, covering exponents from 0 to FOG_TABLE_EXTENT
+const FOG_TABLE_EXTENT: f32 = 8.0;
+
+#[allow(unused)]
+#[derive(Clone)]
+enum FogMode {
+    Linear,
+    Exp,
+    Exp2,
+}
+
+impl FogMode {
+    fn to_reg(&self) -> u32 {
+        match self {
+            FogMode::Linear => REG_FOG_SETTINGS_MODE_LINEAR,
+            FogMode::Exp => REG_FOG_SETTINGS_MODE_EXP,
+            FogMode::Exp2 => REG_FOG_SETTINGS_MODE_EXP2,
+        }
+    }
+}
+
+// Fog start/end/density are in units of the selected coord: window z (0-1) or w (eye distance)
+#[allow(unused)]
+#[derive(Clone)]
+enum FogCoord {
+    Z,
+    W,
+}
+
+impl FogCoord {
+    fn to_reg(&self) -> u32 {
+        match self {
+            FogCoord::Z => REG_FOG_SETTINGS_COORD_SELECT_Z,
+            FogCoord::W => REG_FOG_SETTINGS_COORD_SELECT_W,
+        }
+    }
+
+    // Fixed point scale of the coord as seen by the hardware
+    fn scale(&self) -> f32 {
+        match self {
+            FogCoord::Z => (1 << FOG_COORD_BITS) as f32,
+            FogCoord::W => (1 << RESTORED_W_FRACT_BITS) as f32,
+        }
+    }
+}
+
+fn generate_fog_table() -> Vec<u32> {
+    let entries = 1 << FOG_TABLE_INDEX_BITS;
+    (0..entries).map(|i| {
+        let amount = 1.0 - (-FOG_TABLE_EXTENT * i as f32 / entries as f32).exp();
+        (amount * (1 << (FOG_AMOUNT_BITS - 1)) as f32).round() as u32
+    }).collect()
+}
+
 enum TextureFilter {
     Nearest,
     Bilinear,
@@ -50,6 +170,25 @@ enum TextureDim {
 }
 
 impl TextureDim {
+    fn from_u32(dim: u32) -> TextureDim {
+        match dim {
+            16 => TextureDim::X16,
+            32 => TextureDim::X32,
+            64 => TextureDim::X64,
+            128 => TextureDim::X128,
+            _ => panic!("Unsupported texture dim: {}", dim)
+        }
+    }
+
+    fn to_reg(&self) -> u32 {
+        match *self {
+            TextureDim::X16 => REG_TEXTURE_SETTINGS_DIM_16,
+            TextureDim::X32 => REG_TEXTURE_SETTINGS_DIM_32,
+            TextureDim::X64 => REG_TEXTURE_SETTINGS_DIM_64,
+            TextureDim::X128 => REG_TEXTURE_SETTINGS_DIM_128,
+        }
+    }
+
     fn to_u32(&self) -> u32 {
         match *self {
             TextureDim::X16 => 16,
@@ -60,83 +199,296 @@ impl TextureDim {
     }
 }
 
-enum BlendSrcFactor {
-    Zero,
-    One,
-    SrcAlpha,
-    OneMinusSrcAlpha,
+#[allow(unused)]
+#[derive(Clone)]
+enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl TextureWrap {
+    fn to_reg(&self) -> u32 {
+        match self {
+            TextureWrap::Repeat => REG_TEXTURE_SETTINGS_WRAP_REPEAT,
+            TextureWrap::MirroredRepeat => REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT,
+            TextureWrap::ClampToEdge => REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE,
+            TextureWrap::ClampToBorder => REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER,
+        }
+    }
+}
+
+#[allow(unused)]
+enum TextureMipMode {
+    None,
+    Nearest,
+    Linear,
 }
 
-enum BlendDstFactor {
+impl TextureMipMode {
+    fn to_reg(&self) -> u32 {
+        match self {
+            TextureMipMode::None => REG_TEXTURE_SETTINGS_MIP_MODE_NONE,
+            TextureMipMode::Nearest => REG_TEXTURE_SETTINGS_MIP_MODE_NEAREST,
+            TextureMipMode::Linear => REG_TEXTURE_SETTINGS_MIP_MODE_LINEAR,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TextureFormat {
+    Argb8888,
+    Rgb565,
+    Argb4444,
+    Argb1555,
+    Indexed8,
+    Indexed4,
+    Vq,
+}
+
+impl TextureFormat {
+    fn from_name(name: &str) -> TextureFormat {
+        match name {
+            "argb8888" => TextureFormat::Argb8888,
+            "rgb565" => TextureFormat::Rgb565,
+            "argb4444" => TextureFormat::Argb4444,
+            "argb1555" => TextureFormat::Argb1555,
+            "indexed8" => TextureFormat::Indexed8,
+            "indexed4" => TextureFormat::Indexed4,
+            "vq" => TextureFormat::Vq,
+            _ => panic!("Invalid texture format: {}", name),
+        }
+    }
+
+    fn to_reg(&self) -> u32 {
+        match self {
+            TextureFormat::Argb8888 => REG_TEXTURE_SETTINGS_FORMAT_ARGB8888,
+            TextureFormat::Rgb565 => REG_TEXTURE_SETTINGS_FORMAT_RGB565,
+            TextureFormat::Argb4444 => REG_TEXTURE_SETTINGS_FORMAT_ARGB4444,
+            TextureFormat::Argb1555 => REG_TEXTURE_SETTINGS_FORMAT_ARGB1555,
+            TextureFormat::Indexed8 => REG_TEXTURE_SETTINGS_FORMAT_INDEXED8,
+            TextureFormat::Indexed4 => REG_TEXTURE_SETTINGS_FORMAT_INDEXED4,
+            TextureFormat::Vq => REG_TEXTURE_SETTINGS_FORMAT_VQ,
+        }
+    }
+
+    fn texel_bits(&self) -> u32 {
+        match self {
+            TextureFormat::Argb8888 => 32,
+            TextureFormat::Rgb565 | TextureFormat::Argb4444 | TextureFormat::Argb1555 => 16,
+            TextureFormat::Indexed8 => 8,
+            TextureFormat::Indexed4 => 4,
+            // Per 2x2 block
+            TextureFormat::Vq => 8,
+        }
+    }
+
+    fn palette_entries(&self) -> usize {
+        match self {
+            TextureFormat::Indexed8 => 256,
+            TextureFormat::Indexed4 => 16,
+            _ => 0,
+        }
+    }
+
+    // Converts an ARGB8888 texel to this format, with indexed formats using the index of the closest palette entry
+    fn encode(&self, argb: u32, palette: &[u32]) -> u32 {
+        let comp = |shift: u32, bits: u32| -> u32 {
+            (((argb >> shift) & 0xff) * ((1 << bits) - 1) + 127) / 255
+        };
+
+        match self {
+            TextureFormat::Argb8888 => argb,
+            TextureFormat::Rgb565 => (comp(16, 5) << 11) | (comp(8, 6) << 5) | comp(0, 5),
+            TextureFormat::Argb4444 => (comp(24, 4) << 12) | (comp(16, 4) << 8) | (comp(8, 4) << 4) | comp(0, 4),
+            TextureFormat::Argb1555 => (comp(24, 1) << 15) | (comp(16, 5) << 10) | (comp(8, 5) << 5) | comp(0, 5),
+            TextureFormat::Vq => unreachable!("VQ textures are encoded a block at a time"),
+            TextureFormat::Indexed8 | TextureFormat::Indexed4 => {
+                let distance = |entry: u32| -> i32 {
+                    [0, 8, 16, 24].iter().map(|shift| {
+                        let d = ((argb >> shift) & 0xff) as i32 - ((entry >> shift) & 0xff) as i32;
+                        d * d
+                    }).sum()
+                };
+                palette.iter().enumerate().min_by_key(|(_, entry)| distance(**entry)).unwrap().0 as u32
+            }
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(Clone)]
+enum TextureCombineMode {
+    Modulate,
+    Replace,
+    Decal,
+    Add,
+    Disabled,
+}
+
+impl TextureCombineMode {
+    fn to_reg(&self) -> u32 {
+        match self {
+            TextureCombineMode::Modulate => REG_TEXTURE_SETTINGS_COMBINE_MODE_MODULATE,
+            TextureCombineMode::Replace => REG_TEXTURE_SETTINGS_COMBINE_MODE_REPLACE,
+            TextureCombineMode::Decal => REG_TEXTURE_SETTINGS_COMBINE_MODE_DECAL,
+            TextureCombineMode::Add => REG_TEXTURE_SETTINGS_COMBINE_MODE_ADD,
+            TextureCombineMode::Disabled => REG_TEXTURE_SETTINGS_COMBINE_MODE_DISABLED,
+        }
+    }
+}
+
+// Picks the most common colors (after grouping similar colors) as palette entries, each the average of its group
+fn generate_palette(texels: &[u32], num_entries: usize) -> Vec<u32> {
+    let mut groups = HashMap::new();
+    for &texel in texels {
+        let group = groups.entry(texel & 0xf0f0f0f0).or_insert((0, [0; 4]));
+        group.0 += 1;
+        for (i, shift) in [0, 8, 16, 24].iter().enumerate() {
+            group.1[i] += (texel >> shift) & 0xff;
+        }
+    }
+
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|&(key, (count, _))| (std::cmp::Reverse(count), key));
+    groups.into_iter().take(num_entries).map(|(_, (count, sums))| {
+        [0, 8, 16, 24].iter().zip(sums.iter()).fold(0, |acc, (shift, sum)| acc | (((sum + count / 2) / count) << shift))
+    }).collect()
+}
+
+#[allow(unused)]
+#[derive(Clone)]
+enum BlendFactor {
     Zero,
     One,
     SrcAlpha,
     OneMinusSrcAlpha,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    DstAlpha,
+    OneMinusDstAlpha,
+    ConstantColor,
+    OneMinusConstantColor,
+    ConstantAlpha,
+    OneMinusConstantAlpha,
+}
+
+#[allow(unused)]
+#[derive(Clone)]
+enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
 }
 
 // TODO: Figure out the best representation without duplicating tons of data!!
-//  In particular, all of the deltas are the same for each triangle; only the min values vary
-#[derive(Clone, Default)]
+//  In particular, the vertices are the same for each tile the triangle touches; only the tile origin varies
+#[derive(Clone)]
 struct Triangle {
-    w0_min: u32,
-    w0_dx: u32,
-    w0_dy: u32,
-    w1_min: u32,
-    w1_dx: u32,
-    w1_dy: u32,
-    w2_min: u32,
-    w2_dx: u32,
-    w2_dy: u32,
-    r_min: u32,
-    r_dx: u32,
-    r_dy: u32,
-    g_min: u32,
-    g_dx: u32,
-    g_dy: u32,
-    b_min: u32,
-    b_dx: u32,
-    b_dy: u32,
-    a_min: u32,
-    a_dx: u32,
-    a_dy: u32,
-    w_inverse_min: u32,
-    w_inverse_dx: u32,
-    w_inverse_dy: u32,
-    z_min: u32,
-    z_dx: u32,
-    z_dy: u32,
-    s_min: u32,
-    s_dx: u32,
-    s_dy: u32,
-    t_min: u32,
-    t_dx: u32,
-    t_dy: u32,
+    // Setup vertex words, in the order they're written to the setup vertex data reg
+    vertex_words: [u32; 3 * SETUP_VERTEX_WORDS as usize],
+}
+
+// Window-space origin of a tile
+fn tile_min(tile_index: usize) -> (i32, i32) {
+    let tile_min_x = (tile_index % (WIDTH / TILE_DIM as usize)) * TILE_DIM as usize;
+    let tile_min_y = (tile_index / (WIDTH / TILE_DIM as usize)) * TILE_DIM as usize;
+    (tile_min_x as _, tile_min_y as _)
+}
+
+// Word addresses of a tile's first row in the color, depth, and stencil buffers, which are stored bottom-up
+fn tile_addrs(tile_index: usize) -> (u32, u32, u32) {
+    let (tile_min_x, tile_min_y) = tile_min(tile_index);
+    let pixel_index = ((HEIGHT - 1 - tile_min_y as usize) * WIDTH + tile_min_x as usize) as u32;
+    (COLOR_BUFFER_ADDR + pixel_index / 4, DEPTH_BUFFER_ADDR + pixel_index / 8, STENCIL_BUFFER_ADDR + pixel_index / 16)
 }
 
 struct Context<D: Device> {
     device: D,
 
     back_buffer: Vec<u32>,
-    depth_buffer: Vec<u16>,
+
+    clear_color: u32,
+    clear_depth: u16,
+    clear_stencil: u8,
+    // Skips storing depth tiles, for when depth doesn't need to outlive the current render call
+    discard_depth: bool,
+
+    // Whether each tile has been stored this frame; those that haven't are cleared instead of loaded
+    color_tiles_valid: Vec<bool>,
+    depth_tiles_valid: Vec<bool>,
+    stencil_tiles_valid: Vec<bool>,
 
     depth_test_enable: bool,
     depth_write_mask_enable: bool,
+    depth_func: CompareFunc,
+
+    alpha_test_enable: bool,
+    alpha_test_func: CompareFunc,
+    alpha_test_ref: u8,
+
+    stencil_test_enable: bool,
+    stencil_func: CompareFunc,
+    stencil_ref: u8,
+    stencil_mask: u8,
+    stencil_sfail_op: StencilOp,
+    stencil_dpfail_op: StencilOp,
+    stencil_dppass_op: StencilOp,
+
+    fog_enable: bool,
+    fog_mode: FogMode,
+    fog_coord: FogCoord,
+    fog_color: u32,
+    fog_start: f32,
+    fog_end: f32,
+    fog_density: f32,
 
     // TODO: Move to texture object
     texture_filter: TextureFilter,
-    texture_dim: TextureDim,
-
-    blend_src_factor: BlendSrcFactor,
-    blend_dst_factor: BlendDstFactor,
+    texture_width: TextureDim,
+    texture_height: TextureDim,
+    texture_wrap_s: TextureWrap,
+    texture_wrap_t: TextureWrap,
+    texture_border_color: u32,
+    texture_mip_mode: TextureMipMode,
+    texture_max_level: u32,
+    texture_lod_bias: f32,
+    texture_format: TextureFormat,
+    texture_combine_mode: TextureCombineMode,
+
+    blend_src_factor: BlendFactor,
+    blend_dst_factor: BlendFactor,
+    blend_op: BlendOp,
+    blend_constant: u32,
+
+    viewport_x: i32,
+    viewport_y: i32,
+    viewport_width: u32,
+    viewport_height: u32,
+
+    scissor_test_enable: bool,
+    scissor_x: i32,
+    scissor_y: i32,
+    scissor_width: u32,
+    scissor_height: u32,
 
     model_view: Matrix,
     projection: Matrix,
 
     assembled_triangles: Vec<Vec<Triangle>>,
 
+    command_list: CommandList,
+
     estimated_frame_bin_cycles: u64,
     estimated_frame_reg_cycles: u64,
     estimated_frame_xfer_cycles: u64,
+    // Transfers that overlap with rendering another tile, so they don't count towards the frame
+    estimated_frame_hidden_xfer_cycles: u64,
     estimated_frame_rasterization_cycles: u64,
 }
 
@@ -146,26 +498,80 @@ impl<D: Device> Context<D> {
             device,
 
             back_buffer: vec![0; PIXELS],
-            depth_buffer: vec![0xffff; PIXELS],
+
+            clear_color: 0,
+            clear_depth: 0xffff,
+            clear_stencil: 0,
+            discard_depth: false,
+
+            color_tiles_valid: vec![false; TILES],
+            depth_tiles_valid: vec![false; TILES],
+            stencil_tiles_valid: vec![false; TILES],
 
             depth_test_enable: false,
             depth_write_mask_enable: false,
+            depth_func: CompareFunc::Less,
+
+            alpha_test_enable: false,
+            alpha_test_func: CompareFunc::Always,
+            alpha_test_ref: 0,
+
+            stencil_test_enable: false,
+            stencil_func: CompareFunc::Always,
+            stencil_ref: 0,
+            stencil_mask: 0xff,
+            stencil_sfail_op: StencilOp::Keep,
+            stencil_dpfail_op: StencilOp::Keep,
+            stencil_dppass_op: StencilOp::Keep,
+
+            fog_enable: false,
+            fog_mode: FogMode::Linear,
+            fog_coord: FogCoord::W,
+            fog_color: 0,
+            fog_start: 0.0,
+            fog_end: 1.0,
+            fog_density: 1.0,
 
             texture_filter: TextureFilter::Nearest,
-            texture_dim: TextureDim::X16,
-
-            blend_src_factor: BlendSrcFactor::One,
-            blend_dst_factor: BlendDstFactor::Zero,
+            texture_width: TextureDim::X16,
+            texture_height: TextureDim::X16,
+            texture_wrap_s: TextureWrap::Repeat,
+            texture_wrap_t: TextureWrap::Repeat,
+            texture_border_color: 0,
+            texture_mip_mode: TextureMipMode::None,
+            texture_max_level: 0,
+            texture_lod_bias: 0.0,
+            texture_format: TextureFormat::Argb8888,
+            texture_combine_mode: TextureCombineMode::Modulate,
+
+            blend_src_factor: BlendFactor::One,
+            blend_dst_factor: BlendFactor::Zero,
+            blend_op: BlendOp::Add,
+            blend_constant: 0,
+
+            viewport_x: 0,
+            viewport_y: 0,
+            viewport_width: WIDTH as _,
+            viewport_height: HEIGHT as _,
+
+            scissor_test_enable: false,
+            scissor_x: 0,
+            scissor_y: 0,
+            scissor_width: WIDTH as _,
+            scissor_height: HEIGHT as _,
 
             model_view: Matrix::identity(),
             projection: Matrix::identity(),
 
             // TODO: Fixed capacity and splitting drawcalls on overflow
-            assembled_triangles: vec![Vec::new(); PIXELS / TILE_PIXELS as usize],
+            assembled_triangles: vec![Vec::new(); TILES],
+
+            command_list: CommandList::new(),
 
             estimated_frame_bin_cycles: 0,
             estimated_frame_reg_cycles: 0,
             estimated_frame_xfer_cycles: 0,
+            estimated_frame_hidden_xfer_cycles: 0,
             estimated_frame_rasterization_cycles: 0,
         }
     }
@@ -181,14 +587,80 @@ impl<D: Device> Context<D> {
 
         // Primitive assembly
         for i in (0..verts.len()).step_by(3) {
-            self.assemble_triangle([verts[i + 0], verts[i + 1], verts[i + 2]])
+            self.clip_triangle([verts[i + 0], verts[i + 1], verts[i + 2]])
         }
 
         // Per-drawcall rasterizer setup
+        //  Unlike the per-primitive regs, these aren't shadowed, so everything in flight has to finish first
+        self.wait_idle();
         self.device.write_reg(
             REG_DEPTH_SETTINGS_ADDR,
             (if self.depth_test_enable { 1 } else { 0 } << REG_DEPTH_TEST_ENABLE_BIT) |
-            (if self.depth_write_mask_enable { 1 } else { 0 } << REG_DEPTH_WRITE_MASK_ENABLE_BIT));
+            (if self.depth_write_mask_enable { 1 } else { 0 } << REG_DEPTH_WRITE_MASK_ENABLE_BIT) |
+            (match self.depth_func {
+                CompareFunc::Never => REG_DEPTH_SETTINGS_FUNC_NEVER,
+                CompareFunc::Less => REG_DEPTH_SETTINGS_FUNC_LESS,
+                CompareFunc::Equal => REG_DEPTH_SETTINGS_FUNC_EQUAL,
+                CompareFunc::LessEqual => REG_DEPTH_SETTINGS_FUNC_LEQUAL,
+                CompareFunc::Greater => REG_DEPTH_SETTINGS_FUNC_GREATER,
+                CompareFunc::NotEqual => REG_DEPTH_SETTINGS_FUNC_NOTEQUAL,
+                CompareFunc::GreaterEqual => REG_DEPTH_SETTINGS_FUNC_GEQUAL,
+                CompareFunc::Always => REG_DEPTH_SETTINGS_FUNC_ALWAYS,
+            } << REG_DEPTH_SETTINGS_FUNC_BIT_OFFSET));
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(
+            REG_ALPHA_TEST_SETTINGS_ADDR,
+            (if self.alpha_test_enable { 1 } else { 0 } << REG_ALPHA_TEST_ENABLE_BIT) |
+            (match self.alpha_test_func {
+                CompareFunc::Never => REG_ALPHA_TEST_SETTINGS_FUNC_NEVER,
+                CompareFunc::Less => REG_ALPHA_TEST_SETTINGS_FUNC_LESS,
+                CompareFunc::Equal => REG_ALPHA_TEST_SETTINGS_FUNC_EQUAL,
+                CompareFunc::LessEqual => REG_ALPHA_TEST_SETTINGS_FUNC_LEQUAL,
+                CompareFunc::Greater => REG_ALPHA_TEST_SETTINGS_FUNC_GREATER,
+                CompareFunc::NotEqual => REG_ALPHA_TEST_SETTINGS_FUNC_NOTEQUAL,
+                CompareFunc::GreaterEqual => REG_ALPHA_TEST_SETTINGS_FUNC_GEQUAL,
+                CompareFunc::Always => REG_ALPHA_TEST_SETTINGS_FUNC_ALWAYS,
+            } << REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET) |
+            ((self.alpha_test_ref as u32) << REG_ALPHA_TEST_SETTINGS_REF_BIT_OFFSET));
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(
+            REG_STENCIL_SETTINGS_ADDR,
+            (if self.stencil_test_enable { 1 } else { 0 } << REG_STENCIL_TEST_ENABLE_BIT) |
+            (match self.stencil_func {
+                CompareFunc::Never => REG_STENCIL_SETTINGS_FUNC_NEVER,
+                CompareFunc::Less => REG_STENCIL_SETTINGS_FUNC_LESS,
+                CompareFunc::Equal => REG_STENCIL_SETTINGS_FUNC_EQUAL,
+                CompareFunc::LessEqual => REG_STENCIL_SETTINGS_FUNC_LEQUAL,
+                CompareFunc::Greater => REG_STENCIL_SETTINGS_FUNC_GREATER,
+                CompareFunc::NotEqual => REG_STENCIL_SETTINGS_FUNC_NOTEQUAL,
+                CompareFunc::GreaterEqual => REG_STENCIL_SETTINGS_FUNC_GEQUAL,
+                CompareFunc::Always => REG_STENCIL_SETTINGS_FUNC_ALWAYS,
+            } << REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET) |
+            ((self.stencil_ref as u32) << REG_STENCIL_SETTINGS_REF_BIT_OFFSET) |
+            ((self.stencil_mask as u32) << REG_STENCIL_SETTINGS_MASK_BIT_OFFSET) |
+            (self.stencil_sfail_op.to_reg() << REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET) |
+            (self.stencil_dpfail_op.to_reg() << REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET) |
+            (self.stencil_dppass_op.to_reg() << REG_STENCIL_SETTINGS_DPPASS_OP_BIT_OFFSET));
+        self.estimated_frame_reg_cycles += 1;
+
+        self.device.write_reg(
+            REG_FOG_SETTINGS_ADDR,
+            (if self.fog_enable { 1 } else { 0 } << REG_FOG_ENABLE_BIT) |
+            (self.fog_mode.to_reg() << REG_FOG_SETTINGS_MODE_BIT_OFFSET) |
+            (self.fog_coord.to_reg() << REG_FOG_SETTINGS_COORD_SELECT_BIT_OFFSET));
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_FOG_COLOR_ADDR, self.fog_color);
+        self.estimated_frame_reg_cycles += 1;
+        //  The fog amount (or table position) reaches 1.0 at the end of the range
+        let (fog_start, fog_range) = match self.fog_mode {
+            FogMode::Linear => (self.fog_start, self.fog_end - self.fog_start),
+            FogMode::Exp => (0.0, FOG_TABLE_EXTENT / self.fog_density),
+            FogMode::Exp2 => (0.0, FOG_TABLE_EXTENT.sqrt() / self.fog_density),
+        };
+        let to_fog_reg = |x: f32| x.round().max(0.0).min(((1 << FOG_COORD_BITS) - 1) as f32) as u32;
+        self.device.write_reg(REG_FOG_START_ADDR, to_fog_reg(fog_start * self.fog_coord.scale()));
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_FOG_SCALE_ADDR, to_fog_reg((1 << (FOG_COORD_BITS + FOG_AMOUNT_BITS - 1)) as f32 / (fog_range * self.fog_coord.scale())));
         self.estimated_frame_reg_cycles += 1;
 
         self.device.write_reg(
@@ -197,12 +669,20 @@ impl<D: Device> Context<D> {
                 TextureFilter::Nearest => REG_TEXTURE_SETTINGS_FILTER_SELECT_NEAREST,
                 TextureFilter::Bilinear => REG_TEXTURE_SETTINGS_FILTER_SELECT_BILINEAR,
             } << REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET) |
-            (match self.texture_dim {
-                TextureDim::X16 => REG_TEXTURE_SETTINGS_DIM_16,
-                TextureDim::X32 => REG_TEXTURE_SETTINGS_DIM_32,
-                TextureDim::X64 => REG_TEXTURE_SETTINGS_DIM_64,
-                TextureDim::X128 => REG_TEXTURE_SETTINGS_DIM_128,
-            } << REG_TEXTURE_SETTINGS_DIM_BIT_OFFSET));
+            (self.texture_width.to_reg() << REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET) |
+            (self.texture_height.to_reg() << REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET) |
+            (self.texture_wrap_s.to_reg() << REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET) |
+            (self.texture_wrap_t.to_reg() << REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET) |
+            (self.texture_mip_mode.to_reg() << REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET) |
+            (self.texture_max_level << REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET) |
+            (self.texture_format.to_reg() << REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET) |
+            (self.texture_combine_mode.to_reg() << REG_TEXTURE_SETTINGS_COMBINE_MODE_BIT_OFFSET));
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_TEXTURE_BORDER_COLOR_ADDR, self.texture_border_color);
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(
+            REG_TEXTURE_LOD_BIAS_ADDR,
+            ((self.texture_lod_bias * (1 << TEX_LOD_FRACT_BITS) as f32).round() as i32 as u32) & ((1 << REG_TEXTURE_LOD_BIAS_BITS) - 1));
         self.estimated_frame_reg_cycles += 1;
         // TODO: Proper addr where texture data is loaded
         self.device.write_reg(REG_TEXTURE_BASE_ADDR, 0x00000000);
@@ -211,165 +691,322 @@ impl<D: Device> Context<D> {
         self.device.write_reg(
             REG_BLEND_SETTINGS_ADDR,
             (match self.blend_src_factor {
-                BlendSrcFactor::Zero => REG_BLEND_SETTINGS_SRC_FACTOR_ZERO,
-                BlendSrcFactor::One => REG_BLEND_SETTINGS_SRC_FACTOR_ONE,
-                BlendSrcFactor::SrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA,
-                BlendSrcFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA,
+                BlendFactor::Zero => REG_BLEND_SETTINGS_SRC_FACTOR_ZERO,
+                BlendFactor::One => REG_BLEND_SETTINGS_SRC_FACTOR_ONE,
+                BlendFactor::SrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA,
+                BlendFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA,
+                BlendFactor::SrcColor => REG_BLEND_SETTINGS_SRC_FACTOR_SRC_COLOR,
+                BlendFactor::OneMinusSrcColor => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_COLOR,
+                BlendFactor::DstColor => REG_BLEND_SETTINGS_SRC_FACTOR_DST_COLOR,
+                BlendFactor::OneMinusDstColor => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_COLOR,
+                BlendFactor::DstAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_DST_ALPHA,
+                BlendFactor::OneMinusDstAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_ALPHA,
+                BlendFactor::ConstantColor => REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_COLOR,
+                BlendFactor::OneMinusConstantColor => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_COLOR,
+                BlendFactor::ConstantAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_ALPHA,
+                BlendFactor::OneMinusConstantAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_ALPHA,
             } << REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET) |
             (match self.blend_dst_factor {
-                BlendDstFactor::Zero => REG_BLEND_SETTINGS_DST_FACTOR_ZERO,
-                BlendDstFactor::One => REG_BLEND_SETTINGS_DST_FACTOR_ONE,
-                BlendDstFactor::SrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA,
-                BlendDstFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA,
-            } << REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET));
+                BlendFactor::Zero => REG_BLEND_SETTINGS_DST_FACTOR_ZERO,
+                BlendFactor::One => REG_BLEND_SETTINGS_DST_FACTOR_ONE,
+                BlendFactor::SrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA,
+                BlendFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA,
+                BlendFactor::SrcColor => REG_BLEND_SETTINGS_DST_FACTOR_SRC_COLOR,
+                BlendFactor::OneMinusSrcColor => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_COLOR,
+                BlendFactor::DstColor => REG_BLEND_SETTINGS_DST_FACTOR_DST_COLOR,
+                BlendFactor::OneMinusDstColor => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_COLOR,
+                BlendFactor::DstAlpha => REG_BLEND_SETTINGS_DST_FACTOR_DST_ALPHA,
+                BlendFactor::OneMinusDstAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_ALPHA,
+                BlendFactor::ConstantColor => REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_COLOR,
+                BlendFactor::OneMinusConstantColor => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_COLOR,
+                BlendFactor::ConstantAlpha => REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_ALPHA,
+                BlendFactor::OneMinusConstantAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_ALPHA,
+            } << REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET) |
+            (match self.blend_op {
+                BlendOp::Add => REG_BLEND_SETTINGS_OP_ADD,
+                BlendOp::Subtract => REG_BLEND_SETTINGS_OP_SUBTRACT,
+                BlendOp::ReverseSubtract => REG_BLEND_SETTINGS_OP_REVERSE_SUBTRACT,
+                BlendOp::Min => REG_BLEND_SETTINGS_OP_MIN,
+                BlendOp::Max => REG_BLEND_SETTINGS_OP_MAX,
+            } << REG_BLEND_SETTINGS_OP_BIT_OFFSET));
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_BLEND_CONSTANT_ADDR, self.blend_constant);
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_TILE_CLEAR_COLOR_ADDR, self.clear_color);
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_TILE_CLEAR_DEPTH_ADDR, self.clear_depth as _);
+        self.estimated_frame_reg_cycles += 1;
+        self.device.write_reg(REG_TILE_CLEAR_STENCIL_ADDR, self.clear_stencil as _);
         self.estimated_frame_reg_cycles += 1;
 
         // Primitive rendering
-        for tile_index_y in 0..HEIGHT / (TILE_DIM as usize) {
-            let tile_min_y = (tile_index_y * (TILE_DIM as usize)) as i32;
+        //  Tiles are pipelined through the two tile buffer banks: while a tile is rendered in the render bank, the last
+        //   one is stored from the transfer bank and the next one is loaded into it, and then the banks are swapped
+        let tile_indices = (0..TILES).filter(|&tile_index| !self.assembled_triangles[tile_index].is_empty()).collect::<Vec<_>>();
+        let (first_tile_index, last_tile_index) = match (tile_indices.first(), tile_indices.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return,
+        };
 
-            for tile_index_x in 0..WIDTH / (TILE_DIM as usize) {
-                let tile_min_x = (tile_index_x * (TILE_DIM as usize)) as i32;
+        self.reserve_commands(TILE_LOAD_COMMANDS + 1);
+        self.estimated_frame_xfer_cycles += self.load_tile(first_tile_index);
+        self.command_list.tile_swap();
+
+        for (i, &tile_index) in tile_indices.iter().enumerate() {
+            let (tile_min_x, tile_min_y) = tile_min(tile_index);
+            let mut assembled_triangles = mem::take(&mut self.assembled_triangles[tile_index]);
+
+            // Clip rect in tile-local coords; triangles are only binned to tiles that overlap it, so it's never empty here
+            let (clip_min_x, clip_min_y, clip_max_x, clip_max_y) = self.clip_rect();
+            let tile_max = TILE_DIM as i32 - 1;
+            let scissor_min_x = (clip_min_x - tile_min_x).max(0).min(tile_max) as u32;
+            let scissor_min_y = (clip_min_y - tile_min_y).max(0).min(tile_max) as u32;
+            let scissor_max_x = (clip_max_x - tile_min_x).max(0).min(tile_max) as u32;
+            let scissor_max_y = (clip_max_y - tile_min_y).max(0).min(tile_max) as u32;
+
+            // Store the last tile while this one is rendered
+            self.reserve_commands(TILE_STORE_COMMANDS + 1);
+            if i > 0 {
+                self.estimated_frame_hidden_xfer_cycles += self.store_tile(tile_indices[i - 1]);
+            }
 
-                let tile_index = tile_index_y * (WIDTH / (TILE_DIM as usize)) + tile_index_x;
-                let assembled_triangles = &mut self.assembled_triangles[tile_index];
-                if assembled_triangles.is_empty() {
-                    continue;
+            self.command_list.write_reg(
+                REG_SCISSOR_ADDR,
+                (scissor_min_x << REG_SCISSOR_MIN_X_BIT_OFFSET) |
+                (scissor_min_y << REG_SCISSOR_MIN_Y_BIT_OFFSET) |
+                (scissor_max_x << REG_SCISSOR_MAX_X_BIT_OFFSET) |
+                (scissor_max_y << REG_SCISSOR_MAX_Y_BIT_OFFSET));
+
+            for triangle in assembled_triangles.iter() {
+                self.reserve_commands(PRIMITIVE_COMMANDS);
+
+                // Ensure the last primitive's start has been taken, so its regs are free for this one
+                self.command_list.wait_start();
+                self.command_list.write_reg(REG_SETUP_VERTEX_INDEX_ADDR, 0);
+                for &word in triangle.vertex_words.iter() {
+                    self.command_list.write_reg(REG_SETUP_VERTEX_DATA_ADDR, word);
                 }
+                self.command_list.write_reg(REG_SETUP_START_ADDR, ((tile_min_y as u32) << 16) | tile_min_x as u32);
 
-                // Copy tile into rasterizer memory
-                for y in 0..TILE_DIM as usize {
-                    for x in 0..TILE_DIM as usize / 4 {
-                        let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x * 4;
-                        let mut word = 0;
-                        for i in 0..4 {
-                            word |= (self.back_buffer[buffer_index + i] as u128) << (i * 32);
-                        }
-                        self.device.write_color_buffer_word(y as u32 * TILE_DIM / 4 + x as u32, word);
+                self.estimated_frame_bin_cycles += mem::size_of::<Triangle>() as u64;
 
-                        self.estimated_frame_xfer_cycles += 1;
-                    }
-                }
-                if self.depth_test_enable || self.depth_write_mask_enable {
-                    for y in 0..TILE_DIM as usize {
-                        for x in 0..TILE_DIM as usize / 8 {
-                            let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x * 8;
-                            let mut word = 0;
-                            for i in 0..8 {
-                                word |= (self.depth_buffer[buffer_index + i] as u128) << (i * 16);
-                            }
-                            self.device.write_depth_buffer_word(y as u32 * TILE_DIM / 8 + x as u32, word);
+                // Queue this primitive to start once the last one has been traversed
+                self.command_list.start();
+            }
 
-                            self.estimated_frame_xfer_cycles += 1;
-                        }
-                    }
-                }
+            // Load the next tile while the last primitive is rasterized, then swap it in once everything's done
+            self.reserve_commands(TILE_LOAD_COMMANDS + 1);
+            if let Some(&next_tile_index) = tile_indices.get(i + 1) {
+                self.estimated_frame_hidden_xfer_cycles += self.load_tile(next_tile_index);
+            }
+            self.command_list.tile_swap();
 
-                for triangle in assembled_triangles.iter() {
-                    self.device.write_reg(REG_W0_MIN_ADDR, triangle.w0_min);
-                    self.device.write_reg(REG_W0_DX_ADDR, triangle.w0_dx);
-                    self.device.write_reg(REG_W0_DY_ADDR, triangle.w0_dy);
-                    self.device.write_reg(REG_W1_MIN_ADDR, triangle.w1_min);
-                    self.device.write_reg(REG_W1_DX_ADDR, triangle.w1_dx);
-                    self.device.write_reg(REG_W1_DY_ADDR, triangle.w1_dy);
-                    self.device.write_reg(REG_W2_MIN_ADDR, triangle.w2_min);
-                    self.device.write_reg(REG_W2_DX_ADDR, triangle.w2_dx);
-                    self.device.write_reg(REG_W2_DY_ADDR, triangle.w2_dy);
-                    self.device.write_reg(REG_R_MIN_ADDR, triangle.r_min);
-                    self.device.write_reg(REG_R_DX_ADDR, triangle.r_dx);
-                    self.device.write_reg(REG_R_DY_ADDR, triangle.r_dy);
-                    self.device.write_reg(REG_G_MIN_ADDR, triangle.g_min);
-                    self.device.write_reg(REG_G_DX_ADDR, triangle.g_dx);
-                    self.device.write_reg(REG_G_DY_ADDR, triangle.g_dy);
-                    self.device.write_reg(REG_B_MIN_ADDR, triangle.b_min);
-                    self.device.write_reg(REG_B_DX_ADDR, triangle.b_dx);
-                    self.device.write_reg(REG_B_DY_ADDR, triangle.b_dy);
-                    self.device.write_reg(REG_A_MIN_ADDR, triangle.a_min);
-                    self.device.write_reg(REG_A_DX_ADDR, triangle.a_dx);
-                    self.device.write_reg(REG_A_DY_ADDR, triangle.a_dy);
-                    self.device.write_reg(REG_W_INVERSE_MIN_ADDR, triangle.w_inverse_min);
-                    self.device.write_reg(REG_W_INVERSE_DX_ADDR, triangle.w_inverse_dx);
-                    self.device.write_reg(REG_W_INVERSE_DY_ADDR, triangle.w_inverse_dy);
-                    self.device.write_reg(REG_Z_MIN_ADDR, triangle.z_min);
-                    self.device.write_reg(REG_Z_DX_ADDR, triangle.z_dx);
-                    self.device.write_reg(REG_Z_DY_ADDR, triangle.z_dy);
-                    self.device.write_reg(REG_S_MIN_ADDR, triangle.s_min);
-                    self.device.write_reg(REG_S_DX_ADDR, triangle.s_dx);
-                    self.device.write_reg(REG_S_DY_ADDR, triangle.s_dy);
-                    self.device.write_reg(REG_T_MIN_ADDR, triangle.t_min);
-                    self.device.write_reg(REG_T_DX_ADDR, triangle.t_dx);
-                    self.device.write_reg(REG_T_DY_ADDR, triangle.t_dy);
-                    self.estimated_frame_reg_cycles += 33;
-
-                    self.estimated_frame_bin_cycles += mem::size_of::<Triangle>() as u64;
-
-                    // Ensure last primitive is complete
-                    while self.device.read_reg(REG_STATUS_ADDR) != 0 {
-                        self.estimated_frame_rasterization_cycles += 1;
-                    }
-                    // Dispatch next primitive
-                    self.device.write_reg(REG_START_ADDR, 1);
-                }
+            assembled_triangles.clear();
+            self.assembled_triangles[tile_index] = assembled_triangles;
+        }
 
-                // Ensure last primitive is complete
-                while self.device.read_reg(REG_STATUS_ADDR) != 0 {
-                    self.estimated_frame_rasterization_cycles += 1;
-                }
+        self.reserve_commands(TILE_STORE_COMMANDS);
+        self.estimated_frame_xfer_cycles += self.store_tile(last_tile_index);
+        self.submit_command_list();
+    }
 
-                // Copy rasterizer memory back to tile
-                for y in 0..TILE_DIM as usize {
-                    for x in 0..TILE_DIM as usize / 4 {
-                        let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x * 4;
-                        let word = self.device.read_color_buffer_word(y as u32 * TILE_DIM / 4 + x as u32);
-                        for i in 0..4 {
-                            let tile_pixel = (word >> (32 * i)) as u32;
-                            let a = (tile_pixel >> 24) & 0xff;
-                            let r = (tile_pixel >> 16) & 0xff;
-                            let g = (tile_pixel >> 8) & 0xff;
-                            let b = (tile_pixel >> 0) & 0xff;
-                            /*let r = r + 16 * (assembled_triangles.len() as u32 - 1);
-                            let g = g + 16;
-                            let r = if r > 255 { 255 } else { r };
-                            let g = if g > 255 { 255 } else { g };*/
-                            self.back_buffer[buffer_index + i] = (a << 24) | (r << 16) | (g << 8) | b;
-                        }
+    // Brings a tile into the transfer bank, clearing it instead if it hasn't been stored yet this frame. Returns the
+    //  number of words transferred.
+    fn load_tile(&mut self, tile_index: usize) -> u64 {
+        let (color_addr, depth_addr, stencil_addr) = tile_addrs(tile_index);
+        let mut words = 0;
+        if self.color_tiles_valid[tile_index] {
+            self.command_list.tile_load(TileBuffer::Color, color_addr, -(WIDTH as i32 / 4));
+            words += (TILE_PIXELS / 4) as u64;
+        } else {
+            self.command_list.tile_clear(TileBuffer::Color);
+        }
+        if self.depth_test_enable || self.depth_write_mask_enable {
+            if self.depth_tiles_valid[tile_index] {
+                self.command_list.tile_load(TileBuffer::Depth, depth_addr, -(WIDTH as i32 / 8));
+                words += (TILE_PIXELS / 8) as u64;
+            } else {
+                self.command_list.tile_clear(TileBuffer::Depth);
+            }
+        }
+        if self.stencil_test_enable {
+            if self.stencil_tiles_valid[tile_index] {
+                self.command_list.tile_load(TileBuffer::Stencil, stencil_addr, -(WIDTH as i32 / 16));
+                words += (TILE_PIXELS / 16) as u64;
+            } else {
+                self.command_list.tile_clear(TileBuffer::Stencil);
+            }
+        }
+        words
+    }
 
-                        self.estimated_frame_xfer_cycles += 1;
-                    }
-                }
-                if self.depth_write_mask_enable {
-                    for y in 0..TILE_DIM as usize {
-                        for x in 0..TILE_DIM as usize / 8 {
-                            let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x * 8;
-                            let word = self.device.read_depth_buffer_word(y as u32 * TILE_DIM / 8 + x as u32);
-                            for i in 0..8 {
-                                self.depth_buffer[buffer_index + i] = (word >> (16 * i)) as _;
-                            }
+    // Stores a tile back to memory from the transfer bank. Returns the number of words transferred.
+    fn store_tile(&mut self, tile_index: usize) -> u64 {
+        let (color_addr, depth_addr, stencil_addr) = tile_addrs(tile_index);
+        let mut words = 0;
+        self.command_list.tile_store(TileBuffer::Color, color_addr, -(WIDTH as i32 / 4));
+        words += (TILE_PIXELS / 4) as u64;
+        self.color_tiles_valid[tile_index] = true;
+        // Depth is left as-is in memory if it can't have changed
+        if self.depth_write_mask_enable {
+            if self.discard_depth {
+                self.depth_tiles_valid[tile_index] = false;
+            } else {
+                self.command_list.tile_store(TileBuffer::Depth, depth_addr, -(WIDTH as i32 / 8));
+                words += (TILE_PIXELS / 8) as u64;
+                self.depth_tiles_valid[tile_index] = true;
+            }
+        }
+        // Likewise for stencil, which is only ever updated by the stencil test
+        if self.stencil_test_enable {
+            self.command_list.tile_store(TileBuffer::Stencil, stencil_addr, -(WIDTH as i32 / 16));
+            words += (TILE_PIXELS / 16) as u64;
+            self.stencil_tiles_valid[tile_index] = true;
+        }
+        words
+    }
 
-                            self.estimated_frame_xfer_cycles += 1;
-                        }
-                    }
+    // Leaves room for the given number of commands plus the end command, submitting the list so far if needed
+    fn reserve_commands(&mut self, num_commands: usize) {
+        if self.command_list.len() + num_commands + 1 > COMMAND_LIST_MAX_COMMANDS {
+            self.submit_command_list();
+        }
+    }
+
+    // Clears any tiles that haven't been rendered to this frame, then reads back the color buffer for display
+    fn finish(&mut self) {
+        self.wait_idle();
+        self.device.write_reg(REG_TILE_CLEAR_COLOR_ADDR, self.clear_color);
+        self.estimated_frame_reg_cycles += 1;
+        //  Stores don't modify the transfer bank, so a single clear covers every tile
+        let mut cleared = false;
+        for tile_index in 0..TILES {
+            if !self.color_tiles_valid[tile_index] {
+                let (color_addr, _, _) = tile_addrs(tile_index);
+                // Clear and store
+                self.reserve_commands(2);
+                if !cleared {
+                    self.command_list.tile_clear(TileBuffer::Color);
+                    cleared = true;
                 }
+                self.command_list.tile_store(TileBuffer::Color, color_addr, -(WIDTH as i32 / 4));
+                self.estimated_frame_xfer_cycles += (TILE_PIXELS / 4) as u64;
+                self.color_tiles_valid[tile_index] = true;
+            }
+        }
+        if self.command_list.len() > 0 {
+            self.submit_command_list();
+        }
 
-                assembled_triangles.clear();
+        for (i, pixels) in self.back_buffer.chunks_mut(4).enumerate() {
+            let word = self.device.read_tex_buffer_word(COLOR_BUFFER_ADDR + i as u32);
+            for (j, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = (word >> (j * 32)) as _;
             }
         }
     }
 
-    fn assemble_triangle(&mut self, mut verts: [Vertex; 3]) {
-        // TODO: Proper viewport
-        let viewport_x = 0;
-        let viewport_y = 0;
-        let viewport_width = WIDTH;
-        let viewport_height = HEIGHT;
-
-        // TODO: Clipping, culling, ...
-        for vert in verts.iter() {
-            if vert.position.z() < -vert.position.w() || vert.position.z() > vert.position.w() {
+    fn submit_command_list(&mut self) {
+        self.command_list.end();
+        let words = self.command_list.words();
+        self.device.submit_command_list(COMMAND_LIST_ADDR, &words);
+        self.estimated_frame_reg_cycles += words.len() as u64 + 1;
+        self.command_list.clear();
+
+        // Ensure the whole list (including its last primitive) is complete
+        self.wait_idle();
+    }
+
+    // Submits any pending commands and waits for the device to finish everything in flight
+    fn wait_idle(&mut self) {
+        if self.command_list.len() > 0 {
+            self.submit_command_list();
+            return;
+        }
+
+        while self.device.read_reg(REG_STATUS_ADDR) != 0 {
+            self.estimated_frame_rasterization_cycles += 1;
+        }
+    }
+
+    // Inclusive window-space rect that pixels are limited to: the viewport, intersected with the scissor rect if enabled
+    fn clip_rect(&self) -> (i32, i32, i32, i32) {
+        let mut min_x = self.viewport_x.max(0);
+        let mut min_y = self.viewport_y.max(0);
+        let mut max_x = (self.viewport_x + self.viewport_width as i32 - 1).min(WIDTH as i32 - 1);
+        let mut max_y = (self.viewport_y + self.viewport_height as i32 - 1).min(HEIGHT as i32 - 1);
+        if self.scissor_test_enable {
+            min_x = min_x.max(self.scissor_x);
+            min_y = min_y.max(self.scissor_y);
+            max_x = max_x.min(self.scissor_x + self.scissor_width as i32 - 1);
+            max_y = max_y.min(self.scissor_y + self.scissor_height as i32 - 1);
+        }
+        (min_x, min_y, max_x, max_y)
+    }
+
+    // Clips a triangle against the near/far planes and a guard band around the viewport, and assembles what's left as a
+    //  fan of triangles. The guard band is as large as setup's 16-bit fixed-point positions allow, so only triangles
+    //  that reach far outside the viewport are actually cut; anything else outside the viewport is left to the scissor.
+    fn clip_triangle(&mut self, verts: [Vertex; 3]) {
+        // A pixel short of the edge of setup's range, to leave room for float error
+        let guard_band = ((1 << (15 - SETUP_XY_FRACT_BITS)) - 1) as f32;
+        let viewport_scale_x = self.viewport_width as f32 / 2.0;
+        let viewport_scale_y = self.viewport_height as f32 / 2.0;
+        let viewport_bias_x = self.viewport_x as f32 + viewport_scale_x;
+        let viewport_bias_y = self.viewport_y as f32 + viewport_scale_y;
+
+        // Points are inside each plane where the plane's dot product with their clip-space position is non-negative
+        let planes = [
+            Vec4::new(0.0, 0.0, 1.0, 1.0),
+            Vec4::new(0.0, 0.0, -1.0, 1.0),
+            Vec4::new(viewport_scale_x, 0.0, 0.0, guard_band + viewport_bias_x),
+            Vec4::new(-viewport_scale_x, 0.0, 0.0, guard_band - viewport_bias_x),
+            Vec4::new(0.0, viewport_scale_y, 0.0, guard_band + viewport_bias_y),
+            Vec4::new(0.0, -viewport_scale_y, 0.0, guard_band - viewport_bias_y),
+        ];
+
+        if verts.iter().all(|vert| planes.iter().all(|plane| plane.dot(vert.position) >= 0.0)) {
+            self.assemble_triangle(verts);
+            return;
+        }
+
+        //  Attributes are interpolated in clip space, so they stay perspective-correct
+        let mut polygon = verts.to_vec();
+        for plane in planes.iter() {
+            let mut clipped_polygon = Vec::new();
+            for (i, &a) in polygon.iter().enumerate() {
+                let b = polygon[(i + 1) % polygon.len()];
+                let a_dist = plane.dot(a.position);
+                let b_dist = plane.dot(b.position);
+                if a_dist >= 0.0 {
+                    clipped_polygon.push(a);
+                }
+                if (a_dist >= 0.0) != (b_dist >= 0.0) {
+                    let t = a_dist / (a_dist - b_dist);
+                    clipped_polygon.push(Vertex {
+                        position: a.position + (b.position - a.position) * t,
+                        color: a.color + (b.color - a.color) * t,
+                        tex_coord: a.tex_coord + (b.tex_coord - a.tex_coord) * t,
+                    });
+                }
+            }
+            polygon = clipped_polygon;
+            if polygon.len() < 3 {
                 return;
             }
         }
 
+        for i in 1..polygon.len() - 1 {
+            self.assemble_triangle([polygon[0], polygon[i], polygon[i + 1]]);
+        }
+    }
+
+    fn assemble_triangle(&mut self, mut verts: [Vertex; 3]) {
+        let viewport_x = self.viewport_x;
+        let viewport_y = self.viewport_y;
+        let viewport_width = self.viewport_width;
+        let viewport_height = self.viewport_height;
+
         // Viewport transform
         let mut window_verts = [Vec3::zero(); 3];
         for i in 0..3 {
@@ -382,22 +1019,28 @@ impl<D: Device> Context<D> {
             window_verts[i] = ndc * viewport_scale + viewport_bias;
         }
 
-        fn orient2d(a: Vec2, b: Vec2, c: Vec2) -> f32 {
-            (b.x() - a.x()) * (c.y() - a.y()) - (b.y() - a.y()) * (c.x() - a.x())
+        // Snap positions to the setup unit's fixed-point format
+        //  Clipping keeps them within range, apart from float error
+        let mut window_xy = [(0, 0); 3];
+        for i in 0..3 {
+            let x = (window_verts[i].x() * (1 << SETUP_XY_FRACT_BITS) as f32).round() as i32;
+            let y = (window_verts[i].y() * (1 << SETUP_XY_FRACT_BITS) as f32).round() as i32;
+            window_xy[i] = (x.max(i16::MIN as i32).min(i16::MAX as i32), y.max(i16::MIN as i32).min(i16::MAX as i32));
+        }
+
+        fn orient2d(a: (i32, i32), b: (i32, i32), c: (i32, i32)) -> i64 {
+            (b.0 - a.0) as i64 * (c.1 - a.1) as i64 - (b.1 - a.1) as i64 * (c.0 - a.0) as i64
         }
 
-        let /*mut */scaled_area = orient2d(
-            Vec2::new(window_verts[0].x(), window_verts[0].y()),
-            Vec2::new(window_verts[1].x(), window_verts[1].y()),
-            Vec2::new(window_verts[2].x(), window_verts[2].y()));
+        let /*mut */scaled_area = orient2d(window_xy[0], window_xy[1], window_xy[2]);
 
         // Always cull zero-area triangles
-        if scaled_area == 0.0 {
+        if scaled_area == 0 {
             return;
         }
 
         // Flip backfacing triangles (TODO: Proper back/front face culling)
-        if scaled_area < 0.0 {
+        if scaled_area < 0 {
             return;
             /*let temp = verts[0];
             verts[0] = verts[1];
@@ -405,10 +1048,11 @@ impl<D: Device> Context<D> {
             let temp = window_verts[0];
             window_verts[0] = window_verts[1];
             window_verts[1] = temp;
+            window_xy.swap(0, 1);
             scaled_area = -scaled_area;*/
         }
 
-        let texture_dims = Vec2::splat(self.texture_dim.to_u32() as _);
+        let texture_dims = Vec2::new(self.texture_width.to_u32() as _, self.texture_height.to_u32() as _);
         // Offset to sample texel centers
         let st_bias = match self.texture_filter {
             TextureFilter::Nearest => 0.0,
@@ -424,17 +1068,14 @@ impl<D: Device> Context<D> {
             bb_min = bb_min.min(Vec2::new(window_verts[i].x(), window_verts[i].y()));
             bb_max = bb_max.max(Vec2::new(window_verts[i].x(), window_verts[i].y()));
         }
-        bb_min = bb_min.max(Vec2::new(viewport_x as f32, viewport_y as f32));
-        bb_max = bb_max.min(Vec2::new((viewport_x + viewport_width as i32 - 1) as f32, (viewport_y + viewport_height as i32 - 1) as f32));
-        bb_min = bb_min.max(Vec2::zero());
-        bb_max = bb_max.min(Vec2::new((WIDTH - 1) as f32, (HEIGHT - 1) as f32));
+        let (clip_min_x, clip_min_y, clip_max_x, clip_max_y) = self.clip_rect();
+        bb_min = bb_min.max(Vec2::new(clip_min_x as f32, clip_min_y as f32));
+        bb_max = bb_max.min(Vec2::new(clip_max_x as f32, clip_max_y as f32));
         let bb_min_x = bb_min.x().floor() as i32;
         let bb_min_y = bb_min.y().floor() as i32;
         let bb_max_x = bb_max.x().ceil() as i32;
         let bb_max_y = bb_max.y().ceil() as i32;
 
-        let mut triangle = Triangle::default();
-
         fn to_fixed(x: f32, fract_bits: u32) -> i32 {
             let bits = x.to_bits() as i32;
             let exponent = ((bits >> 23) & 0xff) - 127 - 23 + (fract_bits as i32);
@@ -458,62 +1099,27 @@ impl<D: Device> Context<D> {
             result
         }
 
-        let w0_dx = window_verts[1].y() - window_verts[2].y();
-        let w1_dx = window_verts[2].y() - window_verts[0].y();
-        let w2_dx = window_verts[0].y() - window_verts[1].y();
-        let w0_dy = window_verts[2].x() - window_verts[1].x();
-        let w1_dy = window_verts[0].x() - window_verts[2].x();
-        let w2_dy = window_verts[1].x() - window_verts[0].x();
-
-        triangle.w0_dx = to_fixed(w0_dx, EDGE_FRACT_BITS) as _;
-        triangle.w1_dx = to_fixed(w1_dx, EDGE_FRACT_BITS) as _;
-        triangle.w2_dx = to_fixed(w2_dx, EDGE_FRACT_BITS) as _;
-        triangle.w0_dy = to_fixed(w0_dy, EDGE_FRACT_BITS) as _;
-        triangle.w1_dy = to_fixed(w1_dy, EDGE_FRACT_BITS) as _;
-        triangle.w2_dy = to_fixed(w2_dy, EDGE_FRACT_BITS) as _;
-
-        let w0_dx = w0_dx / scaled_area;
-        let w1_dx = w1_dx / scaled_area;
-        let w2_dx = w2_dx / scaled_area;
-        let w0_dy = w0_dy / scaled_area;
-        let w1_dy = w1_dy / scaled_area;
-        let w2_dy = w2_dy / scaled_area;
-
-        let r_dx = verts[0].color.x() * w0_dx + verts[1].color.x() * w1_dx + verts[2].color.x() * w2_dx;
-        let g_dx = verts[0].color.y() * w0_dx + verts[1].color.y() * w1_dx + verts[2].color.y() * w2_dx;
-        let b_dx = verts[0].color.z() * w0_dx + verts[1].color.z() * w1_dx + verts[2].color.z() * w2_dx;
-        let a_dx = verts[0].color.w() * w0_dx + verts[1].color.w() * w1_dx + verts[2].color.w() * w2_dx;
-        let r_dy = verts[0].color.x() * w0_dy + verts[1].color.x() * w1_dy + verts[2].color.x() * w2_dy;
-        let g_dy = verts[0].color.y() * w0_dy + verts[1].color.y() * w1_dy + verts[2].color.y() * w2_dy;
-        let b_dy = verts[0].color.z() * w0_dy + verts[1].color.z() * w1_dy + verts[2].color.z() * w2_dy;
-        let a_dy = verts[0].color.w() * w0_dy + verts[1].color.w() * w1_dy + verts[2].color.w() * w2_dy;
-        triangle.r_dx = to_fixed(r_dx, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.g_dx = to_fixed(g_dx, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.b_dx = to_fixed(b_dx, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.a_dx = to_fixed(a_dx, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.r_dy = to_fixed(r_dy, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.g_dy = to_fixed(g_dy, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.b_dy = to_fixed(b_dy, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-        triangle.a_dy = to_fixed(a_dy, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-
-        let w_inverse_dx = 1.0 / verts[0].position.w() * w0_dx + 1.0 / verts[1].position.w() * w1_dx + 1.0 / verts[2].position.w() * w2_dx;
-        let w_inverse_dy = 1.0 / verts[0].position.w() * w0_dy + 1.0 / verts[1].position.w() * w1_dy + 1.0 / verts[2].position.w() * w2_dy;
-        triangle.w_inverse_dx = to_fixed(w_inverse_dx, W_INVERSE_FRACT_BITS) as _;
-        triangle.w_inverse_dy = to_fixed(w_inverse_dy, W_INVERSE_FRACT_BITS) as _;
-
-        let z_dx = window_verts[0].z() * w0_dx + window_verts[1].z() * w1_dx + window_verts[2].z() * w2_dx;
-        let z_dy = window_verts[0].z() * w0_dy + window_verts[1].z() * w1_dy + window_verts[2].z() * w2_dy;
-        triangle.z_dx = to_fixed(z_dx, Z_FRACT_BITS) as _;
-        triangle.z_dy = to_fixed(z_dy, Z_FRACT_BITS) as _;
-
-        let s_dx = verts[0].tex_coord.x() * w0_dx + verts[1].tex_coord.x() * w1_dx + verts[2].tex_coord.x() * w2_dx;
-        let t_dx = verts[0].tex_coord.y() * w0_dx + verts[1].tex_coord.y() * w1_dx + verts[2].tex_coord.y() * w2_dx;
-        let s_dy = verts[0].tex_coord.x() * w0_dy + verts[1].tex_coord.x() * w1_dy + verts[2].tex_coord.x() * w2_dy;
-        let t_dy = verts[0].tex_coord.y() * w0_dy + verts[1].tex_coord.y() * w1_dy + verts[2].tex_coord.y() * w2_dy;
-        triangle.s_dx = to_fixed(s_dx, ST_FRACT_BITS) as _;
-        triangle.t_dx = to_fixed(t_dx, ST_FRACT_BITS) as _;
-        triangle.s_dy = to_fixed(s_dy, ST_FRACT_BITS) as _;
-        triangle.t_dy = to_fixed(t_dy, ST_FRACT_BITS) as _;
+        // Setup interpolates attributes across the triangle itself, so each vertex just needs its own values
+        let mut triangle = Triangle {
+            vertex_words: [0; 3 * SETUP_VERTEX_WORDS as usize],
+        };
+        for (i, vert) in verts.iter().enumerate() {
+            let words = &mut triangle.vertex_words[i * SETUP_VERTEX_WORDS as usize..(i + 1) * SETUP_VERTEX_WORDS as usize];
+            words[SETUP_VERTEX_WORD_XY as usize] = ((window_xy[i].1 as u32) << 16) | ((window_xy[i].0 as u32) & 0xffff);
+            let attributes = [
+                to_fixed(vert.color.x(), COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1),
+                to_fixed(vert.color.y(), COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1),
+                to_fixed(vert.color.z(), COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1),
+                to_fixed(vert.color.w(), COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1),
+                to_fixed(1.0 / vert.position.w(), W_INVERSE_FRACT_BITS),
+                to_fixed(window_verts[i].z(), Z_FRACT_BITS),
+                to_fixed(vert.tex_coord.x(), ST_FRACT_BITS),
+                to_fixed(vert.tex_coord.y(), ST_FRACT_BITS),
+            ];
+            for (j, &attribute) in attributes.iter().enumerate() {
+                words[SETUP_VERTEX_WORD_ATTRIBUTES as usize + j] = attribute as _;
+            }
+        }
 
         for tile_index_y in 0..HEIGHT / (TILE_DIM as usize) {
             let tile_min_y = (tile_index_y * (TILE_DIM as usize)) as i32;
@@ -531,51 +1137,485 @@ impl<D: Device> Context<D> {
                     continue;
                 }
 
-                let p = Vec2::new(tile_min_x as f32, tile_min_y as f32) + 0.5; // Offset to sample pixel centers
+                let tile_index = tile_index_y * (WIDTH / (TILE_DIM as usize)) + tile_index_x;
+                self.assembled_triangles[tile_index].push(triangle.clone());
 
-                // TODO: Proper top/left fill rule
-                let w0_min = orient2d(Vec2::new(window_verts[1].x(), window_verts[1].y()), Vec2::new(window_verts[2].x(), window_verts[2].y()), p);
-                let w1_min = orient2d(Vec2::new(window_verts[2].x(), window_verts[2].y()), Vec2::new(window_verts[0].x(), window_verts[0].y()), p);
-                let w2_min = orient2d(Vec2::new(window_verts[0].x(), window_verts[0].y()), Vec2::new(window_verts[1].x(), window_verts[1].y()), p);
-                triangle.w0_min = to_fixed(w0_min, EDGE_FRACT_BITS) as _;
-                triangle.w1_min = to_fixed(w1_min, EDGE_FRACT_BITS) as _;
-                triangle.w2_min = to_fixed(w2_min, EDGE_FRACT_BITS) as _;
+                self.estimated_frame_bin_cycles += mem::size_of::<Triangle>() as u64;
+            }
+        }
+    }
+}
 
-                let w0_min = w0_min / scaled_area;
-                let w1_min = w1_min / scaled_area;
-                let w2_min = w2_min / scaled_area;
+// A texture's mip chain, converted to its format and packed into words for upload
+struct Texture {
+    width: TextureDim,
+    height: TextureDim,
+    max_level: u32,
+    format: TextureFormat,
+    palette: Vec<u32>,
+    words: Vec<u128>,
+}
 
-                let r_min = verts[0].color.x() * w0_min + verts[1].color.x() * w1_min + verts[2].color.x() * w2_min;
-                let g_min = verts[0].color.y() * w0_min + verts[1].color.y() * w1_min + verts[2].color.y() * w2_min;
-                let b_min = verts[0].color.z() * w0_min + verts[1].color.z() * w1_min + verts[2].color.z() * w2_min;
-                let a_min = verts[0].color.w() * w0_min + verts[1].color.w() * w1_min + verts[2].color.w() * w2_min;
-                triangle.r_min = to_fixed(r_min, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-                triangle.g_min = to_fixed(g_min, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-                triangle.b_min = to_fixed(b_min, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
-                triangle.a_min = to_fixed(a_min, COLOR_WHOLE_BITS + COLOR_FRACT_BITS - 1) as _;
+fn load_texture(path: &str, texture_format: TextureFormat) -> Texture {
+    let tex = image::open(path).unwrap();
+
+    let mut texture_width = TextureDim::from_u32(tex.width());
+    let mut texture_height = TextureDim::from_u32(tex.height());
+
+    // Generate mip chain
+    //  Each level is box filtered from the previous one, until either dim would drop below the smallest supported dim
+    let mut mip_levels = Vec::new();
+    let mut level = Vec::new();
+    for y in 0..texture_height.to_u32() {
+        for x in 0..texture_width.to_u32() {
+            let texel = tex.get_pixel(x, texture_height.to_u32() - 1 - y);
+            let r = texel[0];
+            let g = texel[1];
+            let b = texel[2];
+            let a = texel[3];
+            level.push(((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | ((b as u32) << 0));
+        }
+    }
+    mip_levels.push(level);
+    let mut level_width = texture_width.to_u32();
+    let mut level_height = texture_height.to_u32();
+    while level_width > 16 && level_height > 16 && mip_levels.len() < 1 << REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS {
+        let prev_level = mip_levels.last().unwrap();
+        let mut level = Vec::new();
+        for y in 0..level_height / 2 {
+            for x in 0..level_width / 2 {
+                let mut argb = 0;
+                for shift in [0, 8, 16, 24].iter() {
+                    let sum = (0..4).map(|i| {
+                        let texel = prev_level[((y * 2 + i / 2) * level_width + x * 2 + i % 2) as usize];
+                        (texel >> shift) & 0xff
+                    }).sum::<u32>();
+                    argb |= ((sum + 2) / 4) << shift;
+                }
+                level.push(argb);
+            }
+        }
+        mip_levels.push(level);
+        level_width /= 2;
+        level_height /= 2;
+    }
+
+    // Levels are dropped from the top of the chain until the rest fits in memory (only argb8888 textures over 32x32
+    //  don't fit as-is)
+    //  VQ's texel bits are per 2x2 block
+    let level_texel_bits = match texture_format {
+        TextureFormat::Vq => texture_format.texel_bits() / 4,
+        _ => texture_format.texel_bits(),
+    };
+    while mip_levels.len() > 1 && mip_levels.iter().map(|level| level.len() as u32).sum::<u32>() * level_texel_bits / 128 > TEXTURE_MAX_WORDS {
+        mip_levels.remove(0);
+        texture_width = TextureDim::from_u32(texture_width.to_u32() / 2);
+        texture_height = TextureDim::from_u32(texture_height.to_u32() / 2);
+    }
+    let texture_max_level = mip_levels.len() as u32 - 1;
+
+    // Convert texture
+    //  Levels are packed consecutively, starting with level 0
+    //  The palette is shared by all levels, so it's generated from all of their texels
+    let (palette, texels) = match texture_format {
+        TextureFormat::Vq => {
+            //  Blocks' texels are ordered by s/t parity to match the codebook layout
+            let mut blocks = Vec::new();
+            for (level_index, level) in mip_levels.iter().enumerate() {
+                let level_width = texture_width.to_u32() >> level_index;
+                let level_height = texture_height.to_u32() >> level_index;
+                for y in 0..level_height / 2 {
+                    for x in 0..level_width / 2 {
+                        let mut block = [0; 4];
+                        for (i, texel) in block.iter_mut().enumerate() {
+                            let texel_x = x * 2 + (i as u32 & 1);
+                            let texel_y = y * 2 + (i as u32 >> 1);
+                            *texel = level[(texel_y * level_width + texel_x) as usize];
+                        }
+                        blocks.push(block);
+                    }
+                }
+            }
+            let (codebook, indices) = vq::encode(&blocks, 1 << (TEX_PALETTE_INDEX_BITS - 2));
+            println!("VQ texture PSNR: {:.*} dB", 2, vq::psnr(&blocks, &codebook, &indices));
+            (codebook.concat(), indices.into_iter().map(|index| index as u32).collect())
+        }
+        _ => {
+            let palette = generate_palette(&mip_levels.concat(), texture_format.palette_entries());
+            //  Interleave texels for different tex memories to allow single-cycle filtered texel reads
+            let mut texels = Vec::new();
+            for (level_index, level) in mip_levels.iter().enumerate() {
+                let level_width = texture_width.to_u32() >> level_index;
+                let level_height = texture_height.to_u32() >> level_index;
+                for block_y in 0..2 {
+                    for block_x in 0..2 {
+                        for y in 0..level_height / 2 {
+                            for x in 0..level_width / 2 {
+                                let texel_x = block_x + x * 2;
+                                let texel_y = block_y + y * 2;
+                                let argb = level[(texel_y * level_width + texel_x) as usize];
+                                texels.push(texture_format.encode(argb, &palette));
+                            }
+                        }
+                    }
+                }
+            }
+            (palette, texels)
+        }
+    };
 
-                let w_inverse_min = 1.0 / verts[0].position.w() * w0_min + 1.0 / verts[1].position.w() * w1_min + 1.0 / verts[2].position.w() * w2_min;
-                triangle.w_inverse_min = to_fixed(w_inverse_min, W_INVERSE_FRACT_BITS) as _;
+    //  Texels are packed into words, starting from the least significant bits
+    let texel_bits = texture_format.texel_bits();
+    assert!(texels.len() as u32 * texel_bits / 128 <= TEXTURE_MAX_WORDS, "Texture doesn't fit in memory");
+    let words = texels.chunks((128 / texel_bits) as usize).map(|chunk| {
+        chunk.iter().enumerate().fold(0, |acc, (i, &texel)| acc | ((texel as u128) << (i as u32 * texel_bits)))
+    }).collect();
+
+    Texture {
+        width: texture_width,
+        height: texture_height,
+        max_level: texture_max_level,
+        format: texture_format,
+        palette,
+        words,
+    }
+}
 
-                let z_min = window_verts[0].z() * w0_min + window_verts[1].z() * w1_min + window_verts[2].z() * w2_min;
-                triangle.z_min = to_fixed(z_min, Z_FRACT_BITS) as _;
+// Uploads the texture's palette and words, as well as the fog table
+fn init_device<D: Device + ?Sized>(device: &mut D, texture: &Texture) {
+    // Upload palette
+    if !texture.palette.is_empty() {
+        device.write_reg(REG_TEXTURE_PALETTE_INDEX_ADDR, 0);
+        for &entry in texture.palette.iter() {
+            device.write_reg(REG_TEXTURE_PALETTE_DATA_ADDR, entry);
+        }
+    }
 
-                let s_min = verts[0].tex_coord.x() * w0_min + verts[1].tex_coord.x() * w1_min + verts[2].tex_coord.x() * w2_min;
-                let t_min = verts[0].tex_coord.y() * w0_min + verts[1].tex_coord.y() * w1_min + verts[2].tex_coord.y() * w2_min;
-                triangle.s_min = to_fixed(s_min, ST_FRACT_BITS) as _;
-                triangle.t_min = to_fixed(t_min, ST_FRACT_BITS) as _;
+    // Upload fog table
+    device.write_reg(REG_FOG_TABLE_INDEX_ADDR, 0);
+    for entry in generate_fog_table() {
+        device.write_reg(REG_FOG_TABLE_DATA_ADDR, entry);
+    }
 
-                let tile_index = tile_index_y * (WIDTH / (TILE_DIM as usize)) + tile_index_x;
-                self.assembled_triangles[tile_index].push(triangle.clone());
+    // Upload texture
+    for (addr, &word) in texture.words.iter().enumerate() {
+        device.write_tex_buffer_word(addr as _, word);
+    }
+}
 
-                self.estimated_frame_bin_cycles += mem::size_of::<Triangle>() as u64;
-            }
+// Appends the 36 vertices of a textured cube spanning -1 to 1 on each axis
+fn cube(v: &mut Vec<Vertex>) {
+    // Front face
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+
+    // Back face
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+
+    // Left face
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+
+    // Right face
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+
+    // Top face
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+
+    // Bottom face
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 0.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(1.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 1.0),
+    });
+    v.push(Vertex {
+        position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
+        color: Vec4::splat(1.0),
+        tex_coord: Vec2::new(0.0, 0.0),
+    });
+}
+
+// Renders the scene as of the given time and returns the back buffer, or None if the bins were dumped instead
+fn render_frame<D: Device>(device: D, texture: &Texture, frame_time: f64, debug_bins: bool) -> Option<Vec<u32>> {
+    let mut c = Context::new(device);
+
+    c.depth_test_enable = true;
+    c.depth_write_mask_enable = true;
+    // There's only one render call per frame, so depth is never needed again
+    c.discard_depth = true;
+
+    c.texture_width = texture.width;
+    c.texture_height = texture.height;
+    c.texture_mip_mode = TextureMipMode::Linear;
+    c.texture_max_level = texture.max_level;
+    c.texture_format = texture.format;
+
+    c.projection = Matrix::perspective(90.0, WIDTH as f32 / HEIGHT as f32, 1.0, 1000.0);
+
+    let mut view = Matrix::translation(/*-1.0*/0.0, 0.0, -3.0/*-4.0*/);
+    let t = (frame_time * 0.1) as f32;
+    view = view * Matrix::rotation_x(t * 1.1);
+    view = view * Matrix::rotation_y(t * 0.47);
+    view = view * Matrix::rotation_z(t * 0.73);
+
+    /*let mut v = Vec::new();
+
+    let mut model = Matrix::identity();
+    model = model * Matrix::translation(-0.5, 0.0, 0.0);
+    let t = (frame_time * 0.1) as f32;
+    model = model * Matrix::rotation_x(t);
+    model = model * Matrix::rotation_y(t * 0.67);
+    model = model * Matrix::rotation_z(t * 0.133);
+    c.model_view = view * model;
+
+    c.texture_filter = TextureFilter::Nearest;
+
+    cube(&mut v);
+
+    c.render(&mut v);*/
+
+    let mut rng: Pcg32 = SeedableRng::seed_from_u64(0xfadebabedeadbeef);
+
+    for _ in 0..1/*50*/ {
+        let mut v = Vec::new();
+
+        let mut model = Matrix::identity();
+        //model = model * Matrix::translation(0.5, 0.0, 0.0);
+        /*let t = (frame_time * 0.2) as f32 + rng.gen::<f32>() * 30.0;
+        model = model * Matrix::rotation_x(t * 1.1);
+        model = model * Matrix::rotation_y(t * 0.47);
+        model = model * Matrix::rotation_z(t * 0.73);
+        model = model * Matrix::translation(0.0, -0.6 + rng.gen::<f32>() * 1.2, -0.6 + rng.gen::<f32>() * 1.2);
+        model = model * Matrix::scale(1.0 + rng.gen::<f32>() * 0.5, 0.1 + rng.gen::<f32>() * 0.2, 0.04);
+        model = model * Matrix::translation(0.5 + rng.gen::<f32>() * 0.5, 0.0, 0.0);*/
+        c.model_view = view * model;
+
+        c.texture_filter = TextureFilter::Bilinear;
+
+        let transparent = rng.gen::<bool>();
+        if transparent {
+            c.depth_write_mask_enable = false;
+            c.blend_src_factor = BlendFactor::One;
+            c.blend_dst_factor = BlendFactor::One;
+        } else {
+            c.depth_write_mask_enable = true;
+            c.blend_src_factor = BlendFactor::One;
+            c.blend_dst_factor = BlendFactor::Zero;
         }
+
+        cube(&mut v);
+
+        if debug_bins {
+            bins::bin(&mut c.device, c.model_view, c.projection, WIDTH as _, HEIGHT as _, &v).dump();
+            return None;
+        }
+
+        c.render(&mut v);
     }
+
+    c.finish();
+
+    let estimated_frame_cycles = c.estimated_frame_bin_cycles + c.estimated_frame_reg_cycles + c.estimated_frame_xfer_cycles + c.estimated_frame_rasterization_cycles;
+    let frame_budget_cycles = 100000000 / 60;
+    println!("Est. frame cycles: {} / {} ({:.*}%)", estimated_frame_cycles, frame_budget_cycles, 2, estimated_frame_cycles as f64 / frame_budget_cycles as f64 * 100.0);
+    println!("  bin r/w:         {} ({:.*}%)", c.estimated_frame_bin_cycles, 2, c.estimated_frame_bin_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
+    println!("  regs:            {} ({:.*}%)", c.estimated_frame_reg_cycles, 2, c.estimated_frame_reg_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
+    println!("  xfer:            {} ({:.*}%)", c.estimated_frame_xfer_cycles, 2, c.estimated_frame_xfer_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
+    println!("  rasterization:   {} ({:.*}%)", c.estimated_frame_rasterization_cycles, 2, c.estimated_frame_rasterization_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
+    println!("  hidden xfer:     {} (overlapped with rasterization)", c.estimated_frame_hidden_xfer_cycles);
+
+    Some(c.back_buffer)
 }
 
 fn main() {
-    let device_type = env::args().skip(1).nth(0).expect("No device type argument provided");
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let device_type = args.first().expect("No device type argument provided");
+
+    let mut debug_bins = false;
+    let mut texture_format = TextureFormat::Rgb565;
+    let mut frames = 3;
+    let mut options = args.iter().skip(1);
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            // Dumps the hardware bins for the first frame's scene instead of rendering
+            "bins" => debug_bins = true,
+            "--texture-format" => texture_format = TextureFormat::from_name(options.next().expect("No texture format provided")),
+            // Number of frames rendered in compare mode
+            "--frames" => frames = options.next().and_then(|frames| frames.parse().ok()).expect("Invalid frame count"),
+            _ => panic!("Invalid argument: {}", option),
+        }
+    }
+
+    let texture = load_texture("myface.png", texture_format);
+
+    // Compare mode renders with both devices and doesn't open a window
+    if device_type == "compare" {
+        compare(&texture, frames);
+        return;
+    }
 
     let mut device: Box<dyn Device> = match device_type.as_str() {
         "model" => Box::new(model_device::ModelDevice::new()),
@@ -583,324 +1623,337 @@ fn main() {
         _ => panic!("Invalid device type argument")
     };
 
+    if debug_bins && device_type != "sim" {
+        panic!("Bin debugging requires the sim device, as the model doesn't implement the transform or binning units");
+    }
+
+    init_device(&mut *device, &texture);
+
     let mut window = Window::new("strugl", WIDTH, HEIGHT, WindowOptions {
         scale: Scale::X4,
         scale_mode: ScaleMode::AspectRatioStretch,
         ..WindowOptions::default()
     }).unwrap();
 
-    let tex = image::open("myface.png").unwrap();
-
-    // TODO: Move to texture object
-    let texture_dim = match tex.width() {
-        16 => TextureDim::X16,
-        32 => TextureDim::X32,
-        64 => TextureDim::X64,
-        128 => TextureDim::X128,
-        _ => panic!("Unsupported texture size")
-    };
-    if tex.width() != tex.height() {
-        panic!("Non-square textures not supported");
-    }
-
-    // Upload texture
-    //  Interleave texels for different tex memories to allow single-cycle filtered texel reads
-    let mut addr = 0;
-    for block_y in 0..2 {
-        for block_x in 0..2 {
-            for chunk_y in 0..texture_dim.to_u32() / 2 {
-                for chunk_x in 0..texture_dim.to_u32() / 2 / 4 {
-                    let mut word = 0;
-                    for x in 0..4 {
-                        let texel_x = block_x + (chunk_x * 4 + x) * 2;
-                        let texel_y = block_y + chunk_y * 2;
-                        let texel = tex.get_pixel(texel_x, texture_dim.to_u32() - 1 - texel_y);
-                        let r = texel[0];
-                        let g = texel[1];
-                        let b = texel[2];
-                        let a = texel[3];
-                        let argb = ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | ((b as u32) << 0);
-                        word |= (argb as u128) << (x * 32);
-                    }
-                    device.write_tex_buffer_word(addr, word);
-                    addr += 1;
-                }
-            }
-        }
-    }
-
     let start_time = Instant::now();
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        fn cube(v: &mut Vec<Vertex>) {
-            // Front face
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-
-            // Back face
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-
-            // Left face
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-
-            // Right face
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-
-            // Top face
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, 1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-
-            // Bottom face
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 0.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(1.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, 1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 1.0),
-            });
-            v.push(Vertex {
-                position: Vec4::new(-1.0, -1.0, -1.0, 1.0),
-                color: Vec4::splat(1.0),
-                tex_coord: Vec2::new(0.0, 0.0),
-            });
-        }
-
         let frame_time = start_time.elapsed().as_secs_f64();
+        let back_buffer = match render_frame(&mut *device, &texture, frame_time, debug_bins) {
+            Some(back_buffer) => back_buffer,
+            None => return,
+        };
 
-        let mut c = Context::new(&mut *device);
-
-        c.depth_test_enable = true;
-        c.depth_write_mask_enable = true;
+        window.update_with_buffer(&back_buffer, WIDTH, HEIGHT).unwrap();
+    }
+}
 
-        c.texture_dim = texture_dim;
+// Renders frames at fixed times with both the model and sim devices, and exits with an error if any pixels differ
+//  Each frame renders the regular scene followed by each of the feature scenarios
+fn compare(texture: &Texture, frames: u32) {
+    let mut model = model_device::ModelDevice::new();
+    let mut sim = sim_device::SimDevice::new();
+    init_device(&mut model, texture);
+    init_device(&mut sim, texture);
+
+    let mut renders = 0;
+    let mut mismatched_renders = 0;
+    for frame in 0..frames {
+        // Frames are far enough apart that the scene looks quite different in each one
+        let frame_time = frame as f64 * 13.7;
+        let mut back_buffers = vec![(
+            "scene",
+            render_frame(&mut model, texture, frame_time, false).unwrap(),
+            render_frame(&mut sim, texture, frame_time, false).unwrap())];
+        for &scenario in Scenario::ALL.iter() {
+            back_buffers.push((
+                scenario.name(),
+                render_scenario(&mut model, texture, scenario, frame_time),
+                render_scenario(&mut sim, texture, scenario, frame_time)));
+        }
 
-        c.projection = Matrix::perspective(90.0, WIDTH as f32 / HEIGHT as f32, 1.0, 1000.0);
+        for (name, model_back_buffer, sim_back_buffer) in back_buffers {
+            let mismatches = model_back_buffer.iter().zip(sim_back_buffer.iter()).filter(|(model_pixel, sim_pixel)| model_pixel != sim_pixel).count();
+            println!("Frame {} ({}): {} / {} pixels differ", frame, name, mismatches, PIXELS);
+            renders += 1;
+            if mismatches > 0 {
+                mismatched_renders += 1;
+            }
+        }
+    }
 
-        let mut view = Matrix::translation(/*-1.0*/0.0, 0.0, -3.0/*-4.0*/);
-        let t = (frame_time * 0.1) as f32;
-        view = view * Matrix::rotation_x(t * 1.1);
-        view = view * Matrix::rotation_y(t * 0.47);
-        view = view * Matrix::rotation_z(t * 0.73);
+    if mismatched_renders > 0 {
+        eprintln!("{} / {} renders differ between the model and sim devices", mismatched_renders, renders);
+        process::exit(1);
+    }
+}
 
-        /*let mut v = Vec::new();
+// Feature scenarios for compare mode, covering the render state that the regular scene leaves at its defaults
+#[derive(Clone, Copy)]
+enum Scenario {
+    AlphaTest,
+    Stencil,
+    Fog,
+    Wrap,
+    Combine,
+    Blend,
+    Clip,
+}
 
-        let mut model = Matrix::identity();
-        model = model * Matrix::translation(-0.5, 0.0, 0.0);
-        let t = (frame_time * 0.1) as f32;
-        model = model * Matrix::rotation_x(t);
-        model = model * Matrix::rotation_y(t * 0.67);
-        model = model * Matrix::rotation_z(t * 0.133);
-        c.model_view = view * model;
+impl Scenario {
+    const ALL: [Scenario; 7] = [
+        Scenario::AlphaTest,
+        Scenario::Stencil,
+        Scenario::Fog,
+        Scenario::Wrap,
+        Scenario::Combine,
+        Scenario::Blend,
+        Scenario::Clip,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scenario::AlphaTest => "alpha test",
+            Scenario::Stencil => "stencil",
+            Scenario::Fog => "fog",
+            Scenario::Wrap => "texture wrap",
+            Scenario::Combine => "texture combine",
+            Scenario::Blend => "blend",
+            Scenario::Clip => "clipping",
+        }
+    }
 
-        c.texture_filter = TextureFilter::Nearest;
+    // Each cell of the scenario's grid gets its own settings
+    fn cells(&self) -> usize {
+        match self {
+            // One per compare func
+            Scenario::AlphaTest | Scenario::Stencil => 8,
+            // Each mode with each coord
+            Scenario::Fog => 6,
+            // Each s wrap mode with each t wrap mode
+            Scenario::Wrap => 16,
+            Scenario::Combine => 5,
+            // One per src/dst factor pair, then one per op
+            Scenario::Blend => 14 + 5,
+            Scenario::Clip => 1,
+        }
+    }
+}
 
+// Renders a grid of small spinning cubes, each with its own settings for the scenario's features, and returns the back
+//  buffer
+fn render_scenario<D: Device>(device: D, texture: &Texture, scenario: Scenario, frame_time: f64) -> Vec<u32> {
+    const COMPARE_FUNCS: [CompareFunc; 8] = [
+        CompareFunc::Never,
+        CompareFunc::Less,
+        CompareFunc::Equal,
+        CompareFunc::LessEqual,
+        CompareFunc::Greater,
+        CompareFunc::NotEqual,
+        CompareFunc::GreaterEqual,
+        CompareFunc::Always,
+    ];
+    const STENCIL_OPS: [StencilOp; 8] = [
+        StencilOp::Keep,
+        StencilOp::Zero,
+        StencilOp::Replace,
+        StencilOp::Incr,
+        StencilOp::Decr,
+        StencilOp::Invert,
+        StencilOp::IncrWrap,
+        StencilOp::DecrWrap,
+    ];
+    const FOG_MODES: [FogMode; 3] = [FogMode::Linear, FogMode::Exp, FogMode::Exp2];
+    const TEXTURE_WRAPS: [TextureWrap; 4] = [
+        TextureWrap::Repeat,
+        TextureWrap::MirroredRepeat,
+        TextureWrap::ClampToEdge,
+        TextureWrap::ClampToBorder,
+    ];
+    const COMBINE_MODES: [TextureCombineMode; 5] = [
+        TextureCombineMode::Modulate,
+        TextureCombineMode::Replace,
+        TextureCombineMode::Decal,
+        TextureCombineMode::Add,
+        TextureCombineMode::Disabled,
+    ];
+    const BLEND_FACTORS: [BlendFactor; 14] = [
+        BlendFactor::Zero,
+        BlendFactor::One,
+        BlendFactor::SrcAlpha,
+        BlendFactor::OneMinusSrcAlpha,
+        BlendFactor::SrcColor,
+        BlendFactor::OneMinusSrcColor,
+        BlendFactor::DstColor,
+        BlendFactor::OneMinusDstColor,
+        BlendFactor::DstAlpha,
+        BlendFactor::OneMinusDstAlpha,
+        BlendFactor::ConstantColor,
+        BlendFactor::OneMinusConstantColor,
+        BlendFactor::ConstantAlpha,
+        BlendFactor::OneMinusConstantAlpha,
+    ];
+    const BLEND_OPS: [BlendOp; 5] = [BlendOp::Add, BlendOp::Subtract, BlendOp::ReverseSubtract, BlendOp::Min, BlendOp::Max];
+
+    let mut c = Context::new(device);
+
+    // Non-zero clear values, so blending and stencil ops have something to work with
+    c.clear_color = 0x80406080;
+    c.clear_stencil = 0x81;
+
+    c.depth_test_enable = true;
+    c.depth_write_mask_enable = true;
+    c.discard_depth = true;
+
+    c.texture_filter = TextureFilter::Bilinear;
+    c.texture_width = texture.width;
+    c.texture_height = texture.height;
+    c.texture_mip_mode = TextureMipMode::Linear;
+    c.texture_max_level = texture.max_level;
+    c.texture_format = texture.format;
+
+    c.projection = Matrix::perspective(90.0, WIDTH as f32 / HEIGHT as f32, 1.0, 1000.0);
+    let view = Matrix::translation(0.0, 0.0, -3.0);
+
+    //  The view covers -3 to 3 on both axes at the cubes' depth
+    let cells = scenario.cells();
+    let grid_dim = (1..).find(|&dim| dim * dim >= cells).unwrap();
+    let cell_size = 6.0 / grid_dim as f32;
+    let cell_model = |cell: usize| {
+        let x = -3.0 + ((cell % grid_dim) as f32 + 0.5) * cell_size;
+        let y = 3.0 - ((cell / grid_dim) as f32 + 0.5) * cell_size;
+        Matrix::translation(x, y, 0.0)
+    };
+    let cell_cube = |cell: usize| {
+        let t = (frame_time * 0.1) as f32 + cell as f32;
+        let mut model = cell_model(cell);
+        model = model * Matrix::rotation_x(t * 1.1);
+        model = model * Matrix::rotation_y(t * 0.47);
+        model = model * Matrix::rotation_z(t * 0.73);
+        model = model * Matrix::scale(cell_size * 0.3, cell_size * 0.3, cell_size * 0.3);
+
+        // Colors (including alpha) vary over the faces, so the alpha test and blending see a range of values
+        let mut v = Vec::new();
         cube(&mut v);
+        for vert in v.iter_mut() {
+            let p = vert.position;
+            vert.color = Vec4::new(p.x() * 0.5 + 0.5, p.y() * 0.5 + 0.5, p.z() * 0.5 + 0.5, (p.x() + p.y()) * 0.25 + 0.5);
+        }
+        (view * model, v)
+    };
 
-        c.render(&mut v);*/
-
-        let mut rng: Pcg32 = SeedableRng::seed_from_u64(0xfadebabedeadbeef);
-
-        for _ in 0..1/*50*/ {
-            let mut v = Vec::new();
-
-            let mut model = Matrix::identity();
-            //model = model * Matrix::translation(0.5, 0.0, 0.0);
-            /*let t = (frame_time * 0.2) as f32 + rng.gen::<f32>() * 30.0;
-            model = model * Matrix::rotation_x(t * 1.1);
-            model = model * Matrix::rotation_y(t * 0.47);
-            model = model * Matrix::rotation_z(t * 0.73);
-            model = model * Matrix::translation(0.0, -0.6 + rng.gen::<f32>() * 1.2, -0.6 + rng.gen::<f32>() * 1.2);
-            model = model * Matrix::scale(1.0 + rng.gen::<f32>() * 0.5, 0.1 + rng.gen::<f32>() * 0.2, 0.04);
-            model = model * Matrix::translation(0.5 + rng.gen::<f32>() * 0.5, 0.0, 0.0);*/
-            c.model_view = view * model;
-
-            c.texture_filter = TextureFilter::Bilinear;
+    for cell in 0..cells {
+        let (model_view, mut v) = cell_cube(cell);
+        c.model_view = model_view;
 
-            let transparent = rng.gen::<bool>();
-            if transparent {
-                c.depth_write_mask_enable = false;
-                c.blend_src_factor = BlendSrcFactor::One;
-                c.blend_dst_factor = BlendDstFactor::One;
-            } else {
-                c.depth_write_mask_enable = true;
-                c.blend_src_factor = BlendSrcFactor::One;
-                c.blend_dst_factor = BlendDstFactor::Zero;
+        match scenario {
+            Scenario::AlphaTest => {
+                c.alpha_test_enable = true;
+                c.alpha_test_func = COMPARE_FUNCS[cell].clone();
+                c.alpha_test_ref = 0x80;
+            }
+            Scenario::Stencil => {
+                c.stencil_test_enable = true;
+                c.stencil_func = COMPARE_FUNCS[cell].clone();
+                c.stencil_ref = 0x5a;
+                c.stencil_mask = if cell % 2 == 0 { 0xff } else { 0x0f };
+                c.stencil_sfail_op = STENCIL_OPS[cell].clone();
+                c.stencil_dpfail_op = STENCIL_OPS[(cell + 3) % 8].clone();
+                c.stencil_dppass_op = STENCIL_OPS[(cell + 5) % 8].clone();
+
+                // Drawing the same cube again fails the depth test wherever it passed the first time
+                c.depth_func = CompareFunc::Less;
+                c.render(&mut v.clone());
+            }
+            Scenario::Fog => {
+                c.fog_enable = true;
+                c.fog_mode = FOG_MODES[cell % 3].clone();
+                c.fog_color = 0xff8040c0;
+                if cell < 3 {
+                    c.fog_coord = FogCoord::W;
+                    c.fog_start = 2.0;
+                    c.fog_end = 4.0;
+                    c.fog_density = 0.4;
+                } else {
+                    c.fog_coord = FogCoord::Z;
+                    c.fog_start = 0.6;
+                    c.fog_end = 0.75;
+                    c.fog_density = 4.0;
+                }
+            }
+            Scenario::Wrap => {
+                c.texture_wrap_s = TEXTURE_WRAPS[cell % 4].clone();
+                c.texture_wrap_t = TEXTURE_WRAPS[cell / 4].clone();
+                c.texture_border_color = 0xff20c040;
+                for vert in v.iter_mut() {
+                    vert.tex_coord = vert.tex_coord * 3.0 - 1.0;
+                }
+            }
+            Scenario::Combine => {
+                c.texture_combine_mode = COMBINE_MODES[cell].clone();
+            }
+            Scenario::Blend => {
+                if cell < 14 {
+                    c.blend_src_factor = BLEND_FACTORS[cell].clone();
+                    c.blend_dst_factor = BLEND_FACTORS[13 - cell].clone();
+                    c.blend_op = BlendOp::Add;
+                } else {
+                    c.blend_src_factor = BlendFactor::SrcColor;
+                    c.blend_dst_factor = BlendFactor::OneMinusSrcAlpha;
+                    c.blend_op = BLEND_OPS[cell - 14].clone();
+                }
+                c.blend_constant = 0x60a0c040;
             }
+            Scenario::Clip => {
+                // A floor that reaches through the near plane and far past the guard band on every side
+                let t = (frame_time * 0.1) as f32;
+                c.model_view = view * Matrix::translation(0.0, -1.0, 0.0) * Matrix::rotation_y(t);
+                c.texture_filter = TextureFilter::Nearest;
+                let corner = |x: f32, z: f32| Vertex {
+                    position: Vec4::new(x, 0.0, z, 1.0),
+                    color: Vec4::splat(1.0),
+                    tex_coord: Vec2::new(x, z) / 100.0,
+                };
+                v = vec![
+                    corner(-1000.0, 1000.0), corner(1000.0, 1000.0), corner(1000.0, -1000.0),
+                    corner(1000.0, -1000.0), corner(-1000.0, -1000.0), corner(-1000.0, 1000.0),
+                ];
+            }
+        }
 
-            cube(&mut v);
+        c.render(&mut v);
+    }
 
+    // Stencil values only show up once something is drawn against them, so the second pass covers each cell with a
+    //  quad that's tested against a different ref/func, with stencil (and depth) left untouched
+    //  This is a separate render call from the cubes, so the stencil plane has to have been stored and reloaded.
+    if let Scenario::Stencil = scenario {
+        c.depth_test_enable = false;
+        c.depth_write_mask_enable = false;
+        c.texture_combine_mode = TextureCombineMode::Disabled;
+        c.stencil_mask = 0xff;
+        c.stencil_sfail_op = StencilOp::Keep;
+        c.stencil_dpfail_op = StencilOp::Keep;
+        c.stencil_dppass_op = StencilOp::Keep;
+        for cell in 0..cells {
+            c.model_view = view * cell_model(cell) * Matrix::scale(cell_size * 0.45, cell_size * 0.45, 1.0);
+            c.stencil_func = COMPARE_FUNCS[(cell + 2) % 8].clone();
+            c.stencil_ref = 0x40 + cell as u8 * 0x10;
+
+            let corner = |x: f32, y: f32| Vertex {
+                position: Vec4::new(x, y, 0.0, 1.0),
+                color: Vec4::new(x * 0.5 + 0.5, y * 0.5 + 0.5, 1.0, 1.0),
+                tex_coord: Vec2::new(x * 0.5 + 0.5, y * 0.5 + 0.5),
+            };
+            let mut v = vec![
+                corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0),
+                corner(1.0, 1.0), corner(-1.0, 1.0), corner(-1.0, -1.0),
+            ];
             c.render(&mut v);
         }
+    }
 
-        let estimated_frame_cycles = c.estimated_frame_bin_cycles + c.estimated_frame_reg_cycles + c.estimated_frame_xfer_cycles + c.estimated_frame_rasterization_cycles;
-        let frame_budget_cycles = 100000000 / 60;
-        println!("Est. frame cycles: {} / {} ({:.*}%)", estimated_frame_cycles, frame_budget_cycles, 2, estimated_frame_cycles as f64 / frame_budget_cycles as f64 * 100.0);
-        println!("  bin r/w:         {} ({:.*}%)", c.estimated_frame_bin_cycles, 2, c.estimated_frame_bin_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
-        println!("  regs:            {} ({:.*}%)", c.estimated_frame_reg_cycles, 2, c.estimated_frame_reg_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
-        println!("  xfer:            {} ({:.*}%)", c.estimated_frame_xfer_cycles, 2, c.estimated_frame_xfer_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
-        println!("  rasterization:   {} ({:.*}%)", c.estimated_frame_rasterization_cycles, 2, c.estimated_frame_rasterization_cycles as f64 / estimated_frame_cycles as f64 * 100.0);
+    c.finish();
 
-        window.update_with_buffer(&c.back_buffer, WIDTH, HEIGHT).unwrap();
-    }
+    c.back_buffer
 }
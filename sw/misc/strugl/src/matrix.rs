@@ -28,6 +28,10 @@ impl Matrix {
         ret
     }
 
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        self.values[col * NUM_ROWS + row]
+    }
+
     pub fn identity() -> Matrix {
         Matrix {
             values: [
@@ -2,38 +2,243 @@ use crate::device::*;
 
 use rtl::color_thrust::*;
 
+use std::mem;
+
+enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunc {
+    fn test<T: PartialOrd>(&self, a: T, b: T) -> bool {
+        match self {
+            CompareFunc::Never => false,
+            CompareFunc::Less => a < b,
+            CompareFunc::Equal => a == b,
+            CompareFunc::LessEqual => a <= b,
+            CompareFunc::Greater => a > b,
+            CompareFunc::NotEqual => a != b,
+            CompareFunc::GreaterEqual => a >= b,
+            CompareFunc::Always => true,
+        }
+    }
+}
+
+enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Incr,
+    Decr,
+    Invert,
+    IncrWrap,
+    DecrWrap,
+}
+
+impl StencilOp {
+    fn from_reg(reg: u32) -> StencilOp {
+        match reg & ((1 << REG_STENCIL_SETTINGS_OP_BITS) - 1) {
+            REG_STENCIL_SETTINGS_OP_KEEP => StencilOp::Keep,
+            REG_STENCIL_SETTINGS_OP_ZERO => StencilOp::Zero,
+            REG_STENCIL_SETTINGS_OP_REPLACE => StencilOp::Replace,
+            REG_STENCIL_SETTINGS_OP_INCR => StencilOp::Incr,
+            REG_STENCIL_SETTINGS_OP_DECR => StencilOp::Decr,
+            REG_STENCIL_SETTINGS_OP_INVERT => StencilOp::Invert,
+            REG_STENCIL_SETTINGS_OP_INCR_WRAP => StencilOp::IncrWrap,
+            REG_STENCIL_SETTINGS_OP_DECR_WRAP => StencilOp::DecrWrap,
+            _ => unreachable!(),
+        }
+    }
+
+    fn to_reg(&self) -> u32 {
+        match self {
+            StencilOp::Keep => REG_STENCIL_SETTINGS_OP_KEEP,
+            StencilOp::Zero => REG_STENCIL_SETTINGS_OP_ZERO,
+            StencilOp::Replace => REG_STENCIL_SETTINGS_OP_REPLACE,
+            StencilOp::Incr => REG_STENCIL_SETTINGS_OP_INCR,
+            StencilOp::Decr => REG_STENCIL_SETTINGS_OP_DECR,
+            StencilOp::Invert => REG_STENCIL_SETTINGS_OP_INVERT,
+            StencilOp::IncrWrap => REG_STENCIL_SETTINGS_OP_INCR_WRAP,
+            StencilOp::DecrWrap => REG_STENCIL_SETTINGS_OP_DECR_WRAP,
+        }
+    }
+
+    fn apply(&self, stencil: u8, stencil_ref: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stencil,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => stencil_ref,
+            StencilOp::Incr => stencil.saturating_add(1),
+            StencilOp::Decr => stencil.saturating_sub(1),
+            StencilOp::Invert => !stencil,
+            StencilOp::IncrWrap => stencil.wrapping_add(1),
+            StencilOp::DecrWrap => stencil.wrapping_sub(1),
+        }
+    }
+}
+
+enum FogMode {
+    Linear,
+    Exp,
+    Exp2,
+}
+
+enum FogCoord {
+    Z,
+    W,
+}
+
 enum TextureFilter {
     Nearest,
     Bilinear,
 }
 
-enum BlendSrcFactor {
-    Zero,
-    One,
-    SrcAlpha,
-    OneMinusSrcAlpha,
+enum TextureDim {
+    X16,
+    X32,
+    X64,
+    X128,
+}
+
+impl TextureDim {
+    fn to_bits(&self) -> u32 {
+        match self {
+            TextureDim::X16 => 4,
+            TextureDim::X32 => 5,
+            TextureDim::X64 => 6,
+            TextureDim::X128 => 7,
+        }
+    }
 }
 
-enum BlendDstFactor {
+enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+enum TextureMipMode {
+    None,
+    Nearest,
+    Linear,
+}
+
+enum TextureFormat {
+    Argb8888,
+    Rgb565,
+    Argb4444,
+    Argb1555,
+    Indexed8,
+    Indexed4,
+    Vq,
+}
+
+impl TextureFormat {
+    fn texels_per_pixel_bits(&self) -> u32 {
+        match self {
+            TextureFormat::Argb8888 => 0,
+            TextureFormat::Rgb565 | TextureFormat::Argb4444 | TextureFormat::Argb1555 => 1,
+            TextureFormat::Indexed8 | TextureFormat::Vq => 2,
+            TextureFormat::Indexed4 => 3,
+        }
+    }
+}
+
+enum TextureCombineMode {
+    Modulate,
+    Replace,
+    Decal,
+    Add,
+    Disabled,
+}
+
+enum BlendFactor {
     Zero,
     One,
     SrcAlpha,
     OneMinusSrcAlpha,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    DstAlpha,
+    OneMinusDstAlpha,
+    ConstantColor,
+    OneMinusConstantColor,
+    ConstantAlpha,
+    OneMinusConstantAlpha,
+}
+
+enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
 }
 
 pub struct ModelDevice {
     color_buffer: [u32; TILE_PIXELS as usize],
     depth_buffer: [u16; TILE_PIXELS as usize],
+    stencil_buffer: [u8; TILE_PIXELS as usize],
+    // The transfer bank; the fields above are the render bank
+    transfer_color_buffer: [u32; TILE_PIXELS as usize],
+    transfer_depth_buffer: [u16; TILE_PIXELS as usize],
+    transfer_stencil_buffer: [u8; TILE_PIXELS as usize],
 
     tex_buffer: [u128; (1 << TEX_WORD_ADDR_BITS) as usize],
 
     depth_test_enable: bool,
     depth_write_mask_enable: bool,
+    depth_func: CompareFunc,
+
+    alpha_test_enable: bool,
+    alpha_test_func: CompareFunc,
+    alpha_test_ref: u32,
+
+    stencil_test_enable: bool,
+    stencil_func: CompareFunc,
+    stencil_ref: u8,
+    stencil_mask: u8,
+    stencil_sfail_op: StencilOp,
+    stencil_dpfail_op: StencilOp,
+    stencil_dppass_op: StencilOp,
+
+    fog_enable: bool,
+    fog_mode: FogMode,
+    fog_coord: FogCoord,
+    fog_color: u32,
+    fog_start: u32,
+    fog_scale: u32,
+    fog_table: [u32; 1 << FOG_TABLE_INDEX_BITS],
+    fog_table_index: u32,
 
     texture_filter: TextureFilter,
+    texture_width: TextureDim,
+    texture_height: TextureDim,
+    texture_wrap_s: TextureWrap,
+    texture_wrap_t: TextureWrap,
+    texture_base: u32,
+    texture_border_color: u32,
+    texture_mip_mode: TextureMipMode,
+    texture_max_level: u32,
+    texture_lod_bias: u32,
+    texture_format: TextureFormat,
+    texture_palette: [u32; 1 << TEX_PALETTE_INDEX_BITS],
+    texture_palette_index: u32,
+    texture_combine_mode: TextureCombineMode,
 
-    blend_src_factor: BlendSrcFactor,
-    blend_dst_factor: BlendDstFactor,
+    blend_src_factor: BlendFactor,
+    blend_dst_factor: BlendFactor,
+    blend_op: BlendOp,
+    blend_constant: u32,
 
     w0_min: u32,
     w0_dx: u32,
@@ -68,6 +273,16 @@ pub struct ModelDevice {
     t_min: u32,
     t_dx: u32,
     t_dy: u32,
+
+    scissor: u32,
+
+    tile_clear_color: u32,
+    tile_clear_depth: u16,
+    tile_clear_stencil: u8,
+
+    setup_vertices: [[u32; SETUP_VERTEX_WORDS as usize]; 3],
+    setup_vertex_select: u32,
+    setup_vertex_word: u32,
 }
 
 impl ModelDevice {
@@ -75,16 +290,57 @@ impl ModelDevice {
         ModelDevice {
             color_buffer: [0; TILE_PIXELS as usize],
             depth_buffer: [0; TILE_PIXELS as usize],
+            stencil_buffer: [0; TILE_PIXELS as usize],
+            transfer_color_buffer: [0; TILE_PIXELS as usize],
+            transfer_depth_buffer: [0; TILE_PIXELS as usize],
+            transfer_stencil_buffer: [0; TILE_PIXELS as usize],
 
             tex_buffer: [0; (1 << TEX_WORD_ADDR_BITS) as usize],
 
             depth_test_enable: false,
             depth_write_mask_enable: false,
+            depth_func: CompareFunc::Less,
+
+            alpha_test_enable: false,
+            alpha_test_func: CompareFunc::Always,
+            alpha_test_ref: 0,
+
+            stencil_test_enable: false,
+            stencil_func: CompareFunc::Always,
+            stencil_ref: 0,
+            stencil_mask: 0xff,
+            stencil_sfail_op: StencilOp::Keep,
+            stencil_dpfail_op: StencilOp::Keep,
+            stencil_dppass_op: StencilOp::Keep,
+
+            fog_enable: false,
+            fog_mode: FogMode::Linear,
+            fog_coord: FogCoord::Z,
+            fog_color: 0,
+            fog_start: 0,
+            fog_scale: 0,
+            fog_table: [0; 1 << FOG_TABLE_INDEX_BITS],
+            fog_table_index: 0,
 
             texture_filter: TextureFilter::Nearest,
+            texture_width: TextureDim::X16,
+            texture_height: TextureDim::X16,
+            texture_wrap_s: TextureWrap::Repeat,
+            texture_wrap_t: TextureWrap::Repeat,
+            texture_base: 0,
+            texture_border_color: 0,
+            texture_mip_mode: TextureMipMode::None,
+            texture_max_level: 0,
+            texture_lod_bias: 0,
+            texture_format: TextureFormat::Argb8888,
+            texture_palette: [0; 1 << TEX_PALETTE_INDEX_BITS],
+            texture_palette_index: 0,
+            texture_combine_mode: TextureCombineMode::Modulate,
 
-            blend_src_factor: BlendSrcFactor::One,
-            blend_dst_factor: BlendDstFactor::Zero,
+            blend_src_factor: BlendFactor::One,
+            blend_dst_factor: BlendFactor::Zero,
+            blend_op: BlendOp::Add,
+            blend_constant: 0,
 
             w0_min: 0,
             w0_dx: 0,
@@ -119,6 +375,189 @@ impl ModelDevice {
             t_min: 0,
             t_dx: 0,
             t_dy: 0,
+
+            scissor: ((TILE_DIM - 1) << REG_SCISSOR_MAX_X_BIT_OFFSET) | ((TILE_DIM - 1) << REG_SCISSOR_MAX_Y_BIT_OFFSET),
+
+            tile_clear_color: 0,
+            tile_clear_depth: 0,
+            tile_clear_stencil: 0,
+
+            setup_vertices: [[0; SETUP_VERTEX_WORDS as usize]; 3],
+            setup_vertex_select: 0,
+            setup_vertex_word: 0,
+        }
+    }
+
+    // Bus accesses use the render bank, and tile transfers use the transfer bank
+    fn write_tile_buffer_word(&mut self, transfer: bool, tile_buffer: u32, addr: u32, data: u128) {
+        let (color_buffer, depth_buffer, stencil_buffer) = if transfer {
+            (&mut self.transfer_color_buffer, &mut self.transfer_depth_buffer, &mut self.transfer_stencil_buffer)
+        } else {
+            (&mut self.color_buffer, &mut self.depth_buffer, &mut self.stencil_buffer)
+        };
+        match tile_buffer {
+            COMMAND_TILE_BUFFER_COLOR => {
+                for i in 0..4 {
+                    color_buffer[(addr * 4 + i) as usize] = (data >> (i * 32)) as _;
+                }
+            }
+            COMMAND_TILE_BUFFER_DEPTH => {
+                for i in 0..8 {
+                    depth_buffer[(addr * 8 + i) as usize] = (data >> (i * 16)) as _;
+                }
+            }
+            _ => {
+                for i in 0..16 {
+                    stencil_buffer[(addr * 16 + i) as usize] = (data >> (i * 8)) as _;
+                }
+            }
+        }
+    }
+
+    fn read_tile_buffer_word(&self, transfer: bool, tile_buffer: u32, addr: u32) -> u128 {
+        let (color_buffer, depth_buffer, stencil_buffer) = if transfer {
+            (&self.transfer_color_buffer, &self.transfer_depth_buffer, &self.transfer_stencil_buffer)
+        } else {
+            (&self.color_buffer, &self.depth_buffer, &self.stencil_buffer)
+        };
+        let mut ret = 0;
+        match tile_buffer {
+            COMMAND_TILE_BUFFER_COLOR => {
+                for i in 0..4 {
+                    ret |= (color_buffer[(addr * 4 + i) as usize] as u128) << (i * 32);
+                }
+            }
+            COMMAND_TILE_BUFFER_DEPTH => {
+                for i in 0..8 {
+                    ret |= (depth_buffer[(addr * 8 + i) as usize] as u128) << (i * 16);
+                }
+            }
+            _ => {
+                for i in 0..16 {
+                    ret |= (stencil_buffer[(addr * 16 + i) as usize] as u128) << (i * 8);
+                }
+            }
+        }
+        ret
+    }
+
+    fn process_command_list(&mut self, mut addr: u32) {
+        let addr_mask = (1 << TEX_WORD_ADDR_BITS) - 1;
+        loop {
+            let word = self.tex_buffer[addr as usize];
+            addr = (addr + 1) & addr_mask;
+            for half in 0..2 {
+                let command = (word >> (half * COMMAND_BITS)) as u64;
+                let opcode = (command as u32) & ((1 << COMMAND_OPCODE_BITS) - 1);
+                let arg = (command >> COMMAND_ARG_BIT_OFFSET) as u32;
+                let data = (command >> COMMAND_DATA_BIT_OFFSET) as u32;
+                let word_addr = data & addr_mask;
+                match opcode {
+                    COMMAND_OPCODE_END => return,
+                    COMMAND_OPCODE_WRITE_REG => {
+                        // Writes to the command list base reg only start the command processor when they come from the reg bus
+                        let reg_addr = arg & ((1 << REG_BUS_ADDR_BIT_WIDTH) - 1);
+                        if reg_addr != REG_COMMAND_LIST_BASE_ADDR {
+                            self.write_reg(reg_addr, data);
+                        }
+                    }
+                    COMMAND_OPCODE_START => self.rasterize_primitive(),
+                    // Primitives are rasterized synchronously, so there's never anything to wait for
+                    COMMAND_OPCODE_WAIT_IDLE | COMMAND_OPCODE_WAIT_START => (),
+                    COMMAND_OPCODE_TILE_LOAD | COMMAND_OPCODE_TILE_STORE => {
+                        let tile_buffer = arg & ((1 << COMMAND_TILE_BUFFER_BITS) - 1);
+                        let num_words = match tile_buffer {
+                            COMMAND_TILE_BUFFER_COLOR => TILE_PIXELS / 4,
+                            COMMAND_TILE_BUFFER_DEPTH => TILE_PIXELS / 8,
+                            _ => TILE_PIXELS / 16,
+                        };
+                        let row_words = num_words / TILE_DIM;
+                        let pitch = (arg >> (COMMAND_TILE_PITCH_BIT_OFFSET - COMMAND_ARG_BIT_OFFSET)) as u16 as i16 as u32;
+                        for i in 0..num_words {
+                            let ram_addr = (word_addr.wrapping_add((i / row_words).wrapping_mul(pitch)).wrapping_add(i % row_words) & addr_mask) as usize;
+                            if opcode == COMMAND_OPCODE_TILE_LOAD {
+                                self.write_tile_buffer_word(true, tile_buffer, i, self.tex_buffer[ram_addr]);
+                            } else {
+                                self.tex_buffer[ram_addr] = self.read_tile_buffer_word(true, tile_buffer, i);
+                            }
+                        }
+                    }
+                    COMMAND_OPCODE_JUMP => {
+                        addr = word_addr;
+                        break;
+                    }
+                    COMMAND_OPCODE_TILE_CLEAR => {
+                        match arg & ((1 << COMMAND_TILE_BUFFER_BITS) - 1) {
+                            COMMAND_TILE_BUFFER_COLOR => self.transfer_color_buffer = [self.tile_clear_color; TILE_PIXELS as usize],
+                            COMMAND_TILE_BUFFER_DEPTH => self.transfer_depth_buffer = [self.tile_clear_depth; TILE_PIXELS as usize],
+                            COMMAND_TILE_BUFFER_STENCIL => self.transfer_stencil_buffer = [self.tile_clear_stencil; TILE_PIXELS as usize],
+                            _ => ()
+                        }
+                    }
+                    COMMAND_OPCODE_TILE_SWAP => {
+                        mem::swap(&mut self.color_buffer, &mut self.transfer_color_buffer);
+                        mem::swap(&mut self.depth_buffer, &mut self.transfer_depth_buffer);
+                        mem::swap(&mut self.stencil_buffer, &mut self.transfer_stencil_buffer);
+                    }
+                    _ => ()
+                }
+            }
+        }
+    }
+
+    // Bit-exact model of the setup unit
+    fn setup_triangle(&mut self, tile_min_x: u32, tile_min_y: u32) {
+        const AREA_BITS: u32 = 33;
+        const RECIPROCAL_BITS: u32 = 33;
+
+        fn saturate(x: i128) -> u32 {
+            x.max(i32::MIN as i128).min(i32::MAX as i128) as i32 as u32
+        }
+
+        let xy = |i: usize| self.setup_vertices[i][SETUP_VERTEX_WORD_XY as usize];
+        let x = (0..3).map(|i| xy(i) as i16 as i64).collect::<Vec<_>>();
+        let y = (0..3).map(|i| (xy(i) >> 16) as i16 as i64).collect::<Vec<_>>();
+
+        // Sample at pixel centers
+        let p_x = ((tile_min_x as i64) << SETUP_XY_FRACT_BITS) | (1 << (SETUP_XY_FRACT_BITS - 1));
+        let p_y = ((tile_min_y as i64) << SETUP_XY_FRACT_BITS) | (1 << (SETUP_XY_FRACT_BITS - 1));
+
+        // Edge gradients get SETUP_XY_FRACT_BITS more fractional bits to match the edge function values
+        let mut edge_min = [0; 3];
+        let mut edge_dx = [0; 3];
+        let mut edge_dy = [0; 3];
+        for k in 0..3 {
+            let a = (k + 1) % 3;
+            let b = (k + 2) % 3;
+            edge_dx[k] = (y[a] - y[b]) << SETUP_XY_FRACT_BITS;
+            edge_dy[k] = (x[b] - x[a]) << SETUP_XY_FRACT_BITS;
+            edge_min[k] = (x[b] - x[a]) * (p_y - y[a]) + (y[a] - y[b]) * (p_x - x[a]);
+            self.write_reg(REG_W0_MIN_ADDR + k as u32 * 3 + 0, saturate(edge_min[k] as _));
+            self.write_reg(REG_W0_MIN_ADDR + k as u32 * 3 + 1, edge_dx[k] as _);
+            self.write_reg(REG_W0_MIN_ADDR + k as u32 * 3 + 2, edge_dy[k] as _);
+        }
+
+        let area = (((x[1] - x[0]) * (y[2] - y[0]) - (y[1] - y[0]) * (x[2] - x[0])) as u64) & ((1 << AREA_BITS) - 1);
+        let leading_zeros = area.leading_zeros() - (64 - AREA_BITS);
+        let normalized_area = (area << leading_zeros) as u128;
+        let reciprocal = ((1 << (AREA_BITS + RECIPROCAL_BITS - 1)) - 1) / normalized_area;
+        let shift = AREA_BITS + RECIPROCAL_BITS - 1 - leading_zeros;
+
+        for i in 0..SETUP_VERTEX_ATTRIBUTES {
+            let a = |k: usize| self.setup_vertices[k][(SETUP_VERTEX_WORD_ATTRIBUTES + i) as usize] as i32 as i128;
+            let d1 = a(1) - a(0);
+            let d2 = a(2) - a(0);
+            let components = [
+                (edge_min[1], edge_min[2], a(0)),
+                (edge_dx[1], edge_dx[2], 0),
+                (edge_dy[1], edge_dy[2], 0),
+            ];
+            for (j, &(e1, e2, base)) in components.iter().enumerate() {
+                let sum = d1 * e1 as i128 + d2 * e2 as i128;
+                let value = ((sum * reciprocal as i128) >> shift) + base;
+                // Truncated rather than saturated, since pixel values are stepped from these with wrapping adds
+                self.write_reg(REG_R_MIN_ADDR + i * 3 + j as u32, value as u32);
+            }
         }
     }
 
@@ -135,6 +574,17 @@ impl ModelDevice {
         let mut s_row = self.s_min;
         let mut t_row = self.t_min;
 
+        // Per-primitive part of the texture LOD: log2 of the largest s/t derivative (still divided by w)
+        let lod_base = log2_approx(
+            (self.s_dx as i32).unsigned_abs()
+            .max((self.s_dy as i32).unsigned_abs())
+            .max((self.t_dx as i32).unsigned_abs())
+            .max((self.t_dy as i32).unsigned_abs()));
+
+        let scissor_field = |bit_offset: u32| (self.scissor >> bit_offset) & ((1 << TILE_DIM_BITS) - 1);
+        let scissor_x = scissor_field(REG_SCISSOR_MIN_X_BIT_OFFSET)..=scissor_field(REG_SCISSOR_MAX_X_BIT_OFFSET);
+        let scissor_y = scissor_field(REG_SCISSOR_MIN_Y_BIT_OFFSET)..=scissor_field(REG_SCISSOR_MAX_Y_BIT_OFFSET);
+
         for y in 0..TILE_DIM {
             let mut w0 = w0_row;
             let mut w1 = w1_row;
@@ -149,7 +599,7 @@ impl ModelDevice {
             let mut t = t_row;
 
             for x in 0..TILE_DIM {
-                if (w0 | w1 | w2) as i32 >= 0 {
+                if scissor_x.contains(&x) && scissor_y.contains(&y) && (w0 | w1 | w2) as i32 >= 0 {
                     const RESTORED_W_FRACT_BITS: u32 = 8; // Must be less than W_INVERSE_FRACT_BITS and ST_FRACT_BITS
 
                     fn inverse_approx(x: u32) -> u32 {
@@ -165,7 +615,8 @@ impl ModelDevice {
                             e = (((e as u64) * (e as u64)) >> 32) as u32;
                         }
 
-                        return (q >> shr) | (1 << (32 - shr));
+                        // When shr is 0 the implicit leading one is shifted out entirely, as in the RTL
+                        return (q >> shr) | 1u32.checked_shl(32 - shr).unwrap_or(0);
                     }
                     let w_approx = inverse_approx(w_inverse);
 
@@ -178,85 +629,175 @@ impl ModelDevice {
 
                     let w = w_approx;
 
-                    let s = (((s as i32) >> RESTORED_W_FRACT_BITS) * (w as i32)) as u32;
-                    let t = (((t as i32) >> RESTORED_W_FRACT_BITS) * (w as i32)) as u32;
-                    let s_floor = s >> ST_FRACT_BITS;
-                    let t_floor = t >> ST_FRACT_BITS;
-                    let mut s_fract = (s >> (ST_FRACT_BITS - ST_FILTER_FRACT_BITS)) & ((1 << ST_FILTER_FRACT_BITS) - 1);
-                    let mut t_fract = (t >> (ST_FRACT_BITS - ST_FILTER_FRACT_BITS)) & ((1 << ST_FILTER_FRACT_BITS) - 1);
-                    let mut one_minus_s_fract = (1 << ST_FILTER_FRACT_BITS) - s_fract;
-                    let mut one_minus_t_fract = (1 << ST_FILTER_FRACT_BITS) - t_fract;
-                    match self.texture_filter {
-                        TextureFilter::Nearest => {
-                            // Lock weights for nearest filtering
-                            let zero = 0;
-                            let one = 1 << ST_FILTER_FRACT_BITS;
-                            s_fract = zero;
-                            one_minus_s_fract = one;
-                            t_fract = zero;
-                            one_minus_t_fract = one;
+                    // Products are kept at full width, since coarser mip levels use higher bits
+                    let s = (((s as i32) >> RESTORED_W_FRACT_BITS) as i64) * ((w as i32) as i64);
+                    let t = (((t as i32) >> RESTORED_W_FRACT_BITS) as i64) * ((w as i32) as i64);
+
+                    let lod = (lod_base + log2_approx(w)) as i32 + (self.texture_lod_bias as i8 as i32) - (((ST_FRACT_BITS + RESTORED_W_FRACT_BITS) << TEX_LOD_FRACT_BITS) as i32);
+                    let lod = lod.max(0).min((self.texture_max_level << TEX_LOD_FRACT_BITS) as i32) as u32;
+                    let (level0, level1, lod_fract) = match self.texture_mip_mode {
+                        TextureMipMode::None => (0, 0, 0),
+                        TextureMipMode::Nearest => {
+                            let level = (lod + (1 << (TEX_LOD_FRACT_BITS - 1))) >> TEX_LOD_FRACT_BITS;
+                            (level, level, 0)
                         }
-                        TextureFilter::Bilinear => (), // Do nothing
-                    }
-                    let texel_color0 = self.fetch_texel(s_floor + 0, t_floor + 0);
-                    let texel_color1 = self.fetch_texel(s_floor + 1, t_floor + 0);
-                    let texel_color2 = self.fetch_texel(s_floor + 0, t_floor + 1);
-                    let texel_color3 = self.fetch_texel(s_floor + 1, t_floor + 1);
-                    let a_r = (texel_color0.0 * one_minus_s_fract + texel_color1.0 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let a_g = (texel_color0.1 * one_minus_s_fract + texel_color1.1 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let a_b = (texel_color0.2 * one_minus_s_fract + texel_color1.2 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let a_a = (texel_color0.3 * one_minus_s_fract + texel_color1.3 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let b_r = (texel_color2.0 * one_minus_s_fract + texel_color3.0 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let b_g = (texel_color2.1 * one_minus_s_fract + texel_color3.1 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let b_b = (texel_color2.2 * one_minus_s_fract + texel_color3.2 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let b_a = (texel_color2.3 * one_minus_s_fract + texel_color3.3 * s_fract) >> ST_FILTER_FRACT_BITS;
-                    let texel_r = (a_r * one_minus_t_fract + b_r * t_fract) >> ST_FILTER_FRACT_BITS;
-                    let texel_g = (a_g * one_minus_t_fract + b_g * t_fract) >> ST_FILTER_FRACT_BITS;
-                    let texel_b = (a_b * one_minus_t_fract + b_b * t_fract) >> ST_FILTER_FRACT_BITS;
-                    let texel_a = (a_a * one_minus_t_fract + b_a * t_fract) >> ST_FILTER_FRACT_BITS;
-
-                    let r = r >> COLOR_FRACT_BITS;
-                    let g = g >> COLOR_FRACT_BITS;
-                    let b = b >> COLOR_FRACT_BITS;
-                    let a = a >> COLOR_FRACT_BITS;
+                        TextureMipMode::Linear => {
+                            // lod is clamped to the max level, so a nonzero fract means there's always a next level
+                            let level = lod >> TEX_LOD_FRACT_BITS;
+                            let lod_fract = lod & ((1 << TEX_LOD_FRACT_BITS) - 1);
+                            (level, level + if lod_fract != 0 { 1 } else { 0 }, lod_fract)
+                        }
+                    };
+                    let one_minus_lod_fract = (1 << TEX_LOD_FRACT_BITS) - lod_fract;
+
+                    let sample_level = |level: u32| -> (u32, u32, u32, u32) {
+                        let s_floor = (s >> (ST_FRACT_BITS + level)) as u32;
+                        let t_floor = (t >> (ST_FRACT_BITS + level)) as u32;
+                        let mut s_fract = ((s >> (ST_FRACT_BITS - ST_FILTER_FRACT_BITS + level)) as u32) & ((1 << ST_FILTER_FRACT_BITS) - 1);
+                        let mut t_fract = ((t >> (ST_FRACT_BITS - ST_FILTER_FRACT_BITS + level)) as u32) & ((1 << ST_FILTER_FRACT_BITS) - 1);
+                        let mut one_minus_s_fract = (1 << ST_FILTER_FRACT_BITS) - s_fract;
+                        let mut one_minus_t_fract = (1 << ST_FILTER_FRACT_BITS) - t_fract;
+                        match self.texture_filter {
+                            TextureFilter::Nearest => {
+                                // Lock weights for nearest filtering
+                                let zero = 0;
+                                let one = 1 << ST_FILTER_FRACT_BITS;
+                                s_fract = zero;
+                                one_minus_s_fract = one;
+                                t_fract = zero;
+                                one_minus_t_fract = one;
+                            }
+                            TextureFilter::Bilinear => (), // Do nothing
+                        }
+                        let texel_color0 = self.fetch_texel(s_floor + 0, t_floor + 0, level);
+                        let texel_color1 = self.fetch_texel(s_floor + 1, t_floor + 0, level);
+                        let texel_color2 = self.fetch_texel(s_floor + 0, t_floor + 1, level);
+                        let texel_color3 = self.fetch_texel(s_floor + 1, t_floor + 1, level);
+                        let a_r = (texel_color0.0 * one_minus_s_fract + texel_color1.0 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let a_g = (texel_color0.1 * one_minus_s_fract + texel_color1.1 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let a_b = (texel_color0.2 * one_minus_s_fract + texel_color1.2 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let a_a = (texel_color0.3 * one_minus_s_fract + texel_color1.3 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let b_r = (texel_color2.0 * one_minus_s_fract + texel_color3.0 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let b_g = (texel_color2.1 * one_minus_s_fract + texel_color3.1 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let b_b = (texel_color2.2 * one_minus_s_fract + texel_color3.2 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let b_a = (texel_color2.3 * one_minus_s_fract + texel_color3.3 * s_fract) >> ST_FILTER_FRACT_BITS;
+                        let texel_r = (a_r * one_minus_t_fract + b_r * t_fract) >> ST_FILTER_FRACT_BITS;
+                        let texel_g = (a_g * one_minus_t_fract + b_g * t_fract) >> ST_FILTER_FRACT_BITS;
+                        let texel_b = (a_b * one_minus_t_fract + b_b * t_fract) >> ST_FILTER_FRACT_BITS;
+                        let texel_a = (a_a * one_minus_t_fract + b_a * t_fract) >> ST_FILTER_FRACT_BITS;
+                        (texel_r, texel_g, texel_b, texel_a)
+                    };
+                    let level0_color = sample_level(level0);
+                    let level1_color = sample_level(level1);
+                    let texel_r = (level0_color.0 * one_minus_lod_fract + level1_color.0 * lod_fract) >> TEX_LOD_FRACT_BITS;
+                    let texel_g = (level0_color.1 * one_minus_lod_fract + level1_color.1 * lod_fract) >> TEX_LOD_FRACT_BITS;
+                    let texel_b = (level0_color.2 * one_minus_lod_fract + level1_color.2 * lod_fract) >> TEX_LOD_FRACT_BITS;
+                    let texel_a = (level0_color.3 * one_minus_lod_fract + level1_color.3 * lod_fract) >> TEX_LOD_FRACT_BITS;
+
+                    // Only the low whole bits are kept, so components that stray just below zero wrap around
+                    let color_whole = |comp: u32| (comp >> COLOR_FRACT_BITS) & ((1 << COLOR_WHOLE_BITS) - 1);
+                    let r = color_whole(r);
+                    let g = color_whole(g);
+                    let b = color_whole(b);
+                    let a = color_whole(a);
 
                     let scale_comp = |color_comp: u32, texel_comp: u32| -> u32 {
                         (color_comp * texel_comp) >> 8
                     };
 
-                    let r = scale_comp(r, texel_r);
-                    let g = scale_comp(g, texel_g);
-                    let b = scale_comp(b, texel_b);
-                    let a = scale_comp(a, texel_a);
+                    let decal_comp = |color_comp: u32, texel_comp: u32| -> u32 {
+                        (color_comp * (256 - texel_a) + texel_comp * texel_a) >> 8
+                    };
+
+                    let add_comp = |color_comp: u32, texel_comp: u32| -> u32 {
+                        (color_comp + texel_comp).min(256)
+                    };
+
+                    let (r, g, b, a) = match self.texture_combine_mode {
+                        TextureCombineMode::Modulate => (scale_comp(r, texel_r), scale_comp(g, texel_g), scale_comp(b, texel_b), scale_comp(a, texel_a)),
+                        TextureCombineMode::Replace => (texel_r, texel_g, texel_b, texel_a),
+                        TextureCombineMode::Decal => (decal_comp(r, texel_r), decal_comp(g, texel_g), decal_comp(b, texel_b), a),
+                        TextureCombineMode::Add => (add_comp(r, texel_r), add_comp(g, texel_g), add_comp(b, texel_b), scale_comp(a, texel_a)),
+                        TextureCombineMode::Disabled => (r, g, b, a),
+                    };
 
-                    let zero = 0;
-                    let one = 1 << 8;
+                    // Alpha is tested as it will be written, ie. clamped to 8 bits
+                    let alpha_test_result = self.alpha_test_func.test(a.min(0xff), self.alpha_test_ref) || !self.alpha_test_enable;
 
-                    let blend_src_factor = match self.blend_src_factor {
-                        BlendSrcFactor::Zero => zero,
-                        BlendSrcFactor::One => one,
-                        BlendSrcFactor::SrcAlpha => a,
-                        BlendSrcFactor::OneMinusSrcAlpha => one - a,
+                    // Restored w is clamped to the fog coord's range
+                    let fog_coord = match self.fog_coord {
+                        FogCoord::Z => (z >> (Z_FRACT_BITS - 16)) & 0xffff,
+                        FogCoord::W => w.min(0xffff),
+                    };
+                    let fog_x = ((fog_coord.saturating_sub(self.fog_start) * self.fog_scale) >> 16).min(256);
+                    let fog_amount = if self.fog_enable {
+                        match self.fog_mode {
+                            FogMode::Linear => fog_x,
+                            FogMode::Exp => self.fog_table[fog_x.min(255) as usize],
+                            FogMode::Exp2 => self.fog_table[((fog_x * fog_x) >> 8).min(255) as usize],
+                        }
+                    } else {
+                        0
                     };
 
-                    let blend_dst_factor = match self.blend_dst_factor {
-                        BlendDstFactor::Zero => zero,
-                        BlendDstFactor::One => one,
-                        BlendDstFactor::SrcAlpha => a,
-                        BlendDstFactor::OneMinusSrcAlpha => one - a,
+                    let fog_comp = |color_comp: u32, fog_color_comp: u32| -> u32 {
+                        (color_comp * (256 - fog_amount) + fog_color_comp * fog_amount) >> 8
                     };
 
+                    let r = fog_comp(r, (self.fog_color >> 16) & 0xff);
+                    let g = fog_comp(g, (self.fog_color >> 8) & 0xff);
+                    let b = fog_comp(b, (self.fog_color >> 0) & 0xff);
+
                     let buffer_index = y as usize * TILE_DIM as usize + x as usize;
 
                     let prev_color = self.color_buffer[buffer_index];
+                    let prev_r = (prev_color >> 16) & 0xff;
+                    let prev_g = (prev_color >> 8) & 0xff;
+                    let prev_b = (prev_color >> 0) & 0xff;
+                    let prev_a = prev_color >> 24;
+
+                    let constant_r = (self.blend_constant >> 16) & 0xff;
+                    let constant_g = (self.blend_constant >> 8) & 0xff;
+                    let constant_b = (self.blend_constant >> 0) & 0xff;
+                    let constant_a = self.blend_constant >> 24;
+
+                    // Factors are 9 bits wide in hardware, so one minus a larger value wraps
+                    let one: u32 = 1 << 8;
+                    let one_minus = |x: u32| -> u32 {
+                        one.wrapping_sub(x) & 0x1ff
+                    };
 
-                    let r = (r * blend_src_factor) >> 8;
-                    let g = (g * blend_src_factor) >> 8;
-                    let b = (b * blend_src_factor) >> 8;
+                    let blend_factor = |factor: &BlendFactor, src_comp: u32, dst_comp: u32, constant_comp: u32| -> u32 {
+                        match factor {
+                            BlendFactor::Zero => 0,
+                            BlendFactor::One => one,
+                            BlendFactor::SrcAlpha => a,
+                            BlendFactor::OneMinusSrcAlpha => one_minus(a),
+                            BlendFactor::SrcColor => src_comp,
+                            BlendFactor::OneMinusSrcColor => one_minus(src_comp),
+                            BlendFactor::DstColor => dst_comp,
+                            BlendFactor::OneMinusDstColor => one_minus(dst_comp),
+                            BlendFactor::DstAlpha => prev_a,
+                            BlendFactor::OneMinusDstAlpha => one_minus(prev_a),
+                            BlendFactor::ConstantColor => constant_comp,
+                            BlendFactor::OneMinusConstantColor => one_minus(constant_comp),
+                            BlendFactor::ConstantAlpha => constant_a,
+                            BlendFactor::OneMinusConstantAlpha => one_minus(constant_a),
+                        }
+                    };
 
-                    let prev_r = (((prev_color >> 16) & 0xff) * blend_dst_factor) >> 9;
-                    let prev_g = (((prev_color >> 8) & 0xff) * blend_dst_factor) >> 9;
-                    let prev_b = (((prev_color >> 0) & 0xff) * blend_dst_factor) >> 9;
+                    let blend_comp = |src_comp: u32, dst_comp: u32, constant_comp: u32| -> u32 {
+                        let weighted_src = (src_comp * blend_factor(&self.blend_src_factor, src_comp, dst_comp, constant_comp)) >> 8;
+                        let weighted_dst = (dst_comp * blend_factor(&self.blend_dst_factor, src_comp, dst_comp, constant_comp)) >> 8;
+
+                        match self.blend_op {
+                            BlendOp::Add => weighted_src + weighted_dst,
+                            BlendOp::Subtract => weighted_src.saturating_sub(weighted_dst),
+                            BlendOp::ReverseSubtract => weighted_dst.saturating_sub(weighted_src),
+                            BlendOp::Min => src_comp.min(dst_comp),
+                            BlendOp::Max => src_comp.max(dst_comp),
+                        }
+                    };
 
                     let clamp_comp = |comp: u32| -> u32 {
                         if comp >> 8 == 0 {
@@ -266,17 +807,33 @@ impl ModelDevice {
                         }
                     };
 
-                    let r = clamp_comp(r + prev_r);
-                    let g = clamp_comp(g + prev_g);
-                    let b = clamp_comp(b + prev_b);
+                    let r = clamp_comp(blend_comp(r, prev_r, constant_r));
+                    let g = clamp_comp(blend_comp(g, prev_g, constant_g));
+                    let b = clamp_comp(blend_comp(b, prev_b, constant_b));
                     let a = clamp_comp(a);
 
                     let color = (a << 24) | (r << 16) | (g << 8) | b;
 
                     let z = (z >> (Z_FRACT_BITS - 16)) as u16;
-                    let depth_test_result = z < self.depth_buffer[buffer_index] || !self.depth_test_enable;
+                    let prev_z = self.depth_buffer[buffer_index];
+                    let depth_test_result = self.depth_func.test(z, prev_z) || !self.depth_test_enable;
+
+                    let prev_stencil = self.stencil_buffer[buffer_index];
+                    let stencil_test_result = self.stencil_func.test(self.stencil_ref & self.stencil_mask, prev_stencil & self.stencil_mask) || !self.stencil_test_enable;
 
-                    if depth_test_result {
+                    // Pixels discarded by the alpha test don't update stencil
+                    if self.stencil_test_enable && alpha_test_result {
+                        let stencil_op = if !stencil_test_result {
+                            &self.stencil_sfail_op
+                        } else if !depth_test_result {
+                            &self.stencil_dpfail_op
+                        } else {
+                            &self.stencil_dppass_op
+                        };
+                        self.stencil_buffer[buffer_index] = stencil_op.apply(prev_stencil, self.stencil_ref);
+                    }
+
+                    if stencil_test_result && depth_test_result && alpha_test_result {
                         self.color_buffer[buffer_index] = color;
                         if self.depth_write_mask_enable {
                             self.depth_buffer[buffer_index] = z;
@@ -311,13 +868,67 @@ impl ModelDevice {
         }
     }
 
-    fn fetch_texel(&self, s: u32, t: u32) -> (u32, u32, u32, u32) {
-        let texture_width = 2 << 3;//self.texture_width_shift;
-        let texture_height = 2 << 3;//self.texture_height_shift;
-        let s = s as usize & (texture_width - 1);
-        let t = t as usize & (texture_height - 1);
-        // TODO: Proper fetch from correct address
-        let texel = self.tex_buffer[t / 2 * texture_width / 2 + s / 2] as u32;
+    fn fetch_texel(&self, s: u32, t: u32, level: u32) -> (u32, u32, u32, u32) {
+        let width_bits = self.texture_width.to_bits() - level;
+        let height_bits = self.texture_height.to_bits() - level;
+
+        // Returns None if the texel should be replaced with the border color
+        let wrap = |coord: u32, dim_bits: u32, wrap: &TextureWrap| -> Option<u32> {
+            // Texel coords are signed and only as wide as their whole part
+            const COORD_BITS: u32 = 32 - ST_FRACT_BITS;
+            let coord = ((coord << (32 - COORD_BITS)) as i32) >> (32 - COORD_BITS);
+            let dim = 1 << dim_bits;
+            let mask = dim - 1;
+            let out_of_range = coord < 0 || coord >= dim;
+            match wrap {
+                TextureWrap::Repeat => Some(coord & mask),
+                TextureWrap::MirroredRepeat => Some(if coord & dim != 0 { !coord & mask } else { coord & mask }),
+                TextureWrap::ClampToEdge => Some(coord.max(0).min(mask)),
+                TextureWrap::ClampToBorder => if out_of_range { None } else { Some(coord) },
+            }.map(|coord| coord as u32)
+        };
+
+        let texel = match (wrap(s, width_bits, &self.texture_wrap_s), wrap(t, height_bits, &self.texture_wrap_t)) {
+            (Some(s), Some(t)) => {
+                // Texels are interleaved into four buffers by s/t parity to allow single-cycle filtered texel reads
+                let buffer_index = ((t & 1) << 1) | (s & 1);
+                let offset = (buffer_index << (width_bits - 1 + height_bits - 1)) | ((t >> 1) << (width_bits - 1)) | (s >> 1);
+                // Levels are packed consecutively after level 0, each a quarter the size of the last
+                let level0_size_bits = self.texture_width.to_bits() + self.texture_height.to_bits();
+                let level_offset = (0..level).fold(0, |acc, i| acc + (1 << (level0_size_bits - 2 * i)));
+                let offset = match self.texture_format {
+                    // VQ index maps have a single index per 2x2 block, so they're a quarter of the size and have no buffer index
+                    TextureFormat::Vq => (level_offset >> 2) + (((t >> 1) << (width_bits - 1)) | (s >> 1)),
+                    _ => level_offset + offset,
+                };
+                // Offsets are in texels, which are packed into 32-bit pixels from the least significant bits
+                let texels_per_pixel_bits = self.texture_format.texels_per_pixel_bits();
+                let texel_bits = 32 >> texels_per_pixel_bits;
+                let base = (self.texture_base & ((1 << TEX_PIXEL_ADDR_BITS) - 1)) & !((1 << level0_size_bits) - 1);
+                let addr = (base + (offset >> texels_per_pixel_bits)) & ((1 << TEX_PIXEL_ADDR_BITS) - 1);
+                let pixel = (self.tex_buffer[(addr >> 2) as usize] >> ((addr & 3) * 32)) as u32;
+                let texel = ((pixel as u64 >> ((offset & ((1 << texels_per_pixel_bits) - 1)) * texel_bits)) & ((1 << texel_bits) - 1)) as u32;
+
+                // Expands a component to 8 bits by repeating its upper bits
+                let expand = |texel: u32, offset: u32, bits: u32| -> u32 {
+                    let comp = (texel >> offset) & ((1 << bits) - 1);
+                    if bits == 1 {
+                        comp * 0xff
+                    } else {
+                        (comp << (8 - bits)) | (comp >> (2 * bits - 8))
+                    }
+                };
+                match self.texture_format {
+                    TextureFormat::Argb8888 => texel,
+                    TextureFormat::Rgb565 => (0xff << 24) | (expand(texel, 11, 5) << 16) | (expand(texel, 5, 6) << 8) | expand(texel, 0, 5),
+                    TextureFormat::Argb4444 => (expand(texel, 12, 4) << 24) | (expand(texel, 8, 4) << 16) | (expand(texel, 4, 4) << 8) | expand(texel, 0, 4),
+                    TextureFormat::Argb1555 => (expand(texel, 15, 1) << 24) | (expand(texel, 10, 5) << 16) | (expand(texel, 5, 5) << 8) | expand(texel, 0, 5),
+                    TextureFormat::Indexed8 | TextureFormat::Indexed4 => self.texture_palette[texel as usize],
+                    TextureFormat::Vq => self.texture_palette[((texel << 2) | buffer_index) as usize],
+                }
+            }
+            _ => self.texture_border_color,
+        };
         let texel_red = (texel >> 16) & 0xff;
         let texel_green = (texel >> 8) & 0xff;
         let texel_blue = (texel >> 0) & 0xff;
@@ -330,30 +941,172 @@ impl Device for ModelDevice {
     fn write_reg(&mut self, addr: u32, data: u32) {
         match addr {
             REG_START_ADDR => self.rasterize_primitive(),
+            REG_COMMAND_LIST_BASE_ADDR => self.process_command_list(data & ((1 << TEX_WORD_ADDR_BITS) - 1)),
             REG_DEPTH_SETTINGS_ADDR => {
                 self.depth_test_enable = (data & (1 << REG_DEPTH_TEST_ENABLE_BIT)) != 0;
                 self.depth_write_mask_enable = (data & (1 << REG_DEPTH_WRITE_MASK_ENABLE_BIT)) != 0;
+                self.depth_func = match (data >> REG_DEPTH_SETTINGS_FUNC_BIT_OFFSET) & ((1 << REG_DEPTH_SETTINGS_FUNC_BITS) - 1) {
+                    REG_DEPTH_SETTINGS_FUNC_NEVER => CompareFunc::Never,
+                    REG_DEPTH_SETTINGS_FUNC_LESS => CompareFunc::Less,
+                    REG_DEPTH_SETTINGS_FUNC_EQUAL => CompareFunc::Equal,
+                    REG_DEPTH_SETTINGS_FUNC_LEQUAL => CompareFunc::LessEqual,
+                    REG_DEPTH_SETTINGS_FUNC_GREATER => CompareFunc::Greater,
+                    REG_DEPTH_SETTINGS_FUNC_NOTEQUAL => CompareFunc::NotEqual,
+                    REG_DEPTH_SETTINGS_FUNC_GEQUAL => CompareFunc::GreaterEqual,
+                    REG_DEPTH_SETTINGS_FUNC_ALWAYS => CompareFunc::Always,
+                    _ => unreachable!(),
+                };
+            }
+            REG_ALPHA_TEST_SETTINGS_ADDR => {
+                self.alpha_test_enable = (data & (1 << REG_ALPHA_TEST_ENABLE_BIT)) != 0;
+                self.alpha_test_func = match (data >> REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET) & ((1 << REG_ALPHA_TEST_SETTINGS_FUNC_BITS) - 1) {
+                    REG_ALPHA_TEST_SETTINGS_FUNC_NEVER => CompareFunc::Never,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_LESS => CompareFunc::Less,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_EQUAL => CompareFunc::Equal,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_LEQUAL => CompareFunc::LessEqual,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_GREATER => CompareFunc::Greater,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_NOTEQUAL => CompareFunc::NotEqual,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_GEQUAL => CompareFunc::GreaterEqual,
+                    REG_ALPHA_TEST_SETTINGS_FUNC_ALWAYS => CompareFunc::Always,
+                    _ => unreachable!(),
+                };
+                self.alpha_test_ref = (data >> REG_ALPHA_TEST_SETTINGS_REF_BIT_OFFSET) & ((1 << REG_ALPHA_TEST_SETTINGS_REF_BITS) - 1);
+            }
+            REG_STENCIL_SETTINGS_ADDR => {
+                self.stencil_test_enable = (data & (1 << REG_STENCIL_TEST_ENABLE_BIT)) != 0;
+                self.stencil_func = match (data >> REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET) & ((1 << REG_STENCIL_SETTINGS_FUNC_BITS) - 1) {
+                    REG_STENCIL_SETTINGS_FUNC_NEVER => CompareFunc::Never,
+                    REG_STENCIL_SETTINGS_FUNC_LESS => CompareFunc::Less,
+                    REG_STENCIL_SETTINGS_FUNC_EQUAL => CompareFunc::Equal,
+                    REG_STENCIL_SETTINGS_FUNC_LEQUAL => CompareFunc::LessEqual,
+                    REG_STENCIL_SETTINGS_FUNC_GREATER => CompareFunc::Greater,
+                    REG_STENCIL_SETTINGS_FUNC_NOTEQUAL => CompareFunc::NotEqual,
+                    REG_STENCIL_SETTINGS_FUNC_GEQUAL => CompareFunc::GreaterEqual,
+                    REG_STENCIL_SETTINGS_FUNC_ALWAYS => CompareFunc::Always,
+                    _ => unreachable!(),
+                };
+                self.stencil_ref = (data >> REG_STENCIL_SETTINGS_REF_BIT_OFFSET) as _;
+                self.stencil_mask = (data >> REG_STENCIL_SETTINGS_MASK_BIT_OFFSET) as _;
+                self.stencil_sfail_op = StencilOp::from_reg(data >> REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET);
+                self.stencil_dpfail_op = StencilOp::from_reg(data >> REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET);
+                self.stencil_dppass_op = StencilOp::from_reg(data >> REG_STENCIL_SETTINGS_DPPASS_OP_BIT_OFFSET);
+            }
+            REG_FOG_SETTINGS_ADDR => {
+                self.fog_enable = (data & (1 << REG_FOG_ENABLE_BIT)) != 0;
+                self.fog_mode = match (data >> REG_FOG_SETTINGS_MODE_BIT_OFFSET) & ((1 << REG_FOG_SETTINGS_MODE_BITS) - 1) {
+                    REG_FOG_SETTINGS_MODE_LINEAR => FogMode::Linear,
+                    REG_FOG_SETTINGS_MODE_EXP => FogMode::Exp,
+                    REG_FOG_SETTINGS_MODE_EXP2 => FogMode::Exp2,
+                    _ => unreachable!(),
+                };
+                self.fog_coord = match (data >> REG_FOG_SETTINGS_COORD_SELECT_BIT_OFFSET) & 1 {
+                    REG_FOG_SETTINGS_COORD_SELECT_Z => FogCoord::Z,
+                    REG_FOG_SETTINGS_COORD_SELECT_W => FogCoord::W,
+                    _ => unreachable!(),
+                };
             }
             REG_TEXTURE_SETTINGS_ADDR => {
-                self.texture_filter = match (data >> REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_BITS) - 1) {
+                self.texture_filter = match (data >> REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_FILTER_SELECT_BITS) - 1) {
                     REG_TEXTURE_SETTINGS_FILTER_SELECT_NEAREST => TextureFilter::Nearest,
                     REG_TEXTURE_SETTINGS_FILTER_SELECT_BILINEAR => TextureFilter::Bilinear,
                     _ => unreachable!(),
                 };
+                self.texture_width = match (data >> REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_DIM_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_DIM_16 => TextureDim::X16,
+                    REG_TEXTURE_SETTINGS_DIM_32 => TextureDim::X32,
+                    REG_TEXTURE_SETTINGS_DIM_64 => TextureDim::X64,
+                    REG_TEXTURE_SETTINGS_DIM_128 => TextureDim::X128,
+                    _ => unreachable!(),
+                };
+                self.texture_height = match (data >> REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_DIM_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_DIM_16 => TextureDim::X16,
+                    REG_TEXTURE_SETTINGS_DIM_32 => TextureDim::X32,
+                    REG_TEXTURE_SETTINGS_DIM_64 => TextureDim::X64,
+                    REG_TEXTURE_SETTINGS_DIM_128 => TextureDim::X128,
+                    _ => unreachable!(),
+                };
+                self.texture_wrap_s = match (data >> REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_WRAP_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_WRAP_REPEAT => TextureWrap::Repeat,
+                    REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT => TextureWrap::MirroredRepeat,
+                    REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE => TextureWrap::ClampToEdge,
+                    REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER => TextureWrap::ClampToBorder,
+                    _ => unreachable!(),
+                };
+                self.texture_wrap_t = match (data >> REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_WRAP_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_WRAP_REPEAT => TextureWrap::Repeat,
+                    REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT => TextureWrap::MirroredRepeat,
+                    REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE => TextureWrap::ClampToEdge,
+                    REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER => TextureWrap::ClampToBorder,
+                    _ => unreachable!(),
+                };
+                self.texture_mip_mode = match (data >> REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_MIP_MODE_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_MIP_MODE_NONE => TextureMipMode::None,
+                    REG_TEXTURE_SETTINGS_MIP_MODE_NEAREST => TextureMipMode::Nearest,
+                    REG_TEXTURE_SETTINGS_MIP_MODE_LINEAR => TextureMipMode::Linear,
+                    _ => unreachable!(),
+                };
+                self.texture_max_level = (data >> REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_MAX_LEVEL_BITS) - 1);
+                self.texture_format = match (data >> REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_FORMAT_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_FORMAT_ARGB8888 => TextureFormat::Argb8888,
+                    REG_TEXTURE_SETTINGS_FORMAT_RGB565 => TextureFormat::Rgb565,
+                    REG_TEXTURE_SETTINGS_FORMAT_ARGB4444 => TextureFormat::Argb4444,
+                    REG_TEXTURE_SETTINGS_FORMAT_ARGB1555 => TextureFormat::Argb1555,
+                    REG_TEXTURE_SETTINGS_FORMAT_INDEXED8 => TextureFormat::Indexed8,
+                    REG_TEXTURE_SETTINGS_FORMAT_INDEXED4 => TextureFormat::Indexed4,
+                    REG_TEXTURE_SETTINGS_FORMAT_VQ => TextureFormat::Vq,
+                    _ => unreachable!(),
+                };
+                self.texture_combine_mode = match (data >> REG_TEXTURE_SETTINGS_COMBINE_MODE_BIT_OFFSET) & ((1 << REG_TEXTURE_SETTINGS_COMBINE_MODE_BITS) - 1) {
+                    REG_TEXTURE_SETTINGS_COMBINE_MODE_MODULATE => TextureCombineMode::Modulate,
+                    REG_TEXTURE_SETTINGS_COMBINE_MODE_REPLACE => TextureCombineMode::Replace,
+                    REG_TEXTURE_SETTINGS_COMBINE_MODE_DECAL => TextureCombineMode::Decal,
+                    REG_TEXTURE_SETTINGS_COMBINE_MODE_ADD => TextureCombineMode::Add,
+                    REG_TEXTURE_SETTINGS_COMBINE_MODE_DISABLED => TextureCombineMode::Disabled,
+                    _ => unreachable!(),
+                };
             }
+            REG_TEXTURE_BASE_ADDR => { self.texture_base = data; }
             REG_BLEND_SETTINGS_ADDR => {
                 self.blend_src_factor = match (data >> REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET) & ((1 << REG_BLEND_SETTINGS_SRC_FACTOR_BITS) - 1) {
-                    REG_BLEND_SETTINGS_SRC_FACTOR_ZERO => BlendSrcFactor::Zero,
-                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE => BlendSrcFactor::One,
-                    REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA => BlendSrcFactor::SrcAlpha,
-                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA => BlendSrcFactor::OneMinusSrcAlpha,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ZERO => BlendFactor::Zero,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE => BlendFactor::One,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA => BlendFactor::SrcAlpha,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA => BlendFactor::OneMinusSrcAlpha,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_SRC_COLOR => BlendFactor::SrcColor,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_COLOR => BlendFactor::OneMinusSrcColor,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_DST_COLOR => BlendFactor::DstColor,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_COLOR => BlendFactor::OneMinusDstColor,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_DST_ALPHA => BlendFactor::DstAlpha,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_ALPHA => BlendFactor::OneMinusDstAlpha,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_COLOR => BlendFactor::ConstantColor,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_COLOR => BlendFactor::OneMinusConstantColor,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_ALPHA => BlendFactor::ConstantAlpha,
+                    REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_ALPHA => BlendFactor::OneMinusConstantAlpha,
                     _ => unreachable!(),
                 };
                 self.blend_dst_factor = match (data >> REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET) & ((1 << REG_BLEND_SETTINGS_DST_FACTOR_BITS) - 1) {
-                    REG_BLEND_SETTINGS_DST_FACTOR_ZERO => BlendDstFactor::Zero,
-                    REG_BLEND_SETTINGS_DST_FACTOR_ONE => BlendDstFactor::One,
-                    REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA => BlendDstFactor::SrcAlpha,
-                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA => BlendDstFactor::OneMinusSrcAlpha,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ZERO => BlendFactor::Zero,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE => BlendFactor::One,
+                    REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA => BlendFactor::SrcAlpha,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA => BlendFactor::OneMinusSrcAlpha,
+                    REG_BLEND_SETTINGS_DST_FACTOR_SRC_COLOR => BlendFactor::SrcColor,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_COLOR => BlendFactor::OneMinusSrcColor,
+                    REG_BLEND_SETTINGS_DST_FACTOR_DST_COLOR => BlendFactor::DstColor,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_COLOR => BlendFactor::OneMinusDstColor,
+                    REG_BLEND_SETTINGS_DST_FACTOR_DST_ALPHA => BlendFactor::DstAlpha,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_ALPHA => BlendFactor::OneMinusDstAlpha,
+                    REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_COLOR => BlendFactor::ConstantColor,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_COLOR => BlendFactor::OneMinusConstantColor,
+                    REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_ALPHA => BlendFactor::ConstantAlpha,
+                    REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_ALPHA => BlendFactor::OneMinusConstantAlpha,
+                    _ => unreachable!(),
+                };
+                self.blend_op = match (data >> REG_BLEND_SETTINGS_OP_BIT_OFFSET) & ((1 << REG_BLEND_SETTINGS_OP_BITS) - 1) {
+                    REG_BLEND_SETTINGS_OP_ADD => BlendOp::Add,
+                    REG_BLEND_SETTINGS_OP_SUBTRACT => BlendOp::Subtract,
+                    REG_BLEND_SETTINGS_OP_REVERSE_SUBTRACT => BlendOp::ReverseSubtract,
+                    REG_BLEND_SETTINGS_OP_MIN => BlendOp::Min,
+                    REG_BLEND_SETTINGS_OP_MAX => BlendOp::Max,
                     _ => unreachable!(),
                 };
             }
@@ -390,6 +1143,41 @@ impl Device for ModelDevice {
             REG_T_MIN_ADDR => { self.t_min = data; }
             REG_T_DX_ADDR => { self.t_dx = data; }
             REG_T_DY_ADDR => { self.t_dy = data; }
+            REG_BLEND_CONSTANT_ADDR => { self.blend_constant = data; }
+            REG_TEXTURE_BORDER_COLOR_ADDR => { self.texture_border_color = data; }
+            REG_TEXTURE_LOD_BIAS_ADDR => { self.texture_lod_bias = data & ((1 << REG_TEXTURE_LOD_BIAS_BITS) - 1); }
+            REG_TEXTURE_PALETTE_INDEX_ADDR => { self.texture_palette_index = data & ((1 << TEX_PALETTE_INDEX_BITS) - 1); }
+            REG_TEXTURE_PALETTE_DATA_ADDR => {
+                self.texture_palette[self.texture_palette_index as usize] = data;
+                self.texture_palette_index = (self.texture_palette_index + 1) & ((1 << TEX_PALETTE_INDEX_BITS) - 1);
+            }
+            REG_FOG_COLOR_ADDR => { self.fog_color = data; }
+            REG_FOG_START_ADDR => { self.fog_start = data & ((1 << FOG_COORD_BITS) - 1); }
+            REG_FOG_SCALE_ADDR => { self.fog_scale = data & ((1 << FOG_COORD_BITS) - 1); }
+            REG_FOG_TABLE_INDEX_ADDR => { self.fog_table_index = data & ((1 << FOG_TABLE_INDEX_BITS) - 1); }
+            REG_FOG_TABLE_DATA_ADDR => {
+                self.fog_table[self.fog_table_index as usize] = data & ((1 << FOG_AMOUNT_BITS) - 1);
+                self.fog_table_index = (self.fog_table_index + 1) & ((1 << FOG_TABLE_INDEX_BITS) - 1);
+            }
+            REG_SETUP_VERTEX_INDEX_ADDR => {
+                self.setup_vertex_select = data & 3;
+                self.setup_vertex_word = 0;
+            }
+            REG_SETUP_VERTEX_DATA_ADDR => {
+                if self.setup_vertex_select < 3 {
+                    self.setup_vertices[self.setup_vertex_select as usize][self.setup_vertex_word as usize] = data;
+                }
+                self.setup_vertex_word += 1;
+                if self.setup_vertex_word == SETUP_VERTEX_WORDS {
+                    self.setup_vertex_select = (self.setup_vertex_select + 1) & 3;
+                    self.setup_vertex_word = 0;
+                }
+            }
+            REG_SETUP_START_ADDR => self.setup_triangle(data & 0xffff, data >> 16),
+            REG_SCISSOR_ADDR => { self.scissor = data & ((1 << REG_SCISSOR_BITS) - 1); }
+            REG_TILE_CLEAR_COLOR_ADDR => { self.tile_clear_color = data; }
+            REG_TILE_CLEAR_DEPTH_ADDR => { self.tile_clear_depth = data as _; }
+            REG_TILE_CLEAR_STENCIL_ADDR => { self.tile_clear_stencil = data as _; }
             _ => panic!("Unrecognized addr: {}", addr)
         }
     }
@@ -399,27 +1187,155 @@ impl Device for ModelDevice {
             REG_STATUS_ADDR => 0,
             REG_DEPTH_SETTINGS_ADDR => {
                 (if self.depth_test_enable { 1 } else { 0 } << REG_DEPTH_TEST_ENABLE_BIT) |
-                (if self.depth_write_mask_enable { 1 } else { 0 } << REG_DEPTH_WRITE_MASK_ENABLE_BIT)
+                (if self.depth_write_mask_enable { 1 } else { 0 } << REG_DEPTH_WRITE_MASK_ENABLE_BIT) |
+                (match self.depth_func {
+                    CompareFunc::Never => REG_DEPTH_SETTINGS_FUNC_NEVER,
+                    CompareFunc::Less => REG_DEPTH_SETTINGS_FUNC_LESS,
+                    CompareFunc::Equal => REG_DEPTH_SETTINGS_FUNC_EQUAL,
+                    CompareFunc::LessEqual => REG_DEPTH_SETTINGS_FUNC_LEQUAL,
+                    CompareFunc::Greater => REG_DEPTH_SETTINGS_FUNC_GREATER,
+                    CompareFunc::NotEqual => REG_DEPTH_SETTINGS_FUNC_NOTEQUAL,
+                    CompareFunc::GreaterEqual => REG_DEPTH_SETTINGS_FUNC_GEQUAL,
+                    CompareFunc::Always => REG_DEPTH_SETTINGS_FUNC_ALWAYS,
+                } << REG_DEPTH_SETTINGS_FUNC_BIT_OFFSET)
+            }
+            REG_ALPHA_TEST_SETTINGS_ADDR => {
+                (if self.alpha_test_enable { 1 } else { 0 } << REG_ALPHA_TEST_ENABLE_BIT) |
+                (match self.alpha_test_func {
+                    CompareFunc::Never => REG_ALPHA_TEST_SETTINGS_FUNC_NEVER,
+                    CompareFunc::Less => REG_ALPHA_TEST_SETTINGS_FUNC_LESS,
+                    CompareFunc::Equal => REG_ALPHA_TEST_SETTINGS_FUNC_EQUAL,
+                    CompareFunc::LessEqual => REG_ALPHA_TEST_SETTINGS_FUNC_LEQUAL,
+                    CompareFunc::Greater => REG_ALPHA_TEST_SETTINGS_FUNC_GREATER,
+                    CompareFunc::NotEqual => REG_ALPHA_TEST_SETTINGS_FUNC_NOTEQUAL,
+                    CompareFunc::GreaterEqual => REG_ALPHA_TEST_SETTINGS_FUNC_GEQUAL,
+                    CompareFunc::Always => REG_ALPHA_TEST_SETTINGS_FUNC_ALWAYS,
+                } << REG_ALPHA_TEST_SETTINGS_FUNC_BIT_OFFSET) |
+                (self.alpha_test_ref << REG_ALPHA_TEST_SETTINGS_REF_BIT_OFFSET)
+            }
+            REG_STENCIL_SETTINGS_ADDR => {
+                (if self.stencil_test_enable { 1 } else { 0 } << REG_STENCIL_TEST_ENABLE_BIT) |
+                (match self.stencil_func {
+                    CompareFunc::Never => REG_STENCIL_SETTINGS_FUNC_NEVER,
+                    CompareFunc::Less => REG_STENCIL_SETTINGS_FUNC_LESS,
+                    CompareFunc::Equal => REG_STENCIL_SETTINGS_FUNC_EQUAL,
+                    CompareFunc::LessEqual => REG_STENCIL_SETTINGS_FUNC_LEQUAL,
+                    CompareFunc::Greater => REG_STENCIL_SETTINGS_FUNC_GREATER,
+                    CompareFunc::NotEqual => REG_STENCIL_SETTINGS_FUNC_NOTEQUAL,
+                    CompareFunc::GreaterEqual => REG_STENCIL_SETTINGS_FUNC_GEQUAL,
+                    CompareFunc::Always => REG_STENCIL_SETTINGS_FUNC_ALWAYS,
+                } << REG_STENCIL_SETTINGS_FUNC_BIT_OFFSET) |
+                ((self.stencil_ref as u32) << REG_STENCIL_SETTINGS_REF_BIT_OFFSET) |
+                ((self.stencil_mask as u32) << REG_STENCIL_SETTINGS_MASK_BIT_OFFSET) |
+                (self.stencil_sfail_op.to_reg() << REG_STENCIL_SETTINGS_SFAIL_OP_BIT_OFFSET) |
+                (self.stencil_dpfail_op.to_reg() << REG_STENCIL_SETTINGS_DPFAIL_OP_BIT_OFFSET) |
+                (self.stencil_dppass_op.to_reg() << REG_STENCIL_SETTINGS_DPPASS_OP_BIT_OFFSET)
+            }
+            REG_FOG_SETTINGS_ADDR => {
+                (if self.fog_enable { 1 } else { 0 } << REG_FOG_ENABLE_BIT) |
+                (match self.fog_mode {
+                    FogMode::Linear => REG_FOG_SETTINGS_MODE_LINEAR,
+                    FogMode::Exp => REG_FOG_SETTINGS_MODE_EXP,
+                    FogMode::Exp2 => REG_FOG_SETTINGS_MODE_EXP2,
+                } << REG_FOG_SETTINGS_MODE_BIT_OFFSET) |
+                (match self.fog_coord {
+                    FogCoord::Z => REG_FOG_SETTINGS_COORD_SELECT_Z,
+                    FogCoord::W => REG_FOG_SETTINGS_COORD_SELECT_W,
+                } << REG_FOG_SETTINGS_COORD_SELECT_BIT_OFFSET)
             }
             REG_TEXTURE_SETTINGS_ADDR => {
                 (match self.texture_filter {
                     TextureFilter::Nearest => REG_TEXTURE_SETTINGS_FILTER_SELECT_NEAREST,
                     TextureFilter::Bilinear => REG_TEXTURE_SETTINGS_FILTER_SELECT_BILINEAR,
-                }) << REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET
+                } << REG_TEXTURE_SETTINGS_FILTER_SELECT_BIT_OFFSET) |
+                (match self.texture_width {
+                    TextureDim::X16 => REG_TEXTURE_SETTINGS_DIM_16,
+                    TextureDim::X32 => REG_TEXTURE_SETTINGS_DIM_32,
+                    TextureDim::X64 => REG_TEXTURE_SETTINGS_DIM_64,
+                    TextureDim::X128 => REG_TEXTURE_SETTINGS_DIM_128,
+                } << REG_TEXTURE_SETTINGS_WIDTH_BIT_OFFSET) |
+                (match self.texture_height {
+                    TextureDim::X16 => REG_TEXTURE_SETTINGS_DIM_16,
+                    TextureDim::X32 => REG_TEXTURE_SETTINGS_DIM_32,
+                    TextureDim::X64 => REG_TEXTURE_SETTINGS_DIM_64,
+                    TextureDim::X128 => REG_TEXTURE_SETTINGS_DIM_128,
+                } << REG_TEXTURE_SETTINGS_HEIGHT_BIT_OFFSET) |
+                (match self.texture_wrap_s {
+                    TextureWrap::Repeat => REG_TEXTURE_SETTINGS_WRAP_REPEAT,
+                    TextureWrap::MirroredRepeat => REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT,
+                    TextureWrap::ClampToEdge => REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE,
+                    TextureWrap::ClampToBorder => REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER,
+                } << REG_TEXTURE_SETTINGS_WRAP_S_BIT_OFFSET) |
+                (match self.texture_wrap_t {
+                    TextureWrap::Repeat => REG_TEXTURE_SETTINGS_WRAP_REPEAT,
+                    TextureWrap::MirroredRepeat => REG_TEXTURE_SETTINGS_WRAP_MIRRORED_REPEAT,
+                    TextureWrap::ClampToEdge => REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_EDGE,
+                    TextureWrap::ClampToBorder => REG_TEXTURE_SETTINGS_WRAP_CLAMP_TO_BORDER,
+                } << REG_TEXTURE_SETTINGS_WRAP_T_BIT_OFFSET) |
+                (match self.texture_mip_mode {
+                    TextureMipMode::None => REG_TEXTURE_SETTINGS_MIP_MODE_NONE,
+                    TextureMipMode::Nearest => REG_TEXTURE_SETTINGS_MIP_MODE_NEAREST,
+                    TextureMipMode::Linear => REG_TEXTURE_SETTINGS_MIP_MODE_LINEAR,
+                } << REG_TEXTURE_SETTINGS_MIP_MODE_BIT_OFFSET) |
+                (self.texture_max_level << REG_TEXTURE_SETTINGS_MAX_LEVEL_BIT_OFFSET) |
+                (match self.texture_format {
+                    TextureFormat::Argb8888 => REG_TEXTURE_SETTINGS_FORMAT_ARGB8888,
+                    TextureFormat::Rgb565 => REG_TEXTURE_SETTINGS_FORMAT_RGB565,
+                    TextureFormat::Argb4444 => REG_TEXTURE_SETTINGS_FORMAT_ARGB4444,
+                    TextureFormat::Argb1555 => REG_TEXTURE_SETTINGS_FORMAT_ARGB1555,
+                    TextureFormat::Indexed8 => REG_TEXTURE_SETTINGS_FORMAT_INDEXED8,
+                    TextureFormat::Indexed4 => REG_TEXTURE_SETTINGS_FORMAT_INDEXED4,
+                    TextureFormat::Vq => REG_TEXTURE_SETTINGS_FORMAT_VQ,
+                } << REG_TEXTURE_SETTINGS_FORMAT_BIT_OFFSET) |
+                (match self.texture_combine_mode {
+                    TextureCombineMode::Modulate => REG_TEXTURE_SETTINGS_COMBINE_MODE_MODULATE,
+                    TextureCombineMode::Replace => REG_TEXTURE_SETTINGS_COMBINE_MODE_REPLACE,
+                    TextureCombineMode::Decal => REG_TEXTURE_SETTINGS_COMBINE_MODE_DECAL,
+                    TextureCombineMode::Add => REG_TEXTURE_SETTINGS_COMBINE_MODE_ADD,
+                    TextureCombineMode::Disabled => REG_TEXTURE_SETTINGS_COMBINE_MODE_DISABLED,
+                } << REG_TEXTURE_SETTINGS_COMBINE_MODE_BIT_OFFSET)
             }
+            REG_TEXTURE_BASE_ADDR => self.texture_base,
             REG_BLEND_SETTINGS_ADDR => {
                 (match self.blend_src_factor {
-                    BlendSrcFactor::Zero => REG_BLEND_SETTINGS_SRC_FACTOR_ZERO,
-                    BlendSrcFactor::One => REG_BLEND_SETTINGS_SRC_FACTOR_ONE,
-                    BlendSrcFactor::SrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA,
-                    BlendSrcFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA,
+                    BlendFactor::Zero => REG_BLEND_SETTINGS_SRC_FACTOR_ZERO,
+                    BlendFactor::One => REG_BLEND_SETTINGS_SRC_FACTOR_ONE,
+                    BlendFactor::SrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_SRC_ALPHA,
+                    BlendFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_ALPHA,
+                    BlendFactor::SrcColor => REG_BLEND_SETTINGS_SRC_FACTOR_SRC_COLOR,
+                    BlendFactor::OneMinusSrcColor => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_SRC_COLOR,
+                    BlendFactor::DstColor => REG_BLEND_SETTINGS_SRC_FACTOR_DST_COLOR,
+                    BlendFactor::OneMinusDstColor => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_COLOR,
+                    BlendFactor::DstAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_DST_ALPHA,
+                    BlendFactor::OneMinusDstAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_DST_ALPHA,
+                    BlendFactor::ConstantColor => REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_COLOR,
+                    BlendFactor::OneMinusConstantColor => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_COLOR,
+                    BlendFactor::ConstantAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_CONSTANT_ALPHA,
+                    BlendFactor::OneMinusConstantAlpha => REG_BLEND_SETTINGS_SRC_FACTOR_ONE_MINUS_CONSTANT_ALPHA,
                 } << REG_BLEND_SETTINGS_SRC_FACTOR_BIT_OFFSET) |
                 (match self.blend_dst_factor {
-                    BlendDstFactor::Zero => REG_BLEND_SETTINGS_DST_FACTOR_ZERO,
-                    BlendDstFactor::One => REG_BLEND_SETTINGS_DST_FACTOR_ONE,
-                    BlendDstFactor::SrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA,
-                    BlendDstFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA,
-                } << REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET)
+                    BlendFactor::Zero => REG_BLEND_SETTINGS_DST_FACTOR_ZERO,
+                    BlendFactor::One => REG_BLEND_SETTINGS_DST_FACTOR_ONE,
+                    BlendFactor::SrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_SRC_ALPHA,
+                    BlendFactor::OneMinusSrcAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_ALPHA,
+                    BlendFactor::SrcColor => REG_BLEND_SETTINGS_DST_FACTOR_SRC_COLOR,
+                    BlendFactor::OneMinusSrcColor => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_SRC_COLOR,
+                    BlendFactor::DstColor => REG_BLEND_SETTINGS_DST_FACTOR_DST_COLOR,
+                    BlendFactor::OneMinusDstColor => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_COLOR,
+                    BlendFactor::DstAlpha => REG_BLEND_SETTINGS_DST_FACTOR_DST_ALPHA,
+                    BlendFactor::OneMinusDstAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_DST_ALPHA,
+                    BlendFactor::ConstantColor => REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_COLOR,
+                    BlendFactor::OneMinusConstantColor => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_COLOR,
+                    BlendFactor::ConstantAlpha => REG_BLEND_SETTINGS_DST_FACTOR_CONSTANT_ALPHA,
+                    BlendFactor::OneMinusConstantAlpha => REG_BLEND_SETTINGS_DST_FACTOR_ONE_MINUS_CONSTANT_ALPHA,
+                } << REG_BLEND_SETTINGS_DST_FACTOR_BIT_OFFSET) |
+                (match self.blend_op {
+                    BlendOp::Add => REG_BLEND_SETTINGS_OP_ADD,
+                    BlendOp::Subtract => REG_BLEND_SETTINGS_OP_SUBTRACT,
+                    BlendOp::ReverseSubtract => REG_BLEND_SETTINGS_OP_REVERSE_SUBTRACT,
+                    BlendOp::Min => REG_BLEND_SETTINGS_OP_MIN,
+                    BlendOp::Max => REG_BLEND_SETTINGS_OP_MAX,
+                } << REG_BLEND_SETTINGS_OP_BIT_OFFSET)
             }
             REG_W0_MIN_ADDR => self.w0_min,
             REG_W0_DX_ADDR => self.w0_dx,
@@ -454,39 +1370,67 @@ impl Device for ModelDevice {
             REG_T_MIN_ADDR => self.t_min,
             REG_T_DX_ADDR => self.t_dx,
             REG_T_DY_ADDR => self.t_dy,
+            REG_BLEND_CONSTANT_ADDR => self.blend_constant,
+            REG_SCISSOR_ADDR => self.scissor,
+            REG_TILE_CLEAR_COLOR_ADDR => self.tile_clear_color,
+            REG_TILE_CLEAR_DEPTH_ADDR => self.tile_clear_depth as _,
+            REG_TILE_CLEAR_STENCIL_ADDR => self.tile_clear_stencil as _,
+            REG_TEXTURE_BORDER_COLOR_ADDR => self.texture_border_color,
+            REG_TEXTURE_LOD_BIAS_ADDR => self.texture_lod_bias,
+            REG_TEXTURE_PALETTE_INDEX_ADDR => self.texture_palette_index,
+            REG_TEXTURE_PALETTE_DATA_ADDR => self.texture_palette[self.texture_palette_index as usize],
+            REG_FOG_COLOR_ADDR => self.fog_color,
+            REG_FOG_START_ADDR => self.fog_start,
+            REG_FOG_SCALE_ADDR => self.fog_scale,
+            REG_FOG_TABLE_INDEX_ADDR => self.fog_table_index,
+            REG_FOG_TABLE_DATA_ADDR => self.fog_table[self.fog_table_index as usize],
+            REG_SETUP_VERTEX_INDEX_ADDR => self.setup_vertex_select,
+            REG_SETUP_VERTEX_DATA_ADDR => self.setup_vertices.get(self.setup_vertex_select as usize).map_or(0, |vertex| vertex[self.setup_vertex_word as usize]),
             _ => panic!("Unrecognized addr: {}", addr)
         }
     }
 
     fn write_color_buffer_word(&mut self, addr: u32, data: u128) {
-        for i in 0..4 {
-            self.color_buffer[(addr * 4 + i) as usize] = (data >> (i * 32)) as _;
-        }
+        self.write_tile_buffer_word(false, COMMAND_TILE_BUFFER_COLOR, addr, data);
     }
 
     fn read_color_buffer_word(&mut self, addr: u32) -> u128 {
-        let mut ret = 0;
-        for i in 0..4 {
-            ret |= (self.color_buffer[(addr * 4 + i) as usize] as u128) << (i * 32);
-        }
-        ret
+        self.read_tile_buffer_word(false, COMMAND_TILE_BUFFER_COLOR, addr)
     }
 
     fn write_depth_buffer_word(&mut self, addr: u32, data: u128) {
-        for i in 0..8 {
-            self.depth_buffer[(addr * 8 + i) as usize] = (data >> (i * 16)) as _;
-        }
+        self.write_tile_buffer_word(false, COMMAND_TILE_BUFFER_DEPTH, addr, data);
     }
 
     fn read_depth_buffer_word(&mut self, addr: u32) -> u128 {
-        let mut ret = 0;
-        for i in 0..8 {
-            ret |= (self.depth_buffer[(addr * 8 + i) as usize] as u128) << (i * 16);
-        }
-        ret
+        self.read_tile_buffer_word(false, COMMAND_TILE_BUFFER_DEPTH, addr)
+    }
+
+    fn write_stencil_buffer_word(&mut self, addr: u32, data: u128) {
+        self.write_tile_buffer_word(false, COMMAND_TILE_BUFFER_STENCIL, addr, data);
+    }
+
+    fn read_stencil_buffer_word(&mut self, addr: u32) -> u128 {
+        self.read_tile_buffer_word(false, COMMAND_TILE_BUFFER_STENCIL, addr)
     }
 
     fn write_tex_buffer_word(&mut self, addr: u32, data: u128) {
         self.tex_buffer[addr as usize] = data;
     }
+
+    fn read_tex_buffer_word(&mut self, addr: u32) -> u128 {
+        self.tex_buffer[addr as usize]
+    }
+}
+
+// Approximates log2 of x in 5.TEX_LOD_FRACT_BITS fixed point, using the leading one's position for the whole part and the
+//  bits just below it (linearly) for the fractional part. Zero maps to zero.
+fn log2_approx(x: u32) -> u32 {
+    if x == 0 {
+        return 0;
+    }
+
+    let leading_zeros = x.leading_zeros();
+    let normalized_x = x << leading_zeros;
+    ((31 - leading_zeros) << TEX_LOD_FRACT_BITS) | ((normalized_x >> (31 - TEX_LOD_FRACT_BITS)) & ((1 << TEX_LOD_FRACT_BITS) - 1))
 }
@@ -11,6 +11,7 @@ impl SimDevice {
         color_thrust.reset();
         color_thrust.color_buffer_bus_enable = false;
         color_thrust.depth_buffer_bus_enable = false;
+        color_thrust.stencil_buffer_bus_enable = false;
         color_thrust.reg_bus_enable = false;
         color_thrust.mem_bus_enable = false;
         color_thrust.prop();
@@ -142,6 +143,47 @@ impl Device for SimDevice {
         self.color_thrust.depth_buffer_bus_read_data
     }
 
+    fn write_stencil_buffer_word(&mut self, addr: u32, data: u128) {
+        self.color_thrust.stencil_buffer_bus_addr = addr;
+        self.color_thrust.stencil_buffer_bus_enable = true;
+        self.color_thrust.stencil_buffer_bus_write = true;
+        self.color_thrust.stencil_buffer_bus_write_byte_enable = 0xffff;
+        self.color_thrust.stencil_buffer_bus_write_data = data;
+        self.color_thrust.prop();
+        loop {
+            let ready = self.color_thrust.stencil_buffer_bus_ready;
+            self.color_thrust.posedge_clk();
+            self.color_thrust.prop();
+            if ready {
+                break;
+            }
+        }
+        self.color_thrust.stencil_buffer_bus_enable = false;
+        self.color_thrust.prop();
+    }
+
+    fn read_stencil_buffer_word(&mut self, addr: u32) -> u128 {
+        self.color_thrust.stencil_buffer_bus_addr = addr;
+        self.color_thrust.stencil_buffer_bus_enable = true;
+        self.color_thrust.stencil_buffer_bus_write = false;
+        self.color_thrust.prop();
+        loop {
+            let ready = self.color_thrust.stencil_buffer_bus_ready;
+            self.color_thrust.posedge_clk();
+            self.color_thrust.prop();
+            if ready {
+                break;
+            }
+        }
+        self.color_thrust.stencil_buffer_bus_enable = false;
+        self.color_thrust.prop();
+        while !self.color_thrust.stencil_buffer_bus_read_data_valid {
+            self.color_thrust.posedge_clk();
+            self.color_thrust.prop();
+        }
+        self.color_thrust.stencil_buffer_bus_read_data
+    }
+
     fn write_tex_buffer_word(&mut self, addr: u32, data: u128) {
         // TODO: Not sure it makes sense to have this as part of the `Device` trait anymore.
         //  On one hand, it's nice that a whole "system" is present so we can bootstrap a working renderer.
@@ -164,4 +206,26 @@ impl Device for SimDevice {
         self.color_thrust.mem_bus_enable = false;
         self.color_thrust.prop();
     }
+
+    fn read_tex_buffer_word(&mut self, addr: u32) -> u128 {
+        self.color_thrust.mem_bus_addr = addr;
+        self.color_thrust.mem_bus_enable = true;
+        self.color_thrust.mem_bus_write = false;
+        self.color_thrust.prop();
+        loop {
+            let ready = self.color_thrust.mem_bus_ready;
+            self.color_thrust.posedge_clk();
+            self.color_thrust.prop();
+            if ready {
+                break;
+            }
+        }
+        self.color_thrust.mem_bus_enable = false;
+        self.color_thrust.prop();
+        while !self.color_thrust.mem_bus_read_data_valid {
+            self.color_thrust.posedge_clk();
+            self.color_thrust.prop();
+        }
+        self.color_thrust.mem_bus_read_data
+    }
 }
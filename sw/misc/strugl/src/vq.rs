@@ -0,0 +1,62 @@
+// Vector quantization of 2x2 ARGB8888 texel blocks, using k-means (LBG) to build the codebook
+
+const ITERATIONS: usize = 8;
+
+pub type Block = [u32; 4];
+
+fn distance(a: &Block, b: &Block) -> u32 {
+    a.iter().zip(b.iter()).map(|(&a, &b)| {
+        [0, 8, 16, 24].iter().map(|shift| {
+            let d = ((a >> shift) & 0xff) as i32 - ((b >> shift) & 0xff) as i32;
+            (d * d) as u32
+        }).sum::<u32>()
+    }).sum()
+}
+
+fn closest(block: &Block, codebook: &[Block]) -> usize {
+    codebook.iter().enumerate().min_by_key(|(_, entry)| distance(block, entry)).unwrap().0
+}
+
+// Returns the codebook and each block's codebook index
+pub fn encode(blocks: &[Block], num_entries: usize) -> (Vec<Block>, Vec<u8>) {
+    // Seed the codebook with blocks spread evenly across the input
+    let mut codebook = (0..num_entries).map(|i| blocks[i * blocks.len() / num_entries]).collect::<Vec<_>>();
+    let mut indices = vec![0; blocks.len()];
+
+    for _ in 0..ITERATIONS {
+        for (block, index) in blocks.iter().zip(indices.iter_mut()) {
+            *index = closest(block, &codebook);
+        }
+
+        // Move each entry to the mean of its blocks, leaving unused entries where they are
+        let mut sums = vec![([[0u32; 4]; 4], 0u32); num_entries];
+        for (block, &index) in blocks.iter().zip(indices.iter()) {
+            let (sum, count) = &mut sums[index];
+            for (texel_sum, &texel) in sum.iter_mut().zip(block.iter()) {
+                for (comp_sum, shift) in texel_sum.iter_mut().zip([0, 8, 16, 24].iter()) {
+                    *comp_sum += (texel >> shift) & 0xff;
+                }
+            }
+            *count += 1;
+        }
+        for (entry, (sum, count)) in codebook.iter_mut().zip(sums.iter()) {
+            if *count == 0 {
+                continue;
+            }
+            for (texel, texel_sum) in entry.iter_mut().zip(sum.iter()) {
+                *texel = [0, 8, 16, 24].iter().zip(texel_sum.iter()).fold(0, |acc, (shift, comp_sum)| acc | (((comp_sum + count / 2) / count) << shift));
+            }
+        }
+    }
+
+    let indices = blocks.iter().map(|block| closest(block, &codebook) as u8).collect();
+
+    (codebook, indices)
+}
+
+// Peak signal-to-noise ratio of the encoded blocks in dB, across all components
+pub fn psnr(blocks: &[Block], codebook: &[Block], indices: &[u8]) -> f64 {
+    let squared_error = blocks.iter().zip(indices.iter()).map(|(block, &index)| distance(block, &codebook[index as usize]) as f64).sum::<f64>();
+    let mse = squared_error / (blocks.len() * 4 * 4) as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
@@ -23,6 +23,32 @@ fn generate_top<'a>(c: &'a Context<'a>) -> &Module<'a> {
     let xenowing = m.instance("xenowing", "Xenowing");
 
     m.output("leds", xenowing.output("leds"));
+    m.output("display_digits", xenowing.output("display_digits"));
+    m.output("display_segments", xenowing.output("display_segments"));
+
+    xenowing.drive_input("buttons", m.input("buttons", input_interface::NUM_BUTTONS));
+    xenowing.drive_input("switches", m.input("switches", input_interface::NUM_SWITCHES));
+    xenowing.drive_input("pad_data", m.input("pad_data", 1));
+    m.output("pad_latch", xenowing.output("pad_latch"));
+    m.output("pad_clock", xenowing.output("pad_clock"));
+
+    m.output("spi_flash_cs_n", xenowing.output("spi_flash_cs_n"));
+    m.output("spi_flash_sck", xenowing.output("spi_flash_sck"));
+    m.output("spi_flash_mosi", xenowing.output("spi_flash_mosi"));
+    xenowing.drive_input("spi_flash_miso", m.input("spi_flash_miso", 1));
+
+    m.output("sd_cs_n", xenowing.output("sd_cs_n"));
+    m.output("sd_sck", xenowing.output("sd_sck"));
+    m.output("sd_mosi", xenowing.output("sd_mosi"));
+    xenowing.drive_input("sd_miso", m.input("sd_miso", 1));
+
+    xenowing.drive_input("eth_tx_strobe", m.input("eth_tx_strobe", 1));
+    m.output("eth_tx_en", xenowing.output("eth_tx_en"));
+    m.output("eth_tx_data", xenowing.output("eth_tx_data"));
+    xenowing.drive_input("eth_rx_strobe", m.input("eth_rx_strobe", 1));
+    xenowing.drive_input("eth_rx_dv", m.input("eth_rx_dv", 1));
+    xenowing.drive_input("eth_rx_er", m.input("eth_rx_er", 1));
+    xenowing.drive_input("eth_rx_data", m.input("eth_rx_data", 8));
 
     let uart_rx = m.instance("uart_rx", "UartRx");
     uart_rx.drive_input("rx", xenowing.output("tx"));
@@ -0,0 +1,220 @@
+use crate::host_link::{DEVICE_IP, PORT};
+
+use std::collections::VecDeque;
+
+const HOST_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+const HOST_IP: [u8; 4] = [192, 168, 1, 1];
+const HOST_PORT: u16 = 50000;
+
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+
+const PREAMBLE_SIZE: usize = 7;
+const SFD: u8 = 0xd5;
+// Excluding FCS
+const MIN_FRAME_SIZE: usize = 60;
+const IFG_SIZE: usize = 12;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn ip_checksum(header: &[u8]) -> u16 {
+    let mut sum = header.chunks(2).map(|word| ((word[0] as u32) << 8) | word[1] as u32).sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !sum as u16
+}
+
+fn get_u16(buf: &[u8], offset: usize) -> u16 {
+    ((buf[offset] as u16) << 8) | buf[offset + 1] as u16
+}
+
+// In-process stand-in for the network on the other end of the sim's Ethernet pins. It plays the part of a host
+//  (including resolving the device's MAC address with ARP) so that host link datagrams can be exchanged with the
+//  sim without any real network interfaces.
+pub struct EthernetHost {
+    device_mac: Option<[u8; 6]>,
+    has_sent_arp_request: bool,
+    // Datagrams waiting on ARP resolution
+    pending_datagrams: VecDeque<Vec<u8>>,
+
+    // Bytes to present on the device's RX pins, None for idle cycles
+    rx_wire: VecDeque<Option<u8>>,
+
+    tx_wire: Vec<u8>,
+}
+
+impl EthernetHost {
+    pub fn new() -> EthernetHost {
+        EthernetHost {
+            device_mac: None,
+            has_sent_arp_request: false,
+            pending_datagrams: VecDeque::new(),
+
+            rx_wire: VecDeque::new(),
+
+            tx_wire: Vec::new(),
+        }
+    }
+
+    pub fn send_datagram(&mut self, payload: Vec<u8>) {
+        let device_mac = match self.device_mac {
+            Some(device_mac) => device_mac,
+            _ => {
+                if !self.has_sent_arp_request {
+                    self.send_arp_request();
+                    self.has_sent_arp_request = true;
+                }
+                self.pending_datagrams.push_back(payload);
+                return;
+            }
+        };
+
+        let udp_length = 8 + payload.len();
+        let ip_length = 20 + udp_length;
+
+        let mut ip_header = vec![
+            0x45, 0x00, (ip_length >> 8) as u8, ip_length as u8,
+            0x00, 0x00, 0x40, 0x00,
+            64, IP_PROTOCOL_UDP, 0x00, 0x00,
+        ];
+        ip_header.extend(&HOST_IP);
+        ip_header.extend(&DEVICE_IP);
+        let checksum = ip_checksum(&ip_header);
+        ip_header[10] = (checksum >> 8) as u8;
+        ip_header[11] = checksum as u8;
+
+        let mut packet = ip_header;
+        packet.extend(&HOST_PORT.to_be_bytes());
+        packet.extend(&PORT.to_be_bytes());
+        packet.extend(&(udp_length as u16).to_be_bytes());
+        packet.extend(&[0x00, 0x00]);
+        packet.extend(payload);
+
+        self.send_frame(device_mac, ETHERTYPE_IPV4, &packet);
+    }
+
+    fn send_arp_request(&mut self) {
+        let mut packet = vec![
+            0x00, 0x01, // Ethernet
+            0x08, 0x00, // IPv4
+            6, 4,
+            0x00, 0x01, // Request
+        ];
+        packet.extend(&HOST_MAC);
+        packet.extend(&HOST_IP);
+        packet.extend(&[0; 6]);
+        packet.extend(&DEVICE_IP);
+
+        self.send_frame(BROADCAST_MAC, ETHERTYPE_ARP, &packet);
+    }
+
+    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, packet: &[u8]) {
+        let mut frame = Vec::new();
+        frame.extend(&dst);
+        frame.extend(&HOST_MAC);
+        frame.extend(&ethertype.to_be_bytes());
+        frame.extend(packet);
+        if frame.len() < MIN_FRAME_SIZE {
+            frame.resize(MIN_FRAME_SIZE, 0);
+        }
+        let fcs = crc32(&frame);
+        frame.extend(&fcs.to_le_bytes());
+
+        self.rx_wire.extend((0..PREAMBLE_SIZE).map(|_| Some(0x55)));
+        self.rx_wire.push_back(Some(SFD));
+        self.rx_wire.extend(frame.into_iter().map(Some));
+        self.rx_wire.extend((0..IFG_SIZE).map(|_| None));
+    }
+
+    // Returns the (rx_dv, rx_data) pins for the next cycle
+    pub fn next_rx_byte(&mut self) -> (bool, u8) {
+        match self.rx_wire.pop_front() {
+            Some(Some(data)) => (true, data),
+            _ => (false, 0),
+        }
+    }
+
+    // Samples the device's TX pins each cycle, returning the payload of any host link datagram that's been received
+    pub fn update_tx(&mut self, tx_en: bool, tx_data: u8) -> Option<Vec<u8>> {
+        if tx_en {
+            self.tx_wire.push(tx_data);
+            return None;
+        }
+
+        if self.tx_wire.is_empty() {
+            return None;
+        }
+
+        let wire = std::mem::replace(&mut self.tx_wire, Vec::new());
+        self.receive_frame(&wire)
+    }
+
+    fn receive_frame(&mut self, wire: &[u8]) -> Option<Vec<u8>> {
+        let preamble_size = PREAMBLE_SIZE + 1;
+        if wire.len() < preamble_size + MIN_FRAME_SIZE + 4 || wire[..PREAMBLE_SIZE].iter().any(|&x| x != 0x55) || wire[PREAMBLE_SIZE] != SFD {
+            println!("Ethernet: dropping frame with bad preamble/length");
+            return None;
+        }
+        let (frame, fcs) = wire[preamble_size..].split_at(wire.len() - preamble_size - 4);
+        if crc32(frame).to_le_bytes() != fcs {
+            println!("Ethernet: dropping frame with bad FCS");
+            return None;
+        }
+
+        if frame[..6] != HOST_MAC && frame[..6] != BROADCAST_MAC {
+            return None;
+        }
+
+        let packet = &frame[14..];
+        match get_u16(frame, 12) {
+            ETHERTYPE_ARP => {
+                // Reply from the device
+                if get_u16(packet, 6) == 2 && packet[14..18] == DEVICE_IP && packet[24..28] == HOST_IP {
+                    let mut device_mac = [0; 6];
+                    device_mac.copy_from_slice(&packet[8..14]);
+                    self.device_mac = Some(device_mac);
+                    while let Some(payload) = self.pending_datagrams.pop_front() {
+                        self.send_datagram(payload);
+                    }
+                }
+                None
+            }
+            ETHERTYPE_IPV4 => {
+                let header_size = (packet[0] & 0x0f) as usize * 4;
+                if packet[0] >> 4 != 4 || header_size < 20 || header_size > packet.len() || ip_checksum(&packet[..header_size]) != 0 {
+                    println!("Ethernet: dropping packet with bad IP header");
+                    return None;
+                }
+                if packet[9] != IP_PROTOCOL_UDP || packet[12..16] != DEVICE_IP || packet[16..20] != HOST_IP {
+                    return None;
+                }
+                let ip_length = get_u16(packet, 2) as usize;
+                if ip_length < header_size + 8 || ip_length > packet.len() {
+                    println!("Ethernet: dropping packet with bad IP length");
+                    return None;
+                }
+                let udp = &packet[header_size..ip_length];
+                if get_u16(udp, 0) != PORT || get_u16(udp, 2) != HOST_PORT || get_u16(udp, 4) as usize != udp.len() {
+                    println!("Ethernet: dropping datagram with bad UDP header");
+                    return None;
+                }
+                Some(udp[8..].to_vec())
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+// Must match the firmware (see xw/net.h)
+pub const PORT: u16 = 0x5857;
+pub const DEVICE_IP: [u8; 4] = [192, 168, 1, 50];
+pub const MAX_PAYLOAD: usize = 1024;
+
+const MAX_RETRIES: u32 = 10;
+
+pub trait DatagramLink {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()>;
+    // Returns None if no datagram arrives within the timeout
+    fn recv_timeout(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
+
+pub struct UdpLink {
+    socket: UdpSocket,
+}
+
+impl UdpLink {
+    pub fn new(addr: SocketAddr) -> io::Result<UdpLink> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+
+        Ok(UdpLink {
+            socket,
+        })
+    }
+}
+
+impl DatagramLink for UdpLink {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.socket.send(datagram)?;
+
+        Ok(())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        // A zero timeout means "block forever" to the socket
+        self.socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+
+        let mut buf = [0; 2048];
+        match self.socket.recv(&mut buf) {
+            Ok(len) => Ok(Some(buf[..len].to_vec())),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Host side of the UDP host link: a byte stream carried over datagrams. Each datagram starts with a one-byte
+//  header; ours carry a sequence number and the device's carry the sequence number of the last of our datagrams
+//  it's fully consumed. We send one datagram at a time and retransmit it until it's acked. Device datagrams
+//  aren't retransmitted, so a lost response is an error, which is fine for a point-to-point link.
+pub struct HostLink<L: DatagramLink> {
+    link: L,
+    ack_timeout: Duration,
+
+    seq: u8,
+    pending_writes: Vec<u8>,
+    pending_reads: VecDeque<u8>,
+}
+
+impl<L: DatagramLink> HostLink<L> {
+    pub fn new(link: L, ack_timeout: Duration) -> HostLink<L> {
+        HostLink {
+            link,
+            ack_timeout,
+
+            seq: 0,
+            pending_writes: Vec::new(),
+            pending_reads: VecDeque::new(),
+        }
+    }
+
+    // Returns the datagram's ack
+    fn receive(&mut self, datagram: Vec<u8>) -> Option<u8> {
+        let (&ack, data) = datagram.split_first()?;
+        self.pending_reads.extend(data);
+        Some(ack)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.pending_writes.is_empty() {
+            return Ok(());
+        }
+
+        let mut datagram = vec![self.seq];
+        datagram.extend(&self.pending_writes);
+
+        for _ in 0..MAX_RETRIES {
+            self.link.send(&datagram)?;
+
+            let deadline = Instant::now() + self.ack_timeout;
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+
+                if let Some(response) = self.link.recv_timeout(deadline - now)? {
+                    if self.receive(response) == Some(self.seq) {
+                        self.seq = self.seq.wrapping_add(1);
+                        self.pending_writes.clear();
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Device didn't acknowledge datagram"))
+    }
+
+    pub fn try_read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.flush()?;
+
+        if self.pending_reads.is_empty() {
+            if let Some(datagram) = self.link.recv_timeout(Duration::from_millis(1))? {
+                self.receive(datagram);
+            }
+        }
+
+        Ok(self.pending_reads.pop_front())
+    }
+
+    pub fn read_byte(&mut self) -> io::Result<u8> {
+        loop {
+            if let Some(value) = self.try_read_byte()? {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn write_byte(&mut self, value: u8) -> io::Result<()> {
+        self.pending_writes.push(value);
+        if self.pending_writes.len() == MAX_PAYLOAD {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+}
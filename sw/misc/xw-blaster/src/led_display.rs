@@ -0,0 +1,130 @@
+use rtl::led_interface::{self, NUM_DIGITS, NUM_LEDS, PWM_STEPS};
+use rtl::xenowing;
+
+// One full display refresh, which is also a whole number of LED PWM periods
+const FRAME_CYCLES: u32 = led_interface::clocks_per_pwm_step(xenowing::CLOCK_FREQ) * PWM_STEPS * NUM_DIGITS;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Digit {
+    segments: u8,
+    // 0-255, same scale as the brightness regs
+    brightness: u32,
+}
+
+#[derive(PartialEq)]
+struct State {
+    leds: [u32; NUM_LEDS as usize],
+    digits: [Digit; NUM_DIGITS as usize],
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            leds: [0; NUM_LEDS as usize],
+            digits: [Digit { segments: 0, brightness: 0 }; NUM_DIGITS as usize],
+        }
+    }
+}
+
+// Integrates the LED/seven-segment pins over each display refresh so that PWM and multiplexing can be shown as
+//  steady brightness levels, and prints the state whenever it changes
+pub struct LedDisplay {
+    cycle: u32,
+    led_on_cycles: [u32; NUM_LEDS as usize],
+    digit_on_cycles: [u32; NUM_DIGITS as usize],
+    digit_segments: [u8; NUM_DIGITS as usize],
+
+    state: State,
+}
+
+impl LedDisplay {
+    pub fn new() -> LedDisplay {
+        LedDisplay {
+            cycle: 0,
+            led_on_cycles: [0; NUM_LEDS as usize],
+            digit_on_cycles: [0; NUM_DIGITS as usize],
+            digit_segments: [0; NUM_DIGITS as usize],
+
+            state: State::new(),
+        }
+    }
+
+    pub fn update(&mut self, leds: u32, display_digits: u32, display_segments: u32) {
+        for i in 0..NUM_LEDS as usize {
+            if (leds >> i) & 1 != 0 {
+                self.led_on_cycles[i] += 1;
+            }
+        }
+        for i in 0..NUM_DIGITS as usize {
+            if (display_digits >> i) & 1 != 0 {
+                self.digit_on_cycles[i] += 1;
+                self.digit_segments[i] |= display_segments as u8;
+            }
+        }
+
+        self.cycle += 1;
+        if self.cycle < FRAME_CYCLES {
+            return;
+        }
+
+        // Brightness is rounded to the nearest PWM step
+        let brightness = |on_cycles: u32, total_cycles: u32| ((on_cycles as u64 * PWM_STEPS as u64 + total_cycles as u64 / 2) / total_cycles as u64) as u32;
+        let mut state = State::new();
+        for i in 0..NUM_LEDS as usize {
+            state.leds[i] = brightness(self.led_on_cycles[i], FRAME_CYCLES);
+        }
+        for i in 0..NUM_DIGITS as usize {
+            let brightness = brightness(self.digit_on_cycles[i], FRAME_CYCLES / NUM_DIGITS);
+            state.digits[i] = Digit {
+                segments: if brightness > 0 { self.digit_segments[i] } else { 0 },
+                brightness,
+            };
+        }
+
+        if state.leds != self.state.leds {
+            print!("LEDs updated:");
+            for &led in state.leds.iter().rev() {
+                print!(" {:3}", led);
+            }
+            println!();
+        }
+        if state.digits != self.state.digits {
+            println!("Display updated:");
+            print_digits(&state.digits);
+        }
+        self.state = state;
+
+        self.cycle = 0;
+        self.led_on_cycles = [0; NUM_LEDS as usize];
+        self.digit_on_cycles = [0; NUM_DIGITS as usize];
+        self.digit_segments = [0; NUM_DIGITS as usize];
+    }
+}
+
+// Renders digits as three rows of ASCII art, most significant (leftmost) digit first, followed by each digit's
+//  brightness
+fn print_digits(digits: &[Digit]) {
+    let segment = |digit: &Digit, index: u32, c: char| if (digit.segments >> index) & 1 != 0 { c } else { ' ' };
+    let mut rows = [String::new(), String::new(), String::new()];
+    for digit in digits.iter().rev() {
+        rows[0].push(' ');
+        rows[0].push(segment(digit, 0, '_'));
+        rows[0].push_str("  ");
+        rows[1].push(segment(digit, 5, '|'));
+        rows[1].push(segment(digit, 6, '_'));
+        rows[1].push(segment(digit, 1, '|'));
+        rows[1].push(' ');
+        rows[2].push(segment(digit, 4, '|'));
+        rows[2].push(segment(digit, 3, '_'));
+        rows[2].push(segment(digit, 2, '|'));
+        rows[2].push(segment(digit, 7, '.'));
+    }
+    for row in rows.iter() {
+        println!("  {}", row);
+    }
+    print!("  brightness:");
+    for digit in digits.iter().rev() {
+        print!(" {:3}", digit.brightness);
+    }
+    println!();
+}
@@ -3,15 +3,26 @@
 mod modules {
     include!(concat!(env!("OUT_DIR"), "/modules.rs"));
 }
+mod ethernet_host;
+mod host_link;
+mod led_display;
+mod sd_card;
+mod spi_flash;
+mod spi_slave;
 mod vec2;
 mod vec4;
 
+use ethernet_host::*;
+use host_link::*;
+use led_display::*;
 use modules::*;
+use sd_card::*;
+use spi_flash::*;
 use vec2::*;
 use vec4::*;
 
 use image::GenericImageView;
-use minifb::{Scale, ScaleMode, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
 use rtl::color_thrust::*;
 use serialport::prelude::*;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
@@ -19,14 +30,37 @@ use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
 use std::str;
-use std::sync::mpsc::{self, channel, Receiver, Sender};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, channel, Receiver, RecvTimeoutError, Sender};
 use std::thread;
+use std::time::{Duration, Instant};
 
 const WIDTH: usize = 16 * 8;//320;
 const HEIGHT: usize = 16 * 8;//240;
 const PIXELS: usize = WIDTH * HEIGHT;
 
+// Byte addresses from doc/mem_map.txt
+const COLOR_THRUST_REGS_BASE: u32 = 0x04000000;
+const RAM_BASE: u32 = 0x10000000;
+const BUS_WORD_BYTES: u32 = 16;
+
+// Word address in RAM, clear of the program's data and stack
+const COMMAND_LIST_ADDR: u32 = 0x1000;
+
+const SIM_FLASH_IMAGE: &str = "flash.bin";
+const SIM_SD_IMAGE: &str = "sd.img";
+
+// The sim's Ethernet never drops frames, but the device can take a long (wall-clock) time to get around to
+//  acking, especially while it's still booting
+const SIM_ACK_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const UDP_ACK_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Host input (window events, buttons/switches/pad) is polled at least this often, regardless of command traffic
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Clone, Copy)]
 struct Vertex {
     position: Vec2,
@@ -73,10 +107,71 @@ impl From<serialport::Error> for Error {
     }
 }
 
+#[derive(Clone, Copy, Default)]
+struct InputState {
+    buttons: u8,
+    switches: u8,
+    pad: u16,
+}
+
+impl InputState {
+    fn from_window(window: &Window, switches: u8) -> InputState {
+        let mut ret = InputState {
+            switches,
+            ..InputState::default()
+        };
+
+        for (i, key) in [Key::Key1, Key::Key2, Key::Key3, Key::Key4].iter().enumerate() {
+            if window.is_key_down(*key) {
+                ret.buttons |= 1 << i;
+            }
+        }
+
+        // SNES pad bits in shift order
+        for (i, key) in [
+            Key::Z, // B
+            Key::A, // Y
+            Key::RightShift, // Select
+            Key::Enter, // Start
+            Key::Up,
+            Key::Down,
+            Key::Left,
+            Key::Right,
+            Key::X, // A
+            Key::S, // X
+            Key::Q, // L
+            Key::W, // R
+        ].iter().enumerate() {
+            if window.is_key_down(*key) {
+                ret.pad |= 1 << i;
+            }
+        }
+
+        ret
+    }
+
+    fn to_bits(&self) -> u32 {
+        (self.pad as u32) | ((self.buttons as u32) << 16) | ((self.switches as u32) << 24)
+    }
+
+    fn from_bits(bits: u32) -> InputState {
+        InputState {
+            buttons: (bits >> 16) as _,
+            switches: (bits >> 24) as _,
+            pad: bits as _,
+        }
+    }
+}
+
 trait Device {
     fn read_byte(&mut self) -> Result<u8, Error>;
+    // Returns None if no byte is available within a short timeout
+    fn try_read_byte(&mut self) -> Result<Option<u8>, Error>;
     fn write_byte(&mut self, value: u8) -> Result<(), Error>;
 
+    // Only meaningful for devices with simulated input pins; real hardware reads its own buttons/pad
+    fn set_input_state(&mut self, _state: InputState) {}
+
     fn read_u32(&mut self) -> Result<u32, Error> {
         let mut ret = 0x00;
         ret |= (self.read_byte()? as u32) << 0;
@@ -97,19 +192,53 @@ trait Device {
     }
 }
 
+// Datagrams to/from the sim's in-process Ethernet host
+struct SimLink {
+    datagram_tx: Sender<Vec<u8>>,
+    datagram_rx: Receiver<Vec<u8>>,
+}
+
+impl DatagramLink for SimLink {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.datagram_tx.send(datagram.to_vec()).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Sim thread exited"))
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        match self.datagram_rx.recv_timeout(timeout) {
+            Ok(datagram) => Ok(Some(datagram)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "Sim thread exited")),
+        }
+    }
+}
+
 struct SimDevice {
     host_command_rx: Receiver<u8>,
     host_response_tx: Sender<u8>,
+    input_state: Arc<AtomicU32>,
+    // Used instead of the UART when talking to the sim over its Ethernet pins
+    host_link: Option<HostLink<SimLink>>,
 }
 
 impl SimDevice {
-    fn new() -> SimDevice {
+    fn new(use_host_link: bool) -> Result<SimDevice, Error> {
+        let mut spi_flash = SpiFlash::new(SIM_FLASH_IMAGE)?;
+        let mut sd_card = SdCard::new(SIM_SD_IMAGE)?;
+
         let (host_command_tx, host_command_rx) = channel();
         let (host_response_tx, host_response_rx) = channel();
+        let input_state = Arc::new(AtomicU32::new(0));
+        let (host_datagram_tx, host_datagram_rx) = channel::<Vec<u8>>();
+        let (device_datagram_tx, device_datagram_rx) = channel();
 
+        let thread_input_state = input_state.clone();
         // TODO: This is leaky, but I guess it doesn't matter :)
         thread::spawn(move|| {
-            let mut leds = 0b000;
+            let mut led_display = LedDisplay::new();
+            let mut ethernet_host = EthernetHost::new();
+
+            let mut pad_shift = 0;
+            let mut prev_pad_clock = false;
 
             let mut is_sending_byte = false;
 
@@ -124,11 +253,7 @@ impl SimDevice {
                 } else {
                     top.posedge_clk();
 
-                    let new_leds = top.leds;
-                    if new_leds != leds {
-                        println!("LEDs updated: 0b{:08b} -> 0b{:08b}", leds, new_leds);
-                        leds = new_leds;
-                    }
+                    led_display.update(top.leds, top.display_digits, top.display_segments);
 
                     if top.uart_tx_data_valid {
                         host_command_tx.send(top.uart_tx_data as _).unwrap();
@@ -146,29 +271,103 @@ impl SimDevice {
                             top.uart_rx_data = value as u32;
                         }
                     }
+
+                    if let Ok(payload) = host_datagram_rx.try_recv() {
+                        ethernet_host.send_datagram(payload);
+                    }
+                }
+
+                // The sim PHY moves one byte per cycle in each direction
+                let (eth_rx_dv, eth_rx_data) = ethernet_host.next_rx_byte();
+                top.eth_rx_strobe = true;
+                top.eth_rx_dv = eth_rx_dv;
+                top.eth_rx_er = false;
+                top.eth_rx_data = eth_rx_data as _;
+                top.eth_tx_strobe = true;
+
+                let input_state = InputState::from_bits(thread_input_state.load(Ordering::Relaxed));
+                top.buttons = input_state.buttons as _;
+                top.switches = input_state.switches as _;
+
+                // Behavioral NES/SNES-style pad: parallel-loads while latch is high, shifts on clock rising edges, active-low data
+                if top.pad_latch {
+                    pad_shift = input_state.pad;
+                }
+                if top.pad_clock && !prev_pad_clock {
+                    pad_shift >>= 1;
                 }
+                prev_pad_clock = top.pad_clock;
+                top.pad_data = (pad_shift & 1) == 0;
 
                 top.prop();
+
+                // SPI devices respond to pin changes immediately, so re-propagate if their outputs changed
+                let spi_flash_miso = spi_flash.update(top.spi_flash_cs_n, top.spi_flash_sck, top.spi_flash_mosi);
+                let sd_miso = sd_card.update(top.sd_cs_n, top.sd_sck, top.sd_mosi);
+                if spi_flash_miso != top.spi_flash_miso || sd_miso != top.sd_miso {
+                    top.spi_flash_miso = spi_flash_miso;
+                    top.sd_miso = sd_miso;
+                    top.prop();
+                }
+
+                if let Some(payload) = ethernet_host.update_tx(top.eth_tx_en, top.eth_tx_data as _) {
+                    device_datagram_tx.send(payload).unwrap();
+                }
             }
         });
 
-        SimDevice {
+        let host_link = if use_host_link {
+            Some(HostLink::new(SimLink {
+                datagram_tx: host_datagram_tx,
+                datagram_rx: device_datagram_rx,
+            }, SIM_ACK_TIMEOUT))
+        } else {
+            None
+        };
+
+        Ok(SimDevice {
             host_command_rx,
             host_response_tx,
-        }
+            input_state,
+            host_link,
+        })
     }
 }
 
 impl Device for SimDevice {
     fn read_byte(&mut self) -> Result<u8, Error> {
+        if let Some(host_link) = &mut self.host_link {
+            return Ok(host_link.read_byte()?);
+        }
+
         Ok(self.host_command_rx.recv()?)
     }
 
+    fn try_read_byte(&mut self) -> Result<Option<u8>, Error> {
+        if let Some(host_link) = &mut self.host_link {
+            return Ok(host_link.try_read_byte()?);
+        }
+
+        match self.host_command_rx.recv_timeout(Duration::from_millis(1)) {
+            Ok(value) => Ok(Some(value)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(mpsc::RecvError.into()),
+        }
+    }
+
     fn write_byte(&mut self, value: u8) -> Result<(), Error> {
+        if let Some(host_link) = &mut self.host_link {
+            return Ok(host_link.write_byte(value)?);
+        }
+
         self.host_response_tx.send(value)?;
 
         Ok(())
     }
+
+    fn set_input_state(&mut self, state: InputState) {
+        self.input_state.store(state.to_bits(), Ordering::Relaxed);
+    }
 }
 
 struct SerialDevice {
@@ -196,22 +395,22 @@ impl SerialDevice {
 
 impl Device for SerialDevice {
     fn read_byte(&mut self) -> Result<u8, Error> {
-        let mut buf = [0];
         loop {
-            match self.port.read(&mut buf) {
-                Ok(t) => {
-                    if t > 0 {
-                        return Ok(buf[0]);
-                    }
-                }
-                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
-                Err(e) => {
-                    return Err(e.into());
-                }
+            if let Some(value) = self.try_read_byte()? {
+                return Ok(value);
             }
         }
     }
 
+    fn try_read_byte(&mut self) -> Result<Option<u8>, Error> {
+        let mut buf = [0];
+        match self.port.read(&mut buf) {
+            Ok(t) => Ok(if t > 0 { Some(buf[0]) } else { None }),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     fn write_byte(&mut self, value: u8) -> Result<(), Error> {
         self.port.write_all(&[value])?;
 
@@ -219,14 +418,73 @@ impl Device for SerialDevice {
     }
 }
 
+struct UdpDevice {
+    host_link: HostLink<UdpLink>,
+}
+
+impl UdpDevice {
+    fn new(addr: SocketAddr) -> Result<UdpDevice, Error> {
+        Ok(UdpDevice {
+            host_link: HostLink::new(UdpLink::new(addr)?, UDP_ACK_TIMEOUT),
+        })
+    }
+}
+
+impl Device for UdpDevice {
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.host_link.read_byte()?)
+    }
+
+    fn try_read_byte(&mut self) -> Result<Option<u8>, Error> {
+        Ok(self.host_link.try_read_byte()?)
+    }
+
+    fn write_byte(&mut self, value: u8) -> Result<(), Error> {
+        Ok(self.host_link.write_byte(value)?)
+    }
+}
+
 fn main() -> Result<(), Error> {
-    let mut device: Box<dyn Device> = if let Some(port_name) = env::args().nth(1) {
-        println!("Creating serial device on port {}", port_name);
-        Box::new(SerialDevice::new(port_name)?)
-    } else {
-        println!("Creating sim device");
-        Box::new(SimDevice::new())
+    if env::args().nth(1).as_deref() == Some("make-flash-image") {
+        let program_path = env::args().nth(2).ok_or("Usage: xw-blaster make-flash-image <program.bin> <flash.bin>".to_string())?;
+        let flash_path = env::args().nth(3).unwrap_or(SIM_FLASH_IMAGE.into());
+        write_program_image(&program_path, &flash_path)?;
+        println!("Wrote {} to {} at offset 0x{:08x}", program_path, flash_path, PROGRAM_OFFSET);
+        return Ok(());
+    }
+
+    let mut is_host_link = true;
+    let mut device: Box<dyn Device> = match env::args().nth(1).as_deref() {
+        Some("udp") => {
+            let ip = match env::args().nth(2) {
+                Some(ip) => ip.parse::<IpAddr>().map_err(|e| format!("Invalid device IP address: {}", e))?,
+                _ => IpAddr::from(DEVICE_IP),
+            };
+            let addr = SocketAddr::new(ip, PORT);
+            println!("Creating UDP device at {}", addr);
+            Box::new(UdpDevice::new(addr)?)
+        }
+        Some("sim-udp") => {
+            println!("Creating sim device with UDP host link");
+            Box::new(SimDevice::new(true)?)
+        }
+        Some(port_name) => {
+            is_host_link = false;
+            println!("Creating serial device on port {}", port_name);
+            Box::new(SerialDevice::new(port_name.into())?)
+        }
+        _ => {
+            is_host_link = false;
+            println!("Creating sim device");
+            Box::new(SimDevice::new(false)?)
+        }
     };
+    if is_host_link {
+        // The device only learns where we are once we send it something, and it expects to boot from flash since
+        //  the host link isn't available to the boot ROM. End the (empty) frame it starts out waiting on so that it
+        //  requests the next one over the host link.
+        device.write_byte(0x05)?;
+    }
     println!();
 
     let mut back_buffer = vec![0xffff00ff; PIXELS];
@@ -243,8 +501,29 @@ fn main() -> Result<(), Error> {
     println!("ALL SYSTEMS ARE GO");
     println!();
 
+    let mut switches = 0;
+    let mut last_input_poll_time = None;
+
     loop {
-        match device.read_byte()? {
+        // Poll host input between commands as well as while waiting for the device, so that a device that's busy
+        //  sending commands doesn't starve it
+        if last_input_poll_time.is_none_or(|time: Instant| time.elapsed() >= INPUT_POLL_INTERVAL) {
+            last_input_poll_time = Some(Instant::now());
+            window.update();
+            for (i, key) in [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8].iter().enumerate() {
+                if window.is_key_pressed(*key, KeyRepeat::No) {
+                    switches ^= 1 << i;
+                }
+            }
+            device.set_input_state(InputState::from_window(&window, switches));
+        }
+
+        let command_byte = match device.try_read_byte()? {
+            Some(command_byte) => command_byte,
+            _ => continue,
+        };
+
+        match command_byte {
             0x00 => {
                 // XW_UART_COMMAND_PUTC
                 print!("{}", device.read_byte()? as char);
@@ -290,7 +569,24 @@ fn main() -> Result<(), Error> {
                 }
 
                 fn write_reg(addr: u32, data: u32, device: &mut dyn Device) -> Result<(), Error> {
-                    write_word(0x04000000 + addr * 16, data, device)
+                    write_word(COLOR_THRUST_REGS_BASE + addr * BUS_WORD_BYTES, data, device)
+                }
+
+                fn command(opcode: u32, arg: u32, data: u32) -> u64 {
+                    (opcode as u64) | ((arg as u64) << COMMAND_ARG_BIT_OFFSET) | ((data as u64) << COMMAND_DATA_BIT_OFFSET)
+                }
+
+                fn run_command_list(addr: u32, commands: &[u64], device: &mut dyn Device) -> Result<(), Error> {
+                    // Commands are packed two per 128-bit word, starting with the least significant half
+                    for (i, &command) in commands.iter().enumerate() {
+                        let command_addr = RAM_BASE + addr * BUS_WORD_BYTES + i as u32 * 8;
+                        write_word(command_addr, command as _, device)?;
+                        write_word(command_addr + 4, (command >> 32) as _, device)?;
+                    }
+                    device.write_byte(0x06)?;
+                    device.write_u32(addr)?;
+
+                    Ok(())
                 }
 
                 // Upload texture
@@ -481,15 +777,17 @@ fn main() -> Result<(), Error> {
                                     }
                                 }
 
+                                let mut commands = Vec::new();
+
                                 let p = Vec2::new(tile_min_x as f32, tile_min_y as f32) + 0.5; // Offset to sample pixel centers
 
                                 // TODO: Proper top/left fill rule
                                 let w0_min = orient2d(Vec2::new(window_verts[1].x(), window_verts[1].y()), Vec2::new(window_verts[2].x(), window_verts[2].y()), p);
                                 let w1_min = orient2d(Vec2::new(window_verts[2].x(), window_verts[2].y()), Vec2::new(window_verts[0].x(), window_verts[0].y()), p);
                                 let w2_min = orient2d(Vec2::new(window_verts[0].x(), window_verts[0].y()), Vec2::new(window_verts[1].x(), window_verts[1].y()), p);
-                                write_reg(REG_W0_MIN_ADDR, to_fixed(w0_min, w_fract_bits) as _, &mut *device)?;
-                                write_reg(REG_W1_MIN_ADDR, to_fixed(w1_min, w_fract_bits) as _, &mut *device)?;
-                                write_reg(REG_W2_MIN_ADDR, to_fixed(w2_min, w_fract_bits) as _, &mut *device)?;
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_W0_MIN_ADDR, to_fixed(w0_min, w_fract_bits) as _));
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_W1_MIN_ADDR, to_fixed(w1_min, w_fract_bits) as _));
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_W2_MIN_ADDR, to_fixed(w2_min, w_fract_bits) as _));
 
                                 let w0_min = w0_min / scaled_area;
                                 let w1_min = w1_min / scaled_area;
@@ -499,23 +797,26 @@ fn main() -> Result<(), Error> {
                                 let g_min = verts[0].color.y() * w0_min + verts[1].color.y() * w1_min + verts[2].color.y() * w2_min;
                                 let b_min = verts[0].color.z() * w0_min + verts[1].color.z() * w1_min + verts[2].color.z() * w2_min;
                                 let a_min = verts[0].color.w() * w0_min + verts[1].color.w() * w1_min + verts[2].color.w() * w2_min;
-                                write_reg(REG_R_MIN_ADDR, to_fixed(r_min, color_fract_bits) as _, &mut *device)?;
-                                write_reg(REG_G_MIN_ADDR, to_fixed(g_min, color_fract_bits) as _, &mut *device)?;
-                                write_reg(REG_B_MIN_ADDR, to_fixed(b_min, color_fract_bits) as _, &mut *device)?;
-                                write_reg(REG_A_MIN_ADDR, to_fixed(a_min, color_fract_bits) as _, &mut *device)?;
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_R_MIN_ADDR, to_fixed(r_min, color_fract_bits) as _));
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_G_MIN_ADDR, to_fixed(g_min, color_fract_bits) as _));
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_B_MIN_ADDR, to_fixed(b_min, color_fract_bits) as _));
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_A_MIN_ADDR, to_fixed(a_min, color_fract_bits) as _));
 
                                 // TODO!
                                 let w_inverse_min = 1.0 / 1.0;//1.0 / verts[0].position.w() * w0_min + 1.0 / verts[1].position.w() * w1_min + 1.0 / verts[2].position.w() * w2_min;
-                                write_reg(REG_W_INVERSE_MIN_ADDR, to_fixed(w_inverse_min, W_INVERSE_FRACT_BITS) as _, &mut *device)?;
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_W_INVERSE_MIN_ADDR, to_fixed(w_inverse_min, W_INVERSE_FRACT_BITS) as _));
 
                                 let s_min = verts[0].tex_coord.x() * w0_min + verts[1].tex_coord.x() * w1_min + verts[2].tex_coord.x() * w2_min;
                                 let t_min = verts[0].tex_coord.y() * w0_min + verts[1].tex_coord.y() * w1_min + verts[2].tex_coord.y() * w2_min;
-                                write_reg(REG_S_MIN_ADDR, to_fixed(s_min, ST_FRACT_BITS) as _, &mut *device)?;
-                                write_reg(REG_T_MIN_ADDR, to_fixed(t_min, ST_FRACT_BITS) as _, &mut *device)?;
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_S_MIN_ADDR, to_fixed(s_min, ST_FRACT_BITS) as _));
+                                commands.push(command(COMMAND_OPCODE_WRITE_REG, REG_T_MIN_ADDR, to_fixed(t_min, ST_FRACT_BITS) as _));
 
                                 // Rasterize
+                                commands.push(command(COMMAND_OPCODE_START, 0, 0));
+                                commands.push(command(COMMAND_OPCODE_WAIT_IDLE, 0, 0));
+                                commands.push(command(COMMAND_OPCODE_END, 0, 0));
                                 writeln!(&mut stdout, "    rasterize")?;
-                                device.write_byte(0x04)?;
+                                run_command_list(COMMAND_LIST_ADDR, &commands, &mut *device)?;
                                 let mut elapsed_cycles = 0;
                                 elapsed_cycles |= (device.read_byte()? as u64) << 0;
                                 elapsed_cycles |= (device.read_byte()? as u64) << 8;
@@ -0,0 +1,229 @@
+use crate::spi_slave::*;
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const BLOCK_SIZE: usize = 512;
+
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+const R1_PARAMETER_ERROR: u8 = 0x40;
+
+const DATA_TOKEN: u8 = 0xfe;
+const DATA_RESPONSE_ACCEPTED: u8 = 0x05;
+const DATA_RESPONSE_WRITE_ERROR: u8 = 0x0d;
+const DATA_ERROR_TOKEN_OUT_OF_RANGE: u8 = 0x08;
+
+enum State {
+    Command,
+    WriteWaitToken(u64),
+    WriteData(u64),
+}
+
+// Behavioral SD card (SPI mode, SDHC-style block addressing) backed by a disk image file. Only the commands
+//  needed for init and single-block reads/writes are supported. Without an image file, the card behaves as if
+//  the slot is empty (miso stays high).
+pub struct SdCard {
+    image: Option<File>,
+    num_blocks: u64,
+
+    spi: SpiSlave,
+    state: State,
+    command: Vec<u8>,
+    data: Vec<u8>,
+    out: VecDeque<u8>,
+
+    is_idle: bool,
+    is_app_command: bool,
+}
+
+impl SdCard {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<SdCard> {
+        let path = path.as_ref();
+        let (image, num_blocks) = if path.exists() {
+            let image = OpenOptions::new().read(true).write(true).open(path)?;
+            let num_blocks = image.metadata()?.len() / BLOCK_SIZE as u64;
+            (Some(image), num_blocks)
+        } else {
+            (None, 0)
+        };
+
+        Ok(SdCard {
+            image,
+            num_blocks,
+
+            spi: SpiSlave::new(),
+            state: State::Command,
+            command: Vec::new(),
+            data: Vec::new(),
+            out: VecDeque::new(),
+
+            is_idle: true,
+            is_app_command: false,
+        })
+    }
+
+    fn r1(&self, flags: u8) -> u8 {
+        flags | if self.is_idle { R1_IDLE } else { 0 }
+    }
+
+    fn read_block(&mut self, block: u64) -> io::Result<Vec<u8>> {
+        let image = self.image.as_mut().unwrap();
+        let mut ret = vec![0; BLOCK_SIZE];
+        image.seek(SeekFrom::Start(block * BLOCK_SIZE as u64))?;
+        image.read_exact(&mut ret)?;
+        Ok(ret)
+    }
+
+    fn write_block(&mut self, block: u64, data: &[u8]) -> io::Result<()> {
+        let image = self.image.as_mut().unwrap();
+        image.seek(SeekFrom::Start(block * BLOCK_SIZE as u64))?;
+        image.write_all(data)
+    }
+
+    fn command_received(&mut self) {
+        let index = self.command[0] & 0x3f;
+        let arg = ((self.command[1] as u32) << 24) | ((self.command[2] as u32) << 16) | ((self.command[3] as u32) << 8) | (self.command[4] as u32);
+        let is_app_command = self.is_app_command;
+        self.is_app_command = false;
+
+        // NCR: one byte of delay before any response
+        self.out.push_back(0xff);
+
+        match (is_app_command, index) {
+            // GO_IDLE_STATE
+            (_, 0) => {
+                self.is_idle = true;
+                self.out.push_back(R1_IDLE);
+            }
+            // SEND_IF_COND
+            (_, 8) => {
+                let r1 = self.r1(0);
+                self.out.extend(&[r1, 0x00, 0x00, ((arg >> 8) & 0x0f) as u8, arg as u8]);
+            }
+            // STOP_TRANSMISSION/SEND_STATUS
+            (_, 13) => {
+                let r1 = self.r1(0);
+                self.out.extend(&[r1, 0x00]);
+            }
+            // SET_BLOCKLEN (only 512 is supported, as with SDHC cards)
+            (_, 16) => {
+                let r1 = self.r1(if arg as usize == BLOCK_SIZE { 0 } else { R1_PARAMETER_ERROR });
+                self.out.push_back(r1);
+            }
+            // READ_SINGLE_BLOCK
+            (_, 17) => {
+                let block = arg as u64;
+                if block < self.num_blocks {
+                    let r1 = self.r1(0);
+                    self.out.extend(&[r1, 0xff, DATA_TOKEN]);
+                    match self.read_block(block) {
+                        Ok(data) => {
+                            self.out.extend(&data);
+                            // CRC (not checked in SPI mode by default)
+                            self.out.extend(&[0xff, 0xff]);
+                        }
+                        Err(e) => println!("Unable to read SD card image block {}: {}", block, e),
+                    }
+                } else {
+                    let r1 = self.r1(0);
+                    self.out.extend(&[r1, 0xff, DATA_ERROR_TOKEN_OUT_OF_RANGE]);
+                }
+            }
+            // WRITE_BLOCK
+            (_, 24) => {
+                let r1 = self.r1(if (arg as u64) < self.num_blocks { 0 } else { R1_PARAMETER_ERROR });
+                self.out.push_back(r1);
+                if (arg as u64) < self.num_blocks {
+                    self.state = State::WriteWaitToken(arg as u64);
+                }
+            }
+            // SD_SEND_OP_COND
+            (true, 41) => {
+                self.is_idle = false;
+                self.out.push_back(self.r1(0));
+            }
+            // APP_CMD
+            (_, 55) => {
+                self.is_app_command = true;
+                self.out.push_back(self.r1(0));
+            }
+            // READ_OCR (powered up, CCS set)
+            (_, 58) => {
+                let r1 = self.r1(0);
+                self.out.extend(&[r1, 0xc0, 0xff, 0x80, 0x00]);
+            }
+            _ => {
+                let r1 = self.r1(R1_ILLEGAL_COMMAND);
+                self.out.push_back(r1);
+            }
+        }
+    }
+
+    fn byte_received(&mut self, value: u8) {
+        match self.state {
+            State::Command => {
+                // Commands start with 01 in the top bits; anything else between commands is filler
+                if self.command.is_empty() && (value & 0xc0) != 0x40 {
+                    return;
+                }
+                self.command.push(value);
+                if self.command.len() == 6 {
+                    self.out.clear();
+                    self.command_received();
+                    self.command.clear();
+                }
+            }
+            State::WriteWaitToken(block) => {
+                if value == DATA_TOKEN {
+                    self.data.clear();
+                    self.state = State::WriteData(block);
+                }
+            }
+            State::WriteData(block) => {
+                self.data.push(value);
+                // Data + 2 CRC bytes
+                if self.data.len() == BLOCK_SIZE + 2 {
+                    let data = self.data[..BLOCK_SIZE].to_vec();
+                    let response = match self.write_block(block, &data) {
+                        Ok(()) => DATA_RESPONSE_ACCEPTED,
+                        Err(e) => {
+                            println!("Unable to write SD card image block {}: {}", block, e);
+                            DATA_RESPONSE_WRITE_ERROR
+                        }
+                    };
+                    self.out.clear();
+                    self.out.push_back(response);
+                    // Busy for a few bytes while "programming"
+                    self.out.extend(&[0x00; 4]);
+                    self.state = State::Command;
+                }
+            }
+        }
+    }
+
+    // Returns the new miso value
+    pub fn update(&mut self, cs_n: bool, sck: bool, mosi: bool) -> bool {
+        if self.image.is_none() {
+            return true;
+        }
+
+        match self.spi.update(cs_n, sck, mosi) {
+            SpiEvent::Deselected => {
+                self.state = State::Command;
+                self.command.clear();
+                self.out.clear();
+            }
+            SpiEvent::ByteReceived(value) => {
+                self.byte_received(value);
+                let next_byte = self.out.pop_front().unwrap_or(0xff);
+                self.spi.set_next_byte(next_byte);
+            }
+            SpiEvent::None => (),
+        }
+
+        self.spi.miso()
+    }
+}
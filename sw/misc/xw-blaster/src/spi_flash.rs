@@ -0,0 +1,145 @@
+use crate::spi_slave::*;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SIZE: usize = 16 * 1024 * 1024;
+
+pub const PROGRAM_OFFSET: usize = 0x00800000;
+pub const PROGRAM_MAGIC: u32 = 0x47505758; // "XWPG"
+
+const SECTOR_SIZE: usize = 4 * 1024;
+const BLOCK_SIZE: usize = 64 * 1024;
+const PAGE_SIZE: usize = 256;
+
+const ID: [u8; 3] = [0xef, 0x40, 0x18];
+
+// Behavioral SPI NOR flash (mode 0) backed by an image file. Erases and programs are written back to the file
+//  when chip select is released.
+pub struct SpiFlash {
+    data: Vec<u8>,
+    path: PathBuf,
+
+    write_enable: bool,
+    is_dirty: bool,
+
+    spi: SpiSlave,
+    command: Vec<u8>,
+}
+
+impl SpiFlash {
+    // A missing image file is treated as a fully-erased flash
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<SpiFlash> {
+        let path = path.as_ref().to_path_buf();
+        let mut data = if path.exists() { fs::read(&path)? } else { Vec::new() };
+        data.resize(SIZE, 0xff);
+
+        Ok(SpiFlash {
+            data,
+            path,
+
+            write_enable: false,
+            is_dirty: false,
+
+            spi: SpiSlave::new(),
+            command: Vec::new(),
+        })
+    }
+
+    fn addr(&self) -> usize {
+        (((self.command[1] as usize) << 16) | ((self.command[2] as usize) << 8) | (self.command[3] as usize)) % SIZE
+    }
+
+    // Called after each complete byte; returns the next byte to shift out
+    fn byte_received(&mut self) -> u8 {
+        let len = self.command.len();
+        match self.command[0] {
+            // Read
+            0x03 if len >= 4 => self.data[(self.addr() + len - 4) % SIZE],
+            // Fast read (one dummy byte)
+            0x0b if len >= 5 => self.data[(self.addr() + len - 5) % SIZE],
+            // Read status register
+            0x05 => if self.write_enable { 0x02 } else { 0x00 },
+            // Read ID
+            0x9f if len <= ID.len() => ID[len - 1],
+            // Page program
+            0x02 if len >= 5 && self.write_enable => {
+                let addr = self.addr();
+                let page_addr = addr & !(PAGE_SIZE - 1);
+                let addr = page_addr + ((addr + len - 5) & (PAGE_SIZE - 1));
+                self.data[addr] &= self.command[len - 1];
+                self.is_dirty = true;
+                0xff
+            }
+            _ => 0xff,
+        }
+    }
+
+    fn command_complete(&mut self) {
+        if self.command.is_empty() {
+            return;
+        }
+
+        match self.command[0] {
+            // Write enable
+            0x06 => self.write_enable = true,
+            // Write disable
+            0x04 => self.write_enable = false,
+            // Sector/block erase
+            0x20 | 0xd8 if self.command.len() >= 4 && self.write_enable => {
+                let size = if self.command[0] == 0x20 { SECTOR_SIZE } else { BLOCK_SIZE };
+                let start = self.addr() & !(size - 1);
+                for byte in &mut self.data[start..start + size] {
+                    *byte = 0xff;
+                }
+                self.is_dirty = true;
+                self.write_enable = false;
+            }
+            0x02 => self.write_enable = false,
+            _ => (),
+        }
+
+        if self.is_dirty {
+            if let Err(e) = fs::write(&self.path, &self.data) {
+                println!("Unable to write back flash image {}: {}", self.path.display(), e);
+            }
+            self.is_dirty = false;
+        }
+    }
+
+    // Returns the new miso value
+    pub fn update(&mut self, cs_n: bool, sck: bool, mosi: bool) -> bool {
+        match self.spi.update(cs_n, sck, mosi) {
+            SpiEvent::Deselected => {
+                self.command_complete();
+                self.command.clear();
+            }
+            SpiEvent::ByteReceived(value) => {
+                self.command.push(value);
+                let next_byte = self.byte_received();
+                self.spi.set_next_byte(next_byte);
+            }
+            SpiEvent::None => (),
+        }
+
+        self.spi.miso()
+    }
+}
+
+// Writes a program image at PROGRAM_OFFSET into a (possibly existing) flash image file
+pub fn write_program_image<P: AsRef<Path>, Q: AsRef<Path>>(program_path: P, flash_path: Q) -> io::Result<()> {
+    let program = fs::read(program_path)?;
+
+    let flash_path = flash_path.as_ref();
+    let mut data = if flash_path.exists() { fs::read(flash_path)? } else { Vec::new() };
+    let end = PROGRAM_OFFSET + 8 + program.len();
+    if data.len() < end {
+        data.resize(end, 0xff);
+    }
+    data[PROGRAM_OFFSET..PROGRAM_OFFSET + 4].copy_from_slice(&PROGRAM_MAGIC.to_le_bytes());
+    data[PROGRAM_OFFSET + 4..PROGRAM_OFFSET + 8].copy_from_slice(&(program.len() as u32).to_le_bytes());
+    data[PROGRAM_OFFSET + 8..end].copy_from_slice(&program);
+
+    fs::write(flash_path, data)
+}
@@ -0,0 +1,74 @@
+pub enum SpiEvent {
+    None,
+    Deselected,
+    ByteReceived(u8),
+}
+
+// Bit-level SPI slave (mode 0, MSB first) shared by the behavioral device models. Devices respond to
+//  `ByteReceived` events by calling `set_next_byte` with the byte to shift out during the next transfer.
+pub struct SpiSlave {
+    prev_cs_n: bool,
+    prev_sck: bool,
+
+    in_shift: u8,
+    in_bits: u32,
+
+    out_shift: u8,
+    pending_out: Option<u8>,
+}
+
+impl SpiSlave {
+    pub fn new() -> SpiSlave {
+        SpiSlave {
+            prev_cs_n: true,
+            prev_sck: false,
+
+            in_shift: 0,
+            in_bits: 0,
+
+            out_shift: 0xff,
+            pending_out: None,
+        }
+    }
+
+    pub fn update(&mut self, cs_n: bool, sck: bool, mosi: bool) -> SpiEvent {
+        let mut event = SpiEvent::None;
+
+        if cs_n {
+            if !self.prev_cs_n {
+                event = SpiEvent::Deselected;
+            }
+            self.in_bits = 0;
+            self.out_shift = 0xff;
+            self.pending_out = None;
+        } else {
+            if sck && !self.prev_sck {
+                self.in_shift = (self.in_shift << 1) | (mosi as u8);
+                self.in_bits += 1;
+                if self.in_bits == 8 {
+                    self.in_bits = 0;
+                    event = SpiEvent::ByteReceived(self.in_shift);
+                }
+            }
+            if !sck && self.prev_sck {
+                self.out_shift = match self.pending_out.take() {
+                    Some(value) => value,
+                    _ => self.out_shift << 1,
+                };
+            }
+        }
+
+        self.prev_cs_n = cs_n;
+        self.prev_sck = sck;
+
+        event
+    }
+
+    pub fn set_next_byte(&mut self, value: u8) {
+        self.pending_out = Some(value);
+    }
+
+    pub fn miso(&self) -> bool {
+        (self.out_shift & 0x80) != 0
+    }
+}
@@ -1,5 +1,15 @@
 #include <xw/xw.h>
 
+#define COLOR_THRUST_REGS_BASE (0x04000000)
+
+#define COLOR_THRUST_STATUS ((volatile uint32_t *)(COLOR_THRUST_REGS_BASE + 0x00000000))
+#define COLOR_THRUST_START ((volatile uint32_t *)(COLOR_THRUST_REGS_BASE + 0x00000000))
+#define COLOR_THRUST_COMMAND_LIST_BASE ((volatile uint32_t *)(COLOR_THRUST_REGS_BASE + 0x00000340))
+
+#define COLOR_THRUST_COLOR_BUFFER ((uint32_t *)0x05000000)
+
+#define TILE_PIXELS (16 * 16)
+
 void put_u64(uint64_t value)
 {
     xw_puts_nn("0x");
@@ -12,36 +22,41 @@ void put_u64(uint64_t value)
     xw_puts_nn(buf);
 }
 
+// Locally-administered address
+static const uint8_t mac[6] = { 0x02, 0x58, 0x57, 0x00, 0x00, 0x01 };
+
 int main()
 {
+    xw_net_init(mac, XW_NET_IP(192, 168, 1, 50));
+
     xw_puts("Ready for commands!");
 
     while (true)
     {
         // TODO: Proper command
-        xw_uart_write(0x02);
+        xw_host_write(0x02);
 
         bool is_processing_frame = true;
         while (is_processing_frame)
         {
             // TODO: Proper command
-            uint8_t command = xw_uart_read();
+            uint8_t command = xw_host_read();
             switch (command)
             {
             case 0x00:
                 {
                     // write word
                     uint32_t addr = 0;
-                    addr |= ((uint32_t)xw_uart_read() << 0);
-                    addr |= ((uint32_t)xw_uart_read() << 8);
-                    addr |= ((uint32_t)xw_uart_read() << 16);
-                    addr |= ((uint32_t)xw_uart_read() << 24);
+                    addr |= ((uint32_t)xw_host_read() << 0);
+                    addr |= ((uint32_t)xw_host_read() << 8);
+                    addr |= ((uint32_t)xw_host_read() << 16);
+                    addr |= ((uint32_t)xw_host_read() << 24);
 
                     uint32_t data = 0;
-                    data |= ((uint32_t)xw_uart_read() << 0);
-                    data |= ((uint32_t)xw_uart_read() << 8);
-                    data |= ((uint32_t)xw_uart_read() << 16);
-                    data |= ((uint32_t)xw_uart_read() << 24);
+                    data |= ((uint32_t)xw_host_read() << 0);
+                    data |= ((uint32_t)xw_host_read() << 8);
+                    data |= ((uint32_t)xw_host_read() << 16);
+                    data |= ((uint32_t)xw_host_read() << 24);
 
                     *(uint32_t *)addr = data;
                 }
@@ -51,30 +66,30 @@ int main()
                 {
                     // read word
                     uint32_t addr = 0;
-                    addr |= ((uint32_t)xw_uart_read() << 0);
-                    addr |= ((uint32_t)xw_uart_read() << 8);
-                    addr |= ((uint32_t)xw_uart_read() << 16);
-                    addr |= ((uint32_t)xw_uart_read() << 24);
+                    addr |= ((uint32_t)xw_host_read() << 0);
+                    addr |= ((uint32_t)xw_host_read() << 8);
+                    addr |= ((uint32_t)xw_host_read() << 16);
+                    addr |= ((uint32_t)xw_host_read() << 24);
 
                     uint32_t data = *(uint32_t *)addr;
-                    xw_uart_write((data >> 0) & 0xff);
-                    xw_uart_write((data >> 8) & 0xff);
-                    xw_uart_write((data >> 16) & 0xff);
-                    xw_uart_write((data >> 24) & 0xff);
+                    xw_host_write((data >> 0) & 0xff);
+                    xw_host_write((data >> 8) & 0xff);
+                    xw_host_write((data >> 16) & 0xff);
+                    xw_host_write((data >> 24) & 0xff);
                 }
                 break;
 
             case 0x02:
                 {
                     // write tile
-                    uint32_t *addr = (uint32_t *)0x05000000; // TODO: Proper constant
-                    for (int i = 0; i < 256; i++) // TODO: Proper constant
+                    uint32_t *addr = COLOR_THRUST_COLOR_BUFFER;
+                    for (int i = 0; i < TILE_PIXELS; i++)
                     {
                         uint32_t pixel = 0;
-                        pixel |= ((uint32_t)xw_uart_read() << 0);
-                        pixel |= ((uint32_t)xw_uart_read() << 8);
-                        pixel |= ((uint32_t)xw_uart_read() << 16);
-                        pixel |= ((uint32_t)xw_uart_read() << 24);
+                        pixel |= ((uint32_t)xw_host_read() << 0);
+                        pixel |= ((uint32_t)xw_host_read() << 8);
+                        pixel |= ((uint32_t)xw_host_read() << 16);
+                        pixel |= ((uint32_t)xw_host_read() << 24);
 
                         *addr++ = pixel;
                     }
@@ -84,15 +99,15 @@ int main()
             case 0x03:
                 {
                     // read tile
-                    uint32_t *addr = (uint32_t *)0x05000000; // TODO: Proper constant
-                    for (int i = 0; i < 256; i++) // TODO: Proper constant
+                    uint32_t *addr = COLOR_THRUST_COLOR_BUFFER;
+                    for (int i = 0; i < TILE_PIXELS; i++)
                     {
                         uint32_t pixel = *addr++;
 
-                        xw_uart_write((pixel >> 0) & 0xff);
-                        xw_uart_write((pixel >> 8) & 0xff);
-                        xw_uart_write((pixel >> 16) & 0xff);
-                        xw_uart_write((pixel >> 24) & 0xff);
+                        xw_host_write((pixel >> 0) & 0xff);
+                        xw_host_write((pixel >> 8) & 0xff);
+                        xw_host_write((pixel >> 16) & 0xff);
+                        xw_host_write((pixel >> 24) & 0xff);
                     }
                 }
                 break;
@@ -102,26 +117,54 @@ int main()
                     // rasterize
                     uint64_t start_cycles = xw_cycles();
 
-                    *(volatile uint32_t *)0x04000000 = 1; // TODO: Proper constant/value
-                    while (*(volatile uint32_t *)0x04000000)
+                    *COLOR_THRUST_START = 1;
+                    while (*COLOR_THRUST_STATUS)
                         ;
 
                     uint64_t end_cycles = xw_cycles();
                     uint64_t elapsed_cycles = end_cycles - start_cycles;
-                    xw_uart_write((elapsed_cycles >> 0) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 8) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 16) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 24) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 32) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 40) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 48) & 0xff);
-                    xw_uart_write((elapsed_cycles >> 56) & 0xff);
+                    xw_host_write((elapsed_cycles >> 0) & 0xff);
+                    xw_host_write((elapsed_cycles >> 8) & 0xff);
+                    xw_host_write((elapsed_cycles >> 16) & 0xff);
+                    xw_host_write((elapsed_cycles >> 24) & 0xff);
+                    xw_host_write((elapsed_cycles >> 32) & 0xff);
+                    xw_host_write((elapsed_cycles >> 40) & 0xff);
+                    xw_host_write((elapsed_cycles >> 48) & 0xff);
+                    xw_host_write((elapsed_cycles >> 56) & 0xff);
                 }
                 break;
 
             case 0x05:
                 is_processing_frame = false;
                 break;
+
+            case 0x06:
+                {
+                    // run command list
+                    uint32_t addr = 0;
+                    addr |= ((uint32_t)xw_host_read() << 0);
+                    addr |= ((uint32_t)xw_host_read() << 8);
+                    addr |= ((uint32_t)xw_host_read() << 16);
+                    addr |= ((uint32_t)xw_host_read() << 24);
+
+                    uint64_t start_cycles = xw_cycles();
+
+                    *COLOR_THRUST_COMMAND_LIST_BASE = addr;
+                    while (*COLOR_THRUST_STATUS)
+                        ;
+
+                    uint64_t end_cycles = xw_cycles();
+                    uint64_t elapsed_cycles = end_cycles - start_cycles;
+                    xw_host_write((elapsed_cycles >> 0) & 0xff);
+                    xw_host_write((elapsed_cycles >> 8) & 0xff);
+                    xw_host_write((elapsed_cycles >> 16) & 0xff);
+                    xw_host_write((elapsed_cycles >> 24) & 0xff);
+                    xw_host_write((elapsed_cycles >> 32) & 0xff);
+                    xw_host_write((elapsed_cycles >> 40) & 0xff);
+                    xw_host_write((elapsed_cycles >> 48) & 0xff);
+                    xw_host_write((elapsed_cycles >> 56) & 0xff);
+                }
+                break;
             }
         }
     }
@@ -0,0 +1,13 @@
+#ifndef XW_FLASH_H
+#define XW_FLASH_H
+
+#include "inttypes.h"
+
+// Memory-mapped (read-only) view of the flash; reads are slow, as each 16-byte line is a separate SPI transaction
+#define XW_FLASH_XIP ((volatile uint8_t *)0x09000000)
+
+// Returns JEDEC manufacturer ID in bits 16-23 and device ID in bits 0-15
+uint32_t xw_flash_read_id();
+void xw_flash_read(uint32_t addr, uint8_t *dst, uint32_t len);
+
+#endif
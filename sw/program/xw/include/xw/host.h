@@ -0,0 +1,11 @@
+#ifndef XW_HOST_H
+#define XW_HOST_H
+
+#include "inttypes.h"
+
+// Host command transport; commands can arrive over either UART or the UDP host link, and output goes back over
+//  whichever one the last byte was read from
+uint8_t xw_host_read();
+void xw_host_write(uint8_t byte);
+
+#endif
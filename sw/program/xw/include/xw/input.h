@@ -0,0 +1,39 @@
+#ifndef XW_INPUT_H
+#define XW_INPUT_H
+
+#include "inttypes.h"
+
+#define XW_BUTTON_0 (1 << 0)
+#define XW_BUTTON_1 (1 << 1)
+#define XW_BUTTON_2 (1 << 2)
+#define XW_BUTTON_3 (1 << 3)
+
+// SNES pad bits in shift order; NES pads only report the first 8 bits (with A/B in place of B/Y)
+#define XW_PAD_B (1 << 0)
+#define XW_PAD_Y (1 << 1)
+#define XW_PAD_SELECT (1 << 2)
+#define XW_PAD_START (1 << 3)
+#define XW_PAD_UP (1 << 4)
+#define XW_PAD_DOWN (1 << 5)
+#define XW_PAD_LEFT (1 << 6)
+#define XW_PAD_RIGHT (1 << 7)
+#define XW_PAD_A (1 << 8)
+#define XW_PAD_X (1 << 9)
+#define XW_PAD_L (1 << 10)
+#define XW_PAD_R (1 << 11)
+
+uint8_t xw_buttons();
+// Returns buttons pressed since the last call and clears them
+uint8_t xw_buttons_pressed();
+// Returns buttons released since the last call and clears them
+uint8_t xw_buttons_released();
+
+uint8_t xw_switches();
+
+uint16_t xw_pad();
+// Returns pad buttons pressed since the last call and clears them
+uint16_t xw_pad_pressed();
+// Returns pad buttons released since the last call and clears them
+uint16_t xw_pad_released();
+
+#endif
@@ -3,6 +3,19 @@
 
 #include "inttypes.h"
 
+#define XW_NUM_LEDS (8)
+#define XW_NUM_DISPLAY_DIGITS (4)
+
 void xw_set_leds(uint8_t leds);
+// 0 = off, 255 = fully on (default)
+void xw_set_led_brightness(uint32_t led, uint8_t brightness);
+
+// Digit 0 is the rightmost digit; bit n of digit_enable/decimal_points corresponds to digit n
+void xw_display_hex(uint16_t value, uint8_t decimal_points, uint8_t digit_enable);
+// One byte per digit, bits 0-7 = segments a-g, dp
+void xw_display_raw(uint32_t segments, uint8_t digit_enable);
+void xw_display_off();
+// 0 = off, 255 = fully on (default)
+void xw_set_display_brightness(uint8_t brightness);
 
 #endif
@@ -0,0 +1,26 @@
+#ifndef XW_NET_H
+#define XW_NET_H
+
+#include "inttypes.h"
+#include "bool.h"
+
+#define XW_NET_IP(a, b, c, d) (((uint32_t)(a) << 24) | ((uint32_t)(b) << 16) | ((uint32_t)(c) << 8) | (uint32_t)(d))
+
+// UDP port for the host link ("XW")
+#define XW_NET_PORT (0x5857)
+
+// Host link datagrams start with a one-byte header: a sequence number from the host, and the sequence number of
+//  the last fully-consumed host datagram (ack) from the device. The host sends one datagram at a time and
+//  retransmits it until it's acked; duplicates are dropped and re-acked here.
+#define XW_NET_MAX_PAYLOAD (1024)
+
+void xw_net_init(const uint8_t *mac, uint32_t ip);
+
+// Handles any pending ARP/IP traffic and returns the next host link byte if one is available. When the last byte
+//  of a datagram is read, it's acked (along with any pending output).
+bool xw_net_try_read(uint8_t *byte);
+// Output is buffered until xw_net_flush is called, a datagram fills up, or a datagram is acked
+void xw_net_write(uint8_t byte);
+void xw_net_flush();
+
+#endif
@@ -0,0 +1,14 @@
+#ifndef XW_SD_H
+#define XW_SD_H
+
+#include "inttypes.h"
+#include "bool.h"
+
+#define XW_SD_BLOCK_SIZE (512)
+
+// Resets and initializes the card in SPI mode; must succeed before any block reads/writes
+bool xw_sd_init();
+bool xw_sd_read_block(uint32_t block, uint8_t *dst);
+bool xw_sd_write_block(uint32_t block, const uint8_t *src);
+
+#endif
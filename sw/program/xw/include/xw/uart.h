@@ -2,10 +2,13 @@
 #define XW_UART_H
 
 #include "inttypes.h"
+#include "bool.h"
 
 void xw_uart_write(uint8_t byte);
 uint8_t xw_uart_read();
+bool xw_uart_try_read(uint8_t *byte);
 
+// Console output goes to the host over whichever transport it last sent commands on (see host.h)
 void xw_putc(const char c);
 void xw_puts(const char *s);
 void xw_puts_nn(const char *s);
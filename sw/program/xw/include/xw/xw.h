@@ -5,8 +5,13 @@
 #include "bool.h"
 
 #include "cpu.h"
+#include "flash.h"
 #include "leds.h"
 #include "uart.h"
 #include "display.h"
+#include "input.h"
+#include "sd.h"
+#include "net.h"
+#include "host.h"
 
 #endif
@@ -0,0 +1,44 @@
+#include <xw/flash.h>
+
+#define XW_SPI_FLASH_BASE (0x08000000)
+
+#define XW_SPI_FLASH_STATUS ((volatile uint8_t *)(XW_SPI_FLASH_BASE + 0x00000000))
+#define XW_SPI_FLASH_CHIP_SELECT ((volatile uint8_t *)(XW_SPI_FLASH_BASE + 0x00000010))
+#define XW_SPI_FLASH_DATA ((volatile uint8_t *)(XW_SPI_FLASH_BASE + 0x00000020))
+
+#define XW_FLASH_COMMAND_READ (0x03)
+#define XW_FLASH_COMMAND_READ_ID (0x9f)
+
+static uint8_t xw_flash_transfer(uint8_t value)
+{
+    *XW_SPI_FLASH_DATA = value;
+
+    while (!(*XW_SPI_FLASH_STATUS & 1))
+        ;
+
+    return *XW_SPI_FLASH_DATA;
+}
+
+uint32_t xw_flash_read_id()
+{
+    *XW_SPI_FLASH_CHIP_SELECT = 1;
+    xw_flash_transfer(XW_FLASH_COMMAND_READ_ID);
+    uint32_t ret = 0;
+    for (int i = 0; i < 3; i++)
+        ret = (ret << 8) | xw_flash_transfer(0x00);
+    *XW_SPI_FLASH_CHIP_SELECT = 0;
+
+    return ret;
+}
+
+void xw_flash_read(uint32_t addr, uint8_t *dst, uint32_t len)
+{
+    *XW_SPI_FLASH_CHIP_SELECT = 1;
+    xw_flash_transfer(XW_FLASH_COMMAND_READ);
+    xw_flash_transfer((uint8_t)(addr >> 16));
+    xw_flash_transfer((uint8_t)(addr >> 8));
+    xw_flash_transfer((uint8_t)addr);
+    for (uint32_t i = 0; i < len; i++)
+        dst[i] = xw_flash_transfer(0x00);
+    *XW_SPI_FLASH_CHIP_SELECT = 0;
+}
@@ -0,0 +1,38 @@
+#include <xw/bool.h>
+#include <xw/host.h>
+#include <xw/net.h>
+#include <xw/uart.h>
+
+static bool xw_host_is_net;
+
+uint8_t xw_host_read()
+{
+    // Any buffered output has to reach the host before we wait on it
+    if (xw_host_is_net)
+        xw_net_flush();
+
+    while (true)
+    {
+        uint8_t byte;
+
+        if (xw_uart_try_read(&byte))
+        {
+            xw_host_is_net = false;
+            return byte;
+        }
+
+        if (xw_net_try_read(&byte))
+        {
+            xw_host_is_net = true;
+            return byte;
+        }
+    }
+}
+
+void xw_host_write(uint8_t byte)
+{
+    if (xw_host_is_net)
+        xw_net_write(byte);
+    else
+        xw_uart_write(byte);
+}
@@ -0,0 +1,54 @@
+#include <xw/input.h>
+
+#define XW_INPUT_BASE (0x07000000)
+
+#define XW_INPUT_BUTTONS ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000000))
+#define XW_INPUT_BUTTONS_PRESSED ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000010))
+#define XW_INPUT_BUTTONS_RELEASED ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000020))
+#define XW_INPUT_SWITCHES ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000030))
+#define XW_INPUT_PAD ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000040))
+#define XW_INPUT_PAD_PRESSED ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000050))
+#define XW_INPUT_PAD_RELEASED ((volatile uint32_t *)(XW_INPUT_BASE + 0x00000060))
+
+uint8_t xw_buttons()
+{
+    return (uint8_t)*XW_INPUT_BUTTONS;
+}
+
+uint8_t xw_buttons_pressed()
+{
+    uint32_t ret = *XW_INPUT_BUTTONS_PRESSED;
+    *XW_INPUT_BUTTONS_PRESSED = ret;
+    return (uint8_t)ret;
+}
+
+uint8_t xw_buttons_released()
+{
+    uint32_t ret = *XW_INPUT_BUTTONS_RELEASED;
+    *XW_INPUT_BUTTONS_RELEASED = ret;
+    return (uint8_t)ret;
+}
+
+uint8_t xw_switches()
+{
+    return (uint8_t)*XW_INPUT_SWITCHES;
+}
+
+uint16_t xw_pad()
+{
+    return (uint16_t)*XW_INPUT_PAD;
+}
+
+uint16_t xw_pad_pressed()
+{
+    uint32_t ret = *XW_INPUT_PAD_PRESSED;
+    *XW_INPUT_PAD_PRESSED = ret;
+    return (uint16_t)ret;
+}
+
+uint16_t xw_pad_released()
+{
+    uint32_t ret = *XW_INPUT_PAD_RELEASED;
+    *XW_INPUT_PAD_RELEASED = ret;
+    return (uint16_t)ret;
+}
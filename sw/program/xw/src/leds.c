@@ -1,8 +1,43 @@
 #include <xw/leds.h>
 
-#define XW_LEDS ((volatile uint8_t *)0x02000000)
+#define XW_LEDS_BASE (0x02000000)
+
+#define XW_LEDS ((volatile uint8_t *)(XW_LEDS_BASE + 0x00000000))
+#define XW_LED_BRIGHTNESS(led) ((volatile uint8_t *)(XW_LEDS_BASE + 0x00000010 + (led) * 0x10))
+#define XW_DISPLAY_CONTROL ((volatile uint8_t *)(XW_LEDS_BASE + 0x00000090))
+#define XW_DISPLAY_DATA ((volatile uint32_t *)(XW_LEDS_BASE + 0x000000a0))
+#define XW_DISPLAY_BRIGHTNESS ((volatile uint8_t *)(XW_LEDS_BASE + 0x000000b0))
+
+#define XW_DISPLAY_CONTROL_RAW (1 << 4)
 
 void xw_set_leds(uint8_t leds)
 {
     *XW_LEDS = leds;
 }
+
+void xw_set_led_brightness(uint32_t led, uint8_t brightness)
+{
+    *XW_LED_BRIGHTNESS(led) = brightness;
+}
+
+void xw_display_hex(uint16_t value, uint8_t decimal_points, uint8_t digit_enable)
+{
+    *XW_DISPLAY_DATA = ((uint32_t)(decimal_points & 0x0f) << 16) | value;
+    *XW_DISPLAY_CONTROL = digit_enable & 0x0f;
+}
+
+void xw_display_raw(uint32_t segments, uint8_t digit_enable)
+{
+    *XW_DISPLAY_DATA = segments;
+    *XW_DISPLAY_CONTROL = XW_DISPLAY_CONTROL_RAW | (digit_enable & 0x0f);
+}
+
+void xw_display_off()
+{
+    *XW_DISPLAY_CONTROL = 0;
+}
+
+void xw_set_display_brightness(uint8_t brightness)
+{
+    *XW_DISPLAY_BRIGHTNESS = brightness;
+}
@@ -0,0 +1,318 @@
+#include <xw/net.h>
+
+#define XW_ETH_BASE (0x0b000000)
+
+#define XW_ETH_STATUS ((volatile uint32_t *)(XW_ETH_BASE + 0x00000000))
+#define XW_ETH_MAC_ADDR_LOW ((volatile uint32_t *)(XW_ETH_BASE + 0x00000010))
+#define XW_ETH_MAC_ADDR_HIGH ((volatile uint32_t *)(XW_ETH_BASE + 0x00000020))
+#define XW_ETH_RX_LENGTH ((volatile uint32_t *)(XW_ETH_BASE + 0x00000030))
+#define XW_ETH_RX_RELEASE ((volatile uint32_t *)(XW_ETH_BASE + 0x00000040))
+#define XW_ETH_TX_LENGTH ((volatile uint32_t *)(XW_ETH_BASE + 0x00000050))
+
+#define XW_ETH_RX_BUFFER ((volatile uint8_t *)(XW_ETH_BASE + 0x00001000))
+#define XW_ETH_TX_BUFFER ((volatile uint8_t *)(XW_ETH_BASE + 0x00002000))
+
+#define XW_ETH_STATUS_RX_FRAME_AVAILABLE (1 << 0)
+#define XW_ETH_STATUS_TX_BUSY (1 << 1)
+
+#define XW_NET_ETHERTYPE_IPV4 (0x0800)
+#define XW_NET_ETHERTYPE_ARP (0x0806)
+
+#define XW_NET_IP_PROTOCOL_UDP (17)
+
+#define XW_NET_ARP_OPER_REQUEST (1)
+#define XW_NET_ARP_OPER_REPLY (2)
+
+// Frame offsets; outgoing IP headers never have options, so the UDP header and payload offsets are fixed for TX
+#define XW_NET_ETH_DST (0)
+#define XW_NET_ETH_SRC (6)
+#define XW_NET_ETH_TYPE (12)
+#define XW_NET_ETH_HEADER_SIZE (14)
+
+#define XW_NET_ARP_OPER (XW_NET_ETH_HEADER_SIZE + 6)
+#define XW_NET_ARP_SHA (XW_NET_ETH_HEADER_SIZE + 8)
+#define XW_NET_ARP_SPA (XW_NET_ETH_HEADER_SIZE + 14)
+#define XW_NET_ARP_THA (XW_NET_ETH_HEADER_SIZE + 18)
+#define XW_NET_ARP_TPA (XW_NET_ETH_HEADER_SIZE + 24)
+#define XW_NET_ARP_SIZE (XW_NET_ETH_HEADER_SIZE + 28)
+
+#define XW_NET_IP_VERSION_IHL (XW_NET_ETH_HEADER_SIZE + 0)
+#define XW_NET_IP_TOTAL_LENGTH (XW_NET_ETH_HEADER_SIZE + 2)
+#define XW_NET_IP_PROTOCOL (XW_NET_ETH_HEADER_SIZE + 9)
+#define XW_NET_IP_SRC (XW_NET_ETH_HEADER_SIZE + 12)
+#define XW_NET_IP_DST (XW_NET_ETH_HEADER_SIZE + 16)
+#define XW_NET_IP_HEADER_SIZE (20)
+
+#define XW_NET_UDP_SRC_PORT (0)
+#define XW_NET_UDP_DST_PORT (2)
+#define XW_NET_UDP_LENGTH (4)
+#define XW_NET_UDP_HEADER_SIZE (8)
+
+#define XW_NET_TX_UDP (XW_NET_ETH_HEADER_SIZE + XW_NET_IP_HEADER_SIZE)
+#define XW_NET_TX_LINK_HEADER (XW_NET_TX_UDP + XW_NET_UDP_HEADER_SIZE)
+#define XW_NET_TX_PAYLOAD (XW_NET_TX_LINK_HEADER + 1)
+
+static uint8_t xw_net_mac[6];
+static uint32_t xw_net_ip;
+static uint16_t xw_net_ip_id;
+
+// The host we're talking to, taken from the last accepted datagram
+static bool xw_net_has_peer;
+static uint8_t xw_net_peer_mac[6];
+static uint32_t xw_net_peer_ip;
+static uint16_t xw_net_peer_port;
+
+static uint8_t xw_net_last_seq;
+static uint8_t xw_net_ack_seq;
+
+// While a datagram is being read, it stays in the RX buffer (blocking further frames until it's released)
+static bool xw_net_is_reading;
+static uint32_t xw_net_read_offset;
+static uint32_t xw_net_read_end;
+
+static uint32_t xw_net_write_length;
+
+static uint16_t xw_net_get_u16(volatile uint8_t *buf, uint32_t offset)
+{
+    return ((uint16_t)buf[offset] << 8) | (uint16_t)buf[offset + 1];
+}
+
+static uint32_t xw_net_get_u32(volatile uint8_t *buf, uint32_t offset)
+{
+    return ((uint32_t)xw_net_get_u16(buf, offset) << 16) | (uint32_t)xw_net_get_u16(buf, offset + 2);
+}
+
+static void xw_net_put_u16(volatile uint8_t *buf, uint32_t offset, uint16_t value)
+{
+    buf[offset] = (uint8_t)(value >> 8);
+    buf[offset + 1] = (uint8_t)value;
+}
+
+static void xw_net_put_u32(volatile uint8_t *buf, uint32_t offset, uint32_t value)
+{
+    xw_net_put_u16(buf, offset, (uint16_t)(value >> 16));
+    xw_net_put_u16(buf, offset + 2, (uint16_t)value);
+}
+
+static void xw_net_put_mac(volatile uint8_t *buf, uint32_t offset, const uint8_t *mac)
+{
+    for (int i = 0; i < 6; i++)
+        buf[offset + i] = mac[i];
+}
+
+static void xw_net_wait_for_tx()
+{
+    while (*XW_ETH_STATUS & XW_ETH_STATUS_TX_BUSY)
+        ;
+}
+
+static void xw_net_release()
+{
+    *XW_ETH_RX_RELEASE = 1;
+}
+
+// Sends a host link datagram with whatever's been written so far, even if that's nothing (so it can carry an ack)
+static void xw_net_send()
+{
+    // If nothing's been written, the previous frame may still be in flight
+    if (!xw_net_write_length)
+        xw_net_wait_for_tx();
+
+    volatile uint8_t *tx = XW_ETH_TX_BUFFER;
+
+    xw_net_put_mac(tx, XW_NET_ETH_DST, xw_net_peer_mac);
+    xw_net_put_mac(tx, XW_NET_ETH_SRC, xw_net_mac);
+    xw_net_put_u16(tx, XW_NET_ETH_TYPE, XW_NET_ETHERTYPE_IPV4);
+
+    uint32_t udp_length = XW_NET_UDP_HEADER_SIZE + 1 + xw_net_write_length;
+    uint32_t ip_length = XW_NET_IP_HEADER_SIZE + udp_length;
+
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 0, 0x4500); // IPv4, no options, default TOS
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 2, (uint16_t)ip_length);
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 4, xw_net_ip_id++);
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 6, 0x4000); // Don't fragment
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 8, (64 << 8) | XW_NET_IP_PROTOCOL_UDP); // TTL, protocol
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 10, 0);
+    xw_net_put_u32(tx, XW_NET_IP_SRC, xw_net_ip);
+    xw_net_put_u32(tx, XW_NET_IP_DST, xw_net_peer_ip);
+
+    uint32_t checksum = 0;
+    for (int i = 0; i < XW_NET_IP_HEADER_SIZE; i += 2)
+        checksum += xw_net_get_u16(tx, XW_NET_ETH_HEADER_SIZE + i);
+    while (checksum >> 16)
+        checksum = (checksum & 0xffff) + (checksum >> 16);
+    xw_net_put_u16(tx, XW_NET_ETH_HEADER_SIZE + 10, (uint16_t)~checksum);
+
+    // The UDP checksum is optional for IPv4; the FCS already covers the whole frame
+    xw_net_put_u16(tx, XW_NET_TX_UDP + XW_NET_UDP_SRC_PORT, XW_NET_PORT);
+    xw_net_put_u16(tx, XW_NET_TX_UDP + XW_NET_UDP_DST_PORT, xw_net_peer_port);
+    xw_net_put_u16(tx, XW_NET_TX_UDP + XW_NET_UDP_LENGTH, (uint16_t)udp_length);
+    xw_net_put_u16(tx, XW_NET_TX_UDP + 6, 0);
+
+    tx[XW_NET_TX_LINK_HEADER] = xw_net_ack_seq;
+
+    *XW_ETH_TX_LENGTH = XW_NET_TX_PAYLOAD + xw_net_write_length;
+    xw_net_write_length = 0;
+}
+
+static void xw_net_handle_arp()
+{
+    volatile uint8_t *rx = XW_ETH_RX_BUFFER;
+
+    if (xw_net_get_u16(rx, XW_NET_ARP_OPER) != XW_NET_ARP_OPER_REQUEST || xw_net_get_u32(rx, XW_NET_ARP_TPA) != xw_net_ip)
+        return;
+
+    // Pending output lives in the TX buffer, so it has to go out first
+    if (xw_net_write_length)
+        xw_net_send();
+    xw_net_wait_for_tx();
+
+    volatile uint8_t *tx = XW_ETH_TX_BUFFER;
+
+    for (int i = 0; i < 6; i++)
+        tx[XW_NET_ETH_DST + i] = rx[XW_NET_ETH_SRC + i];
+    xw_net_put_mac(tx, XW_NET_ETH_SRC, xw_net_mac);
+    xw_net_put_u16(tx, XW_NET_ETH_TYPE, XW_NET_ETHERTYPE_ARP);
+
+    // Same hardware/protocol types and address sizes as the request
+    for (int i = XW_NET_ETH_HEADER_SIZE; i < XW_NET_ARP_OPER; i++)
+        tx[i] = rx[i];
+    xw_net_put_u16(tx, XW_NET_ARP_OPER, XW_NET_ARP_OPER_REPLY);
+    xw_net_put_mac(tx, XW_NET_ARP_SHA, xw_net_mac);
+    xw_net_put_u32(tx, XW_NET_ARP_SPA, xw_net_ip);
+    for (int i = 0; i < 10; i++)
+        tx[XW_NET_ARP_THA + i] = rx[XW_NET_ARP_SHA + i];
+
+    *XW_ETH_TX_LENGTH = XW_NET_ARP_SIZE;
+}
+
+// Returns true if the frame is a host link datagram, in which case reading starts and the frame is held
+static bool xw_net_handle_ip(uint32_t frame_length)
+{
+    volatile uint8_t *rx = XW_ETH_RX_BUFFER;
+
+    uint8_t version_ihl = rx[XW_NET_IP_VERSION_IHL];
+    if ((version_ihl >> 4) != 4 || rx[XW_NET_IP_PROTOCOL] != XW_NET_IP_PROTOCOL_UDP || xw_net_get_u32(rx, XW_NET_IP_DST) != xw_net_ip)
+        return false;
+
+    uint32_t udp = XW_NET_ETH_HEADER_SIZE + (version_ihl & 0x0f) * 4;
+    if (udp + XW_NET_UDP_HEADER_SIZE > frame_length || XW_NET_ETH_HEADER_SIZE + xw_net_get_u16(rx, XW_NET_IP_TOTAL_LENGTH) > frame_length)
+        return false;
+    if (xw_net_get_u16(rx, udp + XW_NET_UDP_DST_PORT) != XW_NET_PORT)
+        return false;
+    uint32_t udp_length = xw_net_get_u16(rx, udp + XW_NET_UDP_LENGTH);
+    if (udp_length < XW_NET_UDP_HEADER_SIZE + 1 || udp + udp_length > frame_length)
+        return false;
+
+    uint32_t peer_ip = xw_net_get_u32(rx, XW_NET_IP_SRC);
+    uint16_t peer_port = xw_net_get_u16(rx, udp + XW_NET_UDP_SRC_PORT);
+    uint8_t seq = rx[udp + XW_NET_UDP_HEADER_SIZE];
+
+    bool is_same_peer = xw_net_has_peer && peer_ip == xw_net_peer_ip && peer_port == xw_net_peer_port;
+    if (!is_same_peer)
+    {
+        // Any pending output was meant for the old peer
+        xw_net_write_length = 0;
+
+        xw_net_has_peer = true;
+        for (int i = 0; i < 6; i++)
+            xw_net_peer_mac[i] = rx[XW_NET_ETH_SRC + i];
+        xw_net_peer_ip = peer_ip;
+        xw_net_peer_port = peer_port;
+
+        // Nothing from this peer has been acked yet
+        xw_net_ack_seq = seq - 1;
+    }
+    else if (seq == xw_net_last_seq)
+    {
+        // Our ack was lost; the data's already been consumed
+        xw_net_send();
+        return false;
+    }
+
+    xw_net_last_seq = seq;
+    xw_net_read_offset = udp + XW_NET_UDP_HEADER_SIZE + 1;
+    xw_net_read_end = udp + udp_length;
+    return true;
+}
+
+static void xw_net_finish_reading()
+{
+    xw_net_is_reading = false;
+    xw_net_release();
+
+    xw_net_ack_seq = xw_net_last_seq;
+    xw_net_send();
+}
+
+static void xw_net_poll()
+{
+    if (!(*XW_ETH_STATUS & XW_ETH_STATUS_RX_FRAME_AVAILABLE))
+        return;
+
+    volatile uint8_t *rx = XW_ETH_RX_BUFFER;
+    uint32_t frame_length = *XW_ETH_RX_LENGTH;
+    uint16_t ethertype = xw_net_get_u16(rx, XW_NET_ETH_TYPE);
+
+    if (ethertype == XW_NET_ETHERTYPE_ARP && frame_length >= XW_NET_ARP_SIZE)
+    {
+        xw_net_handle_arp();
+    }
+    else if (ethertype == XW_NET_ETHERTYPE_IPV4 && frame_length >= XW_NET_TX_UDP && xw_net_handle_ip(frame_length))
+    {
+        xw_net_is_reading = true;
+        // Empty datagrams are consumed as soon as they arrive
+        if (xw_net_read_offset == xw_net_read_end)
+            xw_net_finish_reading();
+        return;
+    }
+
+    xw_net_release();
+}
+
+void xw_net_init(const uint8_t *mac, uint32_t ip)
+{
+    for (int i = 0; i < 6; i++)
+        xw_net_mac[i] = mac[i];
+    xw_net_ip = ip;
+
+    *XW_ETH_MAC_ADDR_LOW = ((uint32_t)mac[3] << 24) | ((uint32_t)mac[2] << 16) | ((uint32_t)mac[1] << 8) | (uint32_t)mac[0];
+    *XW_ETH_MAC_ADDR_HIGH = ((uint32_t)mac[5] << 8) | (uint32_t)mac[4];
+}
+
+bool xw_net_try_read(uint8_t *byte)
+{
+    if (!xw_net_is_reading)
+    {
+        xw_net_poll();
+        if (!xw_net_is_reading)
+            return false;
+    }
+
+    *byte = XW_ETH_RX_BUFFER[xw_net_read_offset++];
+
+    if (xw_net_read_offset == xw_net_read_end)
+        xw_net_finish_reading();
+
+    return true;
+}
+
+void xw_net_write(uint8_t byte)
+{
+    if (!xw_net_has_peer)
+        return;
+
+    if (!xw_net_write_length)
+        xw_net_wait_for_tx();
+
+    XW_ETH_TX_BUFFER[XW_NET_TX_PAYLOAD + xw_net_write_length++] = byte;
+
+    if (xw_net_write_length == XW_NET_MAX_PAYLOAD)
+        xw_net_send();
+}
+
+void xw_net_flush()
+{
+    if (xw_net_write_length)
+        xw_net_send();
+}
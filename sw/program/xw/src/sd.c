@@ -0,0 +1,209 @@
+#include <xw/sd.h>
+
+#define XW_SD_BASE (0x0a000000)
+
+#define XW_SD_STATUS ((volatile uint8_t *)(XW_SD_BASE + 0x00000000))
+#define XW_SD_CHIP_SELECT ((volatile uint8_t *)(XW_SD_BASE + 0x00000010))
+#define XW_SD_DATA ((volatile uint8_t *)(XW_SD_BASE + 0x00000020))
+#define XW_SD_CLOCK_DIV ((volatile uint8_t *)(XW_SD_BASE + 0x00000030))
+
+// 100mhz / (2 * (124 + 1)) = 400khz during init, 100mhz / (2 * (1 + 1)) = 25mhz after
+#define XW_SD_CLOCK_DIV_INIT (124)
+#define XW_SD_CLOCK_DIV_FAST (1)
+
+#define XW_SD_R1_IDLE (0x01)
+
+#define XW_SD_DATA_TOKEN (0xfe)
+#define XW_SD_DATA_RESPONSE_MASK (0x1f)
+#define XW_SD_DATA_RESPONSE_ACCEPTED (0x05)
+
+#define XW_SD_MAX_RETRIES (100000)
+
+static bool xw_sd_is_sdhc;
+
+static uint8_t xw_sd_transfer(uint8_t value)
+{
+    *XW_SD_DATA = value;
+
+    while (!(*XW_SD_STATUS & 1))
+        ;
+
+    return *XW_SD_DATA;
+}
+
+static void xw_sd_deselect()
+{
+    *XW_SD_CHIP_SELECT = 0;
+    // Card needs an extra byte of clocks to release its output after deselect
+    xw_sd_transfer(0xff);
+}
+
+// Sends a command and returns its R1 response (0xff if the card didn't respond). Chip select is left asserted
+//  so that any remaining response bytes can be read.
+static uint8_t xw_sd_command(uint8_t index, uint32_t arg, uint8_t crc)
+{
+    *XW_SD_CHIP_SELECT = 1;
+    xw_sd_transfer(0xff);
+
+    xw_sd_transfer(0x40 | index);
+    xw_sd_transfer((uint8_t)(arg >> 24));
+    xw_sd_transfer((uint8_t)(arg >> 16));
+    xw_sd_transfer((uint8_t)(arg >> 8));
+    xw_sd_transfer((uint8_t)arg);
+    xw_sd_transfer(crc);
+
+    uint8_t r1 = 0xff;
+    for (int i = 0; i < 8 && (r1 & 0x80); i++)
+        r1 = xw_sd_transfer(0xff);
+
+    return r1;
+}
+
+static uint8_t xw_sd_app_command(uint8_t index, uint32_t arg)
+{
+    xw_sd_command(55, 0, 0x01);
+    xw_sd_deselect();
+    return xw_sd_command(index, arg, 0x01);
+}
+
+bool xw_sd_init()
+{
+    *XW_SD_CLOCK_DIV = XW_SD_CLOCK_DIV_INIT;
+
+    // At least 74 clocks with chip select deasserted to enter native mode
+    *XW_SD_CHIP_SELECT = 0;
+    for (int i = 0; i < 10; i++)
+        xw_sd_transfer(0xff);
+
+    // GO_IDLE_STATE (CRC is required, as the card isn't in SPI mode yet)
+    uint8_t r1 = xw_sd_command(0, 0, 0x95);
+    xw_sd_deselect();
+    if (r1 != XW_SD_R1_IDLE)
+        return false;
+
+    // SEND_IF_COND (2.7-3.6v, check pattern 0xaa); version 1 cards reject this
+    bool is_v2 = false;
+    r1 = xw_sd_command(8, 0x000001aa, 0x87);
+    if (r1 == XW_SD_R1_IDLE)
+    {
+        uint32_t r7 = 0;
+        for (int i = 0; i < 4; i++)
+            r7 = (r7 << 8) | xw_sd_transfer(0xff);
+        if ((r7 & 0xfff) != 0x1aa)
+        {
+            xw_sd_deselect();
+            return false;
+        }
+        is_v2 = true;
+    }
+    xw_sd_deselect();
+
+    // SD_SEND_OP_COND until the card leaves the idle state (HCS set for v2 cards)
+    int retries = 0;
+    do
+    {
+        r1 = xw_sd_app_command(41, is_v2 ? 0x40000000 : 0);
+        xw_sd_deselect();
+        if (++retries == XW_SD_MAX_RETRIES)
+            return false;
+    } while (r1 == XW_SD_R1_IDLE);
+    if (r1 != 0)
+        return false;
+
+    // READ_OCR to check CCS (block addressing)
+    xw_sd_is_sdhc = false;
+    if (is_v2)
+    {
+        r1 = xw_sd_command(58, 0, 0x01);
+        uint8_t ocr_high = xw_sd_transfer(0xff);
+        for (int i = 0; i < 3; i++)
+            xw_sd_transfer(0xff);
+        xw_sd_deselect();
+        if (r1 != 0)
+            return false;
+        xw_sd_is_sdhc = (ocr_high & 0x40) != 0;
+    }
+
+    // SET_BLOCKLEN for byte-addressed cards
+    if (!xw_sd_is_sdhc)
+    {
+        r1 = xw_sd_command(16, XW_SD_BLOCK_SIZE, 0x01);
+        xw_sd_deselect();
+        if (r1 != 0)
+            return false;
+    }
+
+    *XW_SD_CLOCK_DIV = XW_SD_CLOCK_DIV_FAST;
+
+    return true;
+}
+
+static uint32_t xw_sd_block_addr(uint32_t block)
+{
+    return xw_sd_is_sdhc ? block : block * XW_SD_BLOCK_SIZE;
+}
+
+bool xw_sd_read_block(uint32_t block, uint8_t *dst)
+{
+    // READ_SINGLE_BLOCK
+    if (xw_sd_command(17, xw_sd_block_addr(block), 0x01) != 0)
+    {
+        xw_sd_deselect();
+        return false;
+    }
+
+    uint8_t token = 0xff;
+    for (int i = 0; i < XW_SD_MAX_RETRIES && token == 0xff; i++)
+        token = xw_sd_transfer(0xff);
+    if (token != XW_SD_DATA_TOKEN)
+    {
+        xw_sd_deselect();
+        return false;
+    }
+
+    for (int i = 0; i < XW_SD_BLOCK_SIZE; i++)
+        dst[i] = xw_sd_transfer(0xff);
+
+    // CRC (ignored)
+    xw_sd_transfer(0xff);
+    xw_sd_transfer(0xff);
+
+    xw_sd_deselect();
+
+    return true;
+}
+
+bool xw_sd_write_block(uint32_t block, const uint8_t *src)
+{
+    // WRITE_BLOCK
+    if (xw_sd_command(24, xw_sd_block_addr(block), 0x01) != 0)
+    {
+        xw_sd_deselect();
+        return false;
+    }
+
+    xw_sd_transfer(0xff);
+    xw_sd_transfer(XW_SD_DATA_TOKEN);
+    for (int i = 0; i < XW_SD_BLOCK_SIZE; i++)
+        xw_sd_transfer(src[i]);
+
+    // CRC (ignored)
+    xw_sd_transfer(0xff);
+    xw_sd_transfer(0xff);
+
+    uint8_t response = xw_sd_transfer(0xff);
+    if ((response & XW_SD_DATA_RESPONSE_MASK) != XW_SD_DATA_RESPONSE_ACCEPTED)
+    {
+        xw_sd_deselect();
+        return false;
+    }
+
+    // Wait for the card to finish programming
+    bool is_done = false;
+    for (int i = 0; i < XW_SD_MAX_RETRIES && !is_done; i++)
+        is_done = xw_sd_transfer(0xff) != 0x00;
+
+    xw_sd_deselect();
+
+    return is_done;
+}
@@ -1,4 +1,5 @@
 #include <xw/bool.h>
+#include <xw/host.h>
 #include <xw/uart.h>
 
 #define XW_UART_BASE (0x03000000)
@@ -27,10 +28,19 @@ uint8_t xw_uart_read()
     return *XW_UART_RX_READ;
 }
 
+bool xw_uart_try_read(uint8_t *byte)
+{
+    if (!(*XW_UART_RX_STATUS & 1))
+        return false;
+
+    *byte = *XW_UART_RX_READ;
+    return true;
+}
+
 void xw_putc(const char c)
 {
-    xw_uart_write(XW_UART_COMMAND_PUTC);
-    xw_uart_write((uint8_t)c);
+    xw_host_write(XW_UART_COMMAND_PUTC);
+    xw_host_write((uint8_t)c);
 }
 
 void xw_puts(const char *s)